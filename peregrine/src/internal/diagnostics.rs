@@ -0,0 +1,216 @@
+//! Spans and events reported to the `tracing` crate, enabled by the `tracing` feature.
+//!
+//! Without the feature, every function here does nothing, so generated node code can call
+//! them unconditionally.
+
+use crate::Time;
+use crate::internal::exec::ExecEnvironment;
+use crate::internal::operation::InternalResult;
+use crate::internal::placement::{DenseTime, Placement};
+
+/// How much of the engine's behavior is reported to `tracing`.
+///
+/// Each level includes everything reported by the levels before it. Subscribers can still
+/// filter by `tracing` level: simulations are `INFO` spans, operations are `DEBUG` spans,
+/// cache lookups and groundings are `DEBUG` events, and timeline insertions are `TRACE` events.
+/// Operations that overrun their timeout are `WARN` events, which are reported at every verbosity.
+///
+/// Set with [Session::with_trace_verbosity][crate::Session::with_trace_verbosity]. Nothing is
+/// reported without the `tracing` feature.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TraceVerbosity {
+    /// Nothing is reported, except operations that overrun their timeout.
+    Off,
+    /// A span for each simulation, and for each operation body that runs in it.
+    #[default]
+    Operations,
+    /// Also an event for each cache lookup and each grounded operation time.
+    Execution,
+    /// Also an event for each operation inserted into a timeline.
+    All,
+}
+
+/// The span that the operations of one simulation are reported in, and how much to report in it.
+///
+/// Work is spread across threads, so it is passed to operations through their
+/// [ExecEnvironment] instead of being entered.
+pub struct SimulationSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    verbosity: TraceVerbosity,
+}
+
+/// A reference to the [SimulationSpan] of the current simulation, carried by every
+/// [ExecEnvironment].
+///
+/// The environment is copied into every frame of a request chain, so this is a single
+/// reference with the `tracing` feature, and empty without it.
+#[derive(Copy, Clone, Default)]
+pub struct TraceContext<'s> {
+    #[cfg(feature = "tracing")]
+    span: Option<&'s SimulationSpan>,
+    #[cfg(not(feature = "tracing"))]
+    span: std::marker::PhantomData<&'s SimulationSpan>,
+}
+
+impl<'s> TraceContext<'s> {
+    pub fn new(_span: &'s SimulationSpan) -> Self {
+        #[cfg(feature = "tracing")]
+        return Self { span: Some(_span) };
+        #[cfg(not(feature = "tracing"))]
+        Self::default()
+    }
+
+    /// The span to report in, if anything at `level` or above is reported.
+    #[cfg(feature = "tracing")]
+    fn span(self, level: TraceVerbosity) -> Option<&'s tracing::Span> {
+        self.span.filter(|s| s.verbosity >= level).map(|s| &s.span)
+    }
+}
+
+pub fn simulation_span(_verbosity: TraceVerbosity) -> SimulationSpan {
+    #[cfg(feature = "tracing")]
+    return SimulationSpan {
+        span: if _verbosity > TraceVerbosity::Off {
+            tracing::info_span!("simulation")
+        } else {
+            tracing::Span::none()
+        },
+        verbosity: _verbosity,
+    };
+    #[cfg(not(feature = "tracing"))]
+    SimulationSpan {}
+}
+
+// The functions below are called from the generated code of every node, whose stack frames
+// recurse deeply through request chains. Anything that is only done while tracing is kept in
+// separate functions that are never inlined, so that it doesn't grow those frames.
+
+/// Runs an operation body inside a span for the operation, if the environment has one.
+#[inline]
+pub fn in_operation_span<T>(
+    _env: &ExecEnvironment,
+    _node: u64,
+    _resources: &[&'static str],
+    _time: Time,
+    body: impl FnOnce() -> T,
+) -> T {
+    #[cfg(feature = "tracing")]
+    if let Some(parent) = _env.trace.span(TraceVerbosity::Operations) {
+        return traced_operation(parent, _node, _resources, _time, body);
+    }
+    body()
+}
+
+#[cfg(feature = "tracing")]
+#[cold]
+#[inline(never)]
+fn traced_operation<T>(
+    parent: &tracing::Span,
+    node: u64,
+    resources: &[&'static str],
+    time: Time,
+    body: impl FnOnce() -> T,
+) -> T {
+    tracing::debug_span!(
+        parent: parent,
+        "operation",
+        node = format_args!("{node:016x}"),
+        writes = resources.join(","),
+        %time,
+    )
+    .in_scope(body)
+}
+
+/// Reports whether an operation's output was found in the history cache.
+#[inline]
+pub fn cache_lookup(
+    _env: &ExecEnvironment,
+    _node: u64,
+    _resources: &[&'static str],
+    _time: Time,
+    _hit: bool,
+) {
+    #[cfg(feature = "tracing")]
+    if let Some(parent) = _env.trace.span(TraceVerbosity::Execution) {
+        traced_cache_lookup(parent, _node, _resources, _time, _hit);
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[cold]
+#[inline(never)]
+fn traced_cache_lookup(
+    parent: &tracing::Span,
+    node: u64,
+    resources: &[&'static str],
+    time: Time,
+    hit: bool,
+) {
+    let node = format_args!("{node:016x}");
+    let writes = resources.join(",");
+    if hit {
+        tracing::debug!(parent: parent, %node, %writes, %time, "cache hit");
+    } else {
+        tracing::debug!(parent: parent, %node, %writes, %time, "cache miss");
+    }
+}
+
+/// Reports the time a dynamically placed operation was grounded to, or that it couldn't be.
+#[inline]
+pub fn grounding(
+    _env: &ExecEnvironment,
+    _placement: &Placement,
+    _grounded: &InternalResult<DenseTime>,
+) {
+    #[cfg(feature = "tracing")]
+    if let Some(parent) = _env.trace.span(TraceVerbosity::Execution) {
+        traced_grounding(parent, _placement, _grounded);
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[cold]
+#[inline(never)]
+fn traced_grounding(
+    parent: &tracing::Span,
+    placement: &Placement,
+    grounded: &InternalResult<DenseTime>,
+) {
+    use crate::internal::timeline::duration_to_epoch;
+
+    let min = duration_to_epoch(placement.min().when);
+    let max = duration_to_epoch(placement.max().when);
+    match grounded {
+        Ok(time) => {
+            let time = duration_to_epoch(time.when);
+            tracing::debug!(parent: parent, %min, %max, %time, "grounded");
+        }
+        Err(_) => tracing::debug!(parent: parent, %min, %max, "grounding failed"),
+    }
+}
+
+/// Reports an operation inserted into a resource's timeline.
+pub fn timeline_insert(
+    _verbosity: TraceVerbosity,
+    _resource: &'static str,
+    _placement: &Placement,
+) {
+    #[cfg(feature = "tracing")]
+    if _verbosity >= TraceVerbosity::All {
+        use crate::internal::timeline::duration_to_epoch;
+
+        let min = duration_to_epoch(_placement.min().when);
+        let max = duration_to_epoch(_placement.max().when);
+        tracing::trace!(resource = _resource, %min, %max, "timeline insert");
+    }
+}
+
+/// Reports an operation body that is still running after its timeout has passed.
+///
+/// This is reported regardless of [TraceVerbosity], since the body may never return.
+pub fn operation_overrun(_time: Time) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(time = %_time, "operation exceeded its timeout and is still running");
+}
@@ -1,21 +1,59 @@
 #![doc(hidden)]
 
+use crate::internal::diagnostics::{self, TraceContext};
 use crate::internal::history::History;
-use crate::internal::operation::ObservedErrorOutput;
+use crate::internal::operation::grounding::GroundingContinuation;
+use crate::internal::operation::{Continuation, InternalResult, ObservedErrorOutput};
+use crate::internal::placement::{CoincidentWritePolicy, DenseTime, GroundingErrorPolicy};
+use crate::internal::timeline::{MaybeGrounded, Timelines, duration_to_epoch};
+use crate::public::activity::Transient;
+use crate::public::plan::{Discrepancy, Value};
+use crate::public::resource::{Data, FloatPolicy, Resource};
+use crate::{Duration, Time};
+use anyhow::{Context, anyhow};
 use crossbeam::queue::SegQueue;
 use derive_more::Deref;
-use std::cell::UnsafeCell;
+use oneshot::{Receiver, RecvError, RecvTimeoutError, TryRecvError};
+use parking_lot::{Condvar, Mutex};
+use rayon::{Scope, Yield};
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, UnsafeCell};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
+use std::hash::{Hash, Hasher};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Instant;
 
 use std::fmt::{Display, Formatter};
 
-pub const STACK_LIMIT: usize = 2000;
+/// How deep a request chain recurses before it continues in a new rayon task.
+///
+/// This is the same with and without the `tracing` feature, so that enabling it doesn't change
+/// how simulations are scheduled. It is low enough that debug builds with tracing stay well within
+/// the default thread stack.
+pub const STACK_LIMIT: u32 = 500;
 
 #[derive(Copy, Clone)]
 pub struct ExecEnvironment<'s, 'o: 's> {
     pub history: &'o History,
     pub errors: &'s ErrorAccumulator,
-    pub stack_counter: usize,
+    /// A `u32` keeps the environment small; it is copied into every frame of a request chain.
+    pub stack_counter: u32,
+    pub float_policy: FloatPolicy,
+    /// A reference is half the size of the duration, for the same reason.
+    pub operation_timeout: Option<&'o std::time::Duration>,
+    pub coincident_writes: CoincidentWritePolicy,
+    pub grounding_errors: GroundingErrorPolicy,
+    /// Set by [Plan::verify_cache][crate::Plan::verify_cache]; operations skip history lookups
+    /// and compare their fresh outputs against it instead.
+    pub cache_audit: Option<&'s CacheAudit>,
+    /// Where operations are reported, if the simulation is being traced.
+    pub trace: TraceContext<'s>,
+    /// Set by [Plan::view_with][crate::Plan::view_with]; operations fail without running
+    /// once it has been triggered.
+    pub interrupt: Option<&'s Interrupt>,
 }
 
 impl<'s, 'o> ExecEnvironment<'s, 'o> {
@@ -32,6 +70,518 @@ impl<'s, 'o> ExecEnvironment<'s, 'o> {
             ..self
         }
     }
+
+    /// Starts a spawned rayon task with a fresh stack.
+    ///
+    /// The guard marks the simulation as panicked if the task panics, so a fold waiting on
+    /// outputs in the same scope stops waiting for ones that will never be sent. Taking it
+    /// inside the task, instead of wrapping the spawn, keeps the frames of request chains small.
+    pub fn start_task(self) -> (Self, PanicGuard<'s>) {
+        (self.reset(), PanicGuard(self.errors))
+    }
+}
+
+/// Marks the [ErrorAccumulator] as panicked if it is dropped during a panic.
+pub struct PanicGuard<'s>(&'s ErrorAccumulator);
+
+impl Drop for PanicGuard<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.2.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A node's output with its time, and the hash it is stored under in history.
+pub type HashedOutput<'o, R> = (DenseTime, u64, <<R as Resource>::Data as Data<'o>>::Read);
+
+/// A pending root request for a single node's output, created by [request_nodes].
+pub enum RootRequest<'o, R: Resource> {
+    Grounded(
+        DenseTime,
+        Receiver<InternalResult<(u64, <R::Data as Data<'o>>::Read)>>,
+    ),
+    Ungrounded(
+        Receiver<InternalResult<DenseTime>>,
+        Receiver<InternalResult<(u64, <R::Data as Data<'o>>::Read)>>,
+    ),
+}
+
+impl<'o, R: Resource> RootRequest<'o, R> {
+    /// Blocks until the request is finished. Returns `None` if the node produced an error;
+    /// the error itself is reported through the [ErrorAccumulator].
+    pub fn recv(self) -> anyhow::Result<Option<(DenseTime, <R::Data as Data<'o>>::Read)>> {
+        Ok(self.recv_hashed()?.map(|(time, _, read)| (time, read)))
+    }
+
+    /// Like [RootRequest::recv], but also returns the hash the output is stored under in history.
+    pub fn recv_hashed(self) -> anyhow::Result<Option<HashedOutput<'o, R>>> {
+        Ok(match self {
+            RootRequest::Grounded(time, receiver) => {
+                receiver.recv()?.ok().map(|(hash, read)| (time, hash, read))
+            }
+            RootRequest::Ungrounded(grounding_receiver, receiver) => {
+                match (grounding_receiver.recv()?, receiver.recv()?) {
+                    (Ok(time), Ok((hash, read))) => Some((time, hash, read)),
+                    _ => None,
+                }
+            }
+        })
+    }
+
+    /// Like [RootRequest::recv_hashed], but called from inside the scope the request was
+    /// spawned in, before the scope has finished; see [recv_in_scope].
+    pub fn recv_hashed_in_scope(
+        self,
+        errors: &ErrorAccumulator,
+    ) -> anyhow::Result<Option<HashedOutput<'o, R>>> {
+        Ok(match self {
+            RootRequest::Grounded(time, receiver) => recv_in_scope(receiver, errors)?
+                .ok()
+                .map(|(hash, read)| (time, hash, read)),
+            RootRequest::Ungrounded(grounding_receiver, receiver) => {
+                match (
+                    recv_in_scope(grounding_receiver, errors)?,
+                    recv_in_scope(receiver, errors)?,
+                ) {
+                    (Ok(time), Ok((hash, read))) => Some((time, hash, read)),
+                    _ => None,
+                }
+            }
+        })
+    }
+}
+
+/// Receives the output of a task in the current rayon scope.
+///
+/// Instead of blocking the thread while it waits, this runs other pending tasks of the pool,
+/// so it doesn't deadlock when called from the scope's own thread in a single-threaded pool.
+/// If a task of the simulation panicked, the output might never be sent, so this gives up;
+/// the panic is raised when the scope ends.
+fn recv_in_scope<T>(receiver: Receiver<T>, errors: &ErrorAccumulator) -> Result<T, RecvError> {
+    loop {
+        match receiver.try_recv() {
+            Ok(value) => return Ok(value),
+            Err(TryRecvError::Disconnected) => return Err(RecvError),
+            Err(TryRecvError::Empty) if errors.panicked() => return Err(RecvError),
+            Err(TryRecvError::Empty) => {
+                if rayon::yield_now() != Some(Yield::Executed) {
+                    // Nothing to help with, so the task is running on another thread.
+                    match receiver.recv_timeout(std::time::Duration::from_millis(1)) {
+                        Ok(value) => return Ok(value),
+                        Err(RecvTimeoutError::Disconnected) => return Err(RecvError),
+                        Err(RecvTimeoutError::Timeout) => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Spawns root requests for the outputs (and groundings, if needed) of each node.
+pub fn request_nodes<'s, 'o: 's, R: Resource>(
+    nodes: Vec<MaybeGrounded<'o, R>>,
+    scope: &Scope<'s>,
+    timelines: &'s Timelines<'o>,
+    env: ExecEnvironment<'s, 'o>,
+) -> Vec<RootRequest<'o, R>> {
+    let mut requests = Vec::with_capacity(nodes.len());
+    let mut spawns = Vec::with_capacity(nodes.len());
+    for node in nodes {
+        let (sender, receiver) = oneshot::channel();
+
+        match node {
+            MaybeGrounded::Grounded(t, n) => {
+                requests.push(RootRequest::Grounded(t, receiver));
+                spawns.push((n, None, sender));
+            }
+            MaybeGrounded::Ungrounded(n) => {
+                let (grounding_sender, grounding_receiver) = oneshot::channel();
+                requests.push(RootRequest::Ungrounded(grounding_receiver, receiver));
+                spawns.push((n, Some(grounding_sender), sender));
+            }
+        }
+    }
+
+    // Workers pick up the tasks they spawned most recently first,
+    // so prioritized operations are spawned last. Thieves still take the oldest tasks.
+    spawns.sort_by_key(|(n, ..)| n.prioritized());
+    for (n, grounding_sender, sender) in spawns {
+        if let Some(grounding_sender) = grounding_sender {
+            scope.spawn(move |s| {
+                let (env, _guard) = env.start_task();
+                n.request_grounding(
+                    GroundingContinuation::Root(grounding_sender),
+                    true,
+                    s,
+                    timelines,
+                    env,
+                )
+            });
+        }
+        scope.spawn(move |s| {
+            let (env, _guard) = env.start_task();
+            n.request(Continuation::Root(sender), true, s, timelines, env)
+        });
+    }
+    requests
+}
+
+/// Waits for a resource's requests spawned by [request_range], discarding the outputs.
+pub type PendingRange<'o> = Pending<'o, ()>;
+
+/// Waits for the requests spawned for a resource, and collects their outputs.
+pub type Pending<'o, T> = Box<dyn FnOnce() -> anyhow::Result<T> + Send + 'o>;
+
+/// The times a resource was written, and a function that samples it at any time from those
+/// writes, returning `None` before the first one.
+pub type Samples<'o> = (Vec<Time>, Box<dyn Fn(Time) -> Option<Value> + 'o>);
+
+/// Like [PendingRange], but waits for the resource's [Samples].
+pub type PendingSamples<'o> = Pending<'o, Samples<'o>>;
+
+/// Spawns root requests for every node of a resource within the bounds, without collecting
+/// the outputs. Used through [ResourceHistoryPlugin::request_range][crate::internal::resource::ResourceHistoryPlugin::request_range]
+/// to simulate several resources in the same scope.
+pub fn request_range<'s, 'o: 's, R: Resource>(
+    timelines: &'s Timelines<'o>,
+    bounds: (Bound<DenseTime>, Bound<DenseTime>),
+    scope: &Scope<'s>,
+    env: ExecEnvironment<'s, 'o>,
+) -> anyhow::Result<PendingRange<'o>> {
+    if !timelines.contains_resource::<R>() {
+        return Err(anyhow!(
+            "resource {} is not included in the model",
+            R::LABEL
+        ));
+    }
+    let requests = request_nodes(timelines.range::<R>(bounds), scope, timelines, env);
+    Ok(Box::new(move || {
+        for request in requests {
+            request.recv()?;
+        }
+        Ok(())
+    }))
+}
+
+/// Spawns root requests for every node of a resource within the bounds, and the last one
+/// before them, and collects the outputs for sampling. Used through
+/// [ResourceHistoryPlugin::sample_range][crate::internal::resource::ResourceHistoryPlugin::sample_range]
+/// to sample several resources from the same scope.
+pub fn sample_range<'s, 'o: 's, R: Resource>(
+    timelines: &'s Timelines<'o>,
+    bounds: (Bound<DenseTime>, Bound<DenseTime>),
+    scope: &Scope<'s>,
+    env: ExecEnvironment<'s, 'o>,
+) -> anyhow::Result<PendingSamples<'o>> {
+    if !timelines.contains_resource::<R>() {
+        return Err(anyhow!(
+            "resource {} is not included in the model",
+            R::LABEL
+        ));
+    }
+    let nodes = timelines.range_inclusive_previous::<R>(bounds);
+    let requests = request_nodes(nodes, scope, timelines, env);
+    Ok(Box::new(move || {
+        let mut outputs = Vec::with_capacity(requests.len());
+        for request in requests {
+            if let Some(output) = request.recv()? {
+                outputs.push(output);
+            }
+        }
+        // Ungrounded outputs arrive out of order, and later writes at the same time win.
+        outputs.sort_by_key(|(time, _)| *time);
+        let writes = outputs
+            .into_iter()
+            .map(|(time, read)| (duration_to_epoch(time.when), read))
+            .collect::<BTreeMap<_, _>>();
+        let times = writes.keys().copied().collect();
+        let sampler = move |time| {
+            let (_, read) = writes.range(..=time).next_back()?;
+            Some(Value::new::<R>(R::Data::from_read(*read, time)))
+        };
+        Ok((
+            times,
+            Box::new(sampler) as Box<dyn Fn(Time) -> Option<Value>>,
+        ))
+    }))
+}
+
+thread_local! {
+    static DOWNSTREAM_COUNT: Cell<Option<usize>> = const { Cell::new(None) };
+    /// The grounded time of the operation being evaluated, and the start of its plan.
+    static OPS_TIME: Cell<Option<(Time, Time)>> = const { Cell::new(None) };
+}
+
+/// Runs part of an operation's evaluation with [OpsTime] set to its grounded time,
+/// and [OpsElapsed] to the time since `start`.
+///
+/// This must cover both hashing the body and calling it.
+pub fn with_ops_time<T>(time: Time, start: Time, body: impl FnOnce() -> T) -> T {
+    let previous = OPS_TIME.replace(Some((time, start)));
+    let result = body();
+    OPS_TIME.set(previous);
+    result
+}
+
+/// Captured by `op!` bodies that use `ops_time`.
+///
+/// It yields the operation's grounded time, and hashes it too, so that outputs are not
+/// reused from the cache when the operation moves in time.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct OpsTime;
+
+impl OpsTime {
+    pub fn get(&self) -> Time {
+        OPS_TIME
+            .get()
+            .expect("expected ops_time to be set while evaluating an operation")
+            .0
+    }
+}
+
+impl Hash for OpsTime {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.get().hash(state);
+    }
+}
+
+/// Captured by `op!` bodies that use `ops_elapsed`.
+///
+/// It yields the time from the start of the plan to the operation's grounded time, and
+/// hashes it too, like [OpsTime].
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct OpsElapsed;
+
+impl OpsElapsed {
+    pub fn get(&self) -> Duration {
+        let (time, start) = OPS_TIME
+            .get()
+            .expect("expected ops_elapsed to be set while evaluating an operation");
+        time - start
+    }
+}
+
+impl Hash for OpsElapsed {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.get().hash(state);
+    }
+}
+
+/// Captured by `#![not_idempotent]` op bodies.
+///
+/// The engine may rerun a single operation when its inputs change, without rerunning the
+/// rest of its activity. Operations marked `#![not_idempotent]` declare that this is unsafe,
+/// so in debug builds, running the body a second time fails an assertion.
+#[derive(Default, Serialize, Deserialize)]
+pub struct RunOnce {
+    #[serde(skip)]
+    ran: AtomicBool,
+}
+
+impl RunOnce {
+    pub fn check(&self) {
+        let ran = self.ran.swap(true, Ordering::SeqCst);
+        debug_assert!(
+            !ran,
+            "a `#![not_idempotent]` operation was rerun without the rest of its activity"
+        );
+    }
+}
+
+impl Hash for RunOnce {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+/// Calls an operation body with `inputs`, and if it fails with a [Transient] error, calls it
+/// again with fresh inputs from `reread`, up to the number of retries in `retry`.
+///
+/// `retry` is the operation's `#![retry(..)]` policy, if it has one. The delay between attempts
+/// starts at its backoff and doubles each time. It is waited out on the blocking pool, so the
+/// calling worker can run other operations in the meantime.
+pub fn with_retries<I, T>(
+    retry: Option<(u32, std::time::Duration)>,
+    inputs: I,
+    reread: impl Fn() -> I,
+    body: impl Fn(I) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let mut result = body(inputs);
+    let mut attempt = 0;
+    while let Err(e) = &result
+        && e.is::<Transient>()
+        && let Some((retries, backoff)) = retry
+        && attempt < retries
+    {
+        let delay = backoff.saturating_mul(2u32.saturating_pow(attempt));
+        BLOCKING_POOL.install(|| std::thread::sleep(delay));
+        attempt += 1;
+        result = body(reread());
+    }
+    if attempt > 0 {
+        result.with_context(|| format!("gave up after {attempt} retries"))
+    } else {
+        result
+    }
+}
+
+/// Runs an operation body with [downstream_count][crate::downstream_count] set to `count`.
+pub fn with_downstream_count<T>(count: usize, body: impl FnOnce() -> T) -> T {
+    let previous = DOWNSTREAM_COUNT.replace(Some(count));
+    let result = body();
+    DOWNSTREAM_COUNT.set(previous);
+    result
+}
+
+pub(crate) fn current_downstream_count() -> Option<usize> {
+    DOWNSTREAM_COUNT.get()
+}
+
+/// The pool that `#![blocking]` operation bodies run on, so that they don't occupy
+/// the compute pool's workers.
+static BLOCKING_POOL: LazyLock<rayon::ThreadPool> = LazyLock::new(|| {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(cores * BLOCKING_THREADS_PER_CORE)
+        .thread_name(|i| format!("peregrine-blocking-{i}"))
+        .build()
+        .expect("could not build the blocking operation pool")
+});
+
+/// Blocking bodies mostly wait on IO, so the blocking pool has more threads than there are cores.
+const BLOCKING_THREADS_PER_CORE: usize = 4;
+
+/// Runs the body of a `#![blocking]` operation on the blocking pool.
+///
+/// While it waits, the calling worker may run other operations from the compute pool, as rayon
+/// does when installing into another pool, but this isn't guaranteed.
+pub fn run_blocking<T: Send>(body: impl FnOnce() -> T + Send) -> T {
+    let ops_time = OPS_TIME.get();
+    let downstream_count = DOWNSTREAM_COUNT.get();
+    BLOCKING_POOL.install(move || {
+        let previous_time = OPS_TIME.replace(ops_time);
+        let previous_count = DOWNSTREAM_COUNT.replace(downstream_count);
+        let result = body();
+        OPS_TIME.set(previous_time);
+        DOWNSTREAM_COUNT.set(previous_count);
+        result
+    })
+}
+
+/// Runs an operation body, producing an error if it runs for longer than `timeout`.
+///
+/// While the body runs, the [Watchdog] reports it to `tracing` once it passes the limit.
+pub fn with_timeout<T>(
+    timeout: Option<std::time::Duration>,
+    time: Time,
+    body: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    let Some(timeout) = timeout else {
+        return body();
+    };
+
+    let start = Instant::now();
+    let id = WATCHDOG.watch(start + timeout, time);
+    let result = body();
+    WATCHDOG.unwatch(id);
+
+    let elapsed = start.elapsed();
+    if elapsed > timeout {
+        Err(anyhow!(
+            "operation exceeded its timeout of {timeout:?} (took {elapsed:?})"
+        ))
+    } else {
+        result
+    }
+}
+
+/// Runs an operation body with [with_timeout], and records how long it took in the plan's
+/// [trace][crate::internal::profiling::Trace] if the `profiling` feature is enabled.
+pub fn run_body<T>(
+    _timelines: &Timelines,
+    _node: u64,
+    _resources: &[&'static str],
+    timeout: Option<std::time::Duration>,
+    time: Time,
+    body: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+    #[cfg(feature = "profiling")]
+    let start = Instant::now();
+    let result = with_timeout(timeout, time, body);
+    #[cfg(feature = "profiling")]
+    _timelines
+        .trace()
+        .record(_node, _resources, start.elapsed());
+    result
+}
+
+/// Encodes an operation's output for [Timelines::record_execution].
+pub fn encode_output<'o, R: Resource>(read: <R::Data as Data<'o>>::Read, time: Time) -> Vec<u8> {
+    bincode::serde::encode_to_vec(R::Data::from_read(read, time), bincode::config::standard())
+        .expect("could not encode operation output")
+}
+
+static WATCHDOG: LazyLock<Watchdog> = LazyLock::new(Watchdog::start);
+
+/// The deadlines watched by the [Watchdog], and the condition variable that wakes it when one is added.
+type Deadlines = (Mutex<HashMap<u64, (Instant, Time)>>, Condvar);
+
+/// A background thread that reports operation bodies running past their deadlines.
+///
+/// The thread sleeps until the earliest deadline being watched, or until a new one is added,
+/// so it doesn't wake at all while nothing is running with a timeout.
+struct Watchdog {
+    next_id: AtomicU64,
+    deadlines: Arc<Deadlines>,
+}
+
+impl Watchdog {
+    fn start() -> Self {
+        let deadlines: Arc<Deadlines> = Arc::default();
+        let watched = deadlines.clone();
+        std::thread::Builder::new()
+            .name("peregrine-watchdog".to_string())
+            .spawn(move || {
+                let (lock, condvar) = &*watched;
+                let mut watched = lock.lock();
+                loop {
+                    match watched.values().map(|(deadline, _)| *deadline).min() {
+                        None => condvar.wait(&mut watched),
+                        Some(earliest) => {
+                            if condvar.wait_until(&mut watched, earliest).timed_out() {
+                                let now = Instant::now();
+                                // Each overrun is only reported once.
+                                watched.retain(|_, (deadline, time)| {
+                                    if *deadline <= now {
+                                        diagnostics::operation_overrun(*time);
+                                        false
+                                    } else {
+                                        true
+                                    }
+                                });
+                            }
+                        }
+                    }
+                }
+            })
+            .expect("could not spawn the operation watchdog thread");
+        Self {
+            next_id: AtomicU64::new(0),
+            deadlines,
+        }
+    }
+
+    fn watch(&self, deadline: Instant, time: Time) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (lock, condvar) = &*self.deadlines;
+        lock.lock().insert(id, (deadline, time));
+        // The new deadline might be earlier than the one the thread is waiting for.
+        condvar.notify_one();
+        id
+    }
+
+    fn unwatch(&self, id: u64) {
+        self.deadlines.0.lock().remove(&id);
+    }
 }
 
 #[derive(Deref, Default)]
@@ -45,8 +595,100 @@ impl<T> UnsafeSyncCell<T> {
     }
 }
 
+/// A handle that aborts the simulations it is passed to, through
+/// [SimOptions][crate::SimOptions].
+///
+/// Clones share the same flag, so a token can be cancelled from another thread while the
+/// simulation is running.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Aborts any simulation using this token. Operations that already ran keep their outputs.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Decides whether a simulation should stop, and remembers whether it did.
+pub struct Interrupt {
+    cancel_token: Option<CancelToken>,
+    deadline: Option<Instant>,
+    triggered: AtomicBool,
+}
+impl Interrupt {
+    pub fn new(cancel_token: Option<CancelToken>, timeout: Option<std::time::Duration>) -> Self {
+        Self {
+            cancel_token,
+            deadline: timeout.map(|t| Instant::now() + t),
+            triggered: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether the simulation should stop before running another operation.
+    pub fn check(&self) -> bool {
+        let stop = self.triggered.load(Ordering::Relaxed)
+            || self.cancel_token.as_ref().is_some_and(|t| t.is_cancelled())
+            || self.deadline.is_some_and(|d| Instant::now() >= d);
+        if stop {
+            self.triggered.store(true, Ordering::Relaxed);
+        }
+        stop
+    }
+
+    pub fn triggered(&self) -> bool {
+        self.triggered.load(Ordering::Relaxed)
+    }
+
+    pub fn cancelled(&self) -> bool {
+        self.cancel_token.as_ref().is_some_and(|t| t.is_cancelled())
+    }
+}
+
+/// Collects mismatches between cached and freshly computed operation outputs.
+#[derive(Default)]
+pub struct CacheAudit(SegQueue<Discrepancy>);
+impl CacheAudit {
+    /// Compares a freshly computed write against the history entry recorded under the same hash.
+    ///
+    /// Values are compared by their serialized bytes. Writes without a history entry
+    /// have nothing to be compared to.
+    pub fn check<R: Resource>(&self, history: &History, hash: u64, value: &R::Data, written: Time) {
+        let Some(cached) = history.get::<R>(hash, written) else {
+            return;
+        };
+        let cached = R::Data::from_read(cached, written);
+        let config = bincode::config::standard();
+        let matches = match (
+            bincode::serde::encode_to_vec(&cached, config),
+            bincode::serde::encode_to_vec(value, config),
+        ) {
+            (Ok(cached), Ok(fresh)) => cached == fresh,
+            _ => false,
+        };
+        if !matches {
+            self.0.push(Discrepancy {
+                resource: R::LABEL,
+                time: written,
+                hash,
+            });
+        }
+    }
+
+    pub fn into_vec(self) -> Vec<Discrepancy> {
+        self.0.into_iter().collect()
+    }
+}
+
 #[derive(Default, Debug)]
-pub struct ErrorAccumulator(SegQueue<anyhow::Error>);
+pub struct ErrorAccumulator(SegQueue<anyhow::Error>, Mutex<HashSet<usize>>, AtomicBool);
 impl ErrorAccumulator {
     pub fn push(&self, err: anyhow::Error) {
         if !err.is::<ObservedErrorOutput>() {
@@ -54,6 +696,16 @@ impl ErrorAccumulator {
         }
     }
 
+    /// Pushes an error on behalf of `source`, unless it has already reported one.
+    ///
+    /// For failures that are answered to every reader of the same node, like a grounding
+    /// failure, which should still only be reported once.
+    pub fn push_once<T>(&self, source: &T, err: impl FnOnce() -> anyhow::Error) {
+        if self.1.lock().insert(source as *const T as usize) {
+            self.push(err());
+        }
+    }
+
     pub fn into_vec(self) -> Vec<anyhow::Error> {
         self.0.into_iter().collect()
     }
@@ -61,6 +713,11 @@ impl ErrorAccumulator {
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
+
+    /// Whether a task of the simulation panicked; see [ExecEnvironment::start_task].
+    pub fn panicked(&self) -> bool {
+        self.2.load(Ordering::Relaxed)
+    }
 }
 
 impl Display for ErrorAccumulator {
@@ -1,25 +1,57 @@
 #![doc(hidden)]
 
 use crate::Time;
+use crate::internal::operation::Node;
 use crate::internal::resource::ResourceHistoryPlugin;
-use crate::public::resource::{Data, Resource};
+use crate::public::resource::{Data, Resource, ResourceId};
 use ahash::AHasher;
+use anyhow::anyhow;
 use dashmap::DashMap;
+use parking_lot::Mutex;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::any::Any;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::{BuildHasher, Hasher};
 use std::mem::swap;
+use std::sync::Arc;
+use std::time::Instant;
 use type_map::concurrent::{Entry, TypeMap};
 use type_reg::untagged::TypeReg;
 
 pub type PeregrineDefaultHashBuilder = AHasher;
 
 #[derive(Default)]
-#[repr(transparent)]
-pub struct History(TypeMap);
+pub struct History(
+    TypeMap,
+    /// A salt mixed into every key, or zero for none.
+    ///
+    /// See [Session::with_cache_namespace][crate::Session::with_cache_namespace].
+    u64,
+);
 
 impl History {
     pub fn new() -> Self {
-        History(TypeMap::new())
+        History(TypeMap::new(), 0)
+    }
+
+    /// Sets the salt mixed into the keys of all following lookups and insertions.
+    pub fn set_namespace(&mut self, namespace: u64) {
+        self.1 = namespace;
+    }
+
+    pub fn namespace(&self) -> u64 {
+        self.1
+    }
+
+    fn key(&self, hash: u64) -> u64 {
+        if self.1 == 0 {
+            hash
+        } else {
+            let mut hasher = PeregrineDefaultHashBuilder::default();
+            hasher.write_u64(hash);
+            hasher.write_u64(self.1);
+            hasher.finish()
+        }
     }
     pub fn init<R: Resource>(&mut self) {
         match self.0.entry::<InnerHistory<R>>() {
@@ -38,12 +70,16 @@ impl History {
         self.0
             .get::<InnerHistory<R>>()
             .unwrap_or_else(|| panic!("history not initialized for resource: {}", R::LABEL))
-            .insert(hash, value, written)
+            .insert(self.key(hash), value, written)
     }
     pub fn get<R: Resource>(&self, hash: u64, written: Time) -> Option<<R::Data as Data>::Read> {
         self.0
             .get::<InnerHistory<R>>()
-            .and_then(|h| h.get(hash, written))
+            .and_then(|h| h.get(self.key(hash), written))
+    }
+    /// The number of values stored for a resource.
+    pub fn entry_count<R: Resource>(&self) -> usize {
+        self.0.get::<InnerHistory<R>>().map_or(0, |h| h.0.len())
     }
     pub fn take_inner(&mut self) -> TypeMap {
         let mut replacement = TypeMap::new();
@@ -53,11 +89,219 @@ impl History {
     pub fn into_inner(self) -> TypeMap {
         self.0
     }
+
+    /// Copies the given resources' entries into another history, through the same
+    /// plugins used for serialization. Entries already present in the target are kept.
+    pub fn export_into(
+        &self,
+        target: &mut History,
+        resources: &[ResourceId],
+    ) -> anyhow::Result<()> {
+        let mut transfer = type_reg::untagged::TypeMap::<String>::new();
+        for id in resources {
+            let plugin = inventory::iter::<&'static dyn ResourceHistoryPlugin>
+                .into_iter()
+                .find(|p| p.id() == id.id())
+                .ok_or_else(|| anyhow!("no registered resource with id {id:?}"))?;
+            plugin.ser(&self.0, &mut transfer);
+            plugin.de(&mut target.0, &mut transfer);
+        }
+        Ok(())
+    }
+
+    /// Moves all entries from another history into this one, such as a history built
+    /// by a separate worker process.
+    ///
+    /// Entries already present are kept on key collisions; equal keys mean equal values.
+    pub fn merge(&mut self, mut other: History) {
+        for plugin in inventory::iter::<&'static dyn ResourceHistoryPlugin> {
+            plugin.merge(&mut self.0, &mut other.0);
+        }
+    }
+
+    /// Removes every entry whose operation hash is not in `keep` into `retired`, and returns
+    /// how many were removed.
+    pub fn prune(&mut self, keep: &HashSet<u64>, retired: &mut Retired) -> usize {
+        let keys = keep
+            .iter()
+            .map(|hash| self.key(*hash))
+            .collect::<HashSet<_>>();
+        inventory::iter::<&'static dyn ResourceHistoryPlugin>
+            .into_iter()
+            .map(|plugin| plugin.retain(&mut self.0, &|key| keys.contains(&key), retired))
+            .sum()
+    }
+}
+
+/// Values removed from a history by a prune, kept alive until nothing can borrow from them.
+///
+/// Reads borrow the heap data of stored values rather than the map slots they sit in (see
+/// `deref_history_valid_across_realloc`), so moving a value out of the map keeps earlier reads
+/// valid as long as the value itself is not dropped.
+#[derive(Default)]
+pub struct Retired(Vec<Box<dyn Any + Send + Sync>>);
+
+impl Retired {
+    /// Drops every retired value.
+    pub fn release(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Moves the entries of a resource history whose keys are rejected by `keep` into `retired`,
+/// and returns how many were removed.
+pub fn retain<R: Resource>(
+    history: &mut TypeMap,
+    keep: &dyn Fn(u64) -> bool,
+    retired: &mut Retired,
+) -> usize {
+    let Some(inner) = history.get_mut::<InnerHistory<R>>() else {
+        return 0;
+    };
+    let rejected = inner
+        .0
+        .iter()
+        .map(|entry| *entry.key())
+        .filter(|key| !keep(*key))
+        .collect::<Vec<_>>();
+    let removed = rejected
+        .into_iter()
+        .filter_map(|key| inner.0.remove(&key).map(|(_, value)| value))
+        .collect::<Vec<R::Data>>();
+    let count = removed.len();
+    if count > 0 {
+        retired.0.push(Box::new(removed));
+    }
+    count
+}
+
+/// How long [Session::prune_history][crate::Session::prune_history] keeps the entries
+/// of plans that were dropped.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum ClosedPlanRetention {
+    /// Drop everything that only closed plans could reach.
+    #[default]
+    Discard,
+    /// Keep the entries of the given number of most recently closed plans.
+    MostRecent(usize),
+    /// Keep the entries of plans that were closed less than the given time ago.
+    ClosedWithin(std::time::Duration),
+}
+
+/// The history entries that each plan of a session can reach, for
+/// [Session::prune_history][crate::Session::prune_history].
+///
+/// Live plans keep their operations in a [LiveOperations] registry, and their hashes are only
+/// read when a prune runs or the plan is dropped. A dropped plan's hashes are kept as a closed
+/// plan until a prune lets them go.
+#[derive(Default)]
+pub struct Reachability {
+    next_key: u64,
+    live: HashMap<u64, Arc<LiveOperations>>,
+    /// Closed plans, oldest first.
+    closed: VecDeque<(Instant, HashSet<u64>)>,
+}
+
+impl Reachability {
+    /// Registers a new plan, and returns its key and the registry it adds its operations to.
+    pub fn open(&mut self) -> (u64, Arc<LiveOperations>) {
+        let key = self.next_key;
+        self.next_key += 1;
+        let operations = Arc::new(LiveOperations::default());
+        self.live.insert(key, operations.clone());
+        (key, operations)
+    }
+
+    /// Moves a plan from the live plans to the closed plans, with the hashes of its operations.
+    ///
+    /// Must be called while the plan's operations are still allocated.
+    pub fn close(&mut self, key: u64) {
+        if let Some(operations) = self.live.remove(&key) {
+            self.closed.push_back((Instant::now(), operations.hashes()));
+        }
+    }
+
+    /// The hashes reachable from live plans, and from the closed plans kept by `retention`.
+    /// Closed plans that are not kept are forgotten.
+    pub fn retained(&mut self, retention: ClosedPlanRetention) -> HashSet<u64> {
+        let now = Instant::now();
+        let keep_closed = match retention {
+            ClosedPlanRetention::Discard => 0,
+            ClosedPlanRetention::MostRecent(count) => count.min(self.closed.len()),
+            ClosedPlanRetention::ClosedWithin(age) => self
+                .closed
+                .iter()
+                .rev()
+                .take_while(|(closed_at, _)| now.duration_since(*closed_at) < age)
+                .count(),
+        };
+        self.closed.drain(..self.closed.len() - keep_closed);
+
+        let mut hashes = self
+            .closed
+            .iter()
+            .flat_map(|(_, hashes)| hashes)
+            .copied()
+            .collect::<HashSet<_>>();
+        for operations in self.live.values() {
+            hashes.extend(operations.hashes());
+        }
+        hashes
+    }
+}
+
+/// The operations a live plan can reach: its activities' operations and the ones its
+/// daemons created.
+#[derive(Default)]
+pub struct LiveOperations(Mutex<HashMap<usize, &'static dyn Node<'static>>>);
+
+impl LiveOperations {
+    /// Registers an operation.
+    ///
+    /// # Safety
+    ///
+    /// The operation must stay allocated until it is [removed][LiveOperations::remove] or its
+    /// plan is [closed][Reachability::close].
+    pub unsafe fn add<'o>(&self, node: &'o dyn Node<'o>) {
+        let address = node as *const _ as *const u8 as usize;
+        let node =
+            unsafe { std::mem::transmute::<&'o dyn Node<'o>, &'static dyn Node<'static>>(node) };
+        self.0.lock().insert(address, node);
+    }
+
+    pub fn remove(&self, address: usize) {
+        self.0.lock().remove(&address);
+    }
+
+    /// The hashes of the registered operations' cached outputs.
+    fn hashes(&self) -> HashSet<u64> {
+        self.0
+            .lock()
+            .values()
+            .filter_map(|node| node.cached_hash())
+            .collect()
+    }
+}
+
+/// Inserts a resource history into a type map, merging with
+/// any existing history for the same resource.
+pub fn absorb<R: Resource>(output: &mut TypeMap, incoming: InnerHistory<R>) {
+    match output.entry::<InnerHistory<R>>() {
+        Entry::Occupied(mut o) => {
+            let existing = o.get_mut();
+            for (hash, value) in incoming.0 {
+                existing.0.entry(hash).or_insert(value);
+            }
+        }
+        Entry::Vacant(v) => {
+            v.insert(incoming);
+        }
+    }
 }
 
 impl From<TypeMap> for History {
     fn from(value: TypeMap) -> Self {
-        History(value)
+        History(value, 0)
     }
 }
 
@@ -233,4 +477,26 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn history_merge() {
+        let mut first = History::default();
+        first.init::<a>();
+        first.insert::<a>(0, 5, TIME);
+        first.insert::<a>(1, 6, TIME);
+
+        let mut second = History::default();
+        second.init::<a>();
+        second.init::<b>();
+        second.insert::<a>(1, 6, TIME);
+        second.insert::<a>(2, 7, TIME);
+        second.insert::<b>(10, "string".to_string(), TIME);
+
+        first.merge(second);
+
+        assert_eq!(5, first.get::<a>(0, TIME).unwrap());
+        assert_eq!(6, first.get::<a>(1, TIME).unwrap());
+        assert_eq!(7, first.get::<a>(2, TIME).unwrap());
+        assert_eq!("string", first.get::<b>(10, TIME).unwrap());
+    }
 }
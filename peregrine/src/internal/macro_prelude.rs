@@ -27,6 +27,7 @@ pub use peregrine_macros;
 pub use rayon;
 pub use serde;
 pub use serde_closure;
+pub use serde_json;
 pub use smallvec;
 pub use spez;
 pub use type_map;
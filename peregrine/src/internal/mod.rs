@@ -4,10 +4,13 @@
 //! not part of the public API. Almost all of them need to be exposed anyway
 //! so they can be used by generated macro code, but they are hidden in the docs.
 
+pub mod diagnostics;
 pub mod exec;
 pub mod history;
 pub mod macro_prelude;
 pub mod operation;
 pub mod placement;
+#[cfg(feature = "profiling")]
+pub mod profiling;
 pub mod resource;
 pub mod timeline;
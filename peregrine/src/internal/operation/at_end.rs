@@ -0,0 +1,70 @@
+//! Reads of a resource's value at the end of the reading operation's activity, written as
+//! `ref at_end: resource` in [op][crate::op!].
+
+use crate::internal::exec::ExecEnvironment;
+use crate::internal::macro_prelude::DenseTime;
+use crate::internal::operation::reader::{ReadHook, ReadResponse, Reader};
+use crate::internal::operation::{InternalResult, ObservedErrorOutput, Upstream};
+use crate::internal::timeline::Timelines;
+use crate::public::resource::Resource;
+use anyhow::anyhow;
+use std::marker::PhantomData;
+
+/// A pseudo-resource for the value of `R` at the end of the reading operation's activity,
+/// after all of the activity's operations.
+///
+/// It has no timeline; reads of it are served by a [Reader] created for each reader.
+/// The activity's end is looked up by the order of the reading operation, so it must be
+/// placed statically by an activity. Reactive daemons have no end.
+pub struct AtEnd<R>(PhantomData<fn() -> R>);
+
+impl<R> Clone for AtEnd<R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for AtEnd<R> {}
+
+impl<R: Resource> Resource for AtEnd<R> {
+    const LABEL: &'static str = R::LABEL;
+    const ID: u64 = R::ID.wrapping_add(0x92c4_5e1b_d70a_3f68);
+    const UNIT: Option<&'static str> = R::UNIT;
+    type Data = R::Data;
+    const INSTANCE: Self = AtEnd(PhantomData);
+
+    fn initial_condition() -> Option<Self::Data> {
+        None
+    }
+
+    fn custom_upstream<'o>(
+        timelines: &Timelines<'o>,
+        time: DenseTime,
+    ) -> Option<&'o dyn Upstream<'o, Self>> {
+        Some(timelines.alloc(Reader::<Self>::new(timelines.activity_end(time.order))))
+    }
+}
+
+/// Reads `R` at the end of an operation's activity, and passes the value on.
+///
+/// The reader is downstream of the upstream an operation at the activity's end would read `R`
+/// from, so it is invalidated by changes before the end rather than before the reading operation.
+impl<R: Resource> ReadHook for AtEnd<R> {
+    type Input = R;
+
+    fn unreadable<'o>(env: &ExecEnvironment<'_, 'o>) -> InternalResult<ReadResponse<'o, Self>> {
+        env.errors.push(anyhow!(
+            "`ref at_end: {}` can only be read by operations placed statically by an activity",
+            R::LABEL
+        ));
+        Err(ObservedErrorOutput)
+    }
+
+    fn from_input<'o>(
+        response: ReadResponse<'o, R>,
+        _upstream: &'o dyn Upstream<'o, R>,
+        _timelines: &Timelines<'o>,
+    ) -> ReadResponse<'o, Self> {
+        response
+    }
+}
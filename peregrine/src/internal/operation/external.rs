@@ -0,0 +1,172 @@
+//! Reads of the version of an input from outside the simulation, written as
+//! `ref external: name` in [op][crate::op!].
+
+use crate::internal::exec::ExecEnvironment;
+use crate::internal::history::PeregrineDefaultHashBuilder;
+use crate::internal::macro_prelude::DenseTime;
+use crate::internal::operation::grounding::GroundingContinuation;
+use crate::internal::operation::reader::{Request, Requests};
+use crate::internal::operation::{Continuation, Downstream, ObservedErrorOutput, Upstream};
+use crate::internal::timeline::Timelines;
+use crate::public::resource::Resource;
+use anyhow::anyhow;
+use parking_lot::{Mutex, RwLock};
+use rayon::Scope;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The versions of a session's external inputs, set with
+/// [Session::set_external_version][crate::Session::set_external_version].
+#[derive(Default)]
+pub struct ExternalInputs {
+    versions: RwLock<HashMap<String, u64>>,
+    /// Incremented whenever a version changes, so plans can tell when to check their readers.
+    generation: AtomicU64,
+}
+
+impl ExternalInputs {
+    pub fn set(&self, name: &str, version: u64) {
+        let previous = self.versions.write().insert(name.to_string(), version);
+        if previous != Some(version) {
+            self.generation.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<u64> {
+        self.versions.read().get(name).copied()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+}
+
+/// Generated by `op!` for each `ref external: name` read.
+pub trait ExternalName: 'static {
+    const NAME: &'static str;
+    const ID: u64;
+}
+
+/// A pseudo-resource for the version of the external input `N`.
+///
+/// It has no timeline; reads of it are served by an [ExternalReader] created for each reader,
+/// holding the version at the time it was created. The plan checks its readers before each
+/// simulation, and discards the ones whose versions are out of date.
+pub struct External<N>(PhantomData<fn() -> N>);
+
+impl<N> Clone for External<N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<N> Copy for External<N> {}
+
+impl<N: ExternalName> Resource for External<N> {
+    const LABEL: &'static str = N::NAME;
+    const ID: u64 = N::ID;
+    type Data = u64;
+    const INSTANCE: Self = External(PhantomData);
+
+    fn initial_condition() -> Option<Self::Data> {
+        None
+    }
+
+    fn custom_upstream<'o>(
+        timelines: &Timelines<'o>,
+        _time: DenseTime,
+    ) -> Option<&'o dyn Upstream<'o, Self>> {
+        let reader: &'o ExternalReader<'o, N> =
+            timelines.alloc(ExternalReader::new(timelines.external_version(N::NAME)));
+        timelines.register_external_reader(reader);
+        Some(reader)
+    }
+}
+
+/// A reader of an external input, which [Timelines::refresh_external_readers] can discard.
+pub trait RefreshExternal: Sync {
+    /// Invalidates the reader if `inputs` has a different version than it was created with.
+    /// Returns whether the reader is still current.
+    fn refresh(&self, inputs: &ExternalInputs) -> bool;
+}
+
+/// Responds with the version of `N` when the reader was created, or an error if the
+/// session has no version for it.
+pub struct ExternalReader<'o, N: ExternalName> {
+    version: Option<u64>,
+    requests: Mutex<Requests<'o, External<N>>>,
+}
+
+impl<'o, N: ExternalName> ExternalReader<'o, N> {
+    fn new(version: Option<u64>) -> Self {
+        let mut hasher = PeregrineDefaultHashBuilder::default();
+        version.hash(&mut hasher);
+        let result = match version {
+            Some(version) => Ok((hasher.finish(), version)),
+            None => Err(ObservedErrorOutput),
+        };
+        Self {
+            version,
+            requests: Mutex::new(Requests::done(result)),
+        }
+    }
+}
+
+impl<'o, N: ExternalName> RefreshExternal for ExternalReader<'o, N> {
+    fn refresh(&self, inputs: &ExternalInputs) -> bool {
+        if inputs.get(N::NAME) == self.version {
+            return true;
+        }
+        self.requests.lock().discard(None);
+        false
+    }
+}
+
+impl<'o, N: ExternalName> Upstream<'o, External<N>> for ExternalReader<'o, N> {
+    fn request<'s>(
+        &'o self,
+        continuation: Continuation<'o, External<N>>,
+        already_registered: bool,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let Request::Respond(responses) =
+            self.requests.lock().begin(continuation, already_registered)
+        else {
+            unreachable!("external reads are known when the reader is created")
+        };
+        if self.version.is_none() {
+            env.errors.push(anyhow!(
+                "external input `{}` has no version; set one with Session::set_external_version",
+                N::NAME
+            ));
+        }
+        responses.run(scope, timelines, env);
+    }
+
+    fn notify_downstreams(&self, _time_of_change: DenseTime) {
+        unreachable!("external reads are not stored in a timeline")
+    }
+
+    fn register_downstream_early(&self, downstream: &'o dyn Downstream<'o, External<N>>) {
+        self.requests.lock().downstreams.push(downstream);
+    }
+
+    fn request_grounding<'s>(
+        &'o self,
+        _continuation: GroundingContinuation<'o>,
+        _already_registered: bool,
+        _scope: &Scope<'s>,
+        _timelines: &'s Timelines<'o>,
+        _env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        unreachable!()
+    }
+}
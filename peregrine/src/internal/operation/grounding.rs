@@ -48,10 +48,137 @@ impl<'o> GroundingContinuation<'o> {
     }
 }
 
+/// Requests the groundings of a cluster of overlapping ungrounded upstreams once,
+/// and forwards the results to every resolver that needs them.
+///
+/// Without batching, each [UngroundedUpstreamResolver] requests the grounding of every
+/// member of its cluster itself, so a cluster of `n` ops read by `m` downstreams costs `n * m`
+/// grounding requests. Batches are cached per timeline by [Timeline][crate::internal::timeline::Timeline],
+/// keyed by the cluster's members, and invalidated whenever the timeline changes.
+pub struct GroundingBatch<'o, R: Resource> {
+    members: UpstreamVec<'o, R>,
+    state: Mutex<GroundingBatchState<'o>>,
+}
+
+struct GroundingBatchState<'o> {
+    requested: bool,
+    responses: SmallVec<InternalResult<(usize, DenseTime)>, 2>,
+    subscribers: SmallVec<&'o dyn GroundingDownstream<'o>, 2>,
+}
+
+impl<'o, R: Resource> GroundingBatch<'o, R> {
+    pub(crate) fn new(members: UpstreamVec<'o, R>) -> Self {
+        Self {
+            members,
+            state: Mutex::new(GroundingBatchState {
+                requested: false,
+                responses: SmallVec::new(),
+                subscribers: SmallVec::new(),
+            }),
+        }
+    }
+
+    /// Sends the grounding of each member to `subscriber`, marked with the member's index.
+    ///
+    /// Members are only requested by the first subscriber; later subscribers either
+    /// wait for the same responses or have the cached responses replayed immediately.
+    pub fn subscribe<'s>(
+        &'o self,
+        subscriber: &'o dyn GroundingDownstream<'o>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let mut state = self.state.lock();
+        state.subscribers.push(subscriber);
+
+        if state.responses.len() == self.members.len() {
+            let responses = state.responses.clone();
+            drop(state);
+            for response in responses {
+                subscriber.respond_grounding(response, scope, timelines, env.increment());
+            }
+        } else if !state.requested {
+            state.requested = true;
+            drop(state);
+            request_groundings(&self.members, self, scope, timelines, env);
+        }
+    }
+}
+
+impl<'o, R: Resource> GroundingDownstream<'o> for GroundingBatch<'o, R> {
+    fn respond_grounding<'s>(
+        &'o self,
+        value: InternalResult<(usize, DenseTime)>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let mut state = self.state.lock();
+        state.responses.push(value);
+
+        if state.responses.len() == self.members.len() {
+            let responses = state.responses.clone();
+            let subscribers = state.subscribers.clone();
+            drop(state);
+            for subscriber in subscribers {
+                for response in &responses {
+                    subscriber.respond_grounding(*response, scope, timelines, env.increment());
+                }
+            }
+        }
+    }
+
+    fn clear_grounding_cache(&self) {
+        let mut state = self.state.lock();
+        state.requested = false;
+        state.responses.clear();
+        for subscriber in state.subscribers.drain(..) {
+            subscriber.clear_grounding_cache();
+        }
+    }
+}
+
+/// Requests the grounding of each upstream, marking the responses with the upstream's index.
+fn request_groundings<'s, 'o: 's, R: Resource>(
+    upstreams: &'o [&'o dyn Upstream<'o, R>],
+    downstream: &'o dyn GroundingDownstream<'o>,
+    scope: &Scope<'s>,
+    timelines: &'s Timelines<'o>,
+    env: ExecEnvironment<'s, 'o>,
+) {
+    for (i, ungrounded) in upstreams.iter().enumerate().skip(1) {
+        scope.spawn(move |s| {
+            let (env, _guard) = env.start_task();
+            ungrounded.request_grounding(
+                GroundingContinuation::Node(i, downstream),
+                false,
+                s,
+                timelines,
+                env,
+            )
+        });
+    }
+
+    upstreams[0].request_grounding(
+        GroundingContinuation::Node(0, downstream),
+        false,
+        scope,
+        timelines,
+        env.increment(),
+    );
+}
+
 pub struct UngroundedUpstreamResolver<'o, R: Resource> {
     time: DenseTime,
     grounded_upstream: Option<(DenseTime, &'o dyn Upstream<'o, R>)>,
     ungrounded_upstreams: UpstreamVec<'o, R>,
+    /// Shared grounding requests for the ungrounded upstreams, if batching is enabled.
+    batch: Option<&'o GroundingBatch<'o, R>>,
     grounding_responses: Mutex<SmallVec<InternalResult<(usize, DenseTime)>, 1>>,
     continuation: Mutex<Option<Continuation<'o, R>>>,
     downstream: Mutex<Option<&'o dyn Downstream<'o, R>>>,
@@ -65,17 +192,35 @@ impl<'o, R: Resource> UngroundedUpstreamResolver<'o, R> {
         time: DenseTime,
         grounded: Option<(DenseTime, &'o dyn Upstream<'o, R>)>,
         ungrounded: UpstreamVec<'o, R>,
+        batch: Option<&'o GroundingBatch<'o, R>>,
     ) -> Self {
         Self {
             time,
             grounded_upstream: grounded,
             ungrounded_upstreams: ungrounded,
+            batch,
             grounding_responses: Mutex::new(SmallVec::new()),
             continuation: Mutex::new(None),
             downstream: Mutex::new(None),
             cached_decision: Mutex::new(None),
         }
     }
+
+    /// Chooses the latest upstream before this resolver's time, given the groundings of
+    /// the ungrounded upstreams.
+    fn decide(&self, groundings: &[(usize, DenseTime)]) -> (DenseTime, &'o dyn Upstream<'o, R>) {
+        let latest_ungrounded = groundings
+            .iter()
+            .filter(|gr| gr.1 < self.time)
+            .max_by_key(|gr| gr.1);
+
+        match (latest_ungrounded, self.grounded_upstream) {
+            (Some(ug), Some(gr)) if gr.0 > ug.1 => gr,
+            (Some(ug), _) => (ug.1, self.ungrounded_upstreams[ug.0]),
+            (None, Some(gr)) => gr,
+            (None, None) => unreachable!(),
+        }
+    }
 }
 
 impl<'o, R: Resource> Upstream<'o, R> for UngroundedUpstreamResolver<'o, R> {
@@ -118,25 +263,10 @@ impl<'o, R: Resource> Upstream<'o, R> for UngroundedUpstreamResolver<'o, R> {
         *continuation_lock = Some(continuation);
         drop(continuation_lock);
 
-        for (i, ungrounded) in self.ungrounded_upstreams[1..].iter().enumerate() {
-            scope.spawn(move |s| {
-                ungrounded.request_grounding(
-                    GroundingContinuation::Node(i, self),
-                    false,
-                    s,
-                    timelines,
-                    env.reset(),
-                )
-            });
+        match self.batch {
+            Some(batch) => batch.subscribe(self, scope, timelines, env),
+            None => request_groundings(&self.ungrounded_upstreams, self, scope, timelines, env),
         }
-
-        self.ungrounded_upstreams[0].request_grounding(
-            GroundingContinuation::Node(0, self),
-            false,
-            scope,
-            timelines,
-            env.increment(),
-        );
     }
 
     fn notify_downstreams(&self, time_of_change: DenseTime) {
@@ -155,6 +285,13 @@ impl<'o, R: Resource> Upstream<'o, R> for UngroundedUpstreamResolver<'o, R> {
         *self.downstream.lock() = Some(downstream);
     }
 
+    fn source_address(&self) -> Option<usize> {
+        match *self.cached_decision.lock() {
+            Some(Ok((_, upstream))) => upstream.source_address(),
+            _ => None,
+        }
+    }
+
     fn request_grounding<'s>(
         &'o self,
         _continuation: GroundingContinuation<'o>,
@@ -200,25 +337,7 @@ impl<'o, R: Resource> GroundingDownstream<'o> for UngroundedUpstreamResolver<'o,
                     );
                 }
                 Ok(vec) => {
-                    let earliest_ungrounded = vec
-                        .iter()
-                        .filter(|gr| gr.1 < self.time)
-                        .max_by_key(|gr| gr.1);
-
-                    match (earliest_ungrounded, self.grounded_upstream) {
-                        (Some(ug), Some(gr)) => {
-                            if gr.0 > ug.1 {
-                                *decision = Some(Ok(gr));
-                            } else {
-                                *decision = Some(Ok((ug.1, self.ungrounded_upstreams[ug.0])));
-                            }
-                        }
-                        (Some(ug), None) => {
-                            *decision = Some(Ok((ug.1, self.ungrounded_upstreams[ug.0])))
-                        }
-                        (None, Some(gr)) => *decision = Some(Ok(gr)),
-                        _ => unreachable!(),
-                    }
+                    *decision = Some(Ok(self.decide(&vec)));
 
                     decision.unwrap().unwrap().1.request(
                         continuation,
@@ -4,15 +4,18 @@ use crate::internal::macro_prelude::DenseTime;
 use crate::internal::operation::{
     Continuation, Downstream, Node, OperationState, OperationStatus, Upstream,
 };
-use crate::internal::resource::ErasedResource;
+use crate::internal::resource::{ErasedResource, ResourceHistoryPlugin};
 use crate::internal::timeline::{Timelines, duration_to_epoch};
-use crate::public::resource::{Data, Resource};
-use anyhow::anyhow;
+use crate::public::activity::{OperationInfo, OperationTime};
+use crate::public::plan::ModelSnapshot;
+use crate::public::resource::{Data, Resource, ResourceDescriptor};
+use anyhow::{Context, anyhow, bail};
 use hifitime::Duration;
 use parking_lot::Mutex;
 use rayon::Scope;
 use std::collections::HashMap;
 use std::hash::Hasher;
+use std::io::Read;
 
 pub struct InitialConditions(HashMap<u64, Box<dyn ErasedResource>>);
 
@@ -26,11 +29,55 @@ impl InitialConditions {
     pub fn new() -> Self {
         Self(HashMap::new())
     }
+    /// Starts a plan from the state of another plan, taken with [Plan::snapshot][crate::Plan::snapshot].
+    pub fn from_snapshot(snapshot: ModelSnapshot) -> Self {
+        snapshot.values
+    }
     pub fn insert<R: Resource>(mut self, value: R::Data) -> Self {
         let value: WriteValue<R> = WriteValue(value);
         self.0.insert(value.id(), Box::new(value));
         self
     }
+    /// Reads initial conditions from a JSON object that maps resource labels to values,
+    /// like a spacecraft state dump.
+    ///
+    /// Each key is resolved through the registry of every resource in the program, so a label
+    /// declared by more than one resource is rejected as ambiguous. Resources missing from the
+    /// object fall back to their defaults, as with [initial_conditions][crate::initial_conditions!].
+    pub fn from_json(reader: impl Read) -> anyhow::Result<Self> {
+        let values: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_reader(reader).context("initial conditions must be a JSON object")?;
+
+        let mut result = Self::new();
+        for (label, value) in values {
+            let mut plugins = inventory::iter::<&'static dyn ResourceHistoryPlugin>
+                .into_iter()
+                .filter(|p| p.label() == label);
+            let plugin = plugins
+                .next()
+                .ok_or_else(|| anyhow!("no registered resource is labelled {label}"))?;
+            if plugins.next().is_some() {
+                bail!("more than one registered resource is labelled {label}");
+            }
+            plugin
+                .insert_json(&mut result, value)
+                .with_context(|| format!("could not read the initial condition of {label}"))?;
+        }
+        Ok(result)
+    }
+    #[doc(hidden)]
+    pub fn insert_json<R: Resource>(&mut self, value: serde_json::Value) -> anyhow::Result<()> {
+        let value: R::Data = serde_json::from_value(value)?;
+        self.0.insert(R::ID, Box::new(WriteValue::<R>(value)));
+        Ok(())
+    }
+    pub fn get<R: Resource>(&self) -> Option<&R::Data> {
+        unsafe {
+            self.0
+                .get(&R::ID)
+                .map(|v| &v._downcast::<WriteValue<R>>().0)
+        }
+    }
     pub fn take<R: Resource>(&mut self) -> Option<R::Data> {
         unsafe {
             self.0
@@ -54,7 +101,8 @@ type InitialConditionState<'o, R> =
 pub struct InitialConditionOp<'o, R: Resource> {
     value: R::Data,
     state: Mutex<InitialConditionState<'o, R>>,
-    time: Duration,
+    /// Only changed by [Plan::shift][crate::Plan::shift].
+    time: Mutex<Duration>,
 }
 
 impl<R: Resource> InitialConditionOp<'_, R> {
@@ -62,7 +110,7 @@ impl<R: Resource> InitialConditionOp<'_, R> {
         Self {
             value,
             state: Default::default(),
-            time,
+            time: Mutex::new(time),
         }
     }
 }
@@ -75,6 +123,18 @@ impl<'o, R: Resource> Node<'o> for InitialConditionOp<'o, R> {
     fn remove_self(&self, _timelines: &Timelines<'o>, _is_daemon: bool) -> anyhow::Result<()> {
         Err(anyhow!("Cannot remove initial conditions."))
     }
+
+    fn info(&self) -> OperationInfo {
+        OperationInfo {
+            time: OperationTime::Static(duration_to_epoch(*self.time.lock())),
+            reads: vec![],
+            writes: vec![ResourceDescriptor::of::<R>()],
+        }
+    }
+
+    fn clear_cache(&self) {
+        // Initial conditions aren't cached in history.
+    }
 }
 
 impl<'o, R: Resource + 'o> Upstream<'o, R> for InitialConditionOp<'o, R> {
@@ -88,6 +148,7 @@ impl<'o, R: Resource + 'o> Upstream<'o, R> for InitialConditionOp<'o, R> {
     ) where
         'o: 's,
     {
+        timelines.touch::<R>();
         let mut state = self.state.lock();
         let result = match state.status {
             OperationStatus::Dormant => {
@@ -96,7 +157,8 @@ impl<'o, R: Resource + 'o> Upstream<'o, R> for InitialConditionOp<'o, R> {
                 let mut hasher = PeregrineDefaultHashBuilder::default();
                 hasher.write(&bytes);
                 let hash = hasher.finish();
-                let output = (hash, self.value.to_read(duration_to_epoch(self.time)));
+                let time = *self.time.lock();
+                let output = (hash, self.value.to_read(duration_to_epoch(time)));
                 state.status = OperationStatus::Done(Ok(output));
                 output
             }
@@ -127,6 +189,13 @@ impl<'o, R: Resource + 'o> Upstream<'o, R> for InitialConditionOp<'o, R> {
         self.state.lock().downstreams.push(downstream);
     }
 
+    fn shift_initial_condition(&self, by: Duration) {
+        *self.time.lock() += by;
+        let mut state = self.state.lock();
+        state.status = OperationStatus::Dormant;
+        state.downstreams.retain(|d| d.clear_upstream(None));
+    }
+
     fn request_grounding<'s>(
         &'o self,
         continuation: crate::internal::operation::grounding::GroundingContinuation<'o>,
@@ -138,7 +207,7 @@ impl<'o, R: Resource + 'o> Upstream<'o, R> for InitialConditionOp<'o, R> {
         'o: 's,
     {
         continuation.run(
-            Ok(DenseTime::first_at(self.time)),
+            Ok(DenseTime::first_at(*self.time.lock())),
             scope,
             timelines,
             env.increment(),
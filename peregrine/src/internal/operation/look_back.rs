@@ -0,0 +1,92 @@
+//! Reads of a resource's value at an earlier time than the reading operation, written as
+//! `ref(OFFSET): resource` in [op][crate::op!].
+
+use crate::internal::exec::ExecEnvironment;
+use crate::internal::macro_prelude::DenseTime;
+use crate::internal::operation::reader::{ReadHook, ReadResponse, Reader};
+use crate::internal::operation::{InternalResult, ObservedErrorOutput, Upstream};
+use crate::internal::timeline::{Timelines, epoch_to_duration};
+use crate::public::resource::Resource;
+use anyhow::anyhow;
+use hifitime::Duration;
+use std::marker::PhantomData;
+
+/// The offset of a look-back read, generated by [op][crate::op!] for each `ref(..)` read.
+pub trait LookBackOffset: 'static + Send + Sync {
+    const ID: u64;
+
+    fn offset() -> Duration;
+}
+
+/// A pseudo-resource for the value of `R` at the offset `O` from the reading operation,
+/// before any operations at that time.
+///
+/// It has no timeline; reads of it are served by a [Reader] created for each reader.
+/// The offset can't be positive, since the reader would then wait on operations after it,
+/// and it can't reach before the start of the plan.
+pub struct LookBack<R, O>(PhantomData<fn() -> (R, O)>);
+
+impl<R, O> Clone for LookBack<R, O> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R, O> Copy for LookBack<R, O> {}
+
+impl<R: Resource, O: LookBackOffset> Resource for LookBack<R, O> {
+    const LABEL: &'static str = R::LABEL;
+    const ID: u64 = R::ID.wrapping_add(O::ID);
+    const UNIT: Option<&'static str> = R::UNIT;
+    type Data = R::Data;
+    const INSTANCE: Self = LookBack(PhantomData);
+
+    fn initial_condition() -> Option<Self::Data> {
+        None
+    }
+
+    fn custom_upstream<'o>(
+        timelines: &Timelines<'o>,
+        time: DenseTime,
+    ) -> Option<&'o dyn Upstream<'o, Self>> {
+        let offset = O::offset();
+        let when = time.when + offset;
+        // Operations never have order 0, which is left for the initial conditions, so this is
+        // after the initial conditions and before every operation at that time.
+        let at = (offset <= Duration::ZERO && when >= epoch_to_duration(timelines.start()))
+            .then_some(DenseTime { when, order: 1 });
+        Some(timelines.alloc(Reader::<Self>::new(at)))
+    }
+}
+
+/// Reads `R` at an earlier time than an operation, and passes the value on.
+///
+/// The reader is downstream of the upstream an operation at the earlier time would read `R`
+/// from, so it is invalidated by changes before that time rather than before the reading operation.
+impl<R: Resource, O: LookBackOffset> ReadHook for LookBack<R, O> {
+    type Input = R;
+
+    fn unreadable<'o>(env: &ExecEnvironment<'_, 'o>) -> InternalResult<ReadResponse<'o, Self>> {
+        let offset = O::offset();
+        env.errors.push(if offset > Duration::ZERO {
+            anyhow!(
+                "look-back reads of {} can't look ahead, but the offset was {offset}",
+                R::LABEL
+            )
+        } else {
+            anyhow!(
+                "look-back read of {} reaches {offset} back, before the start of the plan",
+                R::LABEL
+            )
+        });
+        Err(ObservedErrorOutput)
+    }
+
+    fn from_input<'o>(
+        response: ReadResponse<'o, R>,
+        _upstream: &'o dyn Upstream<'o, R>,
+        _timelines: &Timelines<'o>,
+    ) -> ReadResponse<'o, Self> {
+        response
+    }
+}
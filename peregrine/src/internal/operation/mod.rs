@@ -1,16 +1,29 @@
 #![doc(hidden)]
 
+pub mod at_end;
+pub mod external;
 pub mod grounding;
 pub mod initial_conditions;
+pub mod look_back;
+pub mod next_change;
 pub mod node_impls;
+pub mod or_default;
+pub mod reader;
+pub mod second_derivative;
+pub mod since;
+pub mod source;
+pub mod window;
 
 use crate::Duration;
+use crate::internal::diagnostics;
 use crate::internal::exec::ExecEnvironment;
 use crate::internal::macro_prelude::DenseTime;
-use crate::internal::timeline::Timelines;
+use crate::internal::placement::{GroundingErrorPolicy, Placement};
+use crate::internal::timeline::{Timelines, duration_to_epoch};
+use crate::public::activity::OperationInfo;
 use crate::public::resource::Data;
 use crate::public::resource::Resource;
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use derive_more::with_trait::Error as DeriveError;
 use grounding::GroundingContinuation;
 use grounding::peregrine_grounding;
@@ -23,6 +36,29 @@ pub type InternalResult<T> = Result<T, ObservedErrorOutput>;
 pub trait Node<'o>: Sync {
     fn insert_self(&'o self, timelines: &Timelines<'o>, is_daemon: bool) -> Result<()>;
     fn remove_self(&self, timelines: &Timelines<'o>, is_daemon: bool) -> Result<()>;
+
+    /// The operation's placement and the resources it reads and writes.
+    fn info(&self) -> OperationInfo;
+
+    /// Forgets the operation's cached output and read responses, so that it runs again
+    /// the next time it is requested.
+    fn clear_cache(&self);
+
+    /// The hash that the operation's cached output is stored under in history, if it has one.
+    fn cached_hash(&self) -> Option<u64> {
+        None
+    }
+
+    /// Whether the operation's cached output is an error.
+    fn failed(&self) -> bool {
+        false
+    }
+
+    /// The label of each resource the operation has read in simulation, with the address
+    /// of the operation it read from.
+    fn upstreams(&self) -> Vec<(&'static str, usize)> {
+        vec![]
+    }
 }
 
 pub trait NodeId {
@@ -70,6 +106,26 @@ pub trait Upstream<'o, R: Resource>: Sync {
     fn notify_downstreams(&self, time_of_change: DenseTime);
     fn register_downstream_early(&self, downstream: &'o dyn Downstream<'o, R>);
 
+    /// The address of the operation whose output this upstream responds with.
+    ///
+    /// Upstreams that forward to another operation only know it after they have responded.
+    fn source_address(&self) -> Option<usize> {
+        Some(self as *const Self as *const u8 as usize)
+    }
+
+    /// Whether the operation started with `#![priority]`, so that root requests for it are
+    /// picked up before others.
+    fn prioritized(&self) -> bool {
+        false
+    }
+
+    /// Moves an initial condition later by `by`, for [Plan::shift][crate::Plan::shift].
+    ///
+    /// Operations are moved by reinserting their activities instead.
+    fn shift_initial_condition(&self, _by: Duration) {
+        unreachable!("only initial conditions can be shifted in place")
+    }
+
     fn request_grounding<'s>(
         &'o self,
         continuation: GroundingContinuation<'o>,
@@ -83,8 +139,12 @@ pub trait Upstream<'o, R: Resource>: Sync {
 
 pub enum Continuation<'o, R: Resource> {
     Node(&'o dyn Downstream<'o, R>),
-    Root(oneshot::Sender<InternalResult<<R::Data as Data<'o>>::Read>>),
-    GroundingWrapper(GroundingContinuation<'o>),
+    Root(oneshot::Sender<InternalResult<(u64, <R::Data as Data<'o>>::Read)>>),
+    /// Converts the output of a delay into the grounding of an operation with the placement,
+    /// whose bounds are used if the delay fails.
+    ///
+    /// Boxed to keep continuations small, since they are passed down every request.
+    GroundingWrapper(Box<(GroundingContinuation<'o>, &'o Placement<'o>)>),
 }
 
 impl<'o, R: Resource> Continuation<'o, R> {
@@ -100,8 +160,9 @@ impl<'o, R: Resource> Continuation<'o, R> {
     {
         match self {
             Continuation::Node(n) => n.respond(value, scope, timelines, env),
-            Continuation::Root(s) => s.send(value.map(|r| r.1)).unwrap(),
-            Continuation::GroundingWrapper(c) => {
+            Continuation::Root(s) => s.send(value).unwrap(),
+            Continuation::GroundingWrapper(wrapper) => {
+                let (c, placement) = *wrapper;
                 if castaway::cast!(R::INSTANCE, peregrine_grounding).is_ok() {
                     assert_eq!(
                         size_of::<InternalResult<(u64, Duration)>>(),
@@ -109,12 +170,13 @@ impl<'o, R: Resource> Continuation<'o, R> {
                     );
                     let v: InternalResult<(u64, Duration)> =
                         unsafe { std::mem::transmute_copy(&value) };
-                    c.run(
-                        v.map(|(_, d)| DenseTime { when: d, order }),
-                        scope,
-                        timelines,
-                        env,
-                    );
+                    let grounded = v.map(|(_, d)| DenseTime {
+                        when: d,
+                        order: env.coincident_writes.grounded_order(order),
+                    });
+                    let grounded = grounded.or_else(|_| grounding_failed(placement, env));
+                    diagnostics::grounding(&env, placement, &grounded);
+                    c.run(grounded, scope, timelines, env);
                 } else {
                     unreachable!()
                 }
@@ -137,6 +199,28 @@ impl<'o, R: Resource> Continuation<'o, R> {
     }
 }
 
+/// Applies the session's [GroundingErrorPolicy] when an operation's delay fails.
+///
+/// Kept out of [Continuation::run] so that it doesn't grow the stack frames of request chains.
+#[cold]
+fn grounding_failed(placement: &Placement, env: ExecEnvironment) -> InternalResult<DenseTime> {
+    match env.grounding_errors {
+        GroundingErrorPolicy::Drop => Err(ObservedErrorOutput),
+        GroundingErrorPolicy::MinBound => Ok(placement.min()),
+        GroundingErrorPolicy::Propagate => {
+            // Every reader of the operation's grounding gets the failure, but it is one error.
+            env.errors.push_once(placement, || {
+                anyhow!(
+                    "could not place the operation between {} and {}: its delay failed",
+                    duration_to_epoch(placement.min().when),
+                    duration_to_epoch(placement.max().when)
+                )
+            });
+            Err(ObservedErrorOutput)
+        }
+    }
+}
+
 pub struct OperationState<O, C, D> {
     pub response_counter: u8,
     pub status: OperationStatus<O>,
@@ -0,0 +1,203 @@
+//! Reads of the time until a resource's next scheduled write, written as
+//! `ref next_change: resource` in [op][crate::op!].
+
+use crate::internal::exec::ExecEnvironment;
+use crate::internal::history::PeregrineDefaultHashBuilder;
+use crate::internal::macro_prelude::DenseTime;
+use crate::internal::operation::grounding::GroundingContinuation;
+use crate::internal::operation::reader::{Request, Requests};
+use crate::internal::operation::{
+    Continuation, Downstream, GroundingDownstream, InternalResult, Upstream,
+};
+use crate::internal::timeline::{MaybeGrounded, Timelines};
+use crate::public::resource::{Data, Resource};
+use crate::{Time, impl_copy_static_data};
+use hifitime::Duration;
+use parking_lot::Mutex;
+use rayon::Scope;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+impl_copy_static_data![Option<Duration>];
+
+/// A pseudo-resource for the time from an operation until the next grounded write to `R`
+/// after it, or `None` if there isn't one.
+///
+/// It has no timeline; reads of it are served by a [NextChangeReader] created for each reader.
+/// Writes by ungrounded operations aren't counted, since their times aren't known until the
+/// plan is simulated.
+pub struct NextChange<R>(PhantomData<fn() -> R>);
+
+impl<R> Clone for NextChange<R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for NextChange<R> {}
+
+impl<R: Resource> Resource for NextChange<R> {
+    const LABEL: &'static str = R::LABEL;
+    const ID: u64 = R::ID.wrapping_add(0xa4e2_07d9_6b3c_f158);
+    type Data = Option<Duration>;
+    const INSTANCE: Self = NextChange(PhantomData);
+
+    fn initial_condition() -> Option<Self::Data> {
+        None
+    }
+
+    fn custom_upstream<'o>(
+        timelines: &Timelines<'o>,
+        time: DenseTime,
+    ) -> Option<&'o dyn Upstream<'o, Self>> {
+        // The write at or before the operation, the ungrounded writes that might overlap it,
+        // and the next grounded write after it. Inserting or removing a write before the next
+        // one notifies at least one of these.
+        let candidates = timelines.range_inclusive_next::<R>(time..=time);
+        let next = candidates.iter().find_map(|candidate| match candidate {
+            MaybeGrounded::Grounded(t, _) if *t > time => Some(*t),
+            _ => None,
+        });
+        let reader: &'o NextChangeReader<'o, R> =
+            timelines.alloc(NextChangeReader::new(time, next));
+        for candidate in &candidates {
+            match candidate {
+                MaybeGrounded::Grounded(_, u) | MaybeGrounded::Ungrounded(u) => {
+                    u.register_downstream_early(reader)
+                }
+            }
+        }
+        Some(reader)
+    }
+}
+
+/// Responds with the time until the next grounded write to `R` after an operation.
+///
+/// The next write is found when the reader is created. The reader is registered downstream
+/// of the writes around it, and when one of them is notified of an insertion or removal
+/// before the next write, the reader is discarded and its downstreams find a new one.
+pub struct NextChangeReader<'o, R: Resource> {
+    next: Option<DenseTime>,
+    state: Mutex<NextChangeState<'o, R>>,
+}
+
+struct NextChangeState<'o, R: Resource> {
+    /// Set once the writes around the reader change. The reader is discarded by its
+    /// downstreams, but it might still be registered with some of its candidates.
+    stale: bool,
+    requests: Requests<'o, NextChange<R>>,
+}
+
+impl<'o, R: Resource> NextChangeReader<'o, R> {
+    fn new(time: DenseTime, next: Option<DenseTime>) -> Self {
+        let gap = next.map(|next| next.when - time.when);
+        let mut hasher = PeregrineDefaultHashBuilder::default();
+        gap.hash(&mut hasher);
+        Self {
+            next,
+            state: Mutex::new(NextChangeState {
+                stale: false,
+                requests: Requests::done(Ok((hasher.finish(), gap))),
+            }),
+        }
+    }
+
+    fn invalidate(&self) {
+        let mut state = self.state.lock();
+        if state.stale {
+            return;
+        }
+        state.stale = true;
+        state.requests.discard(None);
+    }
+}
+
+impl<'o, R: Resource> Upstream<'o, NextChange<R>> for NextChangeReader<'o, R> {
+    fn request<'s>(
+        &'o self,
+        continuation: Continuation<'o, NextChange<R>>,
+        already_registered: bool,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let Request::Respond(responses) = self
+            .state
+            .lock()
+            .requests
+            .begin(continuation, already_registered)
+        else {
+            unreachable!("next change reads are known when the reader is created")
+        };
+        responses.run(scope, timelines, env);
+    }
+
+    fn notify_downstreams(&self, _time_of_change: DenseTime) {
+        unreachable!("next change reads are not stored in a timeline")
+    }
+
+    fn register_downstream_early(&self, downstream: &'o dyn Downstream<'o, NextChange<R>>) {
+        self.state.lock().requests.downstreams.push(downstream);
+    }
+
+    fn request_grounding<'s>(
+        &'o self,
+        _continuation: GroundingContinuation<'o>,
+        _already_registered: bool,
+        _scope: &Scope<'s>,
+        _timelines: &'s Timelines<'o>,
+        _env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        unreachable!()
+    }
+}
+
+/// Registered with the writes around the reader, to be invalidated when they change.
+/// The values they write don't matter.
+impl<'o, R: Resource> Downstream<'o, R> for NextChangeReader<'o, R> {
+    fn respond<'s>(
+        &'o self,
+        _value: InternalResult<(u64, <R::Data as Data<'o>>::Read)>,
+        _scope: &Scope<'s>,
+        _timelines: &'s Timelines<'o>,
+        _env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        unreachable!("next change readers never request their candidates")
+    }
+
+    fn clear_cache(&self) {}
+
+    fn clear_upstream(&self, time_of_change: Option<DenseTime>) -> bool {
+        if let (Some(t), Some(next)) = (time_of_change, self.next)
+            && t > next
+        {
+            return true;
+        }
+        self.invalidate();
+        false
+    }
+}
+
+impl<'o, R: Resource> GroundingDownstream<'o> for NextChangeReader<'o, R> {
+    fn respond_grounding<'s>(
+        &'o self,
+        _value: InternalResult<(usize, DenseTime)>,
+        _scope: &Scope<'s>,
+        _timelines: &'s Timelines<'o>,
+        _env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        unreachable!("next change readers don't request groundings")
+    }
+
+    fn clear_grounding_cache(&self) {
+        self.invalidate();
+    }
+}
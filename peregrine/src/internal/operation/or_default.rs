@@ -0,0 +1,103 @@
+//! Reads of a resource that fall back to a default when it has no value, written as
+//! `ref or(DEFAULT): resource` in [op][crate::op!].
+
+use crate::Time;
+use crate::internal::exec::ExecEnvironment;
+use crate::internal::macro_prelude::DenseTime;
+use crate::internal::operation::reader::{ReadHook, ReadResponse, Reader};
+use crate::internal::operation::{InternalResult, Upstream};
+use crate::internal::timeline::Timelines;
+use crate::public::resource::{Data, MaybeHash, Resource};
+use serde::{Deserialize, Serialize};
+use std::hash::Hasher;
+use std::marker::PhantomData;
+
+/// The hash of a read with no value, in place of an upstream's hash.
+const ABSENT_HASH: u64 = 0x7b31_c0e4_a25d_98f6;
+
+/// A value that might not exist, read by `ref or(DEFAULT): resource`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Optional<T: for<'h> Data<'h>>(pub Option<T>);
+
+impl<T: for<'h> Data<'h>> MaybeHash for Optional<T> {
+    fn is_hashable(&self) -> bool {
+        self.0.is_hashable()
+    }
+
+    fn hash_unchecked<H: Hasher>(&self, state: &mut H) {
+        self.0.hash_unchecked(state);
+    }
+}
+
+impl<'h, T: for<'a> Data<'a>> Data<'h> for Optional<T> {
+    type Read = Option<<T as Data<'h>>::Read>;
+    type Sample = Option<<T as Data<'h>>::Sample>;
+
+    fn to_read(&self, written: Time) -> Self::Read {
+        self.0.as_ref().map(|value| value.to_read(written))
+    }
+    fn from_read(read: Self::Read, now: Time) -> Self {
+        Optional(read.map(|read| T::from_read(read, now)))
+    }
+    fn sample(read: Self::Read, now: Time) -> Self::Sample {
+        read.map(|read| T::sample(read, now))
+    }
+}
+
+/// A pseudo-resource for `R`, or `None` where `R` has no value.
+///
+/// `R` has no value if it isn't in the plan's model, or before the plan's initial conditions.
+/// It has no timeline; reads of it are served by a [Reader] created for each reader.
+pub struct OrDefault<R>(PhantomData<fn() -> R>);
+
+impl<R> Clone for OrDefault<R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for OrDefault<R> {}
+
+impl<R: Resource> Resource for OrDefault<R> {
+    const LABEL: &'static str = R::LABEL;
+    const ID: u64 = R::ID.wrapping_add(0x3d86_f1a0_5c27_e94b);
+    const UNIT: Option<&'static str> = R::UNIT;
+    type Data = Optional<R::Data>;
+    const INSTANCE: Self = OrDefault(PhantomData);
+
+    fn initial_condition() -> Option<Self::Data> {
+        None
+    }
+
+    fn custom_upstream<'o>(
+        timelines: &Timelines<'o>,
+        time: DenseTime,
+    ) -> Option<&'o dyn Upstream<'o, Self>> {
+        Some(timelines.alloc(Reader::<Self>::new(Some(time))))
+    }
+}
+
+/// Reads `R` at an operation's time if it has a value there, and passes the value on.
+///
+/// Whether `R` has a value is decided when the reader is first requested. A missing value
+/// is never invalidated, since there is no upstream to notify the reader.
+impl<R: Resource> ReadHook for OrDefault<R> {
+    type Input = R;
+
+    fn has_value(timelines: &Timelines, at: DenseTime) -> bool {
+        timelines.has_upstream::<R>(at)
+    }
+
+    fn unreadable<'o>(_env: &ExecEnvironment<'_, 'o>) -> InternalResult<ReadResponse<'o, Self>> {
+        Ok((ABSENT_HASH, None))
+    }
+
+    fn from_input<'o>(
+        (hash, read): ReadResponse<'o, R>,
+        _upstream: &'o dyn Upstream<'o, R>,
+        _timelines: &Timelines<'o>,
+    ) -> ReadResponse<'o, Self> {
+        (hash, Some(read))
+    }
+}
@@ -0,0 +1,333 @@
+//! The nodes that serve reads of pseudo-resources, like `ref source: resource`, to the
+//! operations that read them.
+//!
+//! Pseudo-resources have no timeline. Instead, each read creates a reader node through
+//! [Resource::custom_upstream], which is downstream of whatever it reads and upstream of the
+//! operation. Readers that read one resource at one time are a [Reader] with a [ReadHook];
+//! readers with more involved inputs keep their requests in a [Requests].
+
+use crate::internal::exec::{ExecEnvironment, STACK_LIMIT};
+use crate::internal::macro_prelude::DenseTime;
+use crate::internal::operation::grounding::GroundingContinuation;
+use crate::internal::operation::{
+    Continuation, Downstream, GroundingDownstream, InternalResult, OperationStatus, Upstream,
+};
+use crate::internal::timeline::Timelines;
+use crate::public::resource::{Data, Resource};
+use parking_lot::Mutex;
+use rayon::Scope;
+use smallvec::SmallVec;
+
+/// The hash and value of a read of `R`.
+pub type ReadResponse<'o, R> = (u64, <<R as Resource>::Data as Data<'o>>::Read);
+
+/// The requests made of a reader of the pseudo-resource `P`, and its result once it has one.
+pub struct Requests<'o, P: Resource> {
+    pub status: OperationStatus<ReadResponse<'o, P>>,
+    continuations: SmallVec<Continuation<'o, P>, 1>,
+    pub downstreams: SmallVec<&'o dyn Downstream<'o, P>, 1>,
+}
+
+/// What a reader should do with a request, returned by [Requests::begin].
+pub enum Request<'o, P: Resource> {
+    /// The result is already known.
+    Respond(Responses<'o, P>),
+    /// The reader is already working on the result, and will respond when it has it.
+    Wait,
+    /// The reader should start working on the result.
+    Start,
+}
+
+/// A reader's result, and the continuations to pass it to once the reader's lock is released.
+pub struct Responses<'o, P: Resource> {
+    result: InternalResult<ReadResponse<'o, P>>,
+    continuations: SmallVec<Continuation<'o, P>, 1>,
+}
+
+impl<'o, P: Resource> Requests<'o, P> {
+    pub fn new() -> Self {
+        Self::with_status(OperationStatus::Dormant)
+    }
+
+    /// Requests for a reader whose result is known when it is created.
+    pub fn done(result: InternalResult<ReadResponse<'o, P>>) -> Self {
+        Self::with_status(OperationStatus::Done(result))
+    }
+
+    fn with_status(status: OperationStatus<ReadResponse<'o, P>>) -> Self {
+        Self {
+            status,
+            continuations: SmallVec::new(),
+            downstreams: SmallVec::new(),
+        }
+    }
+
+    /// Records a request, and registers its continuation as a downstream unless it already is.
+    pub fn begin(
+        &mut self,
+        continuation: Continuation<'o, P>,
+        already_registered: bool,
+    ) -> Request<'o, P> {
+        if !already_registered && let Some(d) = continuation.to_downstream() {
+            self.downstreams.push(d);
+        }
+
+        match self.status {
+            OperationStatus::Done(result) => Request::Respond(Responses {
+                result,
+                continuations: [continuation].into_iter().collect(),
+            }),
+            OperationStatus::Working => {
+                self.continuations.push(continuation);
+                Request::Wait
+            }
+            OperationStatus::Dormant => {
+                self.continuations.push(continuation);
+                self.status = OperationStatus::Working;
+                Request::Start
+            }
+        }
+    }
+
+    /// Stores the result, and takes the continuations waiting on it.
+    pub fn finish(&mut self, result: InternalResult<ReadResponse<'o, P>>) -> Responses<'o, P> {
+        self.status = OperationStatus::Done(result);
+        Responses {
+            result,
+            continuations: std::mem::take(&mut self.continuations),
+        }
+    }
+
+    /// Forgets the result, if there is one, and clears the caches of the downstreams.
+    pub fn clear_cache(&mut self) {
+        if let OperationStatus::Done(_) = self.status {
+            self.reset();
+        }
+    }
+
+    /// Forgets the result, and clears the caches of the downstreams.
+    pub fn reset(&mut self) {
+        self.status = OperationStatus::Dormant;
+        for d in &self.downstreams {
+            d.clear_cache();
+        }
+    }
+
+    /// Has the downstreams discard the reader and find a new one. The reader keeps its result,
+    /// since it is no longer requested.
+    pub fn discard(&mut self, time_of_change: Option<DenseTime>) {
+        for d in self.downstreams.drain(..) {
+            d.clear_upstream(time_of_change);
+        }
+    }
+}
+
+impl<'o, P: Resource> Default for Requests<'o, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'o, P: Resource> Responses<'o, P> {
+    /// Passes the result to the continuations, continuing one on this thread if the stack allows.
+    pub fn run<'s>(
+        mut self,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let result = self.result;
+        let last = if env.stack_counter < STACK_LIMIT {
+            self.continuations.pop()
+        } else {
+            None
+        };
+        for c in self.continuations {
+            scope.spawn(move |s| {
+                let (env, _guard) = env.start_task();
+                c.run(result, 0, s, timelines, env)
+            });
+        }
+        if let Some(c) = last {
+            c.run(result, 0, scope, timelines, env.increment());
+        }
+    }
+}
+
+/// How a [Reader] serves the pseudo-resource it is implemented for.
+pub trait ReadHook: Resource {
+    /// The resource the reader reads.
+    type Input: Resource;
+
+    /// Whether the input has a value to read at `at`. Readers that can't respond without one
+    /// don't check.
+    fn has_value(_timelines: &Timelines, _at: DenseTime) -> bool {
+        true
+    }
+
+    /// Responds in place of reading the input, when the reader has no time to read it at,
+    /// or the input has no value there.
+    fn unreadable<'o>(_env: &ExecEnvironment<'_, 'o>) -> InternalResult<ReadResponse<'o, Self>> {
+        unreachable!("{} reads always have a value to read", Self::LABEL)
+    }
+
+    /// Builds the reader's response from the input's.
+    fn from_input<'o>(
+        response: ReadResponse<'o, Self::Input>,
+        upstream: &'o dyn Upstream<'o, Self::Input>,
+        timelines: &Timelines<'o>,
+    ) -> ReadResponse<'o, Self>;
+}
+
+/// Reads `P::Input` at a fixed time, and responds with the value of `P` built from it.
+///
+/// It is downstream of the upstream an operation at that time would read the input from,
+/// so it is invalidated by changes before that time.
+pub struct Reader<'o, P: ReadHook> {
+    /// `None` if the input can't be read, like a look-back before the start of the plan.
+    at: Option<DenseTime>,
+    state: Mutex<ReaderState<'o, P>>,
+}
+
+struct ReaderState<'o, P: ReadHook> {
+    upstream: Option<&'o dyn Upstream<'o, P::Input>>,
+    requests: Requests<'o, P>,
+}
+
+impl<'o, P: ReadHook> Reader<'o, P> {
+    pub fn new(at: Option<DenseTime>) -> Self {
+        Self {
+            at,
+            state: Mutex::new(ReaderState {
+                upstream: None,
+                requests: Requests::new(),
+            }),
+        }
+    }
+}
+
+impl<'o, P: ReadHook> Upstream<'o, P> for Reader<'o, P> {
+    fn request<'s>(
+        &'o self,
+        continuation: Continuation<'o, P>,
+        already_registered: bool,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let mut state = self.state.lock();
+        match state.requests.begin(continuation, already_registered) {
+            Request::Respond(responses) => {
+                drop(state);
+                responses.run(scope, timelines, env);
+                return;
+            }
+            Request::Wait => return,
+            Request::Start => {}
+        }
+
+        let (upstream, registered) = match (state.upstream, self.at) {
+            (Some(upstream), _) => (upstream, true),
+            (None, Some(at)) if P::has_value(timelines, at) => {
+                let upstream = timelines.find_upstream::<P::Input>(at);
+                state.upstream = Some(upstream);
+                (upstream, false)
+            }
+            (None, _) => {
+                let responses = state.requests.finish(P::unreadable(&env));
+                drop(state);
+                responses.run(scope, timelines, env);
+                return;
+            }
+        };
+        drop(state);
+        upstream.request(
+            Continuation::Node(self),
+            registered,
+            scope,
+            timelines,
+            env.increment(),
+        );
+    }
+
+    fn notify_downstreams(&self, _time_of_change: DenseTime) {
+        unreachable!("{} reads are not stored in a timeline", P::LABEL)
+    }
+
+    fn register_downstream_early(&self, downstream: &'o dyn Downstream<'o, P>) {
+        self.state.lock().requests.downstreams.push(downstream);
+    }
+
+    fn request_grounding<'s>(
+        &'o self,
+        _continuation: GroundingContinuation<'o>,
+        _already_registered: bool,
+        _scope: &Scope<'s>,
+        _timelines: &'s Timelines<'o>,
+        _env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        unreachable!()
+    }
+}
+
+impl<'o, P: ReadHook> Downstream<'o, P::Input> for Reader<'o, P> {
+    fn respond<'s>(
+        &'o self,
+        value: InternalResult<ReadResponse<'o, P::Input>>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let mut state = self.state.lock();
+        let upstream = state
+            .upstream
+            .expect("expected the upstream to be set before it responds");
+        let result = value.map(|response| P::from_input(response, upstream, timelines));
+        let responses = state.requests.finish(result);
+        drop(state);
+        responses.run(scope, timelines, env);
+    }
+
+    fn clear_cache(&self) {
+        self.state.lock().requests.clear_cache();
+    }
+
+    fn clear_upstream(&self, time_of_change: Option<DenseTime>) -> bool {
+        if let (Some(t), Some(at)) = (time_of_change, self.at)
+            && t >= at
+        {
+            return true;
+        }
+
+        let mut state = self.state.lock();
+        state.upstream = None;
+        state.requests.reset();
+        false
+    }
+}
+
+impl<'o, P: ReadHook> GroundingDownstream<'o> for Reader<'o, P> {
+    fn respond_grounding<'s>(
+        &'o self,
+        _value: InternalResult<(usize, DenseTime)>,
+        _scope: &Scope<'s>,
+        _timelines: &'s Timelines<'o>,
+        _env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        unreachable!("{} reads don't request groundings", P::LABEL)
+    }
+
+    fn clear_grounding_cache(&self) {
+        Downstream::clear_cache(self);
+    }
+}
@@ -0,0 +1,87 @@
+//! Reads of a resource's second derivative, written as `ref d2/dt2: resource` in [op][crate::op!].
+
+use crate::Time;
+use crate::internal::macro_prelude::DenseTime;
+use crate::internal::operation::Upstream;
+use crate::internal::operation::reader::{ReadHook, ReadResponse, Reader};
+use crate::internal::timeline::Timelines;
+use crate::public::resource::{Data, MaybeHash, Resource};
+use serde::{Deserialize, Serialize};
+use std::hash::Hasher;
+use std::marker::PhantomData;
+
+/// Data read as its second derivative, by `ref d2/dt2: resource`.
+///
+/// It is read the same way as `T`, but sampled with [Data::second_derivative].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Curvature<T: for<'h> Data<'h>>(pub T);
+
+impl<T: for<'h> Data<'h>> MaybeHash for Curvature<T> {
+    fn is_hashable(&self) -> bool {
+        self.0.is_hashable()
+    }
+
+    fn hash_unchecked<H: Hasher>(&self, state: &mut H) {
+        self.0.hash_unchecked(state);
+    }
+}
+
+impl<'h, T: for<'a> Data<'a>> Data<'h> for Curvature<T> {
+    type Read = <T as Data<'h>>::Read;
+    type Sample = Option<<T as Data<'h>>::Sample>;
+
+    fn to_read(&self, written: Time) -> Self::Read {
+        self.0.to_read(written)
+    }
+    fn from_read(read: Self::Read, now: Time) -> Self {
+        Curvature(T::from_read(read, now))
+    }
+    fn sample(read: Self::Read, now: Time) -> Self::Sample {
+        T::second_derivative(read, now)
+    }
+}
+
+/// A pseudo-resource for the second derivative of `R`.
+///
+/// It has no timeline; reads of it are served by a [Reader] created for each reader.
+pub struct SecondDerivative<R>(PhantomData<fn() -> R>);
+
+impl<R> Clone for SecondDerivative<R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for SecondDerivative<R> {}
+
+impl<R: Resource> Resource for SecondDerivative<R> {
+    const LABEL: &'static str = R::LABEL;
+    const ID: u64 = R::ID.wrapping_add(0x61c8_3f5e_d09a_4b27);
+    type Data = Curvature<R::Data>;
+    const INSTANCE: Self = SecondDerivative(PhantomData);
+
+    fn initial_condition() -> Option<Self::Data> {
+        None
+    }
+
+    fn custom_upstream<'o>(
+        timelines: &Timelines<'o>,
+        time: DenseTime,
+    ) -> Option<&'o dyn Upstream<'o, Self>> {
+        Some(timelines.alloc(Reader::<Self>::new(Some(time))))
+    }
+}
+
+/// Reads `R` at an operation's time, and passes the value on unchanged.
+impl<R: Resource> ReadHook for SecondDerivative<R> {
+    type Input = R;
+
+    fn from_input<'o>(
+        response: ReadResponse<'o, R>,
+        _upstream: &'o dyn Upstream<'o, R>,
+        _timelines: &Timelines<'o>,
+    ) -> ReadResponse<'o, Self> {
+        response
+    }
+}
@@ -0,0 +1,542 @@
+//! Reads of every value written to a resource since a past time, written as
+//! `ref since(START): resource` in [op][crate::op!].
+
+use crate::Time;
+use crate::internal::exec::ExecEnvironment;
+use crate::internal::history::PeregrineDefaultHashBuilder;
+use crate::internal::macro_prelude::DenseTime;
+use crate::internal::operation::grounding::GroundingContinuation;
+use crate::internal::operation::reader::{Request, Requests};
+use crate::internal::operation::window::WindowLength;
+use crate::internal::operation::{
+    Continuation, Downstream, GroundingDownstream, InternalResult, Upstream,
+};
+use crate::internal::timeline::{MaybeGrounded, Timelines, duration_to_epoch};
+use crate::public::resource::{Data, MaybeHash, Resource};
+use anyhow::bail;
+use hifitime::Duration;
+use parking_lot::Mutex;
+use rayon::Scope;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+/// The values written to a resource during a window before an operation, oldest first,
+/// with the times they were written.
+///
+/// Produced by `ref since(START): resource` reads in [op][crate::op!], which evaluate to
+/// [Writes::since] of `START`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Writes<T> {
+    start: Time,
+    writes: Vec<(Time, T)>,
+}
+
+impl<T> Writes<T> {
+    /// The writes at or after `start`.
+    ///
+    /// Fails if `start` is before the beginning of the window that was read.
+    pub fn since(&self, start: Time) -> anyhow::Result<&[(Time, T)]> {
+        if start < self.start {
+            bail!(
+                "`ref since` can only look back to {}, but was asked for writes since {start}; \
+                give it a longer limit with `ref since(START, LIMIT)`",
+                self.start
+            );
+        }
+        let first = self.writes.partition_point(|(t, _)| *t < start);
+        Ok(&self.writes[first..])
+    }
+}
+
+impl<T: MaybeHash> MaybeHash for Writes<T> {
+    fn is_hashable(&self) -> bool {
+        self.writes.iter().all(|(_, v)| v.is_hashable())
+    }
+
+    fn hash_unchecked<H: Hasher>(&self, state: &mut H) {
+        self.start.hash(state);
+        for (t, v) in &self.writes {
+            t.hash(state);
+            v.hash_unchecked(state);
+        }
+    }
+}
+
+impl<'h, T> Data<'h> for Writes<T>
+where
+    T: 'static + MaybeHash + Clone + Serialize + DeserializeOwned + Send + Sync,
+{
+    type Read = &'h Self;
+    type Sample = &'h Self;
+
+    fn to_read(&self, _written: Time) -> Self::Read {
+        let ptr = self as *const Self;
+        unsafe { &*ptr }
+    }
+    fn from_read(read: Self::Read, _now: Time) -> Self {
+        read.clone()
+    }
+    fn sample(read: Self::Read, _now: Time) -> Self::Sample {
+        read
+    }
+}
+
+/// The limit used by `ref since(START)` reads that don't give one.
+pub struct DefaultSinceLimit;
+
+impl WindowLength for DefaultSinceLimit {
+    const ID: u64 = 0x2c47_e1b9_8d05_f36a;
+
+    fn length() -> Duration {
+        Duration::from_days(1.0)
+    }
+}
+
+/// A pseudo-resource for the writes to `R` during the window `L` before an operation.
+///
+/// It has no timeline; reads of it are served by a [SinceReader] created for each reader.
+pub struct Since<R, L>(PhantomData<fn() -> (R, L)>);
+
+impl<R, L> Clone for Since<R, L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R, L> Copy for Since<R, L> {}
+
+impl<R: Resource, L: WindowLength> Resource for Since<R, L> {
+    const LABEL: &'static str = R::LABEL;
+    const ID: u64 = R::ID
+        .wrapping_add(L::ID)
+        .wrapping_add(0x9e0d_4a7c_13f8_b265);
+    const UNIT: Option<&'static str> = R::UNIT;
+    type Data = Writes<R::Data>;
+    const INSTANCE: Self = Since(PhantomData);
+
+    fn initial_condition() -> Option<Self::Data> {
+        None
+    }
+
+    fn custom_upstream<'o>(
+        timelines: &Timelines<'o>,
+        time: DenseTime,
+    ) -> Option<&'o dyn Upstream<'o, Self>> {
+        let start = DenseTime::first_at(time.when - L::length());
+        let candidates = timelines.range::<R>(start..time);
+        let reader: &'o SinceReader<'o, R, L> =
+            timelines.alloc(SinceReader::new(time, start, candidates));
+        for candidate in &reader.candidates {
+            match candidate {
+                MaybeGrounded::Grounded(_, u) | MaybeGrounded::Ungrounded(u) => {
+                    u.register_downstream_early(reader)
+                }
+            }
+        }
+        Some(reader)
+    }
+}
+
+/// Collects the values of `R` written during a window, in the order they were written.
+///
+/// Like [WindowReader][crate::internal::operation::window::WindowReader], every operation that
+/// might be in the window is a candidate, and the reader is registered downstream of all of them.
+/// Each member of the window is requested through its own [SinceMember], so that responses
+/// can be put back in order.
+pub struct SinceReader<'o, R: Resource, L: WindowLength> {
+    time: DenseTime,
+    start: DenseTime,
+    candidates: Vec<MaybeGrounded<'o, R>>,
+    state: Mutex<SinceState<'o, R, L>>,
+}
+
+type MemberResponse<'o, R> = InternalResult<(u64, <<R as Resource>::Data as Data<'o>>::Read)>;
+
+struct SinceState<'o, R: Resource, L: WindowLength> {
+    /// Set once the window's operations change. The reader is discarded by its downstreams,
+    /// but it might still be registered with some of its candidates.
+    stale: bool,
+    grounding_registered: bool,
+    grounding_responses: SmallVec<InternalResult<(usize, DenseTime)>, 2>,
+    members: Option<Vec<&'o SinceMember<'o, R, L>>>,
+    responses: Vec<Option<MemberResponse<'o, R>>>,
+    remaining: usize,
+    /// Owns the latest result. It is only replaced after all downstreams have been cleared.
+    output: Option<Box<Writes<R::Data>>>,
+    requests: Requests<'o, Since<R, L>>,
+}
+
+impl<'o, R: Resource, L: WindowLength> SinceReader<'o, R, L> {
+    fn new(time: DenseTime, start: DenseTime, candidates: Vec<MaybeGrounded<'o, R>>) -> Self {
+        Self {
+            time,
+            start,
+            candidates,
+            state: Mutex::new(SinceState {
+                stale: false,
+                grounding_registered: false,
+                grounding_responses: SmallVec::new(),
+                members: None,
+                responses: vec![],
+                remaining: 0,
+                output: None,
+                requests: Requests::new(),
+            }),
+        }
+    }
+
+    fn num_ungrounded(&self) -> usize {
+        self.candidates
+            .iter()
+            .filter(|c| matches!(c, MaybeGrounded::Ungrounded(_)))
+            .count()
+    }
+
+    /// Chooses the operations in the window, in the order they write.
+    fn decide(
+        &'o self,
+        groundings: &[(usize, DenseTime)],
+        timelines: &Timelines<'o>,
+    ) -> Vec<&'o SinceMember<'o, R, L>> {
+        let mut placed = vec![];
+        let mut ungrounded_index = 0;
+        for candidate in &self.candidates {
+            match candidate {
+                MaybeGrounded::Grounded(t, u) => placed.push((*t, *u)),
+                MaybeGrounded::Ungrounded(u) => {
+                    let (_, t) = groundings
+                        .iter()
+                        .find(|(i, _)| *i == ungrounded_index)
+                        .expect("expected a grounding for every ungrounded candidate");
+                    placed.push((*t, *u));
+                    ungrounded_index += 1;
+                }
+            }
+        }
+        placed.retain(|(t, _)| self.start <= *t && *t < self.time);
+        placed.sort_by_key(|(t, _)| *t);
+
+        placed
+            .into_iter()
+            .enumerate()
+            .map(|(index, (time, upstream))| {
+                &*timelines.alloc(SinceMember {
+                    reader: self,
+                    index,
+                    time,
+                    upstream,
+                })
+            })
+            .collect()
+    }
+
+    fn request_members<'s>(
+        &'o self,
+        mut state: parking_lot::MutexGuard<SinceState<'o, R, L>>,
+        members: Vec<&'o SinceMember<'o, R, L>>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        if members.is_empty() {
+            self.collect(state, scope, timelines, env);
+            return;
+        }
+        state.responses = vec![None; members.len()];
+        state.remaining = members.len();
+        drop(state);
+        for member in members {
+            scope.spawn(move |s| {
+                let (env, _guard) = env.start_task();
+                member
+                    .upstream
+                    .request(Continuation::Node(member), true, s, timelines, env)
+            });
+        }
+    }
+
+    fn respond_member<'s>(
+        &'o self,
+        index: usize,
+        value: InternalResult<(u64, <R::Data as Data<'o>>::Read)>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let mut state = self.state.lock();
+        state.responses[index] = Some(value);
+        state.remaining -= 1;
+        if state.remaining == 0 {
+            self.collect(state, scope, timelines, env);
+        }
+    }
+
+    /// Builds the result once every member has responded.
+    fn collect<'s>(
+        &'o self,
+        mut state: parking_lot::MutexGuard<SinceState<'o, R, L>>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let members = state
+            .members
+            .clone()
+            .expect("expected members to be decided before collecting");
+        let responses = std::mem::take(&mut state.responses)
+            .into_iter()
+            .map(|r| r.expect("expected a response from every member"))
+            .collect::<InternalResult<Vec<_>>>();
+
+        let result = responses.map(|responses| {
+            // Absolute times are hashed, since the body can filter by an absolute start time.
+            let mut hasher = PeregrineDefaultHashBuilder::default();
+            self.start.when.hash(&mut hasher);
+            let writes = members
+                .iter()
+                .zip(responses)
+                .map(|(member, (hash, read))| {
+                    member.time.when.hash(&mut hasher);
+                    hasher.write_u64(hash);
+                    let written = duration_to_epoch(member.time.when);
+                    (written, R::Data::from_read(read, written))
+                })
+                .collect();
+            let output = Box::new(Writes {
+                start: duration_to_epoch(self.start.when),
+                writes,
+            });
+            let read = unsafe { &*(&*output as *const Writes<R::Data>) };
+            state.output = Some(output);
+            (hasher.finish(), read)
+        });
+        self.finish(state, result, scope, timelines, env);
+    }
+
+    fn finish<'s>(
+        &'o self,
+        mut state: parking_lot::MutexGuard<SinceState<'o, R, L>>,
+        result: InternalResult<(u64, &'o Writes<R::Data>)>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let responses = state.requests.finish(result);
+        drop(state);
+        responses.run(scope, timelines, env);
+    }
+}
+
+impl<'o, R: Resource, L: WindowLength> Upstream<'o, Since<R, L>> for SinceReader<'o, R, L> {
+    fn request<'s>(
+        &'o self,
+        continuation: Continuation<'o, Since<R, L>>,
+        already_registered: bool,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let mut state = self.state.lock();
+        match state.requests.begin(continuation, already_registered) {
+            Request::Respond(responses) => {
+                drop(state);
+                responses.run(scope, timelines, env);
+                return;
+            }
+            Request::Wait => return,
+            Request::Start => {}
+        }
+
+        if let Some(members) = state.members.clone() {
+            self.request_members(state, members, scope, timelines, env);
+        } else if self.num_ungrounded() == 0 {
+            let members = self.decide(&[], timelines);
+            state.members = Some(members.clone());
+            self.request_members(state, members, scope, timelines, env);
+        } else {
+            let already_registered = state.grounding_registered;
+            state.grounding_registered = true;
+            state.grounding_responses.clear();
+            drop(state);
+            let ungrounded = self.candidates.iter().filter_map(|c| match c {
+                MaybeGrounded::Ungrounded(u) => Some(*u),
+                MaybeGrounded::Grounded(..) => None,
+            });
+            for (i, u) in ungrounded.enumerate() {
+                scope.spawn(move |s| {
+                    let (env, _guard) = env.start_task();
+                    u.request_grounding(
+                        GroundingContinuation::Node(i, self),
+                        already_registered,
+                        s,
+                        timelines,
+                        env,
+                    )
+                });
+            }
+        }
+    }
+
+    fn notify_downstreams(&self, _time_of_change: DenseTime) {
+        unreachable!("since reads are not stored in a timeline")
+    }
+
+    fn register_downstream_early(&self, downstream: &'o dyn Downstream<'o, Since<R, L>>) {
+        self.state.lock().requests.downstreams.push(downstream);
+    }
+
+    fn request_grounding<'s>(
+        &'o self,
+        _continuation: GroundingContinuation<'o>,
+        _already_registered: bool,
+        _scope: &Scope<'s>,
+        _timelines: &'s Timelines<'o>,
+        _env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        unreachable!()
+    }
+}
+
+/// Registered with every candidate, to be invalidated when the window changes.
+/// Values are received through [SinceMember] instead.
+impl<'o, R: Resource, L: WindowLength> Downstream<'o, R> for SinceReader<'o, R, L> {
+    fn respond<'s>(
+        &'o self,
+        _value: InternalResult<(u64, <R::Data as Data<'o>>::Read)>,
+        _scope: &Scope<'s>,
+        _timelines: &'s Timelines<'o>,
+        _env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        unreachable!("since readers request their members through SinceMember")
+    }
+
+    fn clear_cache(&self) {
+        let mut state = self.state.lock();
+        if state.stale {
+            return;
+        }
+        state.requests.clear_cache();
+    }
+
+    fn clear_upstream(&self, time_of_change: Option<DenseTime>) -> bool {
+        let mut state = self.state.lock();
+        if state.stale {
+            return false;
+        }
+        if let Some(t) = time_of_change
+            && t >= self.time
+        {
+            return true;
+        }
+
+        state.stale = true;
+        state.requests.discard(time_of_change);
+        false
+    }
+}
+
+impl<'o, R: Resource, L: WindowLength> GroundingDownstream<'o> for SinceReader<'o, R, L> {
+    fn respond_grounding<'s>(
+        &'o self,
+        value: InternalResult<(usize, DenseTime)>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let mut state = self.state.lock();
+        state.grounding_responses.push(value);
+        if state.grounding_responses.len() < self.num_ungrounded() {
+            return;
+        }
+
+        match state
+            .grounding_responses
+            .drain(..)
+            .collect::<InternalResult<Vec<_>>>()
+        {
+            Ok(groundings) => {
+                let members = self.decide(&groundings, timelines);
+                state.members = Some(members.clone());
+                self.request_members(state, members, scope, timelines, env);
+            }
+            Err(e) => self.finish(state, Err(e), scope, timelines, env),
+        }
+    }
+
+    fn clear_grounding_cache(&self) {
+        let mut state = self.state.lock();
+        if state.stale {
+            return;
+        }
+        state.members = None;
+        state.requests.clear_cache();
+    }
+}
+
+/// Requests one member of a [SinceReader]'s window, and passes its value back with its position.
+pub struct SinceMember<'o, R: Resource, L: WindowLength> {
+    reader: &'o SinceReader<'o, R, L>,
+    index: usize,
+    time: DenseTime,
+    upstream: &'o dyn Upstream<'o, R>,
+}
+
+impl<'o, R: Resource, L: WindowLength> Downstream<'o, R> for SinceMember<'o, R, L> {
+    fn respond<'s>(
+        &'o self,
+        value: InternalResult<(u64, <R::Data as Data<'o>>::Read)>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        self.reader
+            .respond_member(self.index, value, scope, timelines, env);
+    }
+
+    fn clear_cache(&self) {
+        unreachable!("since members are never registered as downstreams")
+    }
+
+    fn clear_upstream(&self, _time_of_change: Option<DenseTime>) -> bool {
+        unreachable!("since members are never registered as downstreams")
+    }
+}
+
+impl<'o, R: Resource, L: WindowLength> GroundingDownstream<'o> for SinceMember<'o, R, L> {
+    fn respond_grounding<'s>(
+        &'o self,
+        _value: InternalResult<(usize, DenseTime)>,
+        _scope: &Scope<'s>,
+        _timelines: &'s Timelines<'o>,
+        _env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        unreachable!("since members don't request groundings")
+    }
+
+    fn clear_grounding_cache(&self) {
+        unreachable!("since members are never registered as downstreams")
+    }
+}
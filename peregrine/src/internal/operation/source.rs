@@ -0,0 +1,67 @@
+//! Reads of the activity that wrote a resource's current value, written as
+//! `ref source: resource` in [op][crate::op!].
+
+use crate::internal::history::PeregrineDefaultHashBuilder;
+use crate::internal::macro_prelude::DenseTime;
+use crate::internal::operation::Upstream;
+use crate::internal::operation::reader::{ReadHook, ReadResponse, Reader};
+use crate::internal::timeline::Timelines;
+use crate::public::activity::ActivityId;
+use crate::public::resource::{MaybeHash, Resource};
+use crate::{Time, impl_copy_static_data, impl_maybe_hash_for_hashable};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+impl_maybe_hash_for_hashable![ActivityId];
+impl_copy_static_data![Option<ActivityId>];
+
+/// A pseudo-resource for the activity that wrote the value of `R` read by an operation.
+///
+/// It has no timeline; reads of it are served by a [Reader] created for each reader.
+/// Initial conditions and reactive daemons have no activity, so they are read as `None`.
+pub struct Source<R>(PhantomData<fn() -> R>);
+
+impl<R> Clone for Source<R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R> Copy for Source<R> {}
+
+impl<R: Resource> Resource for Source<R> {
+    const LABEL: &'static str = R::LABEL;
+    const ID: u64 = R::ID.wrapping_add(0x5f0a_9c3e_71d2_4b86);
+    type Data = Option<ActivityId>;
+    const INSTANCE: Self = Source(PhantomData);
+
+    fn initial_condition() -> Option<Self::Data> {
+        None
+    }
+
+    fn custom_upstream<'o>(
+        timelines: &Timelines<'o>,
+        time: DenseTime,
+    ) -> Option<&'o dyn Upstream<'o, Self>> {
+        Some(timelines.alloc(Reader::<Self>::new(Some(time))))
+    }
+}
+
+/// Reads `R` at an operation's time, and responds with the activity that owns the
+/// operation that wrote it.
+impl<R: Resource> ReadHook for Source<R> {
+    type Input = R;
+
+    fn from_input<'o>(
+        _response: ReadResponse<'o, R>,
+        upstream: &'o dyn Upstream<'o, R>,
+        timelines: &Timelines<'o>,
+    ) -> ReadResponse<'o, Self> {
+        let source = upstream
+            .source_address()
+            .and_then(|address| timelines.owner(address));
+        let mut hasher = PeregrineDefaultHashBuilder::default();
+        source.hash(&mut hasher);
+        (hasher.finish(), source)
+    }
+}
@@ -0,0 +1,440 @@
+//! Reads of a resource's extremes over a window of time, written as
+//! `ref range(WINDOW): resource` in [op][crate::op!].
+
+use crate::Time;
+use crate::internal::exec::ExecEnvironment;
+use crate::internal::macro_prelude::DenseTime;
+use crate::internal::operation::grounding::GroundingContinuation;
+use crate::internal::operation::reader::{Request, Requests};
+use crate::internal::operation::{
+    Continuation, Downstream, GroundingDownstream, InternalResult, ObservedErrorOutput, Upstream,
+};
+use crate::internal::timeline::{MaybeGrounded, Timelines};
+use crate::public::resource::{Data, MaybeHash, Resource};
+use hifitime::Duration;
+use parking_lot::Mutex;
+use rayon::Scope;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::hash::Hasher;
+use std::marker::PhantomData;
+
+/// The smallest and largest values of a resource over a window of time.
+///
+/// Produced by `ref range(WINDOW): resource` reads in [op][crate::op!].
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Extremes<T> {
+    pub min: T,
+    pub max: T,
+}
+
+impl<T: MaybeHash> MaybeHash for Extremes<T> {
+    fn is_hashable(&self) -> bool {
+        self.min.is_hashable() && self.max.is_hashable()
+    }
+
+    fn hash_unchecked<H: Hasher>(&self, state: &mut H) {
+        self.min.hash_unchecked(state);
+        self.max.hash_unchecked(state);
+    }
+}
+
+impl<'h, T> Data<'h> for Extremes<T>
+where
+    T: 'static + Copy + MaybeHash + Serialize + DeserializeOwned + Send + Sync,
+{
+    type Read = Self;
+    type Sample = Self;
+
+    fn to_read(&self, _written: Time) -> Self::Read {
+        *self
+    }
+    fn from_read(read: Self::Read, _now: Time) -> Self {
+        read
+    }
+    fn sample(read: Self::Read, _now: Time) -> Self::Sample {
+        read
+    }
+}
+
+/// Data that can be read over a window: comparable values that are read out of history as-is.
+pub trait WindowData: Copy + PartialOrd + for<'h> Data<'h, Read = Self> {}
+
+impl<T: Copy + PartialOrd + for<'h> Data<'h, Read = T>> WindowData for T {}
+
+/// The length of a window, generated by [op][crate::op!] for each `ref range(..)` read.
+pub trait WindowLength: 'static + Send + Sync {
+    const ID: u64;
+
+    fn length() -> Duration;
+}
+
+/// A pseudo-resource for the extremes of `R` over the window `L` before an operation.
+///
+/// It has no timeline; reads of it are served by a [WindowReader] created for each reader.
+pub struct Window<R, L>(PhantomData<fn() -> (R, L)>);
+
+impl<R, L> Clone for Window<R, L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<R, L> Copy for Window<R, L> {}
+
+impl<R, L> Resource for Window<R, L>
+where
+    R: Resource<Data: WindowData>,
+    L: WindowLength,
+{
+    const LABEL: &'static str = R::LABEL;
+    const ID: u64 = R::ID.wrapping_add(L::ID);
+    const UNIT: Option<&'static str> = R::UNIT;
+    type Data = Extremes<R::Data>;
+    const INSTANCE: Self = Window(PhantomData);
+
+    fn initial_condition() -> Option<Self::Data> {
+        None
+    }
+
+    fn custom_upstream<'o>(
+        timelines: &Timelines<'o>,
+        time: DenseTime,
+    ) -> Option<&'o dyn Upstream<'o, Self>> {
+        let start = DenseTime::first_at(time.when - L::length());
+        let candidates = timelines.range_inclusive_previous::<R>(start..time);
+        let reader: &'o WindowReader<'o, R, L> =
+            timelines.alloc(WindowReader::new(time, start, candidates));
+        for candidate in &reader.candidates {
+            match candidate {
+                MaybeGrounded::Grounded(_, u) | MaybeGrounded::Ungrounded(u) => {
+                    u.register_downstream_early(reader)
+                }
+            }
+        }
+        Some(reader)
+    }
+}
+
+/// Collects the values of `R` written during a window and responds with their extremes.
+///
+/// Every operation that might be in the window is a candidate. Ungrounded candidates are
+/// grounded first to decide whether they are actually in the window. The reader is
+/// registered downstream of all candidates, so that inserting or removing an operation
+/// in the window invalidates it.
+pub struct WindowReader<'o, R: Resource<Data: WindowData>, L: WindowLength> {
+    time: DenseTime,
+    start: DenseTime,
+    candidates: Vec<MaybeGrounded<'o, R>>,
+    state: Mutex<WindowState<'o, R, L>>,
+}
+
+struct WindowState<'o, R: Resource<Data: WindowData>, L: WindowLength> {
+    /// Set once the window's operations change. The reader is discarded by its downstreams,
+    /// but it might still be registered with some of its candidates.
+    stale: bool,
+    grounding_registered: bool,
+    grounding_responses: SmallVec<InternalResult<(usize, DenseTime)>, 2>,
+    members: Option<Vec<&'o dyn Upstream<'o, R>>>,
+    remaining: usize,
+    accumulated: InternalResult<(u64, Option<Extremes<R::Data>>)>,
+    requests: Requests<'o, Window<R, L>>,
+}
+
+impl<'o, R, L> WindowReader<'o, R, L>
+where
+    R: Resource<Data: WindowData>,
+    L: WindowLength,
+{
+    fn new(time: DenseTime, start: DenseTime, candidates: Vec<MaybeGrounded<'o, R>>) -> Self {
+        Self {
+            time,
+            start,
+            candidates,
+            state: Mutex::new(WindowState {
+                stale: false,
+                grounding_registered: false,
+                grounding_responses: SmallVec::new(),
+                members: None,
+                remaining: 0,
+                accumulated: Ok((0, None)),
+                requests: Requests::new(),
+            }),
+        }
+    }
+
+    fn num_ungrounded(&self) -> usize {
+        self.candidates
+            .iter()
+            .filter(|c| matches!(c, MaybeGrounded::Ungrounded(_)))
+            .count()
+    }
+
+    /// Chooses the operations that were active during the window: those in the window,
+    /// and the last one before it.
+    fn decide(&self, groundings: &[(usize, DenseTime)]) -> Vec<&'o dyn Upstream<'o, R>> {
+        let mut placed = vec![];
+        let mut ungrounded_index = 0;
+        for candidate in &self.candidates {
+            match candidate {
+                MaybeGrounded::Grounded(t, u) => placed.push((*t, *u)),
+                MaybeGrounded::Ungrounded(u) => {
+                    let (_, t) = groundings
+                        .iter()
+                        .find(|(i, _)| *i == ungrounded_index)
+                        .expect("expected a grounding for every ungrounded candidate");
+                    placed.push((*t, *u));
+                    ungrounded_index += 1;
+                }
+            }
+        }
+
+        let previous = placed
+            .iter()
+            .filter(|(t, _)| *t < self.start)
+            .max_by_key(|(t, _)| *t)
+            .map(|(_, u)| *u);
+
+        placed
+            .iter()
+            .filter(|(t, _)| self.start <= *t && *t < self.time)
+            .map(|(_, u)| *u)
+            .chain(previous)
+            .collect()
+    }
+
+    fn request_members<'s>(
+        &'o self,
+        members: Vec<&'o dyn Upstream<'o, R>>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        for member in members {
+            scope.spawn(move |s| {
+                let (env, _guard) = env.start_task();
+                member.request(Continuation::Node(self), true, s, timelines, env)
+            });
+        }
+    }
+
+    fn finish<'s>(
+        &'o self,
+        mut state: parking_lot::MutexGuard<WindowState<'o, R, L>>,
+        result: InternalResult<(u64, Extremes<R::Data>)>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let responses = state.requests.finish(result);
+        drop(state);
+        responses.run(scope, timelines, env);
+    }
+}
+
+impl<'o, R, L> Upstream<'o, Window<R, L>> for WindowReader<'o, R, L>
+where
+    R: Resource<Data: WindowData>,
+    L: WindowLength,
+{
+    fn request<'s>(
+        &'o self,
+        continuation: Continuation<'o, Window<R, L>>,
+        already_registered: bool,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let mut state = self.state.lock();
+        match state.requests.begin(continuation, already_registered) {
+            Request::Respond(responses) => {
+                drop(state);
+                responses.run(scope, timelines, env);
+                return;
+            }
+            Request::Wait => return,
+            Request::Start => {}
+        }
+
+        if let Some(members) = state.members.clone() {
+            state.remaining = members.len();
+            state.accumulated = Ok((0, None));
+            drop(state);
+            self.request_members(members, scope, timelines, env);
+        } else if self.num_ungrounded() == 0 {
+            let members = self.decide(&[]);
+            state.members = Some(members.clone());
+            state.remaining = members.len();
+            state.accumulated = Ok((0, None));
+            drop(state);
+            self.request_members(members, scope, timelines, env);
+        } else {
+            let already_registered = state.grounding_registered;
+            state.grounding_registered = true;
+            state.grounding_responses.clear();
+            drop(state);
+            let ungrounded = self.candidates.iter().filter_map(|c| match c {
+                MaybeGrounded::Ungrounded(u) => Some(*u),
+                MaybeGrounded::Grounded(..) => None,
+            });
+            for (i, u) in ungrounded.enumerate() {
+                scope.spawn(move |s| {
+                    let (env, _guard) = env.start_task();
+                    u.request_grounding(
+                        GroundingContinuation::Node(i, self),
+                        already_registered,
+                        s,
+                        timelines,
+                        env,
+                    )
+                });
+            }
+        }
+    }
+
+    fn notify_downstreams(&self, _time_of_change: DenseTime) {
+        unreachable!("windows are not stored in a timeline")
+    }
+
+    fn register_downstream_early(&self, downstream: &'o dyn Downstream<'o, Window<R, L>>) {
+        self.state.lock().requests.downstreams.push(downstream);
+    }
+
+    fn request_grounding<'s>(
+        &'o self,
+        _continuation: GroundingContinuation<'o>,
+        _already_registered: bool,
+        _scope: &Scope<'s>,
+        _timelines: &'s Timelines<'o>,
+        _env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        unreachable!()
+    }
+}
+
+impl<'o, R, L> Downstream<'o, R> for WindowReader<'o, R, L>
+where
+    R: Resource<Data: WindowData>,
+    L: WindowLength,
+{
+    fn respond<'s>(
+        &'o self,
+        value: InternalResult<(u64, <R::Data as Data<'o>>::Read)>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let mut state = self.state.lock();
+        state.accumulated = match (state.accumulated, value) {
+            (Ok((hash, extremes)), Ok((value_hash, value))) => {
+                // Responses arrive in any order, so the hashes are combined commutatively.
+                let hash = hash.wrapping_add(value_hash);
+                let extremes = match extremes {
+                    None => Extremes {
+                        min: value,
+                        max: value,
+                    },
+                    Some(Extremes { min, max }) => Extremes {
+                        min: if value < min { value } else { min },
+                        max: if value > max { value } else { max },
+                    },
+                };
+                Ok((hash, Some(extremes)))
+            }
+            _ => Err(ObservedErrorOutput),
+        };
+        state.remaining -= 1;
+
+        if state.remaining == 0 {
+            let result = state.accumulated.map(|(hash, extremes)| {
+                (
+                    hash,
+                    extremes.expect("expected at least one operation in the window"),
+                )
+            });
+            self.finish(state, result, scope, timelines, env);
+        }
+    }
+
+    fn clear_cache(&self) {
+        let mut state = self.state.lock();
+        if state.stale {
+            return;
+        }
+        state.requests.clear_cache();
+    }
+
+    fn clear_upstream(&self, time_of_change: Option<DenseTime>) -> bool {
+        let mut state = self.state.lock();
+        if state.stale {
+            return false;
+        }
+        if let Some(t) = time_of_change
+            && t >= self.time
+        {
+            return true;
+        }
+
+        state.stale = true;
+        state.requests.discard(time_of_change);
+        false
+    }
+}
+
+impl<'o, R, L> GroundingDownstream<'o> for WindowReader<'o, R, L>
+where
+    R: Resource<Data: WindowData>,
+    L: WindowLength,
+{
+    fn respond_grounding<'s>(
+        &'o self,
+        value: InternalResult<(usize, DenseTime)>,
+        scope: &Scope<'s>,
+        timelines: &'s Timelines<'o>,
+        env: ExecEnvironment<'s, 'o>,
+    ) where
+        'o: 's,
+    {
+        let mut state = self.state.lock();
+        state.grounding_responses.push(value);
+        if state.grounding_responses.len() < self.num_ungrounded() {
+            return;
+        }
+
+        match state
+            .grounding_responses
+            .drain(..)
+            .collect::<InternalResult<Vec<_>>>()
+        {
+            Ok(groundings) => {
+                let members = self.decide(&groundings);
+                state.members = Some(members.clone());
+                state.remaining = members.len();
+                state.accumulated = Ok((0, None));
+                drop(state);
+                self.request_members(members, scope, timelines, env);
+            }
+            Err(e) => self.finish(state, Err(e), scope, timelines, env),
+        }
+    }
+
+    fn clear_grounding_cache(&self) {
+        let mut state = self.state.lock();
+        if state.stale {
+            return;
+        }
+        state.members = None;
+        state.requests.clear_cache();
+    }
+}
@@ -10,8 +10,9 @@ use hifitime::Duration;
 use operation::grounding::{Delay, GroundingContinuation};
 use rayon::Scope;
 use serde::{Deserialize, Serialize};
+use std::any::TypeId;
 use std::hash::{Hash, Hasher};
-use std::ops::{Add, AddAssign};
+use std::ops::{Add, AddAssign, Range};
 
 pub trait StaticActivity: Hash {
     const LABEL: &'static str;
@@ -77,6 +78,67 @@ impl AddAssign<Duration> for DenseTime {
     }
 }
 
+/// The first order given out by a plan's order counter.
+///
+/// Orders below this are reserved for operations grounded under [CoincidentWritePolicy::DynamicFirst],
+/// and for the operations of recurring daemons.
+pub(crate) const FIRST_ORDER: u64 = 1 << 62;
+
+/// The first order given to the operations of recurring daemons.
+///
+/// Each daemon's ticks start at their own multiple of [RECURRING_STRIDE] above this, so
+/// coincident ticks of different daemons are ordered the same way however far the plan has
+/// been simulated, and before any activity's operations at the same time.
+pub(crate) const RECURRING_ORDER: u64 = 1 << 61;
+
+/// The number of orders reserved for each recurring daemon's ticks.
+pub(crate) const RECURRING_STRIDE: u64 = 1 << 32;
+
+/// Set on the order of operations grounded under [CoincidentWritePolicy::DynamicLast].
+const DYNAMIC_LAST_BIT: u64 = 1 << 63;
+
+/// How a dynamically placed operation is ordered against statically placed operations
+/// at the exact time it is grounded to.
+///
+/// This only matters when both write to the same resource: the operation ordered later
+/// overwrites the other, and operations after both of them see its value.
+///
+/// Set with [Session::with_coincident_write_policy][crate::Session::with_coincident_write_policy].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum CoincidentWritePolicy {
+    /// The grounded operation comes before all static operations at that time, so their writes win.
+    DynamicFirst,
+    /// The grounded operation comes after all static operations at that time, so its write wins.
+    #[default]
+    DynamicLast,
+}
+
+impl CoincidentWritePolicy {
+    /// Maps the order of the node a grounding came from to the order of the grounded time.
+    pub(crate) fn grounded_order(self, order: u64) -> u64 {
+        match self {
+            CoincidentWritePolicy::DynamicFirst => (order & !FIRST_ORDER).max(1),
+            CoincidentWritePolicy::DynamicLast => order | DYNAMIC_LAST_BIT,
+        }
+    }
+}
+
+/// What happens to a dynamically placed operation when its delay fails to evaluate,
+/// usually because it read a resource whose operation failed.
+///
+/// Set with [Session::with_grounding_error_policy][crate::Session::with_grounding_error_policy].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GroundingErrorPolicy {
+    /// The operation is left out of views, along with the operations that might read its writes.
+    /// Only the original error is reported, and only by the view that first ran into it.
+    #[default]
+    Drop,
+    /// The operation is placed at the earliest time its delay allowed.
+    MinBound,
+    /// An error naming the operation's possible placement is reported by every view that needs it.
+    Propagate,
+}
+
 /// The placement of an activity or operation.
 ///
 /// It might be a statically known concrete time, or a time that is
@@ -133,7 +195,7 @@ impl<'o> Placement<'o> {
         match self {
             Placement::Static(_) => unreachable!(),
             Placement::Dynamic { node, .. } => node.request(
-                Continuation::GroundingWrapper(continuation),
+                Continuation::GroundingWrapper(Box::new((continuation, self))),
                 already_registered,
                 scope,
                 timelines,
@@ -142,12 +204,17 @@ impl<'o> Placement<'o> {
         }
     }
 
+    /// Sets the order of the op at this placement.
+    ///
+    /// Dynamic placements widen their bounds to every order the op might be grounded at,
+    /// under any [CoincidentWritePolicy].
     pub fn set_order(&mut self, order: u64) {
         match self {
             Placement::Static(p) => p.order = order,
             Placement::Dynamic { min, max, .. } => {
-                min.order = order;
-                max.order = order;
+                // Order 0 is reserved for initial conditions.
+                min.order = 1;
+                max.order = order | DYNAMIC_LAST_BIT;
             }
         }
     }
@@ -155,10 +222,7 @@ impl<'o> Placement<'o> {
     pub fn get_order(&self) -> u64 {
         match self {
             Placement::Static(p) => p.order,
-            Placement::Dynamic { min, max, .. } => {
-                assert_eq!(min.order, max.order);
-                min.order
-            }
+            Placement::Dynamic { max, .. } => max.order & !DYNAMIC_LAST_BIT,
         }
     }
 }
@@ -215,5 +279,13 @@ impl<'v, 'o: 'v, F: FnOnce(Placement<'o>) -> Delay<U>, U: Upstream<'o, peregrine
 
 pub(crate) struct DecomposedActivity<'o> {
     pub(crate) activity: *mut dyn Activity,
+    /// The time the activity was inserted at.
+    pub(crate) time: Time,
+    /// The time the activity ends, from the duration its run returned.
+    pub(crate) end: Time,
+    /// The type of the activity behind the pointer.
+    pub(crate) type_id: TypeId,
+    /// The orders given to the activity's operations.
+    pub(crate) orders: Range<u64>,
     pub(crate) operations: Vec<&'o dyn Node<'o>>,
 }
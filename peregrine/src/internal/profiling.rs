@@ -0,0 +1,47 @@
+//! Execution traces of operation bodies, enabled by the `profiling` feature.
+
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::time::Duration;
+
+/// One run of an operation body.
+struct TraceEntry {
+    /// The operation's [NodeId][crate::internal::operation::NodeId].
+    node: u64,
+    /// The labels of the resources the operation writes, comma-separated.
+    resources: String,
+    elapsed: Duration,
+}
+
+/// The operation bodies run by a plan, and how long each took.
+///
+/// Cache hits don't run the body, so they aren't recorded.
+#[derive(Default)]
+pub struct Trace(Mutex<Vec<TraceEntry>>);
+
+impl Trace {
+    pub fn record(&self, node: u64, resources: &[&'static str], elapsed: Duration) {
+        self.0.lock().push(TraceEntry {
+            node,
+            resources: resources.join(","),
+            elapsed,
+        });
+    }
+
+    /// Writes the trace in the folded stack format read by `inferno` and `flamegraph.pl`.
+    ///
+    /// Each line is `resources;op ID NANOSECONDS`, summed over every run of the operation.
+    pub fn write_folded(&self, mut writer: impl Write) -> std::io::Result<()> {
+        let mut stacks = BTreeMap::<String, u128>::new();
+        for entry in self.0.lock().iter() {
+            let stack = format!("{};op {:016x}", entry.resources, entry.node);
+            *stacks.entry(stack).or_default() += entry.elapsed.as_nanos();
+        }
+        for (stack, nanos) in stacks {
+            // Flamegraphs drop empty frames, so every op takes at least a nanosecond.
+            writeln!(writer, "{stack} {}", nanos.max(1))?;
+        }
+        Ok(())
+    }
+}
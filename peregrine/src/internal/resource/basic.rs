@@ -1,5 +1,6 @@
 use crate::Time;
-use crate::public::resource::{Data, MaybeHash};
+use crate::public::resource::{Data, FloatPolicy, MaybeHash};
+use anyhow::bail;
 use duplicate::duplicate_item;
 use hifitime::Duration;
 use ordered_float::OrderedFloat;
@@ -87,11 +88,10 @@ impl_copy_static_data![
     i32,
     i64,
     i128,
-    f32,
-    f64,
     bool,
     char,
     Duration,
+    std::time::Duration,
     Time,
     ()
 ];
@@ -123,6 +123,10 @@ impl<'h, T: Data<'h>> Data<'h> for Vec<T> {
             now,
         }
     }
+    fn apply_float_policy(&mut self, policy: FloatPolicy) -> anyhow::Result<()> {
+        self.iter_mut()
+            .try_for_each(|t| t.apply_float_policy(policy))
+    }
 }
 
 impl<'h, T: Data<'h>, const LENGTH: usize> Data<'h> for SmallVec<T, LENGTH> {
@@ -150,6 +154,10 @@ impl<'h, T: Data<'h>, const LENGTH: usize> Data<'h> for SmallVec<T, LENGTH> {
             now,
         }
     }
+    fn apply_float_policy(&mut self, policy: FloatPolicy) -> anyhow::Result<()> {
+        self.iter_mut()
+            .try_for_each(|t| t.apply_float_policy(policy))
+    }
 }
 
 pub struct SliceSampler<'h, T> {
@@ -243,6 +251,9 @@ impl<'h, T: Data<'h>> Data<'h> for Box<T> {
             now,
         }
     }
+    fn apply_float_policy(&mut self, policy: FloatPolicy) -> anyhow::Result<()> {
+        (**self).apply_float_policy(policy)
+    }
 }
 
 pub struct RefSampler<'h, T, U> {
@@ -306,6 +317,7 @@ impl_maybe_hash_for_hashable![
     bool,
     char,
     Duration,
+    std::time::Duration,
     Time,
     &'_ str,
     String,
@@ -372,20 +384,47 @@ impl_maybe_hash_tuple! { A, 0; B, 1; C, 2; D, 3; E, 4; F, 5; G, 6; H, 7; I, 8; J
 impl_maybe_hash_tuple! { A, 0; B, 1; C, 2; D, 3; E, 4; F, 5; G, 6; H, 7; I, 8; J, 9; K, 10 }
 impl_maybe_hash_tuple! { A, 0; B, 1; C, 2; D, 3; E, 4; F, 5; G, 6; H, 7; I, 8; J, 9; K, 10; L, 11 }
 
-impl MaybeHash for f32 {
+#[duplicate_item(float; [f32]; [f64])]
+impl MaybeHash for float {
     fn is_hashable(&self) -> bool {
-        true
+        self.is_finite()
     }
     fn hash_unchecked<H: Hasher>(&self, state: &mut H) {
         OrderedFloat::from(*self).hash(state);
     }
 }
 
-impl MaybeHash for f64 {
-    fn is_hashable(&self) -> bool {
-        true
+#[duplicate_item(float; [f32]; [f64])]
+impl<'h> Data<'h> for float {
+    type Read = float;
+    type Sample = float;
+
+    fn to_read(&self, _written: Time) -> Self::Read {
+        *self
     }
-    fn hash_unchecked<H: Hasher>(&self, state: &mut H) {
-        OrderedFloat::from(*self).hash(state);
+    fn from_read(read: Self::Read, _now: Time) -> Self {
+        read
+    }
+    fn sample(read: Self::Read, _now: Time) -> Self::Sample {
+        read
+    }
+
+    fn apply_float_policy(&mut self, policy: FloatPolicy) -> anyhow::Result<()> {
+        match policy {
+            FloatPolicy::Allow => {}
+            _ if self.is_nan() => bail!("NaN written to float resource"),
+            FloatPolicy::Error if self.is_infinite() => {
+                bail!("{self} written to float resource")
+            }
+            FloatPolicy::Sanitize if self.is_infinite() => {
+                *self = if self.is_sign_positive() {
+                    float::MAX
+                } else {
+                    float::MIN
+                };
+            }
+            _ => {}
+        }
+        Ok(())
     }
 }
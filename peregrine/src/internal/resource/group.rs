@@ -18,3 +18,10 @@ pub fn sync_single_to_group<
         m:GROUP[which] = m:SINGLE.clone();
     }
 }
+
+/// Copies a submodel's resource into a read-only accessor, for `expose read` in `model!`.
+pub fn mirror<FROM: Resource, TO: Resource<Data = FROM::Data>>(mut ops: Ops) {
+    ops += internal_op! {
+        m:TO = m:FROM.clone();
+    }
+}
@@ -11,11 +11,23 @@ mod basic;
 pub mod group;
 mod num;
 
+use crate::Resource;
+use crate::internal::exec::{ExecEnvironment, PendingRange, PendingSamples};
+use crate::internal::history::Retired;
+use crate::internal::operation::initial_conditions::InitialConditions;
+use crate::internal::placement::DenseTime;
+use crate::internal::timeline::Timelines;
+use rayon::Scope;
+use std::ops::Bound;
 use type_map::concurrent::TypeMap;
 use type_reg::untagged::TypeReg;
 
 #[doc(hidden)]
 pub trait ResourceHistoryPlugin: Sync {
+    fn id(&self) -> u64;
+
+    fn label(&self) -> &'static str;
+
     fn write_type_string(&self) -> String;
 
     fn ser<'h>(&self, input: &'h TypeMap, type_map: &'h mut type_reg::untagged::TypeMap<String>);
@@ -26,8 +38,57 @@ pub trait ResourceHistoryPlugin: Sync {
         output: &'h mut TypeMap,
         type_reg: &'h mut type_reg::untagged::TypeMap<String>,
     );
+
+    /// Moves this resource's history from `source` into `target`, keeping existing entries.
+    fn merge(&self, target: &mut TypeMap, source: &mut TypeMap);
+
+    /// Moves this resource's history entries whose keys are rejected by `keep` into `retired`,
+    /// and returns how many were removed.
+    fn retain(
+        &self,
+        history: &mut TypeMap,
+        keep: &dyn Fn(u64) -> bool,
+        retired: &mut Retired,
+    ) -> usize;
+
+    /// Spawns requests for all of this resource's nodes within the bounds.
+    fn request_range<'s, 'o: 's>(
+        &self,
+        timelines: &'s Timelines<'o>,
+        bounds: (Bound<DenseTime>, Bound<DenseTime>),
+        scope: &Scope<'s>,
+        env: ExecEnvironment<'s, 'o>,
+    ) -> anyhow::Result<PendingRange<'o>>;
+
+    /// Spawns requests for this resource's nodes within the bounds and the last one before
+    /// them, for sampling them afterwards.
+    fn sample_range<'s, 'o: 's>(
+        &self,
+        timelines: &'s Timelines<'o>,
+        bounds: (Bound<DenseTime>, Bound<DenseTime>),
+        scope: &Scope<'s>,
+        env: ExecEnvironment<'s, 'o>,
+    ) -> anyhow::Result<PendingSamples<'o>>;
+
+    /// Deserializes an initial condition for this resource, for [InitialConditions::from_json].
+    fn insert_json(
+        &self,
+        conditions: &mut InitialConditions,
+        value: serde_json::Value,
+    ) -> anyhow::Result<()>;
 }
 
+/// Marks a resource that operations are not allowed to write to.
+///
+/// Implemented for read-only accessors created with `expose read` in [model][crate::model!].
+#[doc(hidden)]
+pub trait ReadOnly: Resource {}
+
+/// Returned by `op!`'s read-only check when an operation writes to a [ReadOnly] resource,
+/// so that the resulting type error names the problem.
+#[doc(hidden)]
+pub struct CannotWriteToReadOnlyResource;
+
 pub trait ErasedResource: Send + Sync {
     fn id(&self) -> u64;
 }
@@ -6,7 +6,7 @@ use uom::{
     si::{Dimension, Units},
 };
 
-use crate::{Data, MaybeHash, Time};
+use crate::{Data, FloatPolicy, MaybeHash, Time};
 
 impl<D, U, V> MaybeHash for uom::si::Quantity<D, U, V>
 where
@@ -43,4 +43,8 @@ where
     fn sample(read: Self::Read, _: Time) -> Self::Sample {
         read
     }
+
+    fn apply_float_policy(&mut self, policy: FloatPolicy) -> anyhow::Result<()> {
+        self.value.apply_float_policy(policy)
+    }
 }
@@ -1,14 +1,21 @@
 #![doc(hidden)]
 
-use crate::internal::history::PassThroughHashBuilder;
+use crate::internal::diagnostics::{self, TraceVerbosity};
+use crate::internal::history::{
+    LiveOperations, PassThroughHashBuilder, PeregrineDefaultHashBuilder,
+};
 use crate::internal::macro_prelude::DenseTime;
-use crate::internal::operation::grounding::UngroundedUpstreamResolver;
+use crate::internal::operation::external::{ExternalInputs, RefreshExternal};
+use crate::internal::operation::grounding::{GroundingBatch, UngroundedUpstreamResolver};
 use crate::internal::operation::initial_conditions::InitialConditionOp;
 use crate::internal::operation::{Node, Upstream, UpstreamVec};
-use crate::internal::placement::Placement;
+use crate::internal::placement::{Placement, RECURRING_ORDER, RECURRING_STRIDE};
 use crate::internal::resource::ErasedResource;
-use crate::public::resource::Resource;
+use crate::public::activity::ActivityId;
+use crate::public::resource::{Data, Resource};
+use anyhow::bail;
 use bumpalo_herd::{Herd, Member};
+use dashmap::DashSet;
 use hifitime::TimeScale::TAI;
 use hifitime::{Duration, Epoch as Time};
 use immutable_chunkmap::map::MapM;
@@ -17,29 +24,89 @@ use parking_lot::{
 };
 use slab::Slab;
 use smallvec::SmallVec;
-use std::collections::{BTreeMap, HashMap};
-use std::ops::{Bound, RangeBounds};
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hasher;
+use std::ops::{Bound, Range, RangeBounds};
+use std::sync::Arc;
 
 pub struct Timelines<'o> {
     map: HashMap<u64, RwLock<Box<dyn ErasedTimeline + 'o>>, PassThroughHashBuilder>,
     herd: &'o Herd,
     reactive_daemons: HashMap<u64, ReactiveDaemon<'o>>,
+    /// Daemons that run at a fixed period, in the order they were added, with their IDs.
+    recurring_daemons: Vec<(u64, RecurringDaemon<'o>)>,
+    batched_grounding: bool,
+    trace_verbosity: TraceVerbosity,
+    /// Operation addresses and resource IDs of writes left out of the timelines.
+    ///
+    /// See [Timelines::coalesce_write].
+    coalesced_writes: HashSet<(usize, u64)>,
+    /// The activities that own each operation, by address.
+    owners: HashMap<usize, ActivityId>,
+    /// The start and end of each activity, by the first order given to its operations, with the
+    /// order after its last operation. The end is read by `ref at_end: resource`.
+    activity_ends: BTreeMap<u64, (u64, Duration, Duration)>,
+    /// The labels of resources that activity operations read through pseudo-resources, like
+    /// `ref(OFFSET): resource`, with how many operations read each.
+    ///
+    /// These reads can reach any earlier write, so recurring daemons that write these resources
+    /// tick from the start of the plan; see [Timelines::run_recurring_daemons].
+    indirect_reads: HashMap<&'static str, usize>,
+    /// The timeline keys of each activity's local resources, by the activity's first order.
+    locals: HashMap<u64, Vec<u64>>,
+    #[cfg(feature = "profiling")]
+    trace: crate::internal::profiling::Trace,
+    /// The latest value each operation wrote to each [no-cache][Resource::NO_CACHE] resource,
+    /// by operation address, with the resource IDs.
+    ///
+    /// An operation's values are dropped when it leaves the plan; see [Timelines::untrack].
+    uncached: Mutex<HashMap<usize, UncachedValues>>,
+    /// IDs of the resources whose operations were requested since the last simulation started.
+    touched: DashSet<u64>,
+    /// One line per operation run, in execution order, if execution is being recorded.
+    execution_log: Option<Mutex<Vec<String>>>,
+    /// The session's external input versions, read by `ref external: name`.
+    external_inputs: Option<&'o ExternalInputs>,
+    /// Readers of external inputs, and the input generation they were last checked against.
+    external_readers: Mutex<(u64, Vec<&'o dyn RefreshExternal>)>,
+    /// The start of the plan, which `ops_elapsed` is measured from.
+    start: Time,
+    /// Upstreams to notify when a batch of insertions ends, if one is in progress.
+    ///
+    /// See [Timelines::defer_notifications].
+    deferred_notifications: Option<Mutex<DeferredNotifications<'o>>>,
+    /// The first error from a reactive daemon's activities since it was last taken.
+    ///
+    /// See [Timelines::take_daemon_failure].
+    daemon_failure: Mutex<Option<anyhow::Error>>,
+    /// The registry the plan's operations are kept in for history pruning, if the session
+    /// prunes its history.
+    ///
+    /// See [Timelines::track_operations].
+    live_operations: Option<Arc<LiveOperations>>,
 }
 
+/// Values written to no-cache resources by one operation, with the resource IDs.
+type UncachedValues = SmallVec<(u64, Box<dyn Any + Send + Sync>), 1>;
+
+/// Notifications of upstreams by address, with the earliest time of change and a
+/// function that sends it.
+type DeferredNotifications<'o> = HashMap<usize, (DenseTime, Box<dyn Fn(DenseTime) + Send + 'o>)>;
+
+/// Runs a reactive daemon's activities at the placement of a write, and returns their operations.
+pub type ReactiveTrigger<'o> =
+    Box<dyn Fn(Placement<'o>, Member<'o>) -> anyhow::Result<Vec<&'o dyn Node<'o>>> + Sync>;
+
 pub struct ReactiveDaemon<'o> {
     triggers: Vec<u64>,
-    #[allow(unused_parens)]
-    trigger_fn: Box<dyn Fn(Placement<'o>, Member<'o>) -> Vec<&'o dyn Node<'o>> + Sync>,
+    trigger_fn: ReactiveTrigger<'o>,
     #[allow(clippy::type_complexity)]
     record: Mutex<HashMap<(DenseTime, Option<DenseTime>), &'o dyn Node<'o>>>,
 }
 
 impl<'o> ReactiveDaemon<'o> {
-    #[allow(unused_parens)]
-    pub fn new(
-        triggers: Vec<u64>,
-        trigger_fn: Box<dyn Fn(Placement<'o>, Member<'o>) -> Vec<&'o dyn Node<'o>> + Sync>,
-    ) -> Self {
+    pub fn new(triggers: Vec<u64>, trigger_fn: ReactiveTrigger<'o>) -> Self {
         Self {
             triggers,
             trigger_fn,
@@ -47,13 +114,246 @@ impl<'o> ReactiveDaemon<'o> {
         }
     }
 }
+/// A daemon that runs at a fixed period from the start of the plan, declared with
+/// `daemon recurring(..)` in [model][crate::model!].
+///
+/// Ticks are only run where the plan is simulated; see [Timelines::run_recurring_daemons].
+pub struct RecurringDaemon<'o> {
+    period: Duration,
+    tick_fn: Box<dyn Fn(Placement<'o>, Member<'o>) -> Vec<&'o dyn Node<'o>> + Sync>,
+    ticks: Mutex<Ticks<'o>>,
+}
+
+/// The ticks of a [RecurringDaemon] that have run.
+#[derive(Default)]
+struct Ticks<'o> {
+    /// The operations of each tick, by how many periods it is after the start of the plan.
+    operations: BTreeMap<u64, Vec<&'o dyn Node<'o>>>,
+    /// Every tick before this one has run.
+    contiguous: u64,
+    /// The resources that the first tick's operations read and write, which every later tick
+    /// must match.
+    footprint: Option<Footprint>,
+}
+
+/// The labels of the resources that a tick's operations read and write.
+#[derive(PartialEq)]
+struct Footprint {
+    reads: HashSet<&'static str>,
+    /// Reads through pseudo-resources, like `ref(OFFSET): resource`, which are also in `reads`.
+    indirect_reads: HashSet<&'static str>,
+    writes: HashSet<&'static str>,
+}
+
+impl<'o> RecurringDaemon<'o> {
+    pub fn new(
+        period: Duration,
+        tick_fn: Box<dyn Fn(Placement<'o>, Member<'o>) -> Vec<&'o dyn Node<'o>> + Sync>,
+    ) -> Self {
+        Self {
+            period,
+            tick_fn,
+            ticks: Mutex::default(),
+        }
+    }
+}
+
 impl<'o> Timelines<'o> {
     pub fn new(herd: &'o Herd) -> Self {
         Self {
             map: HashMap::with_hasher(PassThroughHashBuilder),
             herd,
             reactive_daemons: HashMap::new(),
+            recurring_daemons: vec![],
+            batched_grounding: true,
+            trace_verbosity: TraceVerbosity::default(),
+            coalesced_writes: HashSet::new(),
+            owners: HashMap::new(),
+            activity_ends: BTreeMap::new(),
+            indirect_reads: HashMap::new(),
+            locals: HashMap::new(),
+            #[cfg(feature = "profiling")]
+            trace: Default::default(),
+            uncached: Mutex::new(HashMap::new()),
+            touched: DashSet::new(),
+            execution_log: None,
+            external_inputs: None,
+            external_readers: Mutex::new((0, vec![])),
+            start: duration_to_epoch(Duration::ZERO),
+            deferred_notifications: None,
+            daemon_failure: Mutex::new(None),
+            live_operations: None,
+        }
+    }
+
+    /// Keeps the plan's activity and daemon operations in a registry, which history pruning
+    /// reads their cached hashes from.
+    ///
+    /// The plan must close the registry before any of its operations are deallocated.
+    pub(crate) fn track_operations(&mut self, operations: Arc<LiveOperations>) {
+        self.live_operations = Some(operations);
+    }
+
+    fn track(&self, op: &'o dyn Node<'o>) {
+        if let Some(live) = &self.live_operations {
+            // SAFETY: operations are untracked before they are removed from the plan, and the
+            // plan closes the registry before it drops the rest.
+            unsafe { live.add(op) }
+        }
+    }
+
+    /// Forgets an operation that is leaving the plan for good.
+    fn untrack(&self, op: usize) {
+        if let Some(live) = &self.live_operations {
+            live.remove(op);
         }
+        self.uncached.lock().remove(&op);
+    }
+
+    pub fn set_start(&mut self, start: Time) {
+        self.start = start;
+    }
+
+    /// The start of the plan.
+    pub fn start(&self) -> Time {
+        self.start
+    }
+
+    /// Moves the initial conditions, and with them the start of the plan, later by `by`.
+    ///
+    /// The timelines must not contain any operations.
+    pub fn shift_initial_conditions(&mut self, by: Duration) {
+        let from = epoch_to_duration(self.start);
+        for timeline in self.map.values_mut() {
+            timeline.get_mut().shift_initial_condition(from, by);
+        }
+        self.start += by;
+    }
+
+    pub fn set_external_inputs(&mut self, inputs: &'o ExternalInputs) {
+        self.external_readers.lock().0 = inputs.generation();
+        self.external_inputs = Some(inputs);
+    }
+
+    /// The current version of an external input, or `None` if it has none.
+    pub fn external_version(&self, name: &str) -> Option<u64> {
+        self.external_inputs.and_then(|inputs| inputs.get(name))
+    }
+
+    pub fn register_external_reader(&self, reader: &'o dyn RefreshExternal) {
+        self.external_readers.lock().1.push(reader);
+    }
+
+    /// Discards the readers of external inputs whose versions have changed, so that the
+    /// operations reading them run again.
+    pub(crate) fn refresh_external_readers(&self) {
+        let Some(inputs) = self.external_inputs else {
+            return;
+        };
+        let mut readers = self.external_readers.lock();
+        let generation = inputs.generation();
+        if readers.0 != generation {
+            readers.0 = generation;
+            readers.1.retain(|reader| reader.refresh(inputs));
+        }
+    }
+
+    /// Starts recording each operation's outputs with [Timelines::record_execution].
+    pub fn record_executions(&mut self) {
+        self.execution_log = Some(Mutex::new(vec![]));
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.execution_log.is_some()
+    }
+
+    /// Records that an operation at `time` wrote the encoded `outputs`.
+    pub fn record_execution(&self, time: Time, outputs: &[(&'static str, Vec<u8>)]) {
+        let Some(log) = &self.execution_log else {
+            return;
+        };
+        let mut line = time.to_string();
+        for (label, bytes) in outputs {
+            line.push(' ');
+            line.push_str(label);
+            line.push('=');
+            for byte in bytes {
+                line.push_str(&format!("{byte:02x}"));
+            }
+        }
+        log.lock().push(line);
+    }
+
+    /// The recorded lines, or `None` if executions aren't being recorded.
+    pub(crate) fn execution_log(&self) -> Option<Vec<String>> {
+        self.execution_log.as_ref().map(|log| log.lock().clone())
+    }
+
+    /// Records that an operation writing `R` was requested.
+    pub fn touch<R: Resource>(&self) {
+        if !self.touched.contains(&R::ID) {
+            self.touched.insert(R::ID);
+        }
+    }
+
+    /// Forgets the resources touched by the previous simulation.
+    pub(crate) fn clear_touched(&self) {
+        self.touched.clear();
+    }
+
+    /// IDs of the resources touched since [Timelines::clear_touched].
+    pub(crate) fn touched(&self) -> Vec<u64> {
+        self.touched.iter().map(|id| *id).collect()
+    }
+
+    /// Stores a value written to a [no-cache][Resource::NO_CACHE] resource by the operation at
+    /// `op`, in place of [History::insert][crate::internal::history::History::insert].
+    ///
+    /// Only the operation's latest value is kept; the value from its previous run is dropped.
+    pub fn insert_uncached<R: Resource>(
+        &self,
+        op: usize,
+        value: R::Data,
+        written: Time,
+    ) -> <R::Data as Data<'o>>::Read {
+        let value = Box::new(value);
+        // The box is only dropped when the same operation runs again, which replaces the output
+        // that reads from it, or when the operation leaves the plan.
+        let stored = unsafe { &*(&*value as *const R::Data) };
+        let mut uncached = self.uncached.lock();
+        let values = uncached.entry(op).or_default();
+        match values.iter_mut().find(|(id, _)| *id == R::ID) {
+            Some((_, previous)) => *previous = value,
+            None => values.push((R::ID, value)),
+        }
+        stored.to_read(written)
+    }
+
+    /// The number of values stored for a [no-cache][Resource::NO_CACHE] resource.
+    pub fn uncached_count<R: Resource>(&self) -> usize {
+        self.uncached
+            .lock()
+            .values()
+            .flatten()
+            .filter(|(id, _)| *id == R::ID)
+            .count()
+    }
+
+    /// The execution times of the operations run in this plan.
+    #[cfg(feature = "profiling")]
+    pub fn trace(&self) -> &crate::internal::profiling::Trace {
+        &self.trace
+    }
+
+    /// Sets whether clusters of ungrounded upstreams share their grounding requests.
+    ///
+    /// See [GroundingBatch].
+    pub fn set_batched_grounding(&mut self, enabled: bool) {
+        self.batched_grounding = enabled;
+    }
+
+    pub fn set_trace_verbosity(&mut self, verbosity: TraceVerbosity) {
+        self.trace_verbosity = verbosity;
     }
 
     pub fn init_for_resource<R: Resource>(
@@ -72,16 +372,84 @@ impl<'o> Timelines<'o> {
         self.map.contains_key(&R::ID)
     }
 
+    /// Adds a timeline for a [local][Resource::LOCAL] resource, private to the activity whose
+    /// operations start at the order `scope`.
+    ///
+    /// The activity's orders must already be registered with [Timelines::set_activity_end].
+    pub(crate) fn init_local<R: Resource>(
+        &mut self,
+        scope: u64,
+        time: Duration,
+        op: InitialConditionOp<'o, R>,
+    ) -> anyhow::Result<()> {
+        if !R::LOCAL {
+            bail!("{} is not a local resource", R::LABEL);
+        }
+        let id = local_timeline_id(R::ID, scope);
+        if self.map.contains_key(&id) {
+            bail!("local resource {} was declared more than once", R::LABEL);
+        }
+        self.map.insert(
+            id,
+            RwLock::new(Box::new(Timeline::init(time, self.herd.get().alloc(op)))),
+        );
+        self.locals.entry(scope).or_default().push(id);
+        Ok(())
+    }
+
+    /// Removes the timelines of the local resources of the activity with the given orders.
+    pub(crate) fn remove_locals(&mut self, orders: Range<u64>) {
+        if orders.is_empty() {
+            return;
+        }
+        for id in self.locals.remove(&orders.start).unwrap_or_default() {
+            self.map.remove(&id);
+        }
+    }
+
+    /// The key of the timeline that an operation with the given order uses for `R`.
+    ///
+    /// Local resources have a timeline for each activity, found by the operation's order like
+    /// [Timelines::activity_end], so they can only be used by statically placed operations.
+    fn timeline_id<R: Resource>(&self, order: u64) -> u64 {
+        if !R::LOCAL {
+            return R::ID;
+        }
+        match self.activity_ends.range(..=order).next_back() {
+            Some((scope, (last, ..))) if order < *last => local_timeline_id(R::ID, *scope),
+            _ => panic!(
+                "local resource {} can only be used by the operations of an activity",
+                R::LABEL
+            ),
+        }
+    }
+
+    /// Whether [Timelines::find_upstream] has an upstream to return for `R` at `time`,
+    /// instead of panicking.
+    ///
+    /// There isn't one if `R` isn't in the model, or if `time` is before the initial conditions.
+    pub fn has_upstream<R: Resource>(&self, time: DenseTime) -> bool {
+        self.contains_resource::<R>() && self.inner_timeline::<R>(R::ID).has_grounded_before(time)
+    }
+
     pub fn find_upstream<R: Resource>(&self, time: DenseTime) -> &'o dyn Upstream<'o, R> {
-        let mut inner = self.inner_timeline::<R>();
+        if let Some(upstream) = R::custom_upstream(self, time) {
+            return upstream;
+        }
+        let id = self.timeline_id::<R>(time.order);
+        let mut inner = self.inner_timeline::<R>(id);
         if inner.should_flush() {
             drop(inner);
-            let mut inner_mut = self.inner_timeline_mut::<R>();
+            let mut inner_mut = self.inner_timeline_mut::<R>(id);
             inner_mut.flush();
             drop(inner_mut);
-            inner = self.inner_timeline();
+            inner = self.inner_timeline(id);
+        }
+        if self.batched_grounding {
+            inner.last_before_batched(time, self.herd.get())
+        } else {
+            inner.last_before(time, self.herd.get())
         }
-        inner.last_before(time, self.herd.get())
     }
 
     pub fn insert<R: Resource>(
@@ -90,13 +458,15 @@ impl<'o> Timelines<'o> {
         op: &'o dyn Upstream<'o, R>,
         is_daemon: bool,
     ) -> UpstreamVec<'o, R> {
+        diagnostics::timeline_insert(self.trace_verbosity, R::LABEL, &placement);
+        let id = self.timeline_id::<R>(placement.get_order());
         let (result, times) = match placement {
             Placement::Static(time) => (
-                self.inner_timeline_mut().insert_grounded(time, op),
+                self.inner_timeline_mut(id).insert_grounded(time, op),
                 (time, None),
             ),
             Placement::Dynamic { min, max, .. } => (
-                self.inner_timeline_mut().insert_ungrounded(min, max, op),
+                self.inner_timeline_mut(id).insert_ungrounded(min, max, op),
                 (min, Some(max)),
             ),
         };
@@ -105,11 +475,18 @@ impl<'o> Timelines<'o> {
                 if trigger.triggers.contains(&R::ID) {
                     let mut record = trigger.record.lock();
                     if !record.contains_key(&times) {
-                        let nodes = (trigger.trigger_fn)(placement, self.herd.get());
-                        for node in nodes {
-                            record.insert(times, node);
-                            node.insert_self(self, true)
-                                .expect("Failed to insert daemon trigger");
+                        match (trigger.trigger_fn)(placement, self.herd.get()) {
+                            Ok(nodes) => {
+                                for node in nodes {
+                                    record.insert(times, node);
+                                    self.track(node);
+                                    node.insert_self(self, true)
+                                        .expect("Failed to insert daemon trigger");
+                                }
+                            }
+                            Err(e) => {
+                                self.daemon_failure.lock().get_or_insert(e);
+                            }
                         }
                     }
                 }
@@ -118,14 +495,60 @@ impl<'o> Timelines<'o> {
         result
     }
 
+    /// Tells an upstream that an operation was inserted after it, at `time_of_change`,
+    /// or saves the notification for the end of the batch if one is in progress.
+    pub fn notify<R: Resource>(
+        &self,
+        upstream: &'o dyn Upstream<'o, R>,
+        time_of_change: DenseTime,
+    ) {
+        let Some(deferred) = &self.deferred_notifications else {
+            upstream.notify_downstreams(time_of_change);
+            return;
+        };
+        let address = upstream as *const _ as *const u8 as usize;
+        deferred
+            .lock()
+            .entry(address)
+            .and_modify(|(time, _)| *time = (*time).min(time_of_change))
+            .or_insert_with(|| {
+                (
+                    time_of_change,
+                    Box::new(move |time| upstream.notify_downstreams(time)),
+                )
+            });
+    }
+
+    /// Saves notifications until [Timelines::flush_notifications], so that each upstream is
+    /// notified once per batch of insertions, at the earliest change after it.
+    pub(crate) fn defer_notifications(&mut self) {
+        self.deferred_notifications = Some(Mutex::new(HashMap::new()));
+    }
+
+    /// Sends the notifications saved since [Timelines::defer_notifications], and stops deferring.
+    pub(crate) fn flush_notifications(&mut self) {
+        if let Some(deferred) = self.deferred_notifications.take() {
+            for (time, notify) in deferred.into_inner().into_values() {
+                notify(time);
+            }
+        }
+    }
+
+    /// Takes the error of a reactive daemon whose activities failed while operations were
+    /// being inserted. The operation that triggered it is still inserted, and should be removed.
+    pub(crate) fn take_daemon_failure(&self) -> Option<anyhow::Error> {
+        self.daemon_failure.lock().take()
+    }
+
     pub fn remove<R: Resource + 'o>(&self, placement: Placement<'o>, is_daemon: bool) -> bool {
+        let id = self.timeline_id::<R>(placement.get_order());
         let (result, times) = match placement {
             Placement::Static(time) => (
-                self.inner_timeline_mut::<R>().remove_grounded(time),
+                self.inner_timeline_mut::<R>(id).remove_grounded(time),
                 (time, None),
             ),
             Placement::Dynamic { min, max, .. } => (
-                self.inner_timeline_mut::<R>().remove_ungrounded(min, max),
+                self.inner_timeline_mut::<R>(id).remove_ungrounded(min, max),
                 (min, Some(max)),
             ),
         };
@@ -135,6 +558,7 @@ impl<'o> Timelines<'o> {
                     let mut record = trigger.record.lock();
                     if record.contains_key(&times) {
                         let node = record.remove(&times).unwrap();
+                        self.untrack(node as *const _ as *const u8 as usize);
                         node.remove_self(self, true)
                             .expect("Failed to remove daemon trigger");
                     }
@@ -148,43 +572,69 @@ impl<'o> Timelines<'o> {
         &self,
         bounds: impl RangeBounds<DenseTime> + Clone,
     ) -> Vec<MaybeGrounded<'o, R>> {
-        let mut inner = self.inner_timeline::<R>();
+        let mut inner = self.inner_timeline::<R>(R::ID);
         if inner.should_flush() {
             drop(inner);
-            let mut inner_mut = self.inner_timeline_mut::<R>();
+            let mut inner_mut = self.inner_timeline_mut::<R>(R::ID);
             inner_mut.flush();
             drop(inner_mut);
-            inner = self.inner_timeline();
+            inner = self.inner_timeline(R::ID);
         }
         inner.range(bounds)
     }
 
-    fn inner_timeline<R: Resource>(&self) -> MappedRwLockReadGuard<Timeline<'o, R>> {
+    /// See [Timeline::range_inclusive_next].
+    pub(crate) fn range_inclusive_next<R: Resource>(
+        &self,
+        bounds: impl RangeBounds<DenseTime> + Clone,
+    ) -> Vec<MaybeGrounded<'o, R>> {
+        let mut inner = self.inner_timeline::<R>(R::ID);
+        if inner.should_flush() {
+            drop(inner);
+            let mut inner_mut = self.inner_timeline_mut::<R>(R::ID);
+            inner_mut.flush();
+            drop(inner_mut);
+            inner = self.inner_timeline(R::ID);
+        }
+        inner.range_inclusive_next(bounds)
+    }
+
+    /// See [Timeline::range_inclusive_previous].
+    pub(crate) fn range_inclusive_previous<R: Resource>(
+        &self,
+        bounds: impl RangeBounds<DenseTime> + Clone,
+    ) -> Vec<MaybeGrounded<'o, R>> {
+        let mut inner = self.inner_timeline::<R>(R::ID);
+        if inner.should_flush() {
+            drop(inner);
+            let mut inner_mut = self.inner_timeline_mut::<R>(R::ID);
+            inner_mut.flush();
+            drop(inner_mut);
+            inner = self.inner_timeline(R::ID);
+        }
+        inner.range_inclusive_previous(bounds)
+    }
+
+    pub(crate) fn alloc<T>(&self, value: T) -> &'o mut T {
+        self.herd.get().alloc(value)
+    }
+
+    fn inner_timeline<R: Resource>(&self, id: u64) -> MappedRwLockReadGuard<Timeline<'o, R>> {
         let reference = self
             .map
-            .get(&R::ID)
-            .unwrap_or_else(|| {
-                panic!(
-                    "Could not find resource {}. Is it included in the model?",
-                    R::LABEL
-                )
-            })
+            .get(&id)
+            .unwrap_or_else(|| missing_timeline::<R>())
             .read();
         RwLockReadGuard::map(reference, |r| unsafe {
             &*(r.as_ref() as *const dyn ErasedTimeline as *const Timeline<'o, R>)
         })
     }
 
-    fn inner_timeline_mut<R: Resource>(&self) -> MappedRwLockWriteGuard<Timeline<'o, R>> {
+    fn inner_timeline_mut<R: Resource>(&self, id: u64) -> MappedRwLockWriteGuard<Timeline<'o, R>> {
         let reference = self
             .map
-            .get(&R::ID)
-            .unwrap_or_else(|| {
-                panic!(
-                    "Could not find resource {}. Is it included in the model?",
-                    R::LABEL
-                )
-            })
+            .get(&id)
+            .unwrap_or_else(|| missing_timeline::<R>())
             .write();
         RwLockWriteGuard::map(reference, |r| unsafe {
             &mut *(r.as_mut() as *mut dyn ErasedTimeline as *mut Timeline<'o, R>)
@@ -194,6 +644,411 @@ impl<'o> Timelines<'o> {
     pub fn add_reactive_daemon(&mut self, id: u64, trigger: ReactiveDaemon<'o>) {
         self.reactive_daemons.insert(id, trigger);
     }
+
+    /// Adds a reactive daemon under the first unused ID, for daemons that aren't declared in a model.
+    pub(crate) fn add_runtime_reactive_daemon(&mut self, trigger: ReactiveDaemon<'o>) {
+        let id = (0..)
+            .find(|id| !self.reactive_daemons.contains_key(id))
+            .unwrap();
+        self.add_reactive_daemon(id, trigger);
+    }
+
+    /// Adds a recurring daemon, unless one with the same ID was already added by another
+    /// model that includes the same submodel.
+    pub fn add_recurring_daemon(
+        &mut self,
+        id: u64,
+        daemon: RecurringDaemon<'o>,
+    ) -> anyhow::Result<()> {
+        if daemon.period <= Duration::ZERO {
+            bail!(
+                "recurring daemons must have a positive period, but one has {}",
+                daemon.period
+            );
+        }
+        if !self.recurring_daemons.iter().any(|(i, _)| *i == id) {
+            self.recurring_daemons.push((id, daemon));
+        }
+        Ok(())
+    }
+
+    /// Runs the ticks of the recurring daemons that haven't run yet and that operations within
+    /// `bounds` can read, and the first tick after them.
+    ///
+    /// Operations read the latest write before them, so a daemon whose ticks don't depend on
+    /// each other only runs its ticks within `bounds` and within each activity, starting from
+    /// the tick before each. A daemon must tick from the start of the plan up to the end of
+    /// `bounds` or of the last activity, whichever is later, if its ticks read what they write,
+    /// if its resources are read through pseudo-resources like `ref(OFFSET): resource` that can
+    /// reach any earlier tick, or if a daemon that ticks from the start reads its resources.
+    /// Every tick of a daemon must read and write the same resources as its first tick.
+    ///
+    /// The tick after the end lets reads that look ahead, like `ref next_change`, see it.
+    /// Must not be called during simulation.
+    pub(crate) fn run_recurring_daemons(
+        &self,
+        bounds: &impl RangeBounds<DenseTime>,
+    ) -> anyhow::Result<()> {
+        if self.recurring_daemons.is_empty() {
+            return Ok(());
+        }
+        let start = epoch_to_duration(self.start);
+        let last_activity = self.activity_ends.values().map(|(.., end)| *end).max();
+        let until = match bounds.end_bound() {
+            Bound::Included(t) | Bound::Excluded(t) => Some(t.when),
+            Bound::Unbounded => None,
+        }
+        .max(last_activity)
+        .unwrap_or(start);
+        let (from, to) = (
+            match bounds.start_bound() {
+                Bound::Included(t) | Bound::Excluded(t) => t.when,
+                Bound::Unbounded => start,
+            },
+            match bounds.end_bound() {
+                Bound::Included(t) | Bound::Excluded(t) => t.when,
+                Bound::Unbounded => until,
+            },
+        );
+
+        for (index, (_, daemon)) in self.recurring_daemons.iter().enumerate() {
+            self.run_tick(index, daemon, &mut daemon.ticks.lock(), 0)?;
+        }
+        let from_start = self.daemons_from_start();
+        // A tick can read another daemon's tick from up to one period before it, so the ticks
+        // before a range are run far enough back to cover chains of reads between daemons.
+        let lead = self
+            .recurring_daemons
+            .iter()
+            .zip(&from_start)
+            .filter(|(_, from_start)| !**from_start)
+            .fold(Duration::ZERO, |lead, ((_, daemon), _)| {
+                lead + daemon.period
+            });
+
+        for (index, (_, daemon)) in self.recurring_daemons.iter().enumerate() {
+            let mut ticks = daemon.ticks.lock();
+            if from_start[index] {
+                self.run_ticks(index, daemon, &mut ticks, start, until)?;
+            } else {
+                self.run_ticks(index, daemon, &mut ticks, from - lead, to)?;
+                for (_, activity_start, activity_end) in self.activity_ends.values() {
+                    self.run_ticks(
+                        index,
+                        daemon,
+                        &mut ticks,
+                        *activity_start - lead,
+                        *activity_end,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a daemon's ticks from the last one at or before `from` to the first one after `to`.
+    fn run_ticks(
+        &self,
+        index: usize,
+        daemon: &RecurringDaemon<'o>,
+        ticks: &mut Ticks<'o>,
+        from: Duration,
+        to: Duration,
+    ) -> anyhow::Result<()> {
+        let start = epoch_to_duration(self.start);
+        let tick_of = |time: Duration| -> u64 {
+            let periods = (time - start).total_nanoseconds() / daemon.period.total_nanoseconds();
+            periods.max(0) as u64
+        };
+        let (first, last) = (tick_of(from), tick_of(to) + 1);
+        for tick in first.max(ticks.contiguous)..=last {
+            self.run_tick(index, daemon, ticks, tick)?;
+        }
+        if first <= ticks.contiguous {
+            ticks.contiguous = ticks.contiguous.max(last + 1);
+        }
+        Ok(())
+    }
+
+    /// Inserts the operations of a daemon's tick, unless it has already run.
+    fn run_tick(
+        &self,
+        index: usize,
+        daemon: &RecurringDaemon<'o>,
+        ticks: &mut Ticks<'o>,
+        tick: u64,
+    ) -> anyhow::Result<()> {
+        if ticks.operations.contains_key(&tick) {
+            return Ok(());
+        }
+        let when = epoch_to_duration(self.start)
+            + Duration::from_total_nanoseconds(daemon.period.total_nanoseconds() * tick as i128);
+        let placement = Placement::Static(DenseTime {
+            when,
+            order: RECURRING_ORDER + index as u64 * RECURRING_STRIDE,
+        });
+        let operations = (daemon.tick_fn)(placement, self.herd.get());
+        let footprint = self.footprint(&operations);
+        match &ticks.footprint {
+            None => ticks.footprint = Some(footprint),
+            Some(first) if *first != footprint => bail!(
+                "the recurring daemon tick at {} reads or writes different resources than the first tick",
+                duration_to_epoch(when)
+            ),
+            Some(_) => {}
+        }
+        for (inserted, node) in operations.iter().enumerate() {
+            if let Err(e) = node.insert_self(self, true) {
+                for node in &operations[..inserted] {
+                    node.remove_self(self, true)?;
+                }
+                return Err(e.context(format!(
+                    "could not insert the recurring daemon tick at {}",
+                    duration_to_epoch(when)
+                )));
+            }
+        }
+        for op in &operations {
+            self.track(*op);
+        }
+        ticks.operations.insert(tick, operations);
+        Ok(())
+    }
+
+    /// The resources that a tick's operations read and write.
+    fn footprint(&self, operations: &[&'o dyn Node<'o>]) -> Footprint {
+        let mut footprint = Footprint {
+            reads: HashSet::new(),
+            indirect_reads: HashSet::new(),
+            writes: HashSet::new(),
+        };
+        for info in operations.iter().map(|node| node.info()) {
+            for read in info.reads {
+                footprint.reads.insert(read.label);
+                if !self.map.contains_key(&read.id.id()) {
+                    footprint.indirect_reads.insert(read.label);
+                }
+            }
+            footprint
+                .writes
+                .extend(info.writes.iter().map(|write| write.label));
+        }
+        footprint
+    }
+
+    /// Which recurring daemons must tick from the start of the plan, as described in
+    /// [Timelines::run_recurring_daemons]. Every daemon must have run its first tick.
+    fn daemons_from_start(&self) -> Vec<bool> {
+        let locks = self
+            .recurring_daemons
+            .iter()
+            .map(|(_, daemon)| daemon.ticks.lock())
+            .collect::<Vec<_>>();
+        let footprints = locks
+            .iter()
+            .map(|ticks| ticks.footprint.as_ref().unwrap())
+            .collect::<Vec<_>>();
+        let mut result = footprints
+            .iter()
+            .map(|footprint| {
+                !footprint.reads.is_disjoint(&footprint.writes)
+                    || footprint.writes.iter().any(|write| {
+                        self.indirect_reads.contains_key(write)
+                            || footprints
+                                .iter()
+                                .any(|other| other.indirect_reads.contains(write))
+                    })
+            })
+            .collect::<Vec<_>>();
+        loop {
+            let mut changed = false;
+            for (reader, footprint) in footprints.iter().enumerate() {
+                if !result[reader] {
+                    continue;
+                }
+                for (writer, other) in footprints.iter().enumerate() {
+                    if !result[writer] && !footprint.reads.is_disjoint(&other.writes) {
+                        result[writer] = true;
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                return result;
+            }
+        }
+    }
+
+    /// Removes the operations of all recurring daemon ticks, so that they run again from the
+    /// start of the plan when it is next simulated.
+    pub(crate) fn clear_recurring_daemons(&self) -> anyhow::Result<()> {
+        for (_, daemon) in &self.recurring_daemons {
+            let mut ticks = daemon.ticks.lock();
+            for node in std::mem::take(&mut ticks.operations)
+                .into_values()
+                .flatten()
+            {
+                self.untrack(node as *const _ as *const u8 as usize);
+                node.remove_self(self, true)?;
+            }
+            ticks.contiguous = 0;
+        }
+        Ok(())
+    }
+
+    /// The addresses of all operations in each resource's timeline, excluding initial
+    /// conditions, keyed by resource ID and labelled with the resource.
+    pub(crate) fn operation_addresses(&self) -> HashMap<u64, (&'static str, HashSet<usize>)> {
+        let mut result = HashMap::<u64, (&'static str, HashSet<usize>)>::new();
+        // The timelines of local resources are merged under the resource's ID.
+        for timeline in self.map.values() {
+            let timeline = timeline.read();
+            result
+                .entry(timeline.id())
+                .or_insert_with(|| (timeline.label(), HashSet::new()))
+                .1
+                .extend(timeline.operation_addresses());
+        }
+        result
+    }
+
+    /// Leaves an operation's write to a resource out of the timeline, because it is overwritten
+    /// at the same time before anything can read it.
+    ///
+    /// Must be called before the operation is inserted.
+    pub(crate) fn coalesce_write(&mut self, op: usize, resource: u64) {
+        self.coalesced_writes.insert((op, resource));
+    }
+
+    /// Forgets the coalesced writes of an operation, after it is removed.
+    pub(crate) fn forget_coalesced_writes(&mut self, op: usize) {
+        self.coalesced_writes.retain(|(address, _)| *address != op);
+    }
+
+    /// Whether the operation at `op`'s write to `R` was left out of the timeline.
+    pub fn is_coalesced<R: Resource>(&self, op: usize) -> bool {
+        self.is_coalesced_id(op, R::ID)
+    }
+
+    pub(crate) fn is_coalesced_id(&self, op: usize, resource: u64) -> bool {
+        !self.coalesced_writes.is_empty() && self.coalesced_writes.contains(&(op, resource))
+    }
+
+    /// Records the activity that owns an operation.
+    pub(crate) fn set_owner(&mut self, op: &'o dyn Node<'o>, activity: ActivityId) {
+        self.owners
+            .insert(op as *const _ as *const u8 as usize, activity);
+        self.track(op);
+    }
+
+    pub(crate) fn remove_owner(&mut self, op: usize) {
+        self.owners.remove(&op);
+        self.untrack(op);
+    }
+
+    /// The activity that owns the operation at `op`, if it belongs to one.
+    pub fn owner(&self, op: usize) -> Option<ActivityId> {
+        self.owners.get(&op).copied()
+    }
+
+    /// Records that the activity whose operations were given the `orders` runs from `start`
+    /// to `end`.
+    ///
+    /// Activities without operations aren't recorded, since their empty range would share
+    /// its start with the next activity's.
+    pub(crate) fn set_activity_end(&mut self, orders: Range<u64>, start: Duration, end: Duration) {
+        if !orders.is_empty() {
+            self.activity_ends
+                .insert(orders.start, (orders.end, start, end));
+        }
+    }
+
+    /// Counts the reads of an activity's operations through pseudo-resources, after they are
+    /// inserted, or forgets them after they are removed.
+    pub(crate) fn record_indirect_reads(
+        &mut self,
+        operations: &[&'o dyn Node<'o>],
+        inserted: bool,
+    ) {
+        for read in operations.iter().flat_map(|op| op.info().reads) {
+            // Pseudo-resources don't have timelines.
+            if self.map.contains_key(&read.id.id()) {
+                continue;
+            }
+            if inserted {
+                *self.indirect_reads.entry(read.label).or_default() += 1;
+            } else if let Some(count) = self.indirect_reads.get_mut(read.label) {
+                *count -= 1;
+                if *count == 0 {
+                    self.indirect_reads.remove(read.label);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn remove_activity_end(&mut self, orders: Range<u64>) {
+        if !orders.is_empty() {
+            self.activity_ends.remove(&orders.start);
+        }
+    }
+
+    /// The time after all operations of the activity that owns the operation with the
+    /// given order, or `None` if it doesn't belong to an activity.
+    pub fn activity_end(&self, order: u64) -> Option<DenseTime> {
+        let (_, (last, _, end)) = self.activity_ends.range(..=order).next_back()?;
+        (order < *last).then_some(DenseTime {
+            when: *end,
+            order: *last,
+        })
+    }
+
+    /// Whether any reactive daemon is triggered by writes to the resource.
+    pub(crate) fn has_reactive_trigger(&self, resource: u64) -> bool {
+        self.reactive_daemons
+            .values()
+            .any(|d| d.triggers.contains(&resource))
+    }
+
+    /// All operations currently inserted by reactive and recurring daemons.
+    pub(crate) fn daemon_operations(&self) -> Vec<&'o dyn Node<'o>> {
+        self.reactive_daemons
+            .values()
+            .flat_map(|d| d.record.lock().values().copied().collect::<Vec<_>>())
+            .chain(self.recurring_daemons.iter().flat_map(|(_, d)| {
+                d.ticks
+                    .lock()
+                    .operations
+                    .values()
+                    .flatten()
+                    .copied()
+                    .collect::<Vec<_>>()
+            }))
+            .collect()
+    }
+}
+
+/// The key of the timeline of a local resource, for the activity whose operations start
+/// at the order `scope`.
+fn local_timeline_id(resource: u64, scope: u64) -> u64 {
+    let mut hasher = PeregrineDefaultHashBuilder::default();
+    hasher.write_u64(resource);
+    hasher.write_u64(scope);
+    hasher.finish()
+}
+
+#[cold]
+fn missing_timeline<R: Resource>() -> ! {
+    if R::LOCAL {
+        panic!(
+            "Could not find local resource {}. Did the activity declare it with Ops::local_resource?",
+            R::LABEL
+        )
+    } else {
+        panic!(
+            "Could not find resource {}. Is it included in the model?",
+            R::LABEL
+        )
+    }
 }
 
 // All Epochs/Times are converted to TAI durations because the Ord implementation
@@ -300,16 +1155,31 @@ impl<'o, R: Resource> PossibleUpstreams<'o, R> {
         self,
         time: DenseTime,
         bump: Member<'o>,
+        batches: Option<&GroundingBatches<'o, R>>,
     ) -> &'o dyn Upstream<'o, R> {
         if self.ungrounded.is_empty() {
             self.grounded.expect("Set of possible upstreams is empty").1
         } else if self.grounded.is_none() && self.ungrounded.len() == 1 {
             self.ungrounded[0]
         } else {
+            let batch = batches
+                .filter(|_| self.ungrounded.len() > 1)
+                .map(|batches| {
+                    let key = self
+                        .ungrounded
+                        .iter()
+                        .map(|u| *u as *const _ as *const u8 as usize)
+                        .collect();
+                    *batches
+                        .lock()
+                        .entry(key)
+                        .or_insert_with(|| bump.alloc(GroundingBatch::new(self.ungrounded.clone())))
+                });
             bump.alloc(UngroundedUpstreamResolver::new(
                 time,
                 self.grounded,
                 self.ungrounded,
+                batch,
             ))
         }
     }
@@ -322,8 +1192,14 @@ pub struct Timeline<'o, R: Resource> {
     grounded_buffer: Slab<(DenseTime, &'o dyn Upstream<'o, R>)>,
     /// Map of start durations to active ungrounded ranges
     ungrounded_map: BTreeMap<DenseTime, ActiveUngroundedRanges<'o, R>>,
+    /// Shared grounding requests for clusters of ungrounded upstreams, keyed by the
+    /// addresses of the cluster members. Cleared whenever the timeline changes.
+    grounding_batches: GroundingBatches<'o, R>,
+    initial_condition: &'o dyn Upstream<'o, R>,
 }
 
+type GroundingBatches<'o, R> = Mutex<HashMap<SmallVec<usize, 2>, &'o GroundingBatch<'o, R>>>;
+
 impl<'o, R: Resource> Timeline<'o, R> {
     pub fn init(time: Duration, initial_condition: &'o dyn Upstream<'o, R>) -> Timeline<'o, R> {
         let mut map = MapM::new();
@@ -332,6 +1208,8 @@ impl<'o, R: Resource> Timeline<'o, R> {
             grounded_map: map,
             grounded_buffer: Slab::new(),
             ungrounded_map: BTreeMap::new(),
+            grounding_batches: Mutex::new(HashMap::new()),
+            initial_condition,
         }
     }
 
@@ -379,7 +1257,22 @@ impl<'o, R: Resource> Timeline<'o, R> {
 
     pub fn last_before(&self, eval_time: DenseTime, bump: Member<'o>) -> &'o dyn Upstream<'o, R> {
         let possible = self.search_possible_upstreams(eval_time);
-        possible.into_single_upstream(eval_time, bump)
+        possible.into_single_upstream(eval_time, bump, None)
+    }
+
+    /// Like [Timeline::last_before], but clusters of ungrounded upstreams share a [GroundingBatch].
+    pub fn last_before_batched(
+        &self,
+        eval_time: DenseTime,
+        bump: Member<'o>,
+    ) -> &'o dyn Upstream<'o, R> {
+        let possible = self.search_possible_upstreams(eval_time);
+        possible.into_single_upstream(eval_time, bump, Some(&self.grounding_batches))
+    }
+
+    fn has_grounded_before(&self, time: DenseTime) -> bool {
+        self.grounded_map.range(..time).next_back().is_some()
+            || self.grounded_buffer.iter().any(|(_, (t, _))| *t < time)
     }
 
     pub fn insert_grounded(
@@ -387,12 +1280,14 @@ impl<'o, R: Resource> Timeline<'o, R> {
         time: DenseTime,
         value: &'o dyn Upstream<'o, R>,
     ) -> UpstreamVec<'o, R> {
+        self.grounding_batches.get_mut().clear();
         self.grounded_buffer.insert((time, value));
         self.search_possible_upstreams(time).into_upstream_vec()
     }
 
     pub fn remove_grounded(&mut self, time: DenseTime) -> bool {
         self.flush();
+        self.grounding_batches.get_mut().clear();
         self.grounded_map.remove_cow(&time).is_some()
     }
 
@@ -402,6 +1297,7 @@ impl<'o, R: Resource> Timeline<'o, R> {
         max: DenseTime,
         value: &'o dyn Upstream<'o, R>,
     ) -> UpstreamVec<'o, R> {
+        self.grounding_batches.get_mut().clear();
         let mut result = UpstreamVec::new();
 
         // Find the previous entry before the insertion start time to get ongoing upstreams
@@ -467,6 +1363,8 @@ impl<'o, R: Resource> Timeline<'o, R> {
     }
 
     pub fn remove_ungrounded(&mut self, min: DenseTime, max: DenseTime) -> bool {
+        self.grounding_batches.get_mut().clear();
+
         // Remove the entry at min if it exists
         let entry_removed = self.ungrounded_map.remove(&min).is_some();
 
@@ -533,6 +1431,49 @@ impl<'o, R: Resource> Timeline<'o, R> {
         );
         result
     }
+
+    /// Like [Timeline::range], but also includes the first grounded operation after
+    /// the end of the range, so that the final segment of the range can be drawn in full.
+    pub fn range_inclusive_next(
+        &self,
+        range: impl RangeBounds<DenseTime> + Clone,
+    ) -> Vec<MaybeGrounded<'o, R>> {
+        let mut result = self.range(range.clone());
+        let after = match range.end_bound() {
+            Bound::Included(end) => self
+                .grounded_map
+                .range((Bound::Excluded(*end), Bound::Unbounded))
+                .next(),
+            Bound::Excluded(end) => self.grounded_map.range(*end..).next(),
+            Bound::Unbounded => None,
+        };
+        if let Some((t, upstream)) = after {
+            result.push(MaybeGrounded::Grounded(*t, *upstream));
+        }
+        result
+    }
+
+    /// Like [Timeline::range], but always includes the last grounded operation before
+    /// the start of the range, so that the value at the start of the range is known.
+    pub fn range_inclusive_previous(
+        &self,
+        range: impl RangeBounds<DenseTime> + Clone,
+    ) -> Vec<MaybeGrounded<'o, R>> {
+        let mut result = self.range(range.clone());
+        let before = match range.start_bound() {
+            Bound::Included(start) => self.grounded_map.range(..*start).next_back(),
+            Bound::Excluded(start) => self.grounded_map.range(..=*start).next_back(),
+            Bound::Unbounded => None,
+        };
+        if let Some((t, upstream)) = before
+            && !result
+                .iter()
+                .any(|m| matches!(m, MaybeGrounded::Grounded(other, _) if other == t))
+        {
+            result.insert(0, MaybeGrounded::Grounded(*t, *upstream));
+        }
+        result
+    }
 }
 
 impl<R: Resource> ErasedTimeline for Timeline<'_, R> {
@@ -544,11 +1485,47 @@ impl<R: Resource> ErasedTimeline for Timeline<'_, R> {
             self.grounded_map = self.grounded_map.insert_many(self.grounded_buffer.drain());
         }
     }
+    fn label(&self) -> &'static str {
+        R::LABEL
+    }
+    fn operation_addresses(&self) -> HashSet<usize> {
+        let address = |u: &dyn Upstream<'_, R>| u as *const _ as *const u8 as usize;
+        let mut result = self
+            .grounded_map
+            .range(..)
+            .map(|(_, u)| address(*u))
+            .chain(self.grounded_buffer.iter().map(|(_, (_, u))| address(*u)))
+            .chain(
+                self.ungrounded_map
+                    .values()
+                    .flat_map(|entry| entry.0.values().map(|u| address(*u))),
+            )
+            .collect::<HashSet<_>>();
+        result.remove(&address(self.initial_condition));
+        result
+    }
+    fn shift_initial_condition(&mut self, from: Duration, by: Duration) {
+        self.flush();
+        debug_assert!(
+            self.operation_addresses().is_empty(),
+            "only the initial condition can be shifted in place"
+        );
+        self.grounding_batches.get_mut().clear();
+        self.grounded_map.remove_cow(&DenseTime::first_at(from));
+        self.initial_condition.shift_initial_condition(by);
+        self.grounded_map
+            .insert_cow(DenseTime::first_at(from + by), self.initial_condition);
+    }
 }
 
 trait ErasedTimeline: ErasedResource {
     fn should_flush(&self) -> bool;
     fn flush(&mut self);
+    fn label(&self) -> &'static str;
+    /// See [Timelines::operation_addresses].
+    fn operation_addresses(&self) -> HashSet<usize>;
+    /// See [Timelines::shift_initial_conditions].
+    fn shift_initial_condition(&mut self, from: Duration, by: Duration);
 }
 
 impl<R: Resource> ErasedResource for Timeline<'_, R> {
@@ -569,6 +1546,7 @@ mod tests {
     use super::*;
     use crate::internal::exec::ExecEnvironment;
     use crate::internal::operation::{Continuation, Downstream, Node, Upstream};
+    use crate::public::activity::OperationInfo;
     use bumpalo_herd::Herd;
     use hifitime::Duration;
     use once_cell::sync::Lazy;
@@ -602,6 +1580,10 @@ mod tests {
         fn remove_self(&self, _timelines: &Timelines<'o>, _is_daemon: bool) -> anyhow::Result<()> {
             Ok(())
         }
+        fn info(&self) -> OperationInfo {
+            unimplemented!()
+        }
+        fn clear_cache(&self) {}
     }
     impl<'o> Upstream<'o, dummy> for DummyUpstream {
         fn request<'s>(
@@ -672,11 +1654,18 @@ mod tests {
             history: &HISTORY,
             errors: &ERRORS,
             stack_counter: 0,
+            float_policy: Default::default(),
+            operation_timeout: None,
+            coincident_writes: Default::default(),
+            grounding_errors: Default::default(),
+            cache_audit: None,
+            trace: Default::default(),
+            interrupt: None,
         };
         rayon::scope(|scope| {
             up.request(Continuation::Root(tx), false, scope, &timelines, env);
         });
-        rx.recv().unwrap().unwrap()
+        rx.recv().unwrap().unwrap().1
     }
 
     #[test]
@@ -799,4 +1788,32 @@ mod tests {
         assert!(ids7.contains(&1));
         assert!(ids12.contains(&2));
     }
+    #[test]
+    fn test_range_inclusive_next() {
+        let herd = Herd::new();
+        let timeline = dummy_timeline!(
+            herd,
+            grounded(5.0, 1),
+            grounded(10.0, 2),
+            grounded(15.0, 3),
+            grounded(20.0, 4)
+        );
+        let range = DenseTime::first_at(Duration::from_seconds(7.0))
+            ..DenseTime::first_at(Duration::from_seconds(12.0));
+        fn grounded_ids<'o>(found: Vec<MaybeGrounded<'o, dummy>>, herd: &'o Herd) -> Vec<u32> {
+            found
+                .into_iter()
+                .map(|m| match m {
+                    MaybeGrounded::Grounded(_, up) => get_id(up, herd),
+                    MaybeGrounded::Ungrounded(_) => panic!("expected only grounded operations"),
+                })
+                .collect()
+        }
+
+        assert_eq!(grounded_ids(timeline.range(range.clone()), &herd), vec![2]);
+        assert_eq!(
+            grounded_ids(timeline.range_inclusive_next(range), &herd),
+            vec![2, 3]
+        );
+    }
 }
@@ -70,12 +70,54 @@
 //! be reused even though they are on a different branch. Then, when the branches are merged, a majority
 //! of the final plan has already been simulated. Only the areas that coupled `A` and `B` together need
 //! to be resimulated.
+//! Plans are branched with [Plan::branch] and merged back together with [Plan::merge].
+//! A plan serialized to JSON, activities and all, can be loaded back into a session with
+//! [Session::load_plan], where it finds whatever the session's history already holds for it.
 //!
 //! This approach's main drawback is memory usage. By indiscriminately storing all sim results without
 //! knowing if they will ever be reused, it can build up gigabytes of store after simulating on the
 //! order of tens of millions of operations. Since the keys in the storage are meaningless hashes,
-//! there is currently no good way to prune the history to reduce memory usage. This poses some technical
-//! problems for long-running venues, though I don't believe they are insurmountable.
+//! the history can't tell on its own which entries are still useful. Sessions built
+//! [with history pruning][Session::with_history_pruning] track which entries their plans can reach,
+//! and [Session::prune_history] removes the rest, optionally keeping the ones the most recently
+//! dropped plans reached. Removed values are only freed by [Session::release_pruned_history] once
+//! nothing borrows from the session.
+//!
+//! Serialized histories are keyed by resource name. To rename a resource without invalidating
+//! saved histories, keep its old name on disk with `#[serde_name = "old_name"]`.
+//!
+//! Resources with large values that are cheap to recompute, like a propagated ephemeris, can be
+//! kept out of the history entirely with `#[no_cache]`. Their writes are only held in memory by
+//! the plan, and the operations that write them always run.
+//!
+//! Physical resources can be declared with a unit, as in `battery: f64 [energy::joule] = 100.0;`.
+//! The resource then holds a [uom](https://crates.io/crates/uom) `Energy` stored as an `f64`,
+//! its default is given in joules, and its [unit][Resource::UNIT] is `"joule"`. Since operations
+//! see quantities instead of bare numbers, subtracting a power from an energy without multiplying
+//! it by a time is a compile error. This needs the `uom` feature, which is on by default.
+//!
+//! ```compile_fail
+//! # use peregrine::*;
+//! # use serde::{Serialize, Deserialize};
+//! model! {
+//!     Power {
+//!         battery: f64 [energy::joule] = 100.0;
+//!         heater: f64 [power::watt] = 5.0;
+//!     }
+//! }
+//!
+//! #[derive(Hash, Serialize, Deserialize)]
+//! struct Heat;
+//!
+//! # #[typetag::serde]
+//! impl Activity for Heat {
+//!     fn run(&self, mut ops: Ops) -> anyhow::Result<Duration> {
+//!         ops += op! { m: battery -= r: heater; }; // error: mismatched types
+//!         Ok(Duration::ZERO)
+//!     }
+//! }
+//! # fn main() {}
+//! ```
 //!
 //! ### Models, Submodels, and Encapsulation
 //!
@@ -93,6 +135,57 @@
 //!   developers will (hopefully) write models that are naturally more concurrent by virtue of separation
 //!   of concerns.
 //!
+//! If a parent model needs to observe a submodel's resource without being able to change it, it can
+//! expose a read-only accessor with `expose read <resource> from <SubModel>;`. This generates a new
+//! private resource (named `power_battery` below, or chosen with `as <name>`) that mirrors the
+//! submodel's resource, and that operations are not allowed to write to:
+//!
+//! ```compile_fail
+//! # use peregrine::*;
+//! # use serde::{Serialize, Deserialize};
+//! mod power {
+//!     peregrine::model! {
+//!         pub Power {
+//!             pub(super) battery: f64 = 100.0;
+//!         }
+//!     }
+//! }
+//!
+//! model! {
+//!     pub Spacecraft {}
+//!     mod power::Power;
+//!     expose read power::battery from power::Power;
+//! }
+//!
+//! #[derive(Hash, Serialize, Deserialize)]
+//! struct Tamper;
+//!
+//! # #[typetag::serde]
+//! impl Activity for Tamper {
+//!     fn run(&self, mut ops: Ops) -> anyhow::Result<Duration> {
+//!         ops += op! { w: power_battery = 0.0; }; // error: found `CannotWriteToReadOnlyResource`
+//!         Ok(Duration::ZERO)
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! Models can also declare daemons, which produce operations without being placed in the plan.
+//! `react(battery) check_battery();` calls `check_battery(ops)` right after any activity writes
+//! to `battery`. `daemon recurring(1.hours()) => decay_battery();` calls `decay_battery(ops)`
+//! every hour from the start of the plan. Recurring ticks are only run where the plan has been
+//! simulated, so the horizon doesn't need to be known. Ticks that read what earlier ticks wrote,
+//! like `decay_battery`, run from the start of the plan up to the simulated time; ticks that
+//! don't only run around the queried times and the activities. Their operations come before any
+//! activity's operations at the same time.
+//!
+//! A resource computed from others is declared in the model's body as
+//! `derived margin: f64 = generated - consumed;`. Each lowercase bare name in the expression is a
+//! resource it is computed from; paths like `f64::max` and called functions are left alone. A daemon
+//! recomputes it whenever an activity writes one of those resources, so like any other operation,
+//! it is only simulated when viewed. Operations can read it but not write it. Because daemons don't
+//! react to each other's writes, a derived resource can't be computed from another derived resource.
+//!
 //!
 //! ## Quick-start
 //!
@@ -136,7 +229,7 @@
 //!         ops += op! {
 //!             // This is syntactic sugar for a read-write operation on the sol_counter
 //!             // resource. Resources can be accessed as read-only with `r:`, and write-only
-//!             // with `w:`.
+//!             // with `w:`. `ref:` and `mut:` are longer spellings of `r:` and `m:`.
 //!             m: sol_counter += 1;
 //!         };
 //!         // Return statement indicates the activity had zero duration and no errors
@@ -167,6 +260,129 @@
 //! }
 //! ```
 //!
+//! Every resource tagged anywhere in an operation is read each time it runs, even in branches
+//! that are never taken, and changes to any of them cause it to re-run. If a branch condition
+//! only depends on activity arguments, write it as `if const verbose { .. } else { .. }`
+//! instead. The guard is then evaluated when the operation is created, and resources used only
+//! in the other branch are not read at all. Each operation may contain one top-level `if const`.
+//!
+//! Operations that spend most of their time waiting on IO, like querying an ephemeris
+//! service, can start with `#![blocking]`. Their bodies then run on a separate blocking pool,
+//! sized from the number of cores, so that they don't hold up the simulation's workers.
+//! Everything a blocking operation reads must be [Send].
+//!
+//! Operations whose results are needed soonest, like ones feeding a live display, can start with
+//! `#![priority]`. When a view requests many operations at once, the prioritized ones, and the
+//! operations they read from, are picked up by the workers first. This is only a hint; other
+//! workers may still start on the rest first, and the view's results are still in time order.
+//!
+//! Operations that can fail transiently, like a query over a flaky network, can start with
+//! `#![retry(3)]`. If the body fails with a [Transient] error, it runs again up to three more
+//! times before the error is reported, waiting 10ms before the first retry and twice as long before
+//! each one after. Give a different first delay with `#![retry(3, std::time::Duration::from_secs(1))]`.
+//!
+//! To read the smallest and largest values of a resource over a window before the operation,
+//! write `ref range(10.minutes()): temperature`, which evaluates to an [Extremes] struct. This only
+//! works for resources whose data is comparable and read as-is, like numbers. The window is
+//! a constant expression; it can't depend on activity arguments.
+//!
+//! To read a resource's value at an earlier time, write `ref(-5.minutes()): battery`, which evaluates
+//! to the value `battery` had five minutes before the operation, before any operations at that time.
+//! Like the window, the offset is a constant expression. The operation fails if the offset is
+//! positive, or if it reaches back before the start of the plan.
+//!
+//! For provenance, `ref source: battery` evaluates to the [ActivityId] of the activity that wrote
+//! the value of `battery` the operation would read, or `None` if it came from the initial conditions
+//! or a reactive daemon.
+//!
+//! For predictive logic, `ref next_change: battery` evaluates to the [Duration] until the next write
+//! to `battery` after the operation, or `None` if none is scheduled. Only writes at fixed times count.
+//! To look ahead within an activity, `ref at_end: battery` evaluates to the value `battery` will
+//! have at the end of the operation's activity, after all of its operations, including the ones
+//! added with [Ops::at_activity_end]. The operation can't write to the resource it reads this way,
+//! and it must be placed at a fixed time by an activity, not after a `delay!` or by a daemon.
+//! For curvature, `ref d2/dt2: temperature` evaluates to the analytic second derivative of a
+//! [Polynomial] resource as another polynomial, or `None` for data that doesn't have one.
+//!
+//! To read every value written to a resource during a span, write `ref since(start): downlink_buffer`,
+//! which evaluates to a slice of `(Time, value)` pairs written at or after `start` and before the
+//! operation. The read looks back at most a day; give a longer limit as a constant expression
+//! with `ref since(start, 30.days()): downlink_buffer`. Asking for writes from before the limit
+//! fails the operation. For edge-triggered logic, `ref changed_since(start): mode` evaluates to
+//! whether `mode` was written in that span, without copying the values.
+//!
+//! Operations can depend on data from outside the simulation, like a loaded configuration file,
+//! as long as the session knows its version: `ref external: config_version` evaluates to the version
+//! set with [Session::set_external_version], and changing the version reruns the operations that
+//! read it. Reading the data itself without such a read is hidden state; see below.
+//!
+//! Reading a resource that isn't in the plan's model, or reading before the initial conditions,
+//! panics. For optional resources, `ref or(0.0): heater_power` reads `heater_power`, or evaluates
+//! the default instead if it has no value.
+//!
+//! Tags can appear anywhere in an expression, so comparing two resources into a flag is a single
+//! statement: `w: overheat = r: temperature > r: limit;` reads both resources and writes the result.
+//! Spelled with `mut: overheat`, it also reads the previous value of the flag.
+//!
+//! A resource can be assigned more than once in the same body, like a default followed by an
+//! override in a branch. Each resource is written once per operation, with the last value it was
+//! assigned before the body returned.
+//!
+//! For state machines, `cas: mode, Mode::Idle => Mode::Busy` writes `Mode::Busy` to `mode` only if
+//! it is currently `Mode::Idle`, and evaluates to whether it did. It reads and writes `mode` like `m:`,
+//! and the new value extends to the end of the statement, so the outcome can be written to another
+//! resource with `w: claimed = cas: mode, Mode::Idle => Mode::Busy;`.
+//!
+//! For fault latches and other one-shot flags, `latch: fault_detected = r: temperature > r: limit;`
+//! sets the boolean resource `fault_detected` if the condition is true, and otherwise leaves it as it
+//! was, so once latched it stays true no matter what later latches evaluate to. Only
+//! `reset: fault_detected;` clears it.
+//!
+//! To log events, declare a resource of type [Events] like `downlink_log: Events<Downlink> = Events::new()`,
+//! and append to it with `emit: downlink_log <- Downlink::Started { pass };`. The events an operation
+//! emits are its own write, so unlike accumulating a `Vec` buffer, emitting doesn't get slower as the
+//! log grows. Query the log with [Plan::events].
+//!
+//! When operations need to read the whole buffer back, declare it as a [HistoryList] instead of a
+//! `Vec`, like `downlink_buffer: HistoryList<String> = HistoryList::new()`, and append with
+//! `m: downlink_buffer.push(message);`. Each version of the list shares its elements with the one
+//! before it, so pushing doesn't copy the buffer.
+//!
+//! Many boolean flags that change together can be packed into one resource of type [Flags], like
+//! `fault_flags: Flags<12> = Flags::new()`, and set with `m: fault_flags.set(FAULT_THERMAL);`.
+//!
+//! For resource groups like `heater_*_active`, a member can be chosen by a value known when the
+//! op is constructed, like an activity argument: `w: heater_active[const channel] = true;` writes
+//! only `heater_<channel>_active`, where `channel` is a `HeaterActive`. The group's members must be
+//! in scope, and the group can only be indexed this way within the crate that declares it.
+//!
+//! A `bool` group marked `#[count]`, like `#[count] pump_*_on: bool = false; {a, b}`, also gets a
+//! `pump_on_count: u32` resource that the model keeps equal to the number of members that are
+//! `true`, starting from their initial conditions. Operations can read the count, but not write it:
+//!
+//! ```compile_fail
+//! # use peregrine::*;
+//! # use serde::{Serialize, Deserialize};
+//! model! {
+//!     Pumps {
+//!         #[count]
+//!         pub pump_*_on: bool = false; {a, b}
+//!     }
+//! }
+//!
+//! #[derive(Hash, Serialize, Deserialize)]
+//! struct Miscount;
+//!
+//! # #[typetag::serde]
+//! impl Activity for Miscount {
+//!     fn run(&self, mut ops: Ops) -> anyhow::Result<Duration> {
+//!         ops += op! { w: pump_on_count = 5; }; // error: found `CannotWriteToReadOnlyResource`
+//!         Ok(Duration::ZERO)
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
 //! Next, you need to create a session and plan. You'll typically only have one session object
 //! at a time, but can have multiple active plans running in it.
 //!
@@ -316,6 +532,10 @@
 //! - **Generalized Dynamic Resources;** The [Data] trait allows you to produce arbitrary functions
 //!   from a single operation. This improves quality of life and enables hypotheticals. Currently I've
 //!   implemented [polynomials][resource_types::polynomial::Polynomial] and [piecewise functions][resource_types::piecewise::Piecewise].
+//! - **Daemon Tasks;** Models can declare reactive daemons that respond to writes, and recurring
+//!   daemons that run at a fixed period over the simulated range.
+//! - **Constraint Checking;** flight rules added with [Plan::add_constraint] are checked lazily
+//!   by [Plan::violations], and only rechecked when the resources they constrain change.
 //! - **Timekeeping Builtins;** the [now][resource_types::builtins::now] and [elapsed][resource_types::builtins::elapsed]
 //!   resources are automatically provided to all plans.
 //! - **Its also just really fast in general;** Even in peregrine's worst case (a linear DAG on a
@@ -325,22 +545,6 @@
 //!
 //! This project is currently a proof-of-concept, but I've set it up with future development in mind.
 //! These features could be implemented if there was demand:
-//! - **Stateful activities;** activities that store an internal state as a transient resource that they
-//!   bring to the model
-//! - **Daemon tasks;** background tasks associated with the model that can either generate a statically-known
-//!   set of recurring operations, or create "responsive" operations that are placed immediately after
-//!   any other operation writes to a given resource.
-//! - **Linked lists in history;** the above example of accumulating a `Vec<String>` buffer in a resource
-//!   is *extremely* inefficient. For every operation that writes to it, the vector will be cloned,
-//!   leading to quadratic runtime and memory usage. It is possible but non-trivial to make a linked
-//!   list that lives inside the history hashmap and persists through serialization. (In reality it
-//!   would be an n-ary tree that branches according to changes in the plan, but for any given simulation
-//!   it would appear to be a linked list.)
-//! - **Look-back reads;** currently operations can only read the current value of resources when
-//!   they happen, but there's no reason why they shouldn't be able to look back to a pre-determined
-//!   time.
-//! - **Activity anchoring;** activities could be defined relative to other activities, as long as the
-//!   relationship is known ahead-of-time.
 //! - **Probabilistic Caching;** if the overhead of reading/writing history is a problem, I could
 //!   potentially do pseudo-random caching (such as "only cache if `hash % 10 == 0`") without a large penalty
 //!   to cache misses.
@@ -354,6 +558,8 @@
 //! - **Non-reentrant or non-deterministic activities;** the engine assumes that for the same input,
 //!   all operations will produce the same output, and if a cached value exists in history then it is valid.
 //!   It also assumes that it is OK to only resimulate a portion of an activity's operations.
+//!   Operations that break this assumption can start with `#![not_idempotent]`, which makes rerunning
+//!   them without the rest of their activity fail a debug assertion. `#![idempotent]` states the default.
 
 // Public API - what users should import
 pub mod public;
@@ -368,9 +574,13 @@ pub use hifitime;
 pub use hifitime::{Duration, Epoch as Time};
 pub use peregrine_macros::{Data, MaybeHash, delay, model, op, resource};
 pub use public::{
-    Model,
+    Model, ModelDescriptor, ResourceVisitor,
     activity::*,
+    initial_conditions::InitialConditions,
     plan::*,
+    playback::*,
     resource::{builtins::*, piecewise::*, polynomial::*, timer::*, *},
     session::*,
 };
+#[cfg(feature = "uom")]
+pub use uom;
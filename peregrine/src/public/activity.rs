@@ -1,10 +1,17 @@
+use crate::internal::exec::current_downstream_count;
+use crate::internal::history::History;
 use crate::internal::operation::Node;
+use crate::internal::operation::initial_conditions::InitialConditionOp;
 use crate::internal::placement::{DenseTime, Placement};
-use crate::internal::timeline::epoch_to_duration;
+use crate::internal::timeline::{Timelines, duration_to_epoch, epoch_to_duration};
+use crate::public::resource::{Resource, ResourceDescriptor};
+use anyhow::bail;
 use bumpalo_herd::Member;
 use hifitime::{Duration, Epoch as Time};
 use serde::{Deserialize, Serialize};
+use std::any::TypeId;
 use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
 use std::ops::AddAssign;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -47,6 +54,14 @@ pub trait OpsReceiver<'v, 'o: 'v> {
 /// Alternatively, if you want to decide the mutability at the call site instead of the
 /// function declaration, the function can accept `impl OpsReceiver<'o>`. Both `Ops` and
 /// `&mut Ops` implement [OpsReceiver], and so the function caller can decide which to pass.
+///
+/// ## Aborting
+///
+/// Operations are generated statically, before simulation, so an activity cannot
+/// stop its remaining operations based on a simulated value. It *can* elide them based
+/// on anything known when [Activity::run] is called, like the activity's arguments,
+/// using [Ops::abort_if]. For aborts that depend on simulated state, write to a
+/// flag resource (e.g. `aborted: bool`) and have later operations read it and do nothing.
 #[derive(Clone)]
 pub struct Ops<'v, 'o: 'v> {
     /// The current placement time that operations will be inserted at.
@@ -57,8 +72,27 @@ pub struct Ops<'v, 'o: 'v> {
     /// is unwrapped by the [Plan][crate::Plan] after the activity is done.
     pub(crate) operations: &'v RefCell<Vec<&'o dyn Node<'o>>>,
     pub(crate) order: Arc<AtomicU64>,
+    /// Operations to be placed at the end of the activity. See [Ops::at_activity_end].
+    ///
+    /// `None` for reactive daemons, which are not activities.
+    pub(crate) deferred: Option<&'v RefCell<Vec<DeferredOp<'o>>>>,
+    /// The local resources declared by the activity. See [Ops::local_resource].
+    ///
+    /// `None` for reactive daemons, which are not activities.
+    pub(crate) locals: Option<&'v RefCell<Vec<LocalResource<'o>>>>,
+    /// Whether subsequent pushes should be ignored. See [Ops::abort_if].
+    pub(crate) aborted: bool,
 }
 
+/// Constructs an operation at the end of the activity, once its duration is known.
+pub(crate) type DeferredOp<'o> =
+    Box<dyn FnOnce(Placement<'o>, &Member<'o>) -> &'o (dyn Node<'o> + 'o) + 'o>;
+
+/// Adds the timeline of a local resource for an activity, given the first order of the
+/// activity's operations and its start time, and prepares the resource's history.
+pub(crate) type LocalResource<'o> =
+    Box<dyn FnOnce(&mut Timelines<'o>, &mut History, u64, Duration) -> anyhow::Result<()> + 'o>;
+
 impl<'v, 'o: 'v> Ops<'v, 'o> {
     #[doc(hidden)]
     pub fn new(
@@ -72,13 +106,94 @@ impl<'v, 'o: 'v> Ops<'v, 'o> {
             bump,
             operations,
             order,
+            deferred: None,
+            locals: None,
+            aborted: false,
+        }
+    }
+
+    /// Declares a private resource for this activity, starting at `initial` when the activity starts.
+    ///
+    /// `R` must be declared with `#[local]` in [resource][crate::resource!], and not be part of the
+    /// model. The activity's operations can then read and write it like any other resource,
+    /// but each activity has its own copy, which nothing outside the activity can see.
+    /// This lets an activity pass intermediate state between its operations.
+    ///
+    /// Fails if `R` isn't local, or if it is declared twice by the same activity. Reactive
+    /// daemons can't declare local resources.
+    pub fn local_resource<R: Resource>(&mut self, initial: R::Data) -> anyhow::Result<()> {
+        let Some(locals) = self.locals else {
+            bail!("reactive daemons can't declare local resources");
+        };
+        if !R::LOCAL {
+            bail!(
+                "{} must be declared with #[local] to be used as a local resource",
+                R::LABEL
+            );
         }
+        locals
+            .borrow_mut()
+            .push(Box::new(move |timelines, history, scope, start| {
+                history.init::<R>();
+                timelines.init_local::<R>(scope, start, InitialConditionOp::new(start, initial))
+            }));
+        Ok(())
+    }
+
+    /// Adds an operation at the end of the activity, regardless of the cursor position.
+    ///
+    /// The operation is placed after [Activity::run] returns, at the activity's start time
+    /// plus the returned duration. Useful for cleanup operations.
+    ///
+    /// Reactive daemons have no end, so there the operation is added at the cursor instead.
+    pub fn at_activity_end<N: Node<'o> + 'o>(
+        &mut self,
+        op_ctor: impl FnOnce(Placement<'o>) -> N + 'o,
+    ) {
+        match self.deferred {
+            Some(_) if self.aborted => {}
+            Some(deferred) => deferred.borrow_mut().push(Box::new(move |placement, bump| {
+                bump.alloc(op_ctor(placement))
+            })),
+            None => self.push(op_ctor),
+        }
+    }
+
+    /// Elides all operations pushed through this cursor after this call, if `condition` is true.
+    ///
+    /// The condition must be known before simulation; see [Ops#aborting].
+    /// Copies of the cursor made before the abort are unaffected.
+    pub fn abort_if(&mut self, condition: bool) {
+        self.aborted |= condition;
+    }
+
+    /// Whether this cursor has been aborted with [Ops::abort_if].
+    pub fn is_aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Computes a value once, for any number of the activity's operations to share.
+    ///
+    /// The value is stored in the plan's arena and the returned reference can be captured by
+    /// `op!` bodies like any other variable. Captured values are part of an operation's hash,
+    /// so changing the memoized value invalidates the operations that use it. Use it for
+    /// expensive pure computations that depend only on the activity's arguments.
+    ///
+    /// The value is never dropped.
+    pub fn memo<T: std::hash::Hash + Send + Sync + 'o>(
+        &self,
+        compute: impl FnOnce() -> T,
+    ) -> &'o T {
+        self.bump.alloc(compute())
     }
 }
 
 impl<'v, 'o: 'v> OpsReceiver<'v, 'o> for Ops<'v, 'o> {
     #[inline]
     fn push<N: Node<'o> + 'o>(&mut self, op_ctor: impl FnOnce(Placement<'o>) -> N) {
+        if self.aborted {
+            return;
+        }
         self.placement
             .set_order(self.order.fetch_add(1, Ordering::SeqCst));
         let op = self.bump.alloc(op_ctor(self.placement));
@@ -137,19 +252,183 @@ impl<'o, N: Node<'o> + 'o, F: FnOnce(Placement<'o>) -> N> AddAssign<F> for &mut
     }
 }
 
+/// The output of an `op!` containing an `if const` branch.
+///
+/// The guard is evaluated when the op is constructed, and only the op for the branch that
+/// was taken is added.
+#[doc(hidden)]
+pub enum ConstBranchOp<A, B> {
+    Taken(A),
+    NotTaken(B),
+}
+
+impl<'o, NA, NB, A, B> AddAssign<ConstBranchOp<A, B>> for Ops<'_, 'o>
+where
+    NA: Node<'o> + 'o,
+    NB: Node<'o> + 'o,
+    A: FnOnce(Placement<'o>) -> NA,
+    B: FnOnce(Placement<'o>) -> NB,
+{
+    fn add_assign(&mut self, rhs: ConstBranchOp<A, B>) {
+        match rhs {
+            ConstBranchOp::Taken(a) => self.push(a),
+            ConstBranchOp::NotTaken(b) => self.push(b),
+        }
+    }
+}
+
+impl<'o, NA, NB, A, B> AddAssign<ConstBranchOp<A, B>> for &mut Ops<'_, 'o>
+where
+    NA: Node<'o> + 'o,
+    NB: Node<'o> + 'o,
+    A: FnOnce(Placement<'o>) -> NA,
+    B: FnOnce(Placement<'o>) -> NB,
+{
+    fn add_assign(&mut self, rhs: ConstBranchOp<A, B>) {
+        (**self) += rhs;
+    }
+}
+
+/// The output of an `op!` that writes or reads a group member chosen by `group[const index]`.
+///
+/// The index is evaluated when the op is constructed, and only the op for the selected
+/// member is added. The op types differ between members, so the chosen one is boxed.
+#[doc(hidden)]
+pub struct IndexedOp<'o>(Box<dyn for<'v> FnOnce(&mut Ops<'v, 'o>) + 'o>);
+
+impl<'o> IndexedOp<'o> {
+    pub fn new<T: 'o>(op: T) -> Self
+    where
+        for<'v> Ops<'v, 'o>: AddAssign<T>,
+    {
+        Self(Box::new(move |ops| *ops += op))
+    }
+}
+
+impl<'o> AddAssign<IndexedOp<'o>> for Ops<'_, 'o> {
+    fn add_assign(&mut self, rhs: IndexedOp<'o>) {
+        (rhs.0)(self);
+    }
+}
+
+impl<'o> AddAssign<IndexedOp<'o>> for &mut Ops<'_, 'o> {
+    fn add_assign(&mut self, rhs: IndexedOp<'o>) {
+        (**self) += rhs;
+    }
+}
+
 /// An activity, which produces into a statically-known set of operations.
 /// Returns the activity's final duration and may produce errors.
 #[cfg_attr(feature = "serde", typetag::serde(tag = "type"))]
-pub trait Activity: Send + Sync {
+pub trait Activity: Send + Sync + MoveActivity {
     fn run<'o>(&'o self, ops: Ops<'_, 'o>) -> anyhow::Result<Duration>;
+
+    /// The type of the activity behind a `dyn Activity`, for activities that were deserialized.
+    #[doc(hidden)]
+    fn activity_type_id(&self) -> TypeId
+    where
+        Self: 'static,
+    {
+        TypeId::of::<Self>()
+    }
+}
+
+/// Moves a boxed activity into an arena, which needs its concrete type. Implemented for
+/// every activity.
+#[doc(hidden)]
+pub trait MoveActivity {
+    /// Moves the activity out of the box into the arena, and frees the box.
+    fn move_into_arena<'h>(self: Box<Self>, bump: &Member<'h>) -> &'h mut (dyn Activity + 'static);
+}
+
+impl<A: Activity + 'static> MoveActivity for A {
+    fn move_into_arena<'h>(self: Box<Self>, bump: &Member<'h>) -> &'h mut (dyn Activity + 'static) {
+        bump.alloc(*self)
+    }
+}
+
+/// An activity that can be combined with an adjacent activity of the same type, with
+/// [Plan::merge_adjacent_activities][crate::Plan::merge_adjacent_activities].
+pub trait MergeActivity: Activity + Sized + 'static {
+    /// Combines this activity with `next`, which starts when this one ends, into one activity
+    /// with the same operations as both. Returns `None` if they can't be combined.
+    fn merge(&self, next: &Self) -> Option<Self>;
+}
+
+/// Marks an operation error as transient, so that operations starting with `#![retry(..)]`
+/// run again instead of failing.
+///
+/// ```ignore
+/// return Err(Transient(anyhow!("ephemeris service timed out")).into());
+/// ```
+#[derive(Debug)]
+pub struct Transient(pub anyhow::Error);
+
+impl Display for Transient {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl std::error::Error for Transient {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
 }
 
 /// A unique activity ID.
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Debug)]
-pub struct ActivityId(u32);
+pub struct ActivityId(pub(crate) u32);
 
 impl ActivityId {
     pub fn new(id: u32) -> ActivityId {
         ActivityId(id)
     }
 }
+
+/// The number of downstream operations that have requested the output of the currently
+/// running operation, or `None` if called outside of an operation body.
+///
+/// This is a hint for side work that doesn't change the output, like prefetching data for
+/// the readers or logging how widely a value is used. It counts only the readers that have
+/// requested the output *so far*, so it may be lower than the number of operations that
+/// eventually read it.
+///
+/// The count is not part of the operation's input hash, so it must not affect what the
+/// operation writes. Outputs are cached and reused whenever an operation's inputs are
+/// unchanged, so an output computed under one count is returned under any other.
+pub fn downstream_count() -> Option<usize> {
+    current_downstream_count()
+}
+
+/// A description of a single operation produced by an activity.
+///
+/// See [Plan::activity_operations][crate::Plan::activity_operations].
+#[derive(Clone, Debug, PartialEq)]
+pub struct OperationInfo {
+    pub time: OperationTime,
+    pub reads: Vec<ResourceDescriptor>,
+    pub writes: Vec<ResourceDescriptor>,
+}
+
+/// When an operation is placed.
+///
+/// Operations after a dynamic `delay!` are only known to occur somewhere in a window;
+/// their actual time is decided during simulation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OperationTime {
+    Static(Time),
+    Dynamic { min: Time, max: Time },
+}
+
+impl From<Placement<'_>> for OperationTime {
+    fn from(placement: Placement<'_>) -> Self {
+        match placement {
+            Placement::Static(t) => OperationTime::Static(duration_to_epoch(t.when)),
+            Placement::Dynamic { min, max, .. } => OperationTime::Dynamic {
+                min: duration_to_epoch(min.when),
+                max: duration_to_epoch(max.when),
+            },
+        }
+    }
+}
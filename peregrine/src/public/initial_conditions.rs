@@ -1,3 +1,5 @@
+pub use crate::internal::operation::initial_conditions::InitialConditions;
+
 #[macro_export]
 macro_rules! initial_conditions {
     ($($res:ident : $val:expr),*$(,)?) => {
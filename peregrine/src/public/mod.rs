@@ -2,6 +2,7 @@
 //!
 //! This module contains all user-facing types, traits, and functions.
 
+use crate::public::resource::{Resource, ResourceDescriptor};
 use hifitime::Duration;
 use std::sync::Arc;
 use std::sync::atomic::AtomicU64;
@@ -9,6 +10,7 @@ use std::sync::atomic::AtomicU64;
 pub mod activity;
 pub mod initial_conditions;
 pub mod plan;
+pub mod playback;
 pub mod resource;
 pub mod session;
 
@@ -23,4 +25,44 @@ pub trait Model<'o>: Sync {
         timelines: &mut crate::internal::timeline::Timelines<'o>,
         order: Arc<AtomicU64>,
     ) -> anyhow::Result<()>;
+
+    /// Collects descriptors for every resource in the model, including submodels.
+    ///
+    /// May contain duplicates if submodels share resources; use [Model::descriptor] instead.
+    fn describe_resources(descriptors: &mut Vec<ResourceDescriptor>);
+
+    /// Calls [ResourceVisitor::visit] for every resource in the model, including submodels.
+    ///
+    /// Resources shared by submodels may be visited more than once.
+    fn visit_resources<V: ResourceVisitor>(visitor: &mut V) -> anyhow::Result<()>;
+
+    /// Returns metadata about the model's resources, for exporters and UIs.
+    fn descriptor() -> ModelDescriptor
+    where
+        Self: Sized,
+    {
+        let mut resources = vec![];
+        Self::describe_resources(&mut resources);
+        let mut seen = std::collections::HashSet::new();
+        resources.retain(|r| seen.insert(r.id));
+        ModelDescriptor { resources }
+    }
+}
+
+/// Does something with each resource type in a [Model], through [Model::visit_resources].
+pub trait ResourceVisitor {
+    fn visit<R: Resource>(&mut self) -> anyhow::Result<()>;
+}
+
+/// Metadata describing the resources in a [Model].
+#[derive(Clone, Debug, Default)]
+pub struct ModelDescriptor {
+    pub resources: Vec<ResourceDescriptor>,
+}
+
+impl ModelDescriptor {
+    /// Finds a resource descriptor by its label.
+    pub fn get(&self, label: &str) -> Option<&ResourceDescriptor> {
+        self.resources.iter().find(|r| r.label == label)
+    }
 }
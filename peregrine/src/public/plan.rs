@@ -1,33 +1,72 @@
-use crate::internal::exec::ErrorAccumulator;
-use crate::internal::history::History;
-use crate::internal::macro_prelude::GroundingContinuation;
+use crate::internal::diagnostics::{self, SimulationSpan, TraceContext};
+pub use crate::internal::exec::CancelToken;
+use crate::internal::exec::{
+    CacheAudit, ErrorAccumulator, ExecEnvironment, Interrupt, Pending, RootRequest, request_nodes,
+};
+use crate::internal::history::{History, PeregrineDefaultHashBuilder};
+use crate::internal::operation::Node;
 use crate::internal::operation::initial_conditions::InitialConditions;
-use crate::internal::operation::{Continuation, InternalResult};
-use crate::internal::placement::{DecomposedActivity, DenseTime, Placement};
-use crate::internal::timeline::{MaybeGrounded, Timelines, duration_to_epoch, epoch_to_duration};
-use crate::public::resource::init_builtins_timelines;
-use crate::{Activity, ActivityId, Data, Model, Ops, Resource, Session, Time};
-use anyhow::anyhow;
-use oneshot::Receiver;
-use serde::ser::SerializeSeq;
-use serde::{Serialize, Serializer};
+use crate::internal::placement::{DecomposedActivity, DenseTime, FIRST_ORDER, Placement};
+use crate::internal::resource::ResourceHistoryPlugin;
+use crate::internal::timeline::{
+    MaybeGrounded, ReactiveDaemon, Timelines, duration_to_epoch, epoch_to_duration,
+};
+use crate::public::activity::LocalResource;
+use crate::public::playback::{Playback, PlaybackResources};
+use crate::public::resource::{Events, ResourceDescriptor, ResourceId, init_builtins_timelines};
+use crate::{
+    Activity, ActivityId, Data, MaybeHash, MergeActivity, Model, OperationInfo, OperationTime, Ops,
+    Resource, ResourceVisitor, Session, Time,
+};
+use anyhow::{Context, anyhow, bail};
+use bumpalo_herd::Herd;
+use hifitime::Duration;
+use parking_lot::Mutex;
+use rayon::Scope;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize, Serializer};
+use std::any::{Any, TypeId};
 use std::cell::RefCell;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::Hasher;
 use std::marker::PhantomData;
-use std::ops::RangeBounds;
+use std::ops::{Bound, Range, RangeBounds};
+use std::path::Path;
 use std::sync::Arc;
-use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
 
 /// A plan instance for iterative editing and simulating.
 pub struct Plan<'o, M: Model<'o>> {
     activities: HashMap<ActivityId, DecomposedActivity<'o>>,
     id_counter: u32,
+    /// A version of each activity, which changes whenever an activity is inserted under its ID.
+    versions: HashMap<ActivityId, u64>,
+    /// The versions when the plan was created or branched, which [Plan::delta] compares against.
+    origin: HashMap<ActivityId, u64>,
+    /// The parent and anchor of each activity inserted with [Plan::insert_anchored].
+    anchors: HashMap<ActivityId, (ActivityId, Anchor)>,
     order: Arc<AtomicU64>,
     timelines: Timelines<'o>,
 
     session: &'o Session,
 
     model: PhantomData<M>,
+
+    after_view: Vec<AfterViewHook<'o>>,
+
+    /// The constraints added with [Plan::add_constraint], under their IDs.
+    constraints: BTreeMap<ConstraintId, ConstraintCheck<'o, M>>,
+    constraint_counter: u32,
+
+    /// The key the plan's operations are registered under, if the session uses
+    /// [history pruning][Session::with_history_pruning].
+    reachability_key: Option<u64>,
+
+    /// The plan's own arena, if the session uses [dedicated herds][Session::with_dedicated_herds].
+    ///
+    /// Declared last so that it outlives everything allocated in it.
+    herd: Option<Box<Herd>>,
 }
 
 impl<'o, M: Model<'o> + 'o> Plan<'o, M> {
@@ -38,22 +77,56 @@ impl<'o, M: Model<'o> + 'o> Plan<'o, M> {
         mut initial_conditions: InitialConditions,
     ) -> anyhow::Result<Self> {
         let time = epoch_to_duration(time);
-        let mut timelines = Timelines::new(&session.herd);
-        init_builtins_timelines(time, &mut timelines);
-        let order = Arc::new(AtomicU64::new(1));
+        let herd = session.dedicated_herds.then(Box::<Herd>::default);
+        let mut timelines = Timelines::new(Self::arena(session, &herd));
+        timelines.set_batched_grounding(session.batched_grounding);
+        timelines.set_trace_verbosity(session.trace_verbosity);
+        timelines.set_external_inputs(&session.external_inputs);
+        timelines.set_start(duration_to_epoch(time));
+        if session.deterministic_pool.is_some() {
+            timelines.record_executions();
+        }
+        init_builtins_timelines(time, session.elapsed_tick, &mut timelines);
+        let order = Arc::new(AtomicU64::new(FIRST_ORDER));
         M::init_timelines(time, &mut initial_conditions, &mut timelines, order.clone())?;
+        let reachability_key = session.reachability.as_ref().map(|r| {
+            let (key, operations) = r.lock().open();
+            timelines.track_operations(operations);
+            key
+        });
         Ok(Plan {
             activities: HashMap::new(),
             timelines,
             id_counter: 0,
+            versions: HashMap::new(),
+            origin: HashMap::new(),
+            anchors: HashMap::new(),
             order,
 
             session,
 
             model: PhantomData,
+
+            after_view: vec![],
+
+            constraints: BTreeMap::new(),
+            constraint_counter: 0,
+
+            reachability_key,
+
+            herd,
         })
     }
 
+    /// The arena that activities and operations are allocated in.
+    fn arena(session: &'o Session, herd: &Option<Box<Herd>>) -> &'o Herd {
+        match herd {
+            // SAFETY: the boxed herd never moves, and is dropped after everything that references it.
+            Some(herd) => unsafe { &*(&**herd as *const Herd) },
+            None => &session.herd,
+        }
+    }
+
     /// Reserve memory for a large batch of additional activities.
     ///
     /// Provides a noticeable speedup when loading large plans.
@@ -62,181 +135,2554 @@ impl<'o, M: Model<'o> + 'o> Plan<'o, M> {
     }
 
     /// Inserts a new activity into the plan, and returns its unique ID.
+    ///
+    /// Fails if the plan already contains the session's maximum number of activities;
+    /// see [Session::with_max_activities]. If the activity's [run][Activity::run] returns an error,
+    /// the error is returned and the plan is left unchanged, including the next ID.
     pub fn insert(
         &mut self,
         time: Time,
         activity: impl Activity + 'static,
     ) -> anyhow::Result<ActivityId> {
+        self.check_capacity(time)?;
+
         let id = ActivityId::new(self.id_counter);
+        self.insert_as(id, time, activity)?;
         self.id_counter += 1;
-        let bump = self.session.herd.get();
-        let activity = bump.alloc(activity);
-        let activity_pointer = activity as *mut dyn Activity;
+        self.versions.insert(id, next_version());
+        Ok(id)
+    }
+
+    /// Inserts a batch of activities, and returns their IDs in the same order.
+    ///
+    /// Equivalent to calling [Plan::insert] for each, but operations already in the plan are
+    /// told about the new ones once at the end instead of after every insertion, which speeds
+    /// up loading large plans. If any activity fails, the ones inserted before it are removed
+    /// and the error is returned, leaving the plan as it was.
+    pub fn insert_batch(
+        &mut self,
+        activities: impl IntoIterator<Item = (Time, Box<dyn Activity>)>,
+    ) -> anyhow::Result<Vec<ActivityId>> {
+        let activities = activities.into_iter();
+        self.reserve_activity_capacity(activities.size_hint().0);
+        let first_id = self.id_counter;
+        let mut ids = vec![];
+
+        self.timelines.defer_notifications();
+        let result = activities.into_iter().try_for_each(|(time, activity)| {
+            self.check_capacity(time)?;
+            let id = ActivityId::new(self.id_counter);
+            let type_id = activity.activity_type_id();
+            self.insert_boxed(id, time, type_id, activity)?;
+            self.id_counter += 1;
+            self.versions.insert(id, next_version());
+            ids.push(id);
+            Ok(())
+        });
+        self.timelines.flush_notifications();
+
+        if let Err(e) = result {
+            for id in ids.into_iter().rev() {
+                self.remove(id)?;
+            }
+            self.id_counter = first_id;
+            return Err(e);
+        }
+        Ok(ids)
+    }
+
+    /// Fails if the plan already contains the session's maximum number of activities.
+    fn check_capacity(&self, time: Time) -> anyhow::Result<()> {
+        if let Some(max) = self.session.max_activities
+            && self.activities.len() >= max
+        {
+            return Err(anyhow!(
+                "cannot insert activity at {time}: plan already contains the maximum of {max} activities"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Inserts an activity under a specific ID, such as one it had in a previous
+    /// instance of the plan, so that references to it elsewhere stay valid.
+    ///
+    /// Later calls to [Plan::insert] never reuse the ID. Fails if the ID is already in use,
+    /// or under the same conditions as [Plan::insert].
+    pub fn insert_with_id(
+        &mut self,
+        id: ActivityId,
+        time: Time,
+        activity: impl Activity + 'static,
+    ) -> anyhow::Result<()> {
+        if self.activities.contains_key(&id) {
+            return Err(anyhow!("activity id {id:?} is already in use"));
+        }
+        self.check_capacity(time)?;
+
+        self.insert_as(id, time, activity)?;
+        self.id_counter = self.id_counter.max(id.0 + 1);
+        self.versions.insert(id, next_version());
+        Ok(())
+    }
+
+    /// Inserts an activity placed relative to another activity in the plan, and returns its ID.
+    ///
+    /// The activity starts at an offset from its parent's start or end. When the parent is moved
+    /// with [Plan::move_activity], the activity moves with it, and so do the activities anchored
+    /// to it in turn. If the parent is removed, the activity stays where it is, unanchored.
+    /// Fails if the parent isn't in the plan, or under the same conditions as [Plan::insert].
+    pub fn insert_anchored(
+        &mut self,
+        parent: ActivityId,
+        anchor: Anchor,
+        activity: impl Activity + 'static,
+    ) -> anyhow::Result<ActivityId> {
+        let time = self.anchored_time(parent, anchor)?;
+        let id = self.insert(time, activity)?;
+        self.anchors.insert(id, (parent, anchor));
+        Ok(id)
+    }
+
+    /// Moves an activity to start at `time`, along with every activity anchored to it.
+    ///
+    /// Only the moved activities are run again; operations elsewhere in the plan are only
+    /// resimulated if they read from them. Moving an anchored activity doesn't unanchor it,
+    /// so it moves back into place when its parent moves. If any activity fails at its new
+    /// time, all of them are moved back and the error is returned.
+    pub fn move_activity(&mut self, id: ActivityId, time: Time) -> anyhow::Result<()> {
+        let mut moved = vec![];
+        let mut pending = VecDeque::from([(id, time)]);
+        while let Some((id, time)) = pending.pop_front() {
+            let result = self.reposition(id, time).and_then(|previous| {
+                moved.push((id, previous));
+                self.anchors
+                    .iter()
+                    .filter(|(_, (parent, _))| *parent == id)
+                    .map(|(child, (_, anchor))| Ok((*child, self.anchored_time(id, *anchor)?)))
+                    .collect::<anyhow::Result<Vec<_>>>()
+            });
+            match result {
+                Ok(children) => pending.extend(children),
+                Err(e) => {
+                    for (id, previous) in moved.into_iter().rev() {
+                        self.reposition(id, previous)?;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        for (id, _) in moved {
+            self.versions.insert(id, next_version());
+        }
+
+        Ok(())
+    }
+
+    /// Replaces an activity with a new instance of the same type, such as one with different
+    /// arguments, keeping its ID, start time, and anchors. To move an activity instead, use
+    /// [Plan::move_activity].
+    ///
+    /// Operations that the new arguments don't change still find their outputs in the cache.
+    /// If the activity's duration changes, the activities anchored to its end move with it.
+    /// Fails if the activity isn't an `A`. If the new activity or a moved child fails to run,
+    /// the plan is left as it was and the error is returned.
+    pub fn update<A: Activity + 'static>(
+        &mut self,
+        id: ActivityId,
+        activity: A,
+    ) -> anyhow::Result<()> {
+        let decomposed = self
+            .activities
+            .get(&id)
+            .ok_or_else(|| anyhow!("could not find activity with id {id:?}"))?;
+        if decomposed.type_id != TypeId::of::<A>() {
+            return Err(anyhow!(
+                "activity {id:?} is not a {}",
+                std::any::type_name::<A>()
+            ));
+        }
+        let children = self
+            .anchors
+            .iter()
+            .filter(|(_, (parent, _))| *parent == id)
+            .map(|(child, _)| (*child, self.activities[child].time))
+            .collect::<Vec<_>>();
+
+        let previous = self.detach(id)?;
+        if let Err(e) = self.insert_as(id, previous.time, activity) {
+            self.decompose(id, previous.time, previous.type_id, previous.activity)?;
+            return Err(e);
+        }
+        if let Err(e) = self.follow_anchors(&children) {
+            let replacement = self.detach(id)?;
+            unsafe { std::ptr::drop_in_place(replacement.activity) };
+            self.decompose(id, previous.time, previous.type_id, previous.activity)?;
+            return Err(e);
+        }
+        unsafe { std::ptr::drop_in_place(previous.activity) };
+        self.versions.insert(id, next_version());
+
+        Ok(())
+    }
+
+    /// Moves anchored activities, given with their current start times, to where their anchors
+    /// now place them. If one fails to move, the ones already moved are moved back.
+    fn follow_anchors(&mut self, children: &[(ActivityId, Time)]) -> anyhow::Result<()> {
+        let mut moved = vec![];
+        for (child, start) in children {
+            let (parent, anchor) = self.anchors[child];
+            let result = match self.anchored_time(parent, anchor) {
+                Ok(time) if time == *start => continue,
+                Ok(time) => self.move_activity(*child, time),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                for (child, start) in moved {
+                    self.move_activity(child, start)?;
+                }
+                return Err(e);
+            }
+            moved.push((*child, *start));
+        }
+        Ok(())
+    }
+
+    /// Runs an activity again at a new time, and returns its previous time. If it fails at the
+    /// new time, it is restored at the previous one.
+    fn reposition(&mut self, id: ActivityId, time: Time) -> anyhow::Result<Time> {
+        let decomposed = self.detach(id)?;
+        if let Err(e) = self.decompose(id, time, decomposed.type_id, decomposed.activity) {
+            self.decompose(id, decomposed.time, decomposed.type_id, decomposed.activity)?;
+            return Err(e);
+        }
+        Ok(decomposed.time)
+    }
+
+    /// The time an activity anchored to `parent` starts at.
+    fn anchored_time(&self, parent: ActivityId, anchor: Anchor) -> anyhow::Result<Time> {
+        let decomposed = self
+            .activities
+            .get(&parent)
+            .ok_or_else(|| anyhow!("could not find activity with id {parent:?}"))?;
+        Ok(match anchor {
+            Anchor::Start(offset) => decomposed.time + offset,
+            Anchor::End(offset) => decomposed.end + offset,
+        })
+    }
+
+    /// The ID that the next call to [Plan::insert] will return.
+    ///
+    /// Save this alongside the activities' IDs to reconstruct a plan that allocates
+    /// the same IDs; see [Plan::set_next_activity_id].
+    pub fn next_activity_id(&self) -> ActivityId {
+        ActivityId::new(self.id_counter)
+    }
+
+    /// Sets the ID that the next call to [Plan::insert] will return, so that IDs of activities
+    /// removed before the plan was saved are not reused.
+    ///
+    /// Fails if an activity in the plan already has this ID or a later one.
+    pub fn set_next_activity_id(&mut self, id: ActivityId) -> anyhow::Result<()> {
+        if let Some(max) = self.activities.keys().max()
+            && *max >= id
+        {
+            return Err(anyhow!(
+                "cannot set the next activity id to {id:?}: {max:?} is already in use"
+            ));
+        }
+        self.id_counter = id.0;
+        Ok(())
+    }
+
+    /// Runs the activity and inserts its operations.
+    ///
+    /// If the activity fails, or any of its operations can't be inserted, the plan is left
+    /// as it was before the call.
+    fn insert_as(
+        &mut self,
+        id: ActivityId,
+        time: Time,
+        activity: impl Activity + 'static,
+    ) -> anyhow::Result<()> {
+        let bump = Self::arena(self.session, &self.herd).get();
+        let type_id = Any::type_id(&activity);
+        let activity_pointer = bump.alloc(activity) as *mut dyn Activity;
+        self.decompose(id, time, type_id, activity_pointer)
+            .inspect_err(|_| unsafe { std::ptr::drop_in_place(activity_pointer) })
+    }
+
+    /// Runs an already allocated activity and inserts its operations, like [Plan::insert_as].
+    ///
+    /// The activity is not dropped if this fails.
+    fn decompose(
+        &mut self,
+        id: ActivityId,
+        time: Time,
+        type_id: TypeId,
+        activity_pointer: *mut dyn Activity,
+    ) -> anyhow::Result<()> {
+        let bump = Self::arena(self.session, &self.herd).get();
+        let activity = unsafe { &*activity_pointer };
 
         let operations = RefCell::new(vec![]);
+        let deferred = RefCell::new(vec![]);
+        let locals = RefCell::new(vec![]);
         let placement = Placement::Static(DenseTime::first_at(epoch_to_duration(time)));
         let ops_consumer = Ops {
             placement,
             bump: &bump,
             operations: &operations,
             order: self.order.clone(),
+            deferred: Some(&deferred),
+            locals: Some(&locals),
+            aborted: false,
+        };
+
+        let first_order = self.order.load(Ordering::SeqCst);
+        let duration = match activity.run(ops_consumer) {
+            Ok(duration) => duration,
+            Err(e) => {
+                self.order.store(first_order, Ordering::SeqCst);
+                return Err(e);
+            }
         };
 
-        let _duration = activity.run(ops_consumer)?;
+        let mut end = Placement::Static(DenseTime::first_at(epoch_to_duration(time + duration)));
+        for op_ctor in deferred.into_inner() {
+            end.set_order(self.order.fetch_add(1, Ordering::SeqCst));
+            operations.borrow_mut().push(op_ctor(end, &bump));
+        }
 
+        if self.session.write_coalescing {
+            self.coalesce_writes(&operations.borrow());
+        }
         for op in &*operations.borrow() {
-            op.insert_self(&self.timelines, false)?;
+            self.timelines.set_owner(*op, id);
+        }
+        let orders = first_order..self.order.load(Ordering::SeqCst);
+        let operations = operations.into_inner();
+
+        // Local resources are found by the orders of the activity's operations, so the
+        // orders are registered before the operations are inserted.
+        self.timelines.set_activity_end(
+            orders.clone(),
+            epoch_to_duration(time),
+            epoch_to_duration(time + duration),
+        );
+        let mut failure = self
+            .init_locals(locals.into_inner(), &orders, epoch_to_duration(time))
+            .err();
+        if failure.is_none() {
+            for (inserted, op) in operations.iter().enumerate() {
+                if let Err(e) = op.insert_self(&self.timelines, false) {
+                    for op in &operations[..inserted] {
+                        op.remove_self(&self.timelines, false)?;
+                    }
+                    failure = Some(e);
+                    break;
+                }
+                if let Some(e) = self.timelines.take_daemon_failure() {
+                    for op in &operations[..=inserted] {
+                        op.remove_self(&self.timelines, false)?;
+                    }
+                    failure = Some(e);
+                    break;
+                }
+            }
+        }
+        if let Some(e) = failure {
+            for op in &operations {
+                let address = *op as *const _ as *const u8 as usize;
+                self.timelines.forget_coalesced_writes(address);
+                self.timelines.remove_owner(address);
+            }
+            self.timelines.remove_locals(orders.clone());
+            self.timelines.remove_activity_end(orders);
+            self.order.store(first_order, Ordering::SeqCst);
+            return Err(e);
         }
 
+        self.timelines.record_indirect_reads(&operations, true);
         self.activities.insert(
             id,
             DecomposedActivity {
                 activity: activity_pointer,
-                operations: operations.into_inner(),
+                time,
+                end: time + duration,
+                type_id,
+                orders,
+                operations,
             },
         );
 
-        Ok(id)
+        Ok(())
+    }
+
+    /// Adds the timelines of the local resources an activity declared with [Ops::local_resource].
+    fn init_locals(
+        &mut self,
+        locals: Vec<LocalResource<'o>>,
+        orders: &Range<u64>,
+        start: Duration,
+    ) -> anyhow::Result<()> {
+        // Without operations, nothing could use them.
+        if locals.is_empty() || orders.is_empty() {
+            return Ok(());
+        }
+        let mut history = self.session.history.write();
+        for local in locals {
+            local(&mut self.timelines, &mut history, orders.start, start)?;
+        }
+        Ok(())
+    }
+
+    /// Leaves writes out of the timelines when the next operation at the same time overwrites
+    /// them, with no reads of the resource in between. See [Session::with_write_coalescing].
+    fn coalesce_writes(&mut self, operations: &[&'o dyn Node<'o>]) {
+        // The last write to each resource in the current run of operations at the same time.
+        let mut pending: HashMap<u64, (&'static str, usize)> = HashMap::new();
+        let mut run_time = None;
+        for op in operations {
+            let info = op.info();
+            let OperationTime::Static(time) = info.time else {
+                pending.clear();
+                run_time = None;
+                continue;
+            };
+            if run_time != Some(time) {
+                pending.clear();
+                run_time = Some(time);
+            }
+            // Matched by label, because window reads have their own resource IDs.
+            for read in &info.reads {
+                pending.retain(|_, (label, _)| *label != read.label);
+            }
+            let address = *op as *const _ as *const u8 as usize;
+            for write in &info.writes {
+                let id = write.id.id();
+                if self.timelines.has_reactive_trigger(id) {
+                    continue;
+                }
+                if let Some((_, previous)) = pending.insert(id, (write.label, address)) {
+                    self.timelines.coalesce_write(previous, id);
+                }
+            }
+        }
+    }
+
+    /// Installs a reactive daemon, which runs the activities returned by `daemon` whenever an
+    /// activity writes to any of the `triggers`.
+    ///
+    /// This is the runtime equivalent of `react(..)` in [model][crate::model!]. Like those
+    /// daemons, the returned activities are run at the time of the write, and the operations
+    /// they produce are removed when the triggering operation is. Writes from other daemons
+    /// don't trigger it, and neither do writes that were inserted before it was installed.
+    ///
+    /// If a returned activity fails, the insertion or edit that triggered the daemon fails
+    /// with its error, and the plan is left as it was.
+    pub fn add_reactive_daemon(
+        &mut self,
+        triggers: &[ResourceId],
+        daemon: impl Fn() -> Vec<Box<dyn Activity>> + Sync + 'static,
+    ) {
+        let order = self.order.clone();
+        self.timelines
+            .add_runtime_reactive_daemon(ReactiveDaemon::new(
+                triggers.iter().map(|t| t.id()).collect(),
+                Box::new(move |placement, member| {
+                    let operations = RefCell::new(vec![]);
+                    for activity in daemon() {
+                        let activity: &'o dyn Activity = &**member.alloc(activity);
+                        let ops = Ops::new(placement, &member, &operations, order.clone());
+                        activity.run(ops)?;
+                    }
+                    Ok(operations.into_inner())
+                }),
+            ));
     }
 
     /// Removes an activity from the plan, by ID.
     pub fn remove(&mut self, id: ActivityId) -> anyhow::Result<()> {
+        let decomposed = self.detach(id)?;
+        unsafe { std::ptr::drop_in_place(decomposed.activity) };
+        self.versions.remove(&id);
+        self.forget_anchors(id);
+
+        Ok(())
+    }
+
+    /// Unanchors a removed activity from its parent, and its children from it.
+    fn forget_anchors(&mut self, id: ActivityId) {
+        self.anchors
+            .retain(|child, (parent, _)| *child != id && *parent != id);
+    }
+
+    /// Removes an activity's operations from the plan, without dropping the activity.
+    fn detach(&mut self, id: ActivityId) -> anyhow::Result<DecomposedActivity<'o>> {
         let decomposed = self
             .activities
             .remove(&id)
             .ok_or_else(|| anyhow!("could not find activity with id {id:?}"))?;
-        for op in decomposed.operations {
+        for op in &decomposed.operations {
             op.remove_self(&self.timelines, false)?;
+            let address = *op as *const _ as *const u8 as usize;
+            self.timelines.forget_coalesced_writes(address);
+            self.timelines.remove_owner(address);
         }
-        unsafe { std::ptr::drop_in_place(decomposed.activity) };
+        self.timelines.remove_locals(decomposed.orders.clone());
+        self.timelines
+            .remove_activity_end(decomposed.orders.clone());
+        self.timelines
+            .record_indirect_reads(&decomposed.operations, false);
+        Ok(decomposed)
+    }
+
+    /// Replaces two activities of type `A`, where `second` starts when `first` ends, with the
+    /// single activity returned by [MergeActivity::merge]. The merged activity keeps `first`'s
+    /// ID and start time.
+    ///
+    /// Activities anchored to either one are anchored to the merged activity instead. Those
+    /// anchored to the end of `first` move to the end of the merged activity, and the rest
+    /// stay where they are.
+    ///
+    /// Fails if either activity isn't an `A`, if they aren't adjacent, or if `merge` returns
+    /// `None`. If the merged activity or a moved child fails to run, both activities are
+    /// restored and the error is returned.
+    pub fn merge_adjacent_activities<A: MergeActivity>(
+        &mut self,
+        first: ActivityId,
+        second: ActivityId,
+    ) -> anyhow::Result<()> {
+        if first == second {
+            return Err(anyhow!("cannot merge activity {first:?} with itself"));
+        }
+        let [a, b] = [first, second].map(|id| {
+            let decomposed = self
+                .activities
+                .get(&id)
+                .ok_or_else(|| anyhow!("could not find activity with id {id:?}"))?;
+            if decomposed.type_id != TypeId::of::<A>() {
+                return Err(anyhow!(
+                    "activity {id:?} is not a {}",
+                    std::any::type_name::<A>()
+                ));
+            }
+            Ok(decomposed)
+        });
+        let (a, b) = (a?, b?);
+        if a.end != b.time {
+            return Err(anyhow!(
+                "activity {second:?} starts at {}, not when {first:?} ends at {}",
+                b.time,
+                a.end
+            ));
+        }
+        let merged = unsafe { (*(a.activity as *const A)).merge(&*(b.activity as *const A)) }
+            .ok_or_else(|| anyhow!("activities {first:?} and {second:?} can't be merged"))?;
+
+        let children = self
+            .anchors
+            .iter()
+            .filter(|(child, (parent, _))| {
+                (*parent == first || *parent == second) && **child != first && **child != second
+            })
+            .map(|(child, _)| (*child, self.activities[child].time))
+            .collect::<Vec<_>>();
+        let second_offset = b.time - a.time;
+
+        let a = self.detach(first)?;
+        let b = self.detach(second)?;
+        if let Err(e) = self.insert_as(first, a.time, merged) {
+            self.decompose(first, a.time, a.type_id, a.activity)?;
+            self.decompose(second, b.time, b.type_id, b.activity)?;
+            return Err(e);
+        }
+        // The second activity's children stay where they are, now relative to the merged one.
+        let anchors = self.anchors.clone();
+        for (parent, anchor) in self.anchors.values_mut() {
+            if *parent == second {
+                *parent = first;
+                if let Anchor::Start(offset) = anchor {
+                    *offset += second_offset;
+                }
+            }
+        }
+        if let Err(e) = self.follow_anchors(&children) {
+            self.anchors = anchors;
+            let merged = self.detach(first)?;
+            unsafe { std::ptr::drop_in_place(merged.activity) };
+            self.decompose(first, a.time, a.type_id, a.activity)?;
+            self.decompose(second, b.time, b.type_id, b.activity)?;
+            return Err(e);
+        }
+        unsafe {
+            std::ptr::drop_in_place(a.activity);
+            std::ptr::drop_in_place(b.activity);
+        }
+        self.versions.insert(first, next_version());
+        self.versions.remove(&second);
+        self.forget_anchors(second);
 
         Ok(())
     }
 
-    /// Simulates and returns a view into a section of a resource's timeline.
-    pub fn view<R: Resource>(
-        &self,
-        bounds: impl RangeBounds<Time>,
-    ) -> anyhow::Result<Vec<(Time, <R::Data as Data<'o>>::Read)>> {
-        let mut nodes: Vec<MaybeGrounded<'o, R>> = self.timelines.range((
-            bounds
-                .start_bound()
-                .map(|t| DenseTime::first_at(epoch_to_duration(*t))),
-            bounds
-                .end_bound()
-                .map(|t| DenseTime::last_at(epoch_to_duration(*t))),
-        ));
-
-        let mut receivers: Vec<MaybeGroundedResult<R>> = Vec::with_capacity(nodes.len());
-        let errors = ErrorAccumulator::default();
+    /// Moves the whole plan later by `by`, or earlier if it is negative: the initial conditions
+    /// and every activity, keeping their relative times.
+    ///
+    /// Each activity is run again at its new time. Operations that don't depend on the absolute
+    /// time still find their outputs in the cache; ones that read `now` are rerun. If an
+    /// activity fails at its new time, the plan is moved back and the error is returned.
+    pub fn shift(&mut self, by: Duration) -> anyhow::Result<()> {
+        let mut ids = self.activities.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        let mut activities = vec![];
+        for id in ids {
+            let decomposed = self.detach(id)?;
+            activities.push((id, decomposed.time, decomposed.type_id, decomposed.activity));
+        }
+        // Recurring daemons tick from the start of the plan, so they start over.
+        self.timelines.clear_recurring_daemons()?;
+        self.timelines.shift_initial_conditions(by);
+
+        for (inserted, (id, time, type_id, activity)) in activities.iter().enumerate() {
+            if let Err(e) = self.decompose(*id, *time + by, *type_id, *activity) {
+                for (id, ..) in &activities[..inserted] {
+                    self.detach(*id)?;
+                }
+                self.timelines.shift_initial_conditions(-by);
+                for (id, time, type_id, activity) in &activities {
+                    self.decompose(*id, *time, *type_id, *activity)?;
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates a copy of the plan, with the same start, initial conditions, and activities
+    /// under the same IDs, for editing separately and later reconciling with [Plan::merge].
+    ///
+    /// The branch shares the session's history, so anything already simulated in this plan
+    /// is found in the cache. Activities are copied by serializing them. Reactive daemons added
+    /// with [Plan::add_reactive_daemon], [Plan::on_after_view] hooks, and constraints are not copied.
+    #[cfg(feature = "serde")]
+    pub fn branch(&self) -> anyhow::Result<Plan<'o, M>> {
+        let mut branch = Plan::new(
+            self.session,
+            self.timelines.start(),
+            self.initial_conditions()?,
+        )?;
 
-        enum MaybeGroundedResult<'h, R: Resource> {
-            Grounded(
-                DenseTime,
-                Receiver<InternalResult<<R::Data as Data<'h>>::Read>>,
-            ),
-            Ungrounded(
-                Receiver<InternalResult<DenseTime>>,
-                Receiver<InternalResult<<R::Data as Data<'h>>::Read>>,
-            ),
+        let mut ids = self.activities.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        for id in ids {
+            let decomposed = &self.activities[&id];
+            let activity = clone_activity(unsafe { &*decomposed.activity })
+                .with_context(|| format!("could not copy activity {id:?}"))?;
+            branch.insert_boxed(id, decomposed.time, decomposed.type_id, activity)?;
         }
+        branch.id_counter = self.id_counter;
+        branch.versions = self.versions.clone();
+        branch.origin = self.versions.clone();
+        branch.anchors = self.anchors.clone();
 
-        let timelines = &self.timelines;
+        Ok(branch)
+    }
 
-        let history_lock = self.session.history.read();
-        let history = unsafe { &*(&*history_lock as *const History).cast::<History>() };
+    /// The initial conditions the plan was created with.
+    #[cfg(feature = "serde")]
+    fn initial_conditions(&self) -> anyhow::Result<InitialConditions> {
+        let start = self.timelines.start();
+        self.sample_resources(
+            start,
+            DenseTime::first_at(epoch_to_duration(start))
+                ..=DenseTime::first_at(epoch_to_duration(start)),
+        )
+        .context("could not read the plan's initial conditions")
+    }
 
-        rayon::scope(|scope| {
-            let env = crate::internal::exec::ExecEnvironment {
-                errors: &errors,
-                history,
-                stack_counter: 0,
-            };
-            for node in nodes.drain(..) {
-                let (sender, receiver) = oneshot::channel();
-
-                match node {
-                    MaybeGrounded::Grounded(t, n) => {
-                        receivers.push(MaybeGroundedResult::Grounded(t, receiver));
-                        scope.spawn(move |s| {
-                            n.request(Continuation::Root(sender), true, s, timelines, env.reset())
-                        });
-                    }
-                    MaybeGrounded::Ungrounded(n) => {
-                        let (grounding_sender, grounding_receiver) = oneshot::channel();
-                        receivers.push(MaybeGroundedResult::Ungrounded(
-                            grounding_receiver,
-                            receiver,
-                        ));
-                        scope.spawn(move |s| {
-                            n.request_grounding(
-                                GroundingContinuation::Root(grounding_sender),
-                                true,
-                                s,
-                                timelines,
-                                env.reset(),
-                            )
-                        });
-                        scope.spawn(move |s| {
-                            n.request(Continuation::Root(sender), true, s, timelines, env.reset())
-                        });
-                    }
+    /// The plan's start, initial conditions, and activities, as written by its [Serialize] impl.
+    #[cfg(feature = "serde")]
+    fn saved(&self) -> anyhow::Result<SavedPlan<&dyn Activity>> {
+        struct Encoder<'i> {
+            initial_conditions: &'i InitialConditions,
+            values: BTreeMap<String, serde_json::Value>,
+        }
+
+        impl ResourceVisitor for Encoder<'_> {
+            fn visit<R: Resource>(&mut self) -> anyhow::Result<()> {
+                if let Some(value) = self.initial_conditions.get::<R>() {
+                    self.values
+                        .insert(R::LABEL.to_string(), serde_json::to_value(value)?);
                 }
+                Ok(())
             }
-        });
+        }
 
-        let mut result = Vec::with_capacity(receivers.len());
-        for receiver in receivers {
-            match receiver {
-                MaybeGroundedResult::Grounded(time, receiver) => {
-                    if let Ok(read) = receiver.recv()? {
-                        result.push((duration_to_epoch(time.when), read))
-                    }
-                }
-                MaybeGroundedResult::Ungrounded(grounding_receiver, receiver) => {
-                    if let (Ok(grounding_time), Ok(read)) =
-                        (grounding_receiver.recv()?, receiver.recv()?)
-                    {
-                        result.push((duration_to_epoch(grounding_time.when), read))
+        let initial_conditions = self.initial_conditions()?;
+        let mut encoder = Encoder {
+            initial_conditions: &initial_conditions,
+            values: BTreeMap::new(),
+        };
+        M::visit_resources(&mut encoder)?;
+
+        let mut ids = self.activities.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        Ok(SavedPlan {
+            start: self.timelines.start(),
+            initial_conditions: encoder.values,
+            next_id: self.next_activity_id(),
+            activities: ids
+                .into_iter()
+                .map(|id| {
+                    let decomposed = &self.activities[&id];
+                    SavedActivity {
+                        id,
+                        time: decomposed.time,
+                        anchor: self.anchors.get(&id).copied(),
+                        activity: unsafe { &*decomposed.activity },
                     }
+                })
+                .collect(),
+        })
+    }
+
+    /// Inserts the activities of a saved plan into this new plan, for [Session::load_plan].
+    #[cfg(feature = "serde")]
+    pub(crate) fn restore(&mut self, saved: SavedPlan<Box<dyn Activity>>) -> anyhow::Result<()> {
+        for SavedActivity {
+            id,
+            time,
+            anchor,
+            activity,
+        } in saved.activities
+        {
+            if self.activities.contains_key(&id) {
+                bail!("the saved plan contains activity id {id:?} more than once");
+            }
+            let type_id = activity.activity_type_id();
+            self.insert_boxed(id, time, type_id, activity)
+                .with_context(|| format!("could not load activity {id:?}"))?;
+            self.versions.insert(id, next_version());
+            if let Some(anchor) = anchor {
+                self.anchors.insert(id, anchor);
+            }
+        }
+        if let Some((child, (parent, _))) = self
+            .anchors
+            .iter()
+            .find(|(_, (parent, _))| !self.activities.contains_key(parent))
+        {
+            bail!("activity {child:?} is anchored to {parent:?}, which is not in the saved plan");
+        }
+        self.origin = self.versions.clone();
+        self.set_next_activity_id(saved.next_id)
+    }
+
+    /// The activities inserted and removed since the plan was created, either new or with
+    /// [Plan::branch].
+    pub fn delta(&self) -> PlanDelta {
+        let changed = |id: &&ActivityId| self.origin.get(id) != self.versions.get(id);
+        PlanDelta {
+            inserted: self.versions.keys().filter(changed).copied().collect(),
+            removed: self.origin.keys().filter(changed).copied().collect(),
+        }
+    }
+
+    /// Applies the changes made in `other` since it was branched to this plan, which should be
+    /// the plan it was branched from or another branch of it.
+    ///
+    /// Activities removed in `other` are removed here, and activities inserted in `other` are
+    /// copied here. New activities keep their IDs unless the IDs are already used here, in which
+    /// case they get new ones; the returned map gives the ID here of every activity inserted
+    /// in `other`. Activities inserted in `other` with [Plan::insert_anchored] stay anchored to
+    /// their parents, unless the parent is no longer here. Operations that didn't change on
+    /// either side find their outputs in the session's history, so only the parts of the plan
+    /// affected by both sides are simulated again. Changes that were already merged are skipped.
+    ///
+    /// Fails without changing the plan if both plans changed the same activity differently,
+    /// or if the plans aren't in the same session. [Plan::shift] is not tracked, so branches
+    /// that are shifted can't be merged meaningfully.
+    #[cfg(feature = "serde")]
+    pub fn merge(
+        &mut self,
+        other: &Plan<'o, M>,
+    ) -> anyhow::Result<BTreeMap<ActivityId, ActivityId>> {
+        if !other.is_in(self.session) {
+            bail!("cannot merge plans from different sessions");
+        }
+        let changed = other
+            .origin
+            .keys()
+            .chain(other.versions.keys())
+            .filter(|id| other.origin.get(id) != other.versions.get(id))
+            .copied()
+            .collect::<BTreeSet<_>>();
+        let conflicts = changed
+            .iter()
+            .filter(|id| {
+                let here = self.versions.get(id);
+                other.origin.contains_key(id)
+                    && here != other.origin.get(id)
+                    && here != other.versions.get(id)
+            })
+            .collect::<Vec<_>>();
+        if !conflicts.is_empty() {
+            bail!("both plans changed the activities {conflicts:?}");
+        }
+
+        let versions = self.versions.clone();
+        let id_counter = self.id_counter;
+        let mut detached = vec![];
+        let mut inserted = vec![];
+        let result = self.apply_changes(other, changed, &mut detached, &mut inserted);
+        if result.is_err() {
+            for id in inserted {
+                let decomposed = self.detach(id)?;
+                unsafe { std::ptr::drop_in_place(decomposed.activity) };
+            }
+            for (id, decomposed) in detached {
+                self.decompose(id, decomposed.time, decomposed.type_id, decomposed.activity)?;
+            }
+            self.versions = versions;
+            self.id_counter = id_counter;
+        } else {
+            for (_, decomposed) in detached {
+                unsafe { std::ptr::drop_in_place(decomposed.activity) };
+            }
+            self.anchors.retain(|child, (parent, _)| {
+                self.activities.contains_key(child) && self.activities.contains_key(parent)
+            });
+        }
+        result
+    }
+
+    /// Does the work of [Plan::merge], recording what it changed so that it can be undone.
+    #[cfg(feature = "serde")]
+    fn apply_changes(
+        &mut self,
+        other: &Plan<'o, M>,
+        changed: BTreeSet<ActivityId>,
+        detached: &mut Vec<(ActivityId, DecomposedActivity<'o>)>,
+        inserted: &mut Vec<ActivityId>,
+    ) -> anyhow::Result<BTreeMap<ActivityId, ActivityId>> {
+        let mut ids = BTreeMap::new();
+        for id in changed {
+            let version = other.versions.get(&id).copied();
+            if self.versions.get(&id).copied() == version {
+                if version.is_some() {
+                    ids.insert(id, id);
                 }
+                continue;
             }
+            let new_id = if other.origin.contains_key(&id) {
+                // Unchanged here, or there would have been a conflict.
+                if self.activities.contains_key(&id) {
+                    detached.push((id, self.detach(id)?));
+                    self.versions.remove(&id);
+                }
+                id
+            } else if self.activities.contains_key(&id) {
+                ActivityId::new(self.id_counter)
+            } else {
+                id
+            };
+            let Some(version) = version else {
+                continue;
+            };
+
+            let decomposed = &other.activities[&id];
+            let activity = clone_activity(unsafe { &*decomposed.activity })
+                .with_context(|| format!("could not copy activity {id:?}"))?;
+            self.insert_boxed(new_id, decomposed.time, decomposed.type_id, activity)?;
+            self.id_counter = self.id_counter.max(new_id.0 + 1);
+            self.versions.insert(new_id, version);
+            inserted.push(new_id);
+            ids.insert(id, new_id);
         }
 
-        if !errors.is_empty() {
-            return Err(anyhow::anyhow!("{:?}", errors));
+        for (id, new_id) in &ids {
+            if let Some((parent, anchor)) = other.anchors.get(id) {
+                let parent = ids.get(parent).copied().unwrap_or(*parent);
+                self.anchors.insert(*new_id, (parent, *anchor));
+            }
         }
 
-        Ok(result)
+        Ok(ids)
     }
 
-    /// Samples a resource at a specific time.
-    pub fn sample<R: Resource>(&self, time: Time) -> anyhow::Result<<R::Data as Data<'o>>::Sample> {
-        let view = self
-            .view::<R>(time..=time)?
-            .into_iter()
-            .collect::<BTreeMap<_, _>>();
-        let latest = view
-            .range(..=time)
-            .next_back()
-            .ok_or_else(|| anyhow!("No operations to sample found at or before {time}"))?;
-        Ok(R::Data::sample(*latest.1, time))
+    /// Moves a boxed activity into the plan's arena, and runs it like [Plan::insert_as].
+    fn insert_boxed(
+        &mut self,
+        id: ActivityId,
+        time: Time,
+        type_id: TypeId,
+        activity: Box<dyn Activity>,
+    ) -> anyhow::Result<()> {
+        let bump = Self::arena(self.session, &self.herd).get();
+        let activity_pointer = activity.move_into_arena(&bump) as *mut dyn Activity;
+        self.decompose(id, time, type_id, activity_pointer)
+            .inspect_err(|_| unsafe { std::ptr::drop_in_place(activity_pointer) })
     }
-}
 
-impl<'o, M: Model<'o>> Drop for Plan<'o, M> {
-    fn drop(&mut self) {
-        for decomposed in self.activities.values() {
-            unsafe { std::ptr::drop_in_place(decomposed.activity) };
+    /// Checks the plan's internal bookkeeping, and returns an error listing any problems.
+    ///
+    /// Every operation in a resource's timeline must belong to an activity or a reactive
+    /// daemon, and every operation of an activity must be in the timelines of the resources
+    /// it writes. A failure means there is a bug in peregrine, not in the model.
+    pub fn validate_integrity(&self) -> anyhow::Result<()> {
+        let timelines = self.timelines.operation_addresses();
+        let mut owned = HashSet::new();
+        let mut problems = vec![];
+
+        let operations = self
+            .activities
+            .iter()
+            .flat_map(|(id, decomposed)| decomposed.operations.iter().map(|op| (Some(*id), *op)))
+            .chain(
+                self.timelines
+                    .daemon_operations()
+                    .into_iter()
+                    .map(|op| (None, op)),
+            );
+        for (owner, op) in operations {
+            let address = op as *const _ as *const u8 as usize;
+            owned.insert(address);
+            for write in op.info().writes {
+                if !self.timelines.is_coalesced_id(address, write.id.id())
+                    && !timelines
+                        .get(&write.id.id())
+                        .is_some_and(|(_, ops)| ops.contains(&address))
+                {
+                    problems.push(match owner {
+                        Some(id) => format!(
+                            "an operation of activity {id:?} is missing from the {} timeline",
+                            write.label
+                        ),
+                        None => format!(
+                            "a daemon operation is missing from the {} timeline",
+                            write.label
+                        ),
+                    });
+                }
+            }
+        }
+
+        for (label, ops) in timelines.values() {
+            let orphans = ops.difference(&owned).count();
+            if orphans > 0 {
+                problems.push(format!(
+                    "{orphans} orphaned operation(s) in the {label} timeline"
+                ));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(problems.join("\n")))
         }
     }
-}
 
+    /// Lists the operations an activity produced, with their placements and the
+    /// resources they read and write, in the order the activity pushed them.
+    ///
+    /// Dynamically placed operations report the window they might occur in, not their simulated time.
+    pub fn activity_operations(&self, id: ActivityId) -> anyhow::Result<Vec<OperationInfo>> {
+        let decomposed = self
+            .activities
+            .get(&id)
+            .ok_or_else(|| anyhow!("could not find activity with id {id:?}"))?;
+        Ok(decomposed.operations.iter().map(|op| op.info()).collect())
+    }
+
+    /// Sums a contribution from each of an activity's operations, such as the cost of each
+    /// write to a resource, without simulating anything.
+    ///
+    /// `contribution` is called once per operation, in the order the activity pushed them.
+    pub fn activity_aggregate<T: std::iter::Sum>(
+        &self,
+        id: ActivityId,
+        contribution: impl Fn(&OperationInfo) -> T,
+    ) -> anyhow::Result<T> {
+        let decomposed = self
+            .activities
+            .get(&id)
+            .ok_or_else(|| anyhow!("could not find activity with id {id:?}"))?;
+        Ok(decomposed
+            .operations
+            .iter()
+            .map(|op| contribution(&op.info()))
+            .sum())
+    }
+
+    /// Lists the activities anchored to an activity with [Plan::insert_anchored], with their
+    /// placements, in order of their start times.
+    ///
+    /// Fails if the activity isn't in the plan.
+    pub fn children(&self, id: ActivityId) -> anyhow::Result<Vec<ActivityPlacement>> {
+        if !self.activities.contains_key(&id) {
+            bail!("could not find activity with id {id:?}");
+        }
+        let mut result = self
+            .anchors
+            .iter()
+            .filter(|(_, (parent, _))| *parent == id)
+            .map(|(child, _)| self.placement(*child))
+            .collect::<Vec<_>>();
+        result.sort_by_key(|placement| (placement.start, placement.id));
+        Ok(result)
+    }
+
+    /// Builds the tree of activities anchored to an activity, and to those activities in turn,
+    /// for example to draw them as a nested Gantt chart.
+    ///
+    /// Fails if the activity isn't in the plan.
+    pub fn activity_tree(&self, id: ActivityId) -> anyhow::Result<ActivityTree> {
+        if !self.activities.contains_key(&id) {
+            bail!("could not find activity with id {id:?}");
+        }
+        Ok(ActivityTree {
+            activity: self.placement(id),
+            children: self
+                .children(id)?
+                .into_iter()
+                .map(|child| self.activity_tree(child.id))
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    /// The placement of an activity that is in the plan.
+    fn placement(&self, id: ActivityId) -> ActivityPlacement {
+        let decomposed = &self.activities[&id];
+        ActivityPlacement {
+            id,
+            start: decomposed.time,
+            end: decomposed.end,
+            anchor: self.anchors.get(&id).map(|(_, anchor)| *anchor),
+        }
+    }
+
+    /// Lists the activities with at least one operation that reads or writes `R`, in ID order.
+    pub fn activities_touching<R: Resource>(&self) -> Vec<ActivityId> {
+        let descriptor = ResourceDescriptor::of::<R>();
+        let mut result = self
+            .activities
+            .iter()
+            .filter(|(_, decomposed)| {
+                decomposed.operations.iter().any(|op| {
+                    let info = op.info();
+                    info.reads.contains(&descriptor) || info.writes.contains(&descriptor)
+                })
+            })
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        result.sort();
+        result
+    }
+
+    /// Simulates and returns a view into a section of a resource's timeline.
+    ///
+    /// If there are no writes within the bounds, the view holds the last write before them.
+    /// A point like `time..=time` holds only the write in effect at that time, which is the last
+    /// of the writes at exactly that time, if there are any. Bounds that contain no times, like
+    /// `time..time` or a range whose end is before its start, give an empty view.
+    pub fn view<R: Resource>(
+        &self,
+        bounds: impl RangeBounds<Time>,
+    ) -> anyhow::Result<Vec<(Time, <R::Data as Data<'o>>::Read)>> {
+        self.view_with::<R>(bounds, SimOptions::default())
+    }
+
+    /// Like [Plan::view], but the simulation can be aborted partway through by a timeout or
+    /// a [CancelToken].
+    ///
+    /// An aborted view returns an [Interrupted] error. Operations that finished before the
+    /// abort keep their outputs, and the rest run again on the next view.
+    pub fn view_with<R: Resource>(
+        &self,
+        bounds: impl RangeBounds<Time>,
+        options: SimOptions,
+    ) -> anyhow::Result<Vec<(Time, <R::Data as Data<'o>>::Read)>> {
+        let started = Instant::now();
+        let interrupt = Interrupt::new(options.cancel_token, options.timeout);
+        if is_empty(&bounds) {
+            self.run_after_view::<R>(0, started);
+            return Ok(vec![]);
+        }
+        let point = matches!(
+            (bounds.start_bound(), bounds.end_bound()),
+            (Bound::Included(start), Bound::Included(end)) if start == end
+        );
+        let nodes = self.timelines.range(self.prepare_bounds(bounds)?);
+        let mut result = self.fold_hashed_nodes_with::<R, _>(
+            nodes,
+            Some(&interrupt),
+            vec![],
+            |mut result, time, _, read| {
+                result.push((time, read));
+                result
+            },
+        )?;
+        if point {
+            result.drain(..result.len().saturating_sub(1));
+        }
+        self.run_after_view::<R>(result.len(), started);
+        Ok(result)
+    }
+
+    /// Like [Plan::view], but also includes the first operation after the end of the bounds.
+    ///
+    /// Useful for rendering, where the final segment of the view needs to be drawn in full.
+    /// Bounds that contain no times give an empty view.
+    pub fn view_inclusive_next<R: Resource>(
+        &self,
+        bounds: impl RangeBounds<Time>,
+    ) -> anyhow::Result<Vec<(Time, <R::Data as Data<'o>>::Read)>> {
+        let started = Instant::now();
+        if is_empty(&bounds) {
+            self.run_after_view::<R>(0, started);
+            return Ok(vec![]);
+        }
+        let nodes = self
+            .timelines
+            .range_inclusive_next(self.prepare_bounds(bounds)?);
+        let result = self.simulate_nodes::<R>(nodes)?;
+        self.run_after_view::<R>(result.len(), started);
+        Ok(result)
+    }
+
+    /// Registers a hook that is called after each successful [Plan::view] or
+    /// [Plan::view_inclusive_next], for example to flush metrics to an external store.
+    ///
+    /// Hooks run on the calling thread once the results are collected, in the order they
+    /// were registered.
+    pub fn on_after_view(&mut self, hook: impl Fn(&SimStats) + Send + Sync + 'o) {
+        self.after_view.push(Box::new(hook));
+    }
+
+    fn run_after_view<R: Resource>(&self, values: usize, started: Instant) {
+        if self.after_view.is_empty() {
+            return;
+        }
+        let stats = SimStats {
+            resource: R::LABEL,
+            values,
+            elapsed: started.elapsed(),
+        };
+        for hook in &self.after_view {
+            hook(&stats);
+        }
+    }
+
+    /// The time of the plan's last operation, or `None` if it has no activities.
+    ///
+    /// Operations whose time is decided during simulation count as their latest possible time.
+    pub fn horizon(&self) -> Option<Time> {
+        self.activities
+            .values()
+            .flat_map(|decomposed| &decomposed.operations)
+            .map(|op| match op.info().time {
+                OperationTime::Static(time) => time,
+                OperationTime::Dynamic { max, .. } => max,
+            })
+            .max()
+    }
+
+    /// Like [Plan::view], but clamps the end of `bounds` to the plan's [horizon][Plan::horizon],
+    /// and marks where the simulated region ends.
+    ///
+    /// Past the horizon there are no more operations, so the resource holds its last value.
+    /// UIs can use [HorizonView::extrapolated_from] to draw that region differently.
+    pub fn view_to_horizon<R: Resource>(
+        &self,
+        bounds: Range<Time>,
+    ) -> anyhow::Result<HorizonView<<R::Data as Data<'o>>::Read>> {
+        let extrapolated_from = match self.horizon() {
+            Some(horizon) if horizon >= bounds.end => None,
+            Some(horizon) => Some(horizon.max(bounds.start)),
+            None => Some(bounds.start),
+        };
+        let values = match extrapolated_from {
+            Some(end) => self.view::<R>(bounds.start..=end)?,
+            None => self.view::<R>(bounds)?,
+        };
+        Ok(HorizonView {
+            values,
+            extrapolated_from,
+        })
+    }
+
+    /// Samples a resource over `bounds` as contiguous `(start, end, value)` segments of constant value.
+    ///
+    /// Consecutive writes of equal values are merged into one segment, where equality is decided
+    /// by the samples' [MaybeHash] hashes; unhashable values are never merged. The first segment
+    /// starts at `bounds.start` and the last ends at `bounds.end`.
+    ///
+    /// This is meant for piecewise-constant resources like modes and flags. Each value is sampled
+    /// at the time it was written, so resources that evolve between writes are not represented exactly.
+    #[allow(clippy::type_complexity)]
+    pub fn segments<R: Resource>(
+        &self,
+        bounds: Range<Time>,
+    ) -> anyhow::Result<Vec<(Time, Time, <R::Data as Data<'o>>::Sample)>> {
+        let nodes = self
+            .timelines
+            .range_inclusive_previous::<R>(self.prepare_bounds(bounds.clone())?);
+        let writes = self.simulate_nodes::<R>(nodes)?;
+
+        let mut segments: Vec<(Time, <R::Data as Data<'o>>::Sample, Option<u64>)> = vec![];
+        for (time, read) in writes {
+            if time >= bounds.end {
+                break;
+            }
+            let start = time.max(bounds.start);
+            let value = R::Data::sample(read, time);
+            let hash = value.is_hashable().then(|| {
+                let mut hasher = PeregrineDefaultHashBuilder::default();
+                value.hash_unchecked(&mut hasher);
+                hasher.finish()
+            });
+
+            // Later writes at the same time (or before the bounds) replace earlier ones.
+            if segments.last().is_some_and(|last| last.0 == start) {
+                segments.pop();
+            }
+            if hash.is_some() && segments.last().is_some_and(|last| last.2 == hash) {
+                continue;
+            }
+            segments.push((start, value, hash));
+        }
+
+        let ends = segments
+            .iter()
+            .skip(1)
+            .map(|segment| segment.0)
+            .chain(std::iter::once(bounds.end))
+            .collect::<Vec<_>>();
+        Ok(segments
+            .into_iter()
+            .zip(ends)
+            .map(|((start, value, _), end)| (start, end, value))
+            .collect())
+    }
+
+    /// Finds the earliest time in `bounds` at which a resource doesn't satisfy `constraint`.
+    ///
+    /// The constraint is only checked at the start of the bounds and at each write after it,
+    /// in time order. The writes are simulated in a single scope, but each is only requested once
+    /// the one before it passed, and checking stops at the first failure, so the operations after
+    /// it don't run. Writes with ungrounded times aren't in order until they run, so if the bounds
+    /// hold any, every write is simulated first. Like [Plan::segments], each value is sampled at
+    /// the time it was written, so it is meant for piecewise-constant resources.
+    pub fn first_violation<R: Resource>(
+        &self,
+        bounds: Range<Time>,
+        mut constraint: impl FnMut(<R::Data as Data<'o>>::Sample) -> bool + Send,
+    ) -> anyhow::Result<Option<Time>> {
+        enum Write<'o, R: Resource> {
+            Simulated(<R::Data as Data<'o>>::Read),
+            Pending(MaybeGrounded<'o, R>),
+        }
+
+        let nodes = self
+            .timelines
+            .range_inclusive_previous::<R>(self.prepare_bounds(bounds.clone())?);
+        let grounded_times = nodes
+            .iter()
+            .map(|node| match node {
+                MaybeGrounded::Grounded(time, _) => Some(duration_to_epoch(time.when)),
+                MaybeGrounded::Ungrounded(_) => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        let (times, writes): (Vec<_>, Vec<_>) = match grounded_times {
+            Some(times) => (times, nodes.into_iter().map(Write::Pending).collect()),
+            None => self
+                .simulate_nodes::<R>(nodes)?
+                .into_iter()
+                .map(|(time, read)| (time, Write::Simulated(read)))
+                .unzip(),
+        };
+
+        self.simulate_in_scope(None, |scope, timelines, env| {
+            for (i, write) in writes.into_iter().enumerate() {
+                if times[i] >= bounds.end {
+                    break;
+                }
+                let time = times[i].max(bounds.start);
+
+                // Later writes at the same time (or before the bounds) replace earlier ones.
+                if times.get(i + 1).is_some_and(|next| *next <= time) {
+                    continue;
+                }
+                let read = match write {
+                    Write::Simulated(read) => read,
+                    Write::Pending(node) => {
+                        let request = request_nodes(vec![node], scope, timelines, env)
+                            .pop()
+                            .expect("expected a request for the node");
+                        match request.recv_hashed_in_scope(env.errors)? {
+                            Some((_, _, read)) => read,
+                            None => continue,
+                        }
+                    }
+                };
+                if !constraint(R::Data::sample(read, time)) {
+                    return Ok(Some(time));
+                }
+            }
+            Ok(None)
+        })
+    }
+
+    /// Adds a flight rule that a resource must satisfy, and returns its ID.
+    ///
+    /// Constraints are checked lazily by [Plan::violations]. Each constraint remembers the writes
+    /// it was last checked against, so after an edit only the constraints on resources whose
+    /// values changed are checked again.
+    ///
+    /// Data that changes between writes, like a [Linear][crate::Linear] battery draining or data
+    /// that [interpolates][Data::INTERPOLATES], is checked at the first and last instant before
+    /// each following write. If those differ, the time the constraint starts or stops holding is
+    /// found by bisection, to the nanosecond. A violation that starts and ends between two writes
+    /// without including either of those instants isn't found.
+    pub fn add_constraint<R: Resource>(
+        &mut self,
+        constraint: impl Fn(<R::Data as Data<'o>>::Sample) -> bool + Send + Sync + 'o,
+    ) -> ConstraintId {
+        let id = ConstraintId(self.constraint_counter);
+        self.constraint_counter += 1;
+
+        let checked = Mutex::new(None);
+        self.constraints.insert(
+            id,
+            Box::new(move |plan, bounds| {
+                let nodes = plan
+                    .timelines
+                    .range_inclusive_previous::<R>(plan.prepare_bounds(bounds.clone())?);
+                let (writes, hasher) = plan.fold_hashed_nodes::<R, _>(
+                    nodes,
+                    (vec![], PeregrineDefaultHashBuilder::default()),
+                    |(mut writes, mut hasher), time, hash, read| {
+                        hasher.write_i128(epoch_to_duration(time).total_nanoseconds());
+                        hasher.write_u64(hash);
+                        writes.push((time, read));
+                        (writes, hasher)
+                    },
+                )?;
+                let key = (bounds.clone(), hasher.finish());
+
+                let mut checked = checked.lock();
+                if let Some((checked_key, intervals)) = &*checked
+                    && *checked_key == key
+                {
+                    return Ok(Vec::clone(intervals));
+                }
+
+                let mut intervals: Vec<(Time, Time)> = vec![];
+                let mut writes = writes.into_iter().peekable();
+                while let Some((time, read)) = writes.next() {
+                    if time >= bounds.end {
+                        break;
+                    }
+                    let start = time.max(bounds.start);
+
+                    // Later writes at the same time (or before the bounds) replace earlier ones.
+                    let next = writes.peek().copied();
+                    if next.is_some_and(|(next, _)| next <= start) {
+                        continue;
+                    }
+                    let end = next.map_or(bounds.end, |(next, _)| next.min(bounds.end));
+                    let satisfied = |at: Time| {
+                        constraint(match next {
+                            Some(next) if <R::Data as Data<'o>>::INTERPOLATES => {
+                                R::Data::interpolate((time, read), next, at)
+                            }
+                            _ => R::Data::sample(read, at),
+                        })
+                    };
+                    let violated = match segment_crossing(start, end, satisfied) {
+                        (false, None) => start..end,
+                        (true, None) => continue,
+                        (false, Some(crossing)) => start..crossing,
+                        (true, Some(crossing)) => crossing..end,
+                    };
+                    match intervals.last_mut() {
+                        Some(last) if last.1 == violated.start => last.1 = violated.end,
+                        _ => intervals.push((violated.start, violated.end)),
+                    }
+                }
+                *checked = Some((key, intervals.clone()));
+                Ok(intervals)
+            }),
+        );
+        id
+    }
+
+    /// Removes a constraint added with [Plan::add_constraint].
+    pub fn remove_constraint(&mut self, id: ConstraintId) -> anyhow::Result<()> {
+        self.constraints
+            .remove(&id)
+            .map(|_| ())
+            .ok_or_else(|| anyhow!("could not find constraint with id {id:?}"))
+    }
+
+    /// The intervals within `bounds` in which the plan violates its constraints, ordered by
+    /// start time and then by constraint.
+    ///
+    /// Adjacent intervals in which the same constraint fails are joined into one violation.
+    pub fn violations(&self, bounds: Range<Time>) -> anyhow::Result<Vec<Violation>> {
+        let mut violations = vec![];
+        for (id, check) in &self.constraints {
+            violations.extend(
+                check(self, &bounds)?
+                    .into_iter()
+                    .map(|(start, end)| Violation {
+                        constraint: *id,
+                        start,
+                        end,
+                    }),
+            );
+        }
+        violations.sort_by_key(|v| (v.start, v.constraint));
+        Ok(violations)
+    }
+
+    /// Samples a resource every `step` from the start of `bounds` until its end (exclusive).
+    #[allow(clippy::type_complexity)]
+    pub fn sample_grid<R: Resource>(
+        &self,
+        bounds: Range<Time>,
+        step: Duration,
+    ) -> anyhow::Result<Vec<(Time, <R::Data as Data<'o>>::Sample)>> {
+        if step <= Duration::ZERO {
+            bail!("sample grid step must be positive, got {step}");
+        }
+        let nodes = self
+            .timelines
+            .range_inclusive_previous::<R>(self.prepare_bounds(bounds.clone())?);
+        let writes = self.simulate_nodes::<R>(nodes)?;
+
+        let mut result = vec![];
+        let mut writes = writes.into_iter().peekable();
+        let mut latest = None;
+        let mut time = bounds.start;
+        while time < bounds.end {
+            while let Some((_, read)) = writes.next_if(|(written, _)| *written <= time) {
+                latest = Some(read);
+            }
+            let read = latest
+                .ok_or_else(|| anyhow!("No operations to sample found at or before {time}"))?;
+            result.push((time, R::Data::sample(read, time)));
+            time += step;
+        }
+        Ok(result)
+    }
+
+    /// Samples a resource every `step` over `bounds` into a [Profile], for plotting or exporting.
+    ///
+    /// Like [Plan::sample_grid], dynamic resources like [Linear][crate::Linear] and
+    /// [Piecewise][crate::Piecewise] are evaluated at each step, not just where they were written.
+    #[allow(clippy::type_complexity)]
+    pub fn profile<R: Resource>(
+        &self,
+        bounds: Range<Time>,
+        step: Duration,
+    ) -> anyhow::Result<Profile<<R::Data as Data<'o>>::Sample>> {
+        Ok(Profile {
+            resource: R::LABEL,
+            unit: R::UNIT,
+            points: self.sample_grid::<R>(bounds, step)?,
+        })
+    }
+
+    /// The events emitted to an [Events] resource within `bounds`, in time order, and in the
+    /// order they were emitted within each operation.
+    pub fn events<R, E>(&self, bounds: impl RangeBounds<Time>) -> anyhow::Result<Vec<(Time, &'o E)>>
+    where
+        R: Resource<Data = Events<E>>,
+        E: 'static + MaybeHash + Clone + Serialize + DeserializeOwned + Send + Sync,
+    {
+        let inside = (bounds.start_bound().cloned(), bounds.end_bound().cloned());
+        let nodes = self.timelines.range(self.prepare_bounds(bounds)?);
+        Ok(self
+            .simulate_nodes::<R>(nodes)?
+            .into_iter()
+            .filter(|(time, _)| inside.contains(time))
+            .flat_map(|(time, log)| log.emitted().iter().map(move |event| (time, event)))
+            .collect())
+    }
+
+    /// Samples a resource in this plan and `other` at the same times, for comparing them.
+    ///
+    /// Returns `(time, self's value, other's value)` every `step` across `bounds`; see [Plan::sample_grid].
+    #[allow(clippy::type_complexity)]
+    pub fn diff_resource<R: Resource>(
+        &self,
+        other: &Plan<'o, M>,
+        bounds: Range<Time>,
+        step: Duration,
+    ) -> anyhow::Result<
+        Vec<(
+            Time,
+            <R::Data as Data<'o>>::Sample,
+            <R::Data as Data<'o>>::Sample,
+        )>,
+    > {
+        let ours = self.sample_grid::<R>(bounds.clone(), step)?;
+        let theirs = other.sample_grid::<R>(bounds, step)?;
+        Ok(ours
+            .into_iter()
+            .zip(theirs)
+            .map(|((time, ours), (_, theirs))| (time, ours, theirs))
+            .collect())
+    }
+
+    /// Folds over the writes to a resource within `bounds`, in time order.
+    ///
+    /// Only the writes inside the bounds are included, not the value before them. When every
+    /// write in the bounds has a grounded time, each value is passed to `f` as soon as it is
+    /// simulated, while later operations are still running, without collecting them first.
+    /// Writes with ungrounded times aren't in order until they run, so if the bounds hold any,
+    /// every value is simulated and sorted before `f` is called. `f` runs on the simulation's
+    /// threads, so it must be [Send].
+    pub fn reduce<R: Resource, A: Send>(
+        &self,
+        bounds: impl RangeBounds<Time>,
+        init: A,
+        mut f: impl FnMut(A, Time, <R::Data as Data<'o>>::Read) -> A + Send,
+    ) -> anyhow::Result<A> {
+        let inside = (bounds.start_bound().cloned(), bounds.end_bound().cloned());
+        let nodes = self.timelines.range(self.prepare_bounds(bounds)?);
+        self.fold_nodes::<R, A>(nodes, init, |accumulator, time, read| {
+            if inside.contains(&time) {
+                f(accumulator, time, read)
+            } else {
+                accumulator
+            }
+        })
+    }
+
+    fn simulate_nodes<R: Resource>(
+        &self,
+        nodes: Vec<MaybeGrounded<'o, R>>,
+    ) -> anyhow::Result<Vec<(Time, <R::Data as Data<'o>>::Read)>> {
+        let capacity = nodes.len();
+        self.fold_nodes::<R, _>(
+            nodes,
+            Vec::with_capacity(capacity),
+            |mut result, time, read| {
+                result.push((time, read));
+                result
+            },
+        )
+    }
+
+    fn fold_nodes<R: Resource, A: Send>(
+        &self,
+        nodes: Vec<MaybeGrounded<'o, R>>,
+        init: A,
+        mut f: impl FnMut(A, Time, <R::Data as Data<'o>>::Read) -> A + Send,
+    ) -> anyhow::Result<A> {
+        self.fold_hashed_nodes(nodes, init, |accumulator, time, _, read| {
+            f(accumulator, time, read)
+        })
+    }
+
+    /// Like [Plan::fold_nodes], but also passes the hash each output is stored under in history.
+    fn fold_hashed_nodes<R: Resource, A: Send>(
+        &self,
+        nodes: Vec<MaybeGrounded<'o, R>>,
+        init: A,
+        f: impl FnMut(A, Time, u64, <R::Data as Data<'o>>::Read) -> A + Send,
+    ) -> anyhow::Result<A> {
+        self.fold_hashed_nodes_with(nodes, None, init, f)
+    }
+
+    /// Like [Plan::fold_hashed_nodes], but stops running operations once `interrupt` triggers.
+    fn fold_hashed_nodes_with<R: Resource, A: Send>(
+        &self,
+        nodes: Vec<MaybeGrounded<'o, R>>,
+        interrupt: Option<&Interrupt>,
+        init: A,
+        mut f: impl FnMut(A, Time, u64, <R::Data as Data<'o>>::Read) -> A + Send,
+    ) -> anyhow::Result<A> {
+        self.simulate_in_scope(interrupt, |scope, timelines, env| {
+            let requests = request_nodes(nodes, scope, timelines, env);
+
+            // Grounded ops come back in request order, which is time order, so each output
+            // is folded as soon as it is ready, while later ops are still running. Ungrounded
+            // ops' times aren't known in advance, and coincident writes must be in simulation
+            // order, so if there are any every output is received and sorted first.
+            let in_order = requests
+                .iter()
+                .all(|request| matches!(request, RootRequest::Grounded(..)));
+            let mut accumulator = init;
+            if in_order {
+                for request in requests {
+                    if let Some((time, hash, read)) = request.recv_hashed_in_scope(env.errors)? {
+                        accumulator = f(accumulator, duration_to_epoch(time.when), hash, read);
+                    }
+                }
+            } else {
+                let mut result = Vec::with_capacity(requests.len());
+                for request in requests {
+                    if let Some(output) = request.recv_hashed_in_scope(env.errors)? {
+                        result.push(output);
+                    }
+                }
+                result.sort_by_key(|(time, ..)| *time);
+                for (time, hash, read) in result {
+                    accumulator = f(accumulator, duration_to_epoch(time.when), hash, read);
+                }
+            }
+            Ok(accumulator)
+        })
+    }
+
+    /// Runs `run` in a new simulation scope, where it can request nodes of the timelines and
+    /// receive their outputs. The outputs are received on this thread, while the ops run in the pool.
+    ///
+    /// Errors reported by operations take precedence over the result of `run`, and an
+    /// interruption over both.
+    fn simulate_in_scope<T: Send>(
+        &self,
+        interrupt: Option<&Interrupt>,
+        run: impl for<'s> FnOnce(
+            &Scope<'s>,
+            &'s Timelines<'o>,
+            ExecEnvironment<'s, 'o>,
+        ) -> anyhow::Result<T>
+        + Send,
+    ) -> anyhow::Result<T> {
+        let errors = ErrorAccumulator::default();
+        let timelines = &self.timelines;
+
+        let history_lock = self.session.history.read();
+        let history = unsafe { &*(&*history_lock as *const History).cast::<History>() };
+
+        let span = diagnostics::simulation_span(self.session.trace_verbosity);
+        let env = ExecEnvironment {
+            interrupt,
+            ..self.exec_environment(&errors, history, &span)
+        };
+        timelines.clear_touched();
+        timelines.refresh_external_readers();
+        let result = self
+            .session
+            .install(|| rayon::in_place_scope(|scope| run(scope, timelines, env)));
+
+        if let Some(interrupt) = interrupt
+            && interrupt.triggered()
+        {
+            self.clear_failed_operations();
+            return Err(if interrupt.cancelled() {
+                Interrupted::Cancelled
+            } else {
+                Interrupted::TimedOut
+            }
+            .into());
+        }
+        let result = result?;
+
+        if !errors.is_empty() {
+            let messages = errors
+                .into_vec()
+                .iter()
+                .map(|e| format!("{e:#}"))
+                .collect::<Vec<_>>();
+            return Err(anyhow!(messages.join("\n")));
+        }
+
+        Ok(result)
+    }
+
+    /// Simulates several resources over the same bounds, without returning their values.
+    ///
+    /// All resources are requested in a single parallel pass, so this is faster than calling
+    /// [Plan::view] on each in turn. Use it to warm the cache before querying many resources,
+    /// for example when opening a dashboard.
+    pub fn simulate_resources(
+        &self,
+        resources: &[ResourceId],
+        bounds: impl RangeBounds<Time>,
+    ) -> anyhow::Result<()> {
+        let plugins = resources
+            .iter()
+            .map(|id| {
+                inventory::iter::<&'static dyn ResourceHistoryPlugin>
+                    .into_iter()
+                    .find(|p| p.id() == id.id())
+                    .ok_or_else(|| anyhow!("no registered resource with id {id:?}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        self.simulate_plugins(&plugins, bounds, None)
+    }
+
+    /// Samples several resources at every time any of them is written within `bounds`.
+    ///
+    /// Each row holds the value of every resource that has one at that time, so the table
+    /// changes only where something was written. If the bounds have a start, the first row is
+    /// at the start, holding the values from before it. All resources are simulated in a single
+    /// parallel pass, like [Plan::simulate_resources]. Useful for exporting a telemetry table
+    /// without choosing a grid step.
+    #[allow(clippy::type_complexity)]
+    pub fn sample_at_all_events(
+        &self,
+        resources: &[ResourceId],
+        bounds: impl RangeBounds<Time>,
+    ) -> anyhow::Result<Vec<(Time, HashMap<ResourceId, Value>)>> {
+        let descriptor = M::descriptor();
+        let mut plugins = vec![];
+        let mut ids = vec![];
+        for id in resources {
+            if ids.contains(id) {
+                continue;
+            }
+            if !descriptor
+                .resources
+                .iter()
+                .any(|resource| resource.id == *id)
+            {
+                bail!("resource {id:?} is not in the plan's model");
+            }
+            plugins.push(
+                inventory::iter::<&'static dyn ResourceHistoryPlugin>
+                    .into_iter()
+                    .find(|p| p.id() == id.id())
+                    .ok_or_else(|| anyhow!("no registered resource with id {id:?}"))?,
+            );
+            ids.push(*id);
+        }
+
+        let bounds = (bounds.start_bound().cloned(), bounds.end_bound().cloned());
+        let samples =
+            self.request_plugins(bounds, None, |timelines, dense_bounds, scope, env| {
+                plugins
+                    .iter()
+                    .map(|plugin| plugin.sample_range(timelines, dense_bounds, scope, env))
+                    .collect()
+            })?;
+
+        // Writes before the start are sampled at the start.
+        let events = samples
+            .iter()
+            .flat_map(|(times, _)| times)
+            .map(|time| match bounds.0 {
+                Bound::Included(start) | Bound::Excluded(start) => (*time).max(start),
+                Bound::Unbounded => *time,
+            })
+            .filter(|time| bounds.contains(time))
+            .collect::<BTreeSet<_>>();
+
+        Ok(events
+            .into_iter()
+            .map(|time| {
+                let row = ids
+                    .iter()
+                    .zip(&samples)
+                    .filter_map(|(id, (_, sampler))| Some((*id, sampler(time)?)))
+                    .collect();
+                (time, row)
+            })
+            .collect())
+    }
+
+    /// Reruns every operation without reading from the history, and reports the outputs
+    /// that differ from what the history has cached for the same inputs.
+    ///
+    /// Meant for auditing the cache in tests and debugging, for example to catch an
+    /// unhashed input or a hash collision. It is much slower than a normal simulation,
+    /// since nothing is reused. A correct model returns no discrepancies.
+    pub fn verify_cache(&self, bounds: impl RangeBounds<Time>) -> anyhow::Result<Vec<Discrepancy>> {
+        let plugins = M::descriptor()
+            .resources
+            .iter()
+            .filter_map(|resource| {
+                inventory::iter::<&'static dyn ResourceHistoryPlugin>
+                    .into_iter()
+                    .find(|p| p.id() == resource.id.id())
+            })
+            .collect::<Vec<_>>();
+
+        for operation in self
+            .activities
+            .values()
+            .flat_map(|decomposed| decomposed.operations.iter().copied())
+            .chain(self.timelines.daemon_operations())
+        {
+            operation.clear_cache();
+        }
+
+        let audit = CacheAudit::default();
+        self.simulate_plugins(&plugins, bounds, Some(&audit))?;
+        Ok(audit.into_vec())
+    }
+
+    /// The resources whose operations were requested during the most recent simulation,
+    /// like a [Plan::sample] or [Plan::view], sorted by ID.
+    ///
+    /// Operations that were already simulated respond without requesting their own reads,
+    /// so this is the part of the dependency graph that the query actually traversed.
+    pub fn last_touched_resources(&self) -> Vec<ResourceId> {
+        let mut result = self
+            .timelines
+            .touched()
+            .into_iter()
+            .map(ResourceId::from_id)
+            .collect::<Vec<_>>();
+        result.sort();
+        result
+    }
+
+    /// The number of values this plan holds for a [no-cache][Resource::NO_CACHE] resource.
+    ///
+    /// This is at most one per operation that writes the resource, however often they re-run.
+    pub fn uncached_entry_count<R: Resource>(&self) -> usize {
+        self.timelines.uncached_count::<R>()
+    }
+
+    /// Writes the outputs of every operation this plan has run, in the order they ran, to a
+    /// golden file for [Plan::assert_matches_golden].
+    ///
+    /// Each line holds an operation's time and the encoded values it wrote. Requires a session
+    /// with [deterministic execution][Session::with_deterministic_execution].
+    pub fn write_golden(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let log = self.execution_log()?;
+        let mut contents = log.join("\n");
+        contents.push('\n');
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Fails if the operations this plan has run, or their outputs or order, differ from a
+    /// golden file written by [Plan::write_golden].
+    ///
+    /// Requires a session with [deterministic execution][Session::with_deterministic_execution].
+    pub fn assert_matches_golden(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let log = self.execution_log()?;
+        let golden = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read golden file {}", path.display()))?;
+        let golden = golden.lines().collect::<Vec<_>>();
+
+        for (i, (actual, expected)) in log.iter().zip(&golden).enumerate() {
+            if actual != expected {
+                bail!(
+                    "operation {i} differs from {}:\n  expected: {expected}\n  actual:   {actual}",
+                    path.display()
+                );
+            }
+        }
+        if log.len() != golden.len() {
+            bail!(
+                "{} operations ran, but {} has {}",
+                log.len(),
+                path.display(),
+                golden.len()
+            );
+        }
+        Ok(())
+    }
+
+    fn execution_log(&self) -> anyhow::Result<Vec<String>> {
+        self.timelines.execution_log().ok_or_else(|| {
+            anyhow!("operations are only recorded in sessions with deterministic execution")
+        })
+    }
+
+    fn simulate_plugins(
+        &self,
+        plugins: &[&&'static dyn ResourceHistoryPlugin],
+        bounds: impl RangeBounds<Time>,
+        cache_audit: Option<&CacheAudit>,
+    ) -> anyhow::Result<()> {
+        self.request_plugins(bounds, cache_audit, |timelines, bounds, scope, env| {
+            plugins
+                .iter()
+                .map(|plugin| plugin.request_range(timelines, bounds, scope, env))
+                .collect()
+        })?;
+        Ok(())
+    }
+
+    /// Spawns the requests made by `request` for several resources in a single parallel pass,
+    /// and waits for their outputs.
+    fn request_plugins<T>(
+        &self,
+        bounds: impl RangeBounds<Time>,
+        cache_audit: Option<&CacheAudit>,
+        request: impl for<'s> FnOnce(
+            &'s Timelines<'o>,
+            (Bound<DenseTime>, Bound<DenseTime>),
+            &Scope<'s>,
+            ExecEnvironment<'s, 'o>,
+        ) -> anyhow::Result<Vec<Pending<'o, T>>>
+        + Send,
+    ) -> anyhow::Result<Vec<T>> {
+        let bounds = self.prepare_bounds(bounds)?;
+        let errors = ErrorAccumulator::default();
+        let timelines = &self.timelines;
+
+        let history_lock = self.session.history.read();
+        let history = unsafe { &*(&*history_lock as *const History).cast::<History>() };
+
+        let span = diagnostics::simulation_span(self.session.trace_verbosity);
+        let env = ExecEnvironment {
+            cache_audit,
+            ..self.exec_environment(&errors, history, &span)
+        };
+        timelines.clear_touched();
+        timelines.refresh_external_readers();
+        let pending = self
+            .session
+            .install(|| rayon::scope(|scope| request(timelines, bounds, scope, env)));
+
+        let outputs = pending?
+            .into_iter()
+            .map(|wait| wait())
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        if !errors.is_empty() {
+            let messages = errors
+                .into_vec()
+                .iter()
+                .map(|e| format!("{e:#}"))
+                .collect::<Vec<_>>();
+            return Err(anyhow!(messages.join("\n")));
+        }
+
+        Ok(outputs)
+    }
+
+    /// Forgets the outputs of operations that failed, so that an interrupted simulation
+    /// doesn't leave the errors of the operations it skipped in their caches.
+    fn clear_failed_operations(&self) {
+        for op in self
+            .activities
+            .values()
+            .flat_map(|decomposed| decomposed.operations.iter().copied())
+            .chain(self.timelines.daemon_operations())
+        {
+            if op.failed() {
+                op.clear_cache();
+            }
+        }
+    }
+
+    /// Converts the bounds of a query to dense times, and runs the recurring daemons far
+    /// enough to cover them.
+    fn prepare_bounds(
+        &self,
+        bounds: impl RangeBounds<Time>,
+    ) -> anyhow::Result<(Bound<DenseTime>, Bound<DenseTime>)> {
+        let bounds = dense_bounds(bounds);
+        self.timelines.run_recurring_daemons(&bounds)?;
+        Ok(bounds)
+    }
+
+    fn exec_environment<'s>(
+        &self,
+        errors: &'s ErrorAccumulator,
+        history: &'o History,
+        span: &'s SimulationSpan,
+    ) -> ExecEnvironment<'s, 'o> {
+        ExecEnvironment {
+            errors,
+            history,
+            stack_counter: 0,
+            float_policy: self.session.float_policy,
+            operation_timeout: self.session.operation_timeout.as_ref(),
+            coincident_writes: self.session.coincident_writes,
+            grounding_errors: self.session.grounding_errors,
+            cache_audit: None,
+            trace: TraceContext::new(span),
+            interrupt: None,
+        }
+    }
+
+    /// Samples a resource at a specific time.
+    ///
+    /// If the resource's data [interpolates][Data::INTERPOLATES], the sample is blended
+    /// between the writes before and after `time`.
+    ///
+    /// When `time` is exactly the time of a write, the sample is taken from the newly written
+    /// value by default, so a [Linear][crate::Linear] resource is sampled on the new line. The
+    /// session's [WriteSamplePolicy] can choose the previous value instead.
+    pub fn sample<R: Resource>(&self, time: Time) -> anyhow::Result<<R::Data as Data<'o>>::Sample> {
+        let interpolates = <R::Data as Data<'o>>::INTERPOLATES;
+        let previous =
+            !interpolates && self.session.write_sample_policy == WriteSamplePolicy::PreviousSegment;
+        let nodes = if interpolates {
+            self.timelines
+                .range_inclusive_next(self.prepare_bounds(time..=time)?)
+        } else if previous {
+            self.timelines
+                .range_inclusive_previous(self.prepare_bounds(time..=time)?)
+        } else {
+            self.timelines.range(self.prepare_bounds(time..=time)?)
+        };
+        let view = self.simulate_nodes::<R>(nodes)?;
+        let view = view.into_iter().collect::<BTreeMap<_, _>>();
+        let before = if previous {
+            view.range(..time).next_back()
+        } else {
+            None
+        };
+        let latest = before
+            .or_else(|| view.range(..=time).next_back())
+            .ok_or_else(|| anyhow!("No operations to sample found at or before {time}"))?;
+        if interpolates
+            && let Some(next) = view.range((Bound::Excluded(time), Bound::Unbounded)).next()
+        {
+            return Ok(R::Data::interpolate(
+                (*latest.0, *latest.1),
+                (*next.0, *next.1),
+                time,
+            ));
+        }
+        Ok(R::Data::sample(*latest.1, time))
+    }
+
+    /// The hash of the history entry that `R` resolves to at `time`, used by
+    /// [Session::shares_state].
+    pub(crate) fn state_hash<R: Resource>(&self, time: Time) -> anyhow::Result<u64> {
+        let nodes = self.timelines.range(self.prepare_bounds(time..=time)?);
+        self.fold_hashed_nodes::<R, _>(nodes, None, |latest, write_time, hash, _| {
+            if write_time <= time {
+                Some(hash)
+            } else {
+                latest
+            }
+        })?
+        .ok_or_else(|| anyhow!("No operations found at or before {time}"))
+    }
+
+    /// Whether the plan was created by `session`.
+    pub(crate) fn is_in(&self, session: &Session) -> bool {
+        std::ptr::eq(self.session, session)
+    }
+
+    /// Samples the value of every resource in the model at `time`.
+    ///
+    /// A new plan can be started from the snapshot with [InitialConditions::from_snapshot],
+    /// to fork a plan from a point in its timeline.
+    pub fn snapshot(&self, time: Time) -> anyhow::Result<ModelSnapshot> {
+        Ok(ModelSnapshot {
+            time,
+            values: self.sample_resources(time, self.prepare_bounds(time..=time)?)?,
+        })
+    }
+
+    /// Samples the value of every resource in the model at `time`, from the operations
+    /// within `bounds`.
+    fn sample_resources(
+        &self,
+        time: Time,
+        bounds: impl RangeBounds<DenseTime> + Clone,
+    ) -> anyhow::Result<InitialConditions> {
+        struct Snapshotter<'p, 'o, M: Model<'o>, B> {
+            plan: &'p Plan<'o, M>,
+            time: Time,
+            bounds: B,
+            values: InitialConditions,
+        }
+
+        impl<'o, M: Model<'o> + 'o, B: RangeBounds<DenseTime> + Clone> ResourceVisitor
+            for Snapshotter<'_, 'o, M, B>
+        {
+            fn visit<R: Resource>(&mut self) -> anyhow::Result<()> {
+                let nodes = self.plan.timelines.range::<R>(self.bounds.clone());
+                let view = self.plan.simulate_nodes::<R>(nodes)?;
+                let view = view.into_iter().collect::<BTreeMap<_, _>>();
+                let (_, read) = view.range(..=self.time).next_back().ok_or_else(|| {
+                    anyhow!("No operations to sample found at or before {}", self.time)
+                })?;
+                let value = R::Data::from_read(*read, self.time);
+                self.values = std::mem::take(&mut self.values).insert::<R>(value);
+                Ok(())
+            }
+        }
+
+        let mut snapshotter = Snapshotter {
+            plan: self,
+            time,
+            bounds,
+            values: InitialConditions::new(),
+        };
+        M::visit_resources(&mut snapshotter)?;
+        Ok(snapshotter.values)
+    }
+
+    /// Writes the graph of dependencies between the plan's operations in graphviz DOT format.
+    ///
+    /// Each operation is a node labeled with the resources it writes and its time, and each read
+    /// is an edge from the reading operation to the operation it read from. Edges are recorded
+    /// during simulation, so only reads that have been simulated are included. Reads that
+    /// weren't served by an operation in the plan, like reads of the initial conditions, point
+    /// to plain nodes named after the resource.
+    pub fn export_dag(&self, mut writer: impl std::io::Write) -> anyhow::Result<()> {
+        let mut ids = self.activities.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        let operations = ids
+            .iter()
+            .flat_map(|id| self.activities[id].operations.iter().copied())
+            .chain(self.timelines.daemon_operations())
+            .collect::<Vec<_>>();
+        let indices = operations
+            .iter()
+            .enumerate()
+            .map(|(i, op)| (*op as *const _ as *const u8 as usize, i))
+            .collect::<HashMap<_, _>>();
+
+        writeln!(writer, "digraph operations {{")?;
+        for (i, op) in operations.iter().enumerate() {
+            let info = op.info();
+            let writes = info
+                .writes
+                .iter()
+                .map(|w| w.label)
+                .collect::<Vec<_>>()
+                .join(", ");
+            let time = match info.time {
+                OperationTime::Static(time) => time.to_string(),
+                OperationTime::Dynamic { min, max } => format!("{min} to {max}"),
+            };
+            writeln!(writer, "    op{i} [label=\"{writes}\\n{time}\"];")?;
+        }
+
+        let mut others = HashMap::new();
+        for (i, op) in operations.iter().enumerate() {
+            for (label, address) in op.upstreams() {
+                let upstream = match indices.get(&address) {
+                    Some(j) => format!("op{j}"),
+                    None => {
+                        let next = others.len();
+                        let k = *others.entry(address).or_insert(next);
+                        if k == next {
+                            writeln!(writer, "    other{k} [label=\"{label}\", shape=plaintext];")?;
+                        }
+                        format!("other{k}")
+                    }
+                };
+                writeln!(writer, "    op{i} -> {upstream} [label=\"{label}\"];")?;
+            }
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+
+    /// Writes the execution time of every operation body this plan has run, in the folded
+    /// stack format read by `inferno` and other flamegraph tools.
+    ///
+    /// Each line is one operation, under a frame for the resources it writes, with the total
+    /// nanoseconds spent in its body. Operations served from the cache are not included.
+    #[cfg(feature = "profiling")]
+    pub fn export_trace(&self, writer: impl std::io::Write) -> anyhow::Result<()> {
+        Ok(self.timelines.trace().write_folded(writer)?)
+    }
+
+    /// Samples the given resources at wall-clock intervals, starting at `start`.
+    ///
+    /// Simulation time advances `speed` times faster than the wall clock. See [Playback].
+    pub fn playback<S: PlaybackResources<'o>>(
+        &self,
+        start: Time,
+        speed: f64,
+        interval: std::time::Duration,
+    ) -> Playback<'_, 'o, M, S> {
+        Playback::new(self, start, speed, interval)
+    }
+
+    /// Copies the cached history of the given resources into another session,
+    /// so that plans in that session can reuse this plan's simulation results.
+    ///
+    /// Entries the target session already has are kept.
+    pub fn export_history_into(
+        &self,
+        target: &Session,
+        resources: &[ResourceId],
+    ) -> anyhow::Result<()> {
+        if std::ptr::eq(self.session, target) {
+            return Ok(());
+        }
+        let source = self.session.history.read();
+        let mut target = target.history.write();
+        source.export_into(&mut target, resources)
+    }
+}
+
+/// The values of a plan's resources at one time, taken with [Plan::snapshot].
+pub struct ModelSnapshot {
+    /// The time the resources were sampled at.
+    pub time: Time,
+    pub(crate) values: InitialConditions,
+}
+
+impl ModelSnapshot {
+    /// The value of a resource at the time of the snapshot.
+    pub fn get<R: Resource>(&self) -> Option<&R::Data> {
+        self.values.get::<R>()
+    }
+}
+
+/// A type-erased resource value, returned by [Plan::sample_at_all_events].
+pub struct Value(Box<dyn Any + Send + Sync>);
+
+impl Value {
+    pub(crate) fn new<R: Resource>(data: R::Data) -> Self {
+        Self(Box::new(data))
+    }
+
+    /// The value, if it belongs to a resource with the data type of `R`.
+    pub fn get<R: Resource>(&self) -> Option<&R::Data> {
+        self.0.downcast_ref()
+    }
+}
+
+/// Which value [Plan::sample] uses when sampling a resource at exactly the time of a write.
+///
+/// Only matters for data whose samples depend on the time, like [Linear][crate::Linear]:
+/// the value written at that time and the value before it can sample differently there.
+/// Data that [interpolates][Data::INTERPOLATES] is blended between writes, so it is the
+/// same either way.
+///
+/// Set with [Session::with_write_sample_policy].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WriteSamplePolicy {
+    /// Sample the newly written value.
+    #[default]
+    NewSegment,
+    /// Sample the value from before the write, as if approaching the time from the left.
+    /// If there is no earlier value, the newly written one is sampled.
+    PreviousSegment,
+}
+
+/// An operation output that differs from the cached output for the same inputs,
+/// reported by [Plan::verify_cache].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discrepancy {
+    /// The label of the written resource.
+    pub resource: &'static str,
+    /// When the operation wrote it.
+    pub time: Time,
+    /// The hash of the operation's inputs, under which the cached output is stored.
+    pub hash: u64,
+}
+
+/// Where an activity inserted with [Plan::insert_anchored] starts, relative to its parent.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Anchor {
+    /// An offset from the parent's start.
+    Start(Duration),
+    /// An offset from the parent's end.
+    End(Duration),
+}
+
+/// Where an activity is in a plan, returned by [Plan::children] and [Plan::activity_tree].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ActivityPlacement {
+    pub id: ActivityId,
+    pub start: Time,
+    /// The start plus the duration the activity returned.
+    pub end: Time,
+    /// How the activity is placed relative to its parent, if it has one.
+    pub anchor: Option<Anchor>,
+}
+
+/// An activity and the activities anchored to it, returned by [Plan::activity_tree].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActivityTree {
+    pub activity: ActivityPlacement,
+    /// The subtrees of the activities anchored to this one, in order of their start times.
+    pub children: Vec<ActivityTree>,
+}
+
+/// The activities inserted into and removed from a plan since it was created or branched,
+/// returned by [Plan::delta].
+///
+/// An activity that was replaced under the same ID, like by [Plan::merge_adjacent_activities],
+/// is in both sets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlanDelta {
+    /// The IDs of activities inserted into the plan, and still in it.
+    pub inserted: BTreeSet<ActivityId>,
+    /// The IDs of activities removed from the plan that were in it when it was branched.
+    pub removed: BTreeSet<ActivityId>,
+}
+
+/// A new activity version, unique across all plans.
+fn next_version() -> u64 {
+    static NEXT_VERSION: AtomicU64 = AtomicU64::new(0);
+    NEXT_VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Copies an activity by round-tripping it through its serialized form.
+#[cfg(feature = "serde")]
+fn clone_activity(activity: &dyn Activity) -> anyhow::Result<Box<dyn Activity>> {
+    Ok(serde_json::from_value(serde_json::to_value(activity)?)?)
+}
+
+/// A view that stops at the plan's horizon, returned by [Plan::view_to_horizon].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HorizonView<T> {
+    /// The values written within the requested bounds, up to the horizon.
+    pub values: Vec<(Time, T)>,
+    /// The time after which the requested bounds are past the plan's last operation, if they
+    /// extend that far. Values from then on are extrapolated, not simulated.
+    pub extrapolated_from: Option<Time>,
+}
+
+/// A resource sampled at evenly spaced times, returned by [Plan::profile].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Profile<T> {
+    /// The label of the sampled resource.
+    pub resource: &'static str,
+    /// The resource's unit, if it declared one.
+    pub unit: Option<&'static str>,
+    pub points: Vec<(Time, T)>,
+}
+
+impl<T> Profile<T> {
+    /// Converts each sampled value, like to take the value of a [Polynomial][crate::Polynomial]
+    /// before writing it with [Profile::write_csv].
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> Profile<U> {
+        Profile {
+            resource: self.resource,
+            unit: self.unit,
+            points: self
+                .points
+                .into_iter()
+                .map(|(time, value)| (time, f(value)))
+                .collect(),
+        }
+    }
+
+    /// Writes the profile as two CSV columns, the time and the value.
+    ///
+    /// The value column is headed by the resource's label, followed by its unit in parentheses
+    /// if it has one.
+    pub fn write_csv(&self, mut writer: impl std::io::Write) -> anyhow::Result<()>
+    where
+        T: std::fmt::Display,
+    {
+        match self.unit {
+            Some(unit) => writeln!(writer, "time,{} ({unit})", self.resource)?,
+            None => writeln!(writer, "time,{}", self.resource)?,
+        }
+        for (time, value) in &self.points {
+            writeln!(writer, "{time},{value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Statistics about a simulation, passed to hooks registered with [Plan::on_after_view].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimStats {
+    /// The label of the viewed resource.
+    pub resource: &'static str,
+    /// How many values the view returned.
+    pub values: usize,
+    /// The wall-clock time spent simulating and collecting the values.
+    pub elapsed: std::time::Duration,
+}
+
+/// Options for [Plan::view_with].
+#[derive(Debug, Clone, Default)]
+pub struct SimOptions {
+    /// How long the simulation may run before it is aborted.
+    pub timeout: Option<std::time::Duration>,
+    /// Aborts the simulation when cancelled, for example from another thread.
+    pub cancel_token: Option<CancelToken>,
+}
+
+/// The error returned by [Plan::view_with] when the simulation is aborted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interrupted {
+    Cancelled,
+    TimedOut,
+}
+
+impl std::fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Interrupted::Cancelled => write!(f, "simulation was cancelled"),
+            Interrupted::TimedOut => write!(f, "simulation timed out"),
+        }
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+type AfterViewHook<'o> = Box<dyn Fn(&SimStats) + Send + Sync + 'o>;
+
+/// A unique constraint ID, returned by [Plan::add_constraint].
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct ConstraintId(u32);
+
+/// An interval in which a plan violates one of its constraints, returned by [Plan::violations].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub constraint: ConstraintId,
+    /// The first time the constraint fails.
+    pub start: Time,
+    /// The time the constraint holds again, or the end of the requested bounds.
+    pub end: Time,
+}
+
+/// Checks a constraint over some bounds, and returns the intervals in which it fails.
+type ConstraintCheck<'o, M> =
+    Box<dyn Fn(&Plan<'o, M>, &Range<Time>) -> anyhow::Result<Vec<(Time, Time)>> + Send + Sync + 'o>;
+
+/// Whether the bounds contain no times at all.
+fn is_empty(bounds: &impl RangeBounds<Time>) -> bool {
+    match (bounds.start_bound(), bounds.end_bound()) {
+        (Bound::Included(start), Bound::Included(end)) => start > end,
+        (Bound::Included(start) | Bound::Excluded(start), Bound::Excluded(end))
+        | (Bound::Excluded(start), Bound::Included(end)) => start >= end,
+        _ => false,
+    }
+}
+
+/// Whether a constraint holds at the start of the segment `[start, end)` of one write, and the
+/// first time it changes within the segment, if it does.
+///
+/// Only the first and last nanosecond of the segment are checked, and if they differ, the
+/// change is found by bisection, so the result is assumed to change at most once.
+fn segment_crossing(
+    start: Time,
+    end: Time,
+    satisfied: impl Fn(Time) -> bool,
+) -> (bool, Option<Time>) {
+    let nanosecond = Duration::from_total_nanoseconds(1);
+    let at_start = satisfied(start);
+    let last = end - nanosecond;
+    if last <= start || satisfied(last) == at_start {
+        return (at_start, None);
+    }
+    // `low` has the result at the start, and `high` doesn't.
+    let (mut low, mut high) = (start, last);
+    while high - low > nanosecond {
+        let middle = low + Duration::from_total_nanoseconds((high - low).total_nanoseconds() / 2);
+        if satisfied(middle) == at_start {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    (at_start, Some(high))
+}
+
+fn dense_bounds(bounds: impl RangeBounds<Time>) -> (Bound<DenseTime>, Bound<DenseTime>) {
+    (
+        bounds
+            .start_bound()
+            .map(|t| DenseTime::first_at(epoch_to_duration(*t))),
+        bounds
+            .end_bound()
+            .map(|t| DenseTime::last_at(epoch_to_duration(*t))),
+    )
+}
+
+impl<'o, M: Model<'o>> Drop for Plan<'o, M> {
+    fn drop(&mut self) {
+        if let (Some(reachability), Some(key)) = (&self.session.reachability, self.reachability_key)
+        {
+            reachability.lock().close(key);
+        }
+        for decomposed in self.activities.values() {
+            unsafe { std::ptr::drop_in_place(decomposed.activity) };
+        }
+    }
+}
+
+/// A plan's start, initial conditions, and activities, which is how plans are serialized.
+///
+/// Activities are borrowed from the plan when saving, and boxed when loading.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SavedPlan<A> {
+    start: Time,
+    /// The initial conditions, keyed by resource label like [InitialConditions::from_json].
+    initial_conditions: BTreeMap<String, serde_json::Value>,
+    next_id: ActivityId,
+    activities: Vec<SavedActivity<A>>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SavedActivity<A> {
+    id: ActivityId,
+    time: Time,
+    /// The parent and anchor, if the activity was inserted with [Plan::insert_anchored].
+    anchor: Option<(ActivityId, Anchor)>,
+    activity: A,
+}
+
+#[cfg(feature = "serde")]
+impl SavedPlan<Box<dyn Activity>> {
+    pub(crate) fn start(&self) -> Time {
+        self.start
+    }
+
+    /// Resolves the saved initial conditions against the resources of `M`.
+    pub(crate) fn initial_conditions<'o, M: Model<'o>>(
+        &mut self,
+    ) -> anyhow::Result<InitialConditions> {
+        struct Decoder {
+            values: BTreeMap<String, serde_json::Value>,
+            result: InitialConditions,
+        }
+
+        impl ResourceVisitor for Decoder {
+            fn visit<R: Resource>(&mut self) -> anyhow::Result<()> {
+                if let Some(value) = self.values.remove(R::LABEL) {
+                    self.result.insert_json::<R>(value).with_context(|| {
+                        format!("could not read the initial condition of {}", R::LABEL)
+                    })?;
+                }
+                Ok(())
+            }
+        }
+
+        let mut decoder = Decoder {
+            values: std::mem::take(&mut self.initial_conditions),
+            result: InitialConditions::new(),
+        };
+        M::visit_resources(&mut decoder)?;
+        if let Some(label) = decoder.values.keys().next() {
+            bail!("the saved plan has an initial condition for {label}, which is not in the model");
+        }
+        Ok(decoder.result)
+    }
+}
+
+/// Writes the plan's start, initial conditions, and activities, which can be loaded
+/// back with [Session::load_plan].
+#[cfg(feature = "serde")]
+impl<'o, M: Model<'o> + 'o> Serialize for Plan<'o, M> {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.saved()
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+/// Writes the IDs of the plan's activities.
+#[cfg(not(feature = "serde"))]
 impl<'o, M: Model<'o>> Serialize for Plan<'o, M> {
     fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
+        use serde::ser::SerializeSeq;
+
         let mut seq = serializer.serialize_seq(Some(self.activities.len()))?;
         for id in self.activities.keys() {
             seq.serialize_element(&id)?;
@@ -244,3 +2690,75 @@ impl<'o, M: Model<'o>> Serialize for Plan<'o, M> {
         seq.end()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{Activity, Duration, Ops, Session, Time, initial_conditions, model};
+    use peregrine_macros::internal_op;
+    use serde::{Deserialize, Serialize};
+
+    #[allow(unused_imports)]
+    use crate as peregrine;
+
+    model! {
+        Counter {
+            count: u32;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Increment;
+
+    #[typetag::serde]
+    impl Activity for Increment {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> anyhow::Result<Duration> {
+            ops += internal_op! { m: count += 1; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    fn seconds(s: f64) -> Time {
+        Time::from_tai_seconds(s)
+    }
+
+    #[test]
+    fn validate_integrity() -> anyhow::Result<()> {
+        let session = Session::new();
+        let mut plan =
+            session.new_plan::<Counter>(seconds(0.0), initial_conditions! { count: 0 })?;
+        let first = plan.insert(seconds(1.0), Increment)?;
+        plan.insert(seconds(2.0), Increment)?;
+        plan.remove(first)?;
+        plan.validate_integrity()?;
+
+        // Forget an activity's operations without removing them from the timeline.
+        let id = plan.insert(seconds(3.0), Increment)?;
+        let orphaned = plan
+            .activities
+            .get_mut(&id)
+            .unwrap()
+            .operations
+            .pop()
+            .unwrap();
+        let message = plan.validate_integrity().unwrap_err().to_string();
+        assert!(
+            message.contains("1 orphaned operation(s) in the count timeline"),
+            "{message}"
+        );
+
+        // Remove an operation from the timeline without forgetting it.
+        plan.activities
+            .get_mut(&id)
+            .unwrap()
+            .operations
+            .push(orphaned);
+        orphaned.remove_self(&plan.timelines, false)?;
+        let message = plan.validate_integrity().unwrap_err().to_string();
+        assert!(
+            message.contains("missing from the count timeline"),
+            "{message}"
+        );
+
+        Ok(())
+    }
+}
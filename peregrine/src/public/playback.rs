@@ -0,0 +1,84 @@
+//! Wall-clock playback of a plan, for dashboards and operators watching a plan unfold.
+
+use crate::{Data, Duration, Model, Plan, Resource, Time};
+use std::marker::PhantomData;
+use std::time::Instant;
+
+/// A tuple of resources that can be sampled together during [Plan::playback].
+///
+/// Implemented for tuples of up to four resources, e.g. `(a,)` or `(a, b)`.
+pub trait PlaybackResources<'o> {
+    type Samples;
+
+    fn sample<M: Model<'o> + 'o>(plan: &Plan<'o, M>, time: Time) -> anyhow::Result<Self::Samples>;
+}
+
+macro_rules! impl_playback_resources {
+    ($($r:ident),+) => {
+        impl<'o, $($r: Resource),+> PlaybackResources<'o> for ($($r,)+) {
+            type Samples = ($(<$r::Data as Data<'o>>::Sample,)+);
+
+            fn sample<M: Model<'o> + 'o>(plan: &Plan<'o, M>, time: Time) -> anyhow::Result<Self::Samples> {
+                Ok(($(plan.sample::<$r>(time)?,)+))
+            }
+        }
+    };
+}
+
+impl_playback_resources!(A);
+impl_playback_resources!(A, B);
+impl_playback_resources!(A, B, C);
+impl_playback_resources!(A, B, C, D);
+
+/// A polling iterator that samples resources at wall-clock intervals.
+///
+/// Each call to [Iterator::next] blocks until the next tick is due, then yields the
+/// simulation time of that tick and the sampled values. Ticks are `interval` apart on
+/// the wall clock and `interval * speed` apart in simulation time.
+///
+/// The iterator never ends on its own; use [Iterator::take] or similar to stop it.
+pub struct Playback<'p, 'o, M: Model<'o>, S: PlaybackResources<'o>> {
+    plan: &'p Plan<'o, M>,
+    start: Time,
+    step: Duration,
+    interval: std::time::Duration,
+    started: Option<Instant>,
+    tick: u32,
+    resources: PhantomData<S>,
+}
+
+impl<'p, 'o, M: Model<'o> + 'o, S: PlaybackResources<'o>> Playback<'p, 'o, M, S> {
+    pub(crate) fn new(
+        plan: &'p Plan<'o, M>,
+        start: Time,
+        speed: f64,
+        interval: std::time::Duration,
+    ) -> Self {
+        Self {
+            plan,
+            start,
+            step: Duration::from_seconds(interval.as_secs_f64() * speed),
+            interval,
+            started: None,
+            tick: 0,
+            resources: PhantomData,
+        }
+    }
+}
+
+impl<'o, M: Model<'o> + 'o, S: PlaybackResources<'o>> Iterator for Playback<'_, 'o, M, S> {
+    type Item = anyhow::Result<(Time, S::Samples)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        let due = started + self.interval * self.tick;
+        let now = Instant::now();
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+
+        let time = self.start + self.step * self.tick as i64;
+        self.tick += 1;
+        Some(S::sample(self.plan, time).map(|samples| (time, samples)))
+    }
+}
@@ -9,14 +9,21 @@ use crate as peregrine;
 use crate::Time;
 use crate::public::resource::{Data, MaybeHash};
 
-pub(crate) fn init_builtins_timelines<'o>(time: Duration, timelines: &mut Timelines<'o>) {
+pub(crate) fn init_builtins_timelines<'o>(
+    time: Duration,
+    elapsed_tick: Option<Duration>,
+    timelines: &mut Timelines<'o>,
+) {
     timelines.init_for_resource(
         time,
         InitialConditionOp::<'o, now>::new(time, PeregrineTimeTracker),
     );
     timelines.init_for_resource(
         time,
-        InitialConditionOp::<'o, elapsed>::new(time, PeregrineElapsedTimeTracker),
+        InitialConditionOp::<'o, elapsed>::new(
+            time,
+            PeregrineElapsedTimeTracker { tick: elapsed_tick },
+        ),
     );
 }
 
@@ -30,6 +37,9 @@ peregrine::resource!(
     ///
     /// Using this resource will prevent your operation from using cached
     /// values if it is translated in time.
+    ///
+    /// Inside `op!`, the `ops_time` variable holds the same time without
+    /// reading this resource.
     pub now: PeregrineTimeTracker;
 
     /// A resource for the current elapsed [Duration] of the simulation,
@@ -38,6 +48,11 @@ peregrine::resource!(
     /// This is a builtin and will automatically be added to all models.
     /// Unlike [now], elapsed does contain data that could be overwritten,
     /// but this is illegal and if you try to do so it will [panic] at runtime.
+    ///
+    /// It can be rounded down to a tick with [Session::with_elapsed_precision][crate::Session::with_elapsed_precision].
+    ///
+    /// Inside `op!`, the `ops_elapsed` variable holds the same duration, unrounded, without
+    /// reading this resource.
     pub elapsed: PeregrineElapsedTimeTracker;
 );
 
@@ -72,7 +87,10 @@ impl Data<'_> for PeregrineTimeTracker {
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, Default)]
 #[doc(hidden)]
-pub struct PeregrineElapsedTimeTracker;
+pub struct PeregrineElapsedTimeTracker {
+    /// The tick that samples are rounded down to, if any.
+    tick: Option<Duration>,
+}
 
 impl MaybeHash for PeregrineElapsedTimeTracker {
     fn is_hashable(&self) -> bool {
@@ -85,18 +103,21 @@ impl MaybeHash for PeregrineElapsedTimeTracker {
 }
 
 impl Data<'_> for PeregrineElapsedTimeTracker {
-    type Read = Time;
+    type Read = (Time, Option<Duration>);
     type Sample = Duration;
 
     fn to_read(&self, written: Time) -> Self::Read {
-        written
+        (written, self.tick)
     }
 
     fn from_read(_read: Self::Read, _now: Time) -> Self {
         panic!("You cannot write to the `elapsed` builtin. Use a Stopwatch.")
     }
 
-    fn sample(written: Self::Read, now: Time) -> Self::Sample {
-        now - written
+    fn sample((written, tick): Self::Read, now: Time) -> Self::Sample {
+        match tick {
+            Some(tick) => (now - written).floor(tick),
+            None => now - written,
+        }
     }
 }
@@ -0,0 +1,80 @@
+use crate::Time;
+use crate::public::resource::{Data, MaybeHash};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+
+/// The data of an event log resource, appended to with `emit: log <- event;` in [op][crate::op!].
+///
+/// Each write holds the events emitted by one operation, so the log is the resource's timeline of
+/// writes rather than a growing buffer, and emitting is constant time. Read the events back with
+/// [Plan::events][crate::Plan::events]. Operations that read the resource see only the events
+/// of the latest operation that emitted any.
+///
+/// This is deliberately not a [HistoryList][crate::HistoryList]. Pushing onto a list means reading
+/// the version before it, so every emitting operation would depend on the one before it, and
+/// changing an early operation would resimulate every later one that emits to the same log.
+/// Keeping each operation's events in its own write leaves emitters independent of each other.
+/// Use a [HistoryList][crate::HistoryList] instead if operations need to read the whole log.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Events<E>(Vec<E>);
+
+impl<E> Events<E> {
+    /// An empty log, to use as the resource's initial condition.
+    pub fn new() -> Self {
+        Events(Vec::new())
+    }
+
+    /// Appends an event to this write.
+    pub fn push(&mut self, event: E) {
+        self.0.push(event);
+    }
+
+    /// The events in this write, in the order they were emitted.
+    pub fn emitted(&self) -> &[E] {
+        &self.0
+    }
+
+    /// The latest event.
+    pub fn latest(&self) -> Option<&E> {
+        self.0.last()
+    }
+}
+
+impl<E> Default for Events<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: MaybeHash> MaybeHash for Events<E> {
+    fn is_hashable(&self) -> bool {
+        self.0.iter().all(|e| e.is_hashable())
+    }
+
+    fn hash_unchecked<H: Hasher>(&self, state: &mut H) {
+        self.0.len().hash(state);
+        for e in &self.0 {
+            e.hash_unchecked(state);
+        }
+    }
+}
+
+impl<'h, E> Data<'h> for Events<E>
+where
+    E: 'static + MaybeHash + Clone + Serialize + DeserializeOwned + Send + Sync,
+{
+    type Read = &'h Self;
+    type Sample = Option<&'h E>;
+
+    fn to_read(&self, _written: Time) -> Self::Read {
+        let ptr = self as *const Self;
+        unsafe { &*ptr }
+    }
+    fn from_read(read: Self::Read, _now: Time) -> Self {
+        read.clone()
+    }
+    fn sample(read: Self::Read, _now: Time) -> Self::Sample {
+        read.latest()
+    }
+}
@@ -0,0 +1,119 @@
+use crate::public::resource::Data;
+use crate::{MaybeHash, Time};
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+
+/// A packed set of up to 64 boolean flags.
+///
+/// Cheaper to store and hash than a [resource group][crate::resource!] of `bool`s, when the
+/// flags tend to change together. Flags are read and written in operations by index, like
+/// `m: fault_flags.set(3);` and `w: safe_mode = r: fault_flags.get(3);`.
+///
+/// More than 64 flags don't compile:
+///
+/// ```compile_fail
+/// let flags = peregrine::Flags::<65>::default();
+/// ```
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Flags<const N: usize>(u64);
+
+impl<const N: usize> Flags<N> {
+    /// Evaluated wherever flags are created or indexed, so that `N` can't exceed the bits
+    /// of a `u64`.
+    const FITS: () = assert!(N <= 64, "Flags can hold at most 64 flags");
+
+    /// All flags cleared.
+    pub fn new() -> Self {
+        let () = Self::FITS;
+        Self(0)
+    }
+
+    /// Flags from their packed bits, with flag `i` in bit `i`.
+    ///
+    /// # Panics
+    ///
+    /// If any bit at or above `N` is set.
+    pub fn from_bits(bits: u64) -> Self {
+        assert!(
+            N == 64 || bits >> N == 0,
+            "bits {bits:#x} don't fit in {N} flags"
+        );
+        let mut flags = Self::new();
+        flags.0 = bits;
+        flags
+    }
+
+    /// The packed bits, with flag `i` in bit `i`.
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub fn get(&self, index: usize) -> bool {
+        self.0 & Self::mask(index) != 0
+    }
+
+    pub fn set(&mut self, index: usize) {
+        self.0 |= Self::mask(index);
+    }
+
+    pub fn clear(&mut self, index: usize) {
+        self.0 &= !Self::mask(index);
+    }
+
+    /// Sets or clears a flag.
+    pub fn assign(&mut self, index: usize, value: bool) {
+        if value {
+            self.set(index);
+        } else {
+            self.clear(index);
+        }
+    }
+
+    /// How many flags are set.
+    pub fn count(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    fn mask(index: usize) -> u64 {
+        let () = Self::FITS;
+        assert!(
+            index < N,
+            "flag index {index} is out of range for {N} flags"
+        );
+        1 << index
+    }
+}
+
+impl<const N: usize> Default for Flags<N> {
+    fn default() -> Self {
+        let () = Self::FITS;
+        Self(0)
+    }
+}
+
+impl<'h, const N: usize> Data<'h> for Flags<N> {
+    type Read = Self;
+    type Sample = Self;
+
+    fn to_read(&self, _written: Time) -> Self::Read {
+        *self
+    }
+
+    fn from_read(read: Self::Read, _now: Time) -> Self {
+        read
+    }
+
+    fn sample(read: Self::Read, _now: Time) -> Self::Sample {
+        read
+    }
+}
+
+impl<const N: usize> MaybeHash for Flags<N> {
+    fn is_hashable(&self) -> bool {
+        true
+    }
+
+    fn hash_unchecked<H: Hasher>(&self, state: &mut H) {
+        self.hash(state);
+    }
+}
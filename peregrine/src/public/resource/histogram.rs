@@ -0,0 +1,153 @@
+use crate::Time;
+use crate::public::resource::{Data, MaybeHash};
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// A histogram of samples accumulated over a plan, like the distribution of observation durations.
+///
+/// Samples are added in operations with `m: durations.add(value);`. Bin `i` counts the samples
+/// in `edges[i]..edges[i + 1]`. Samples below the first edge or at or above the last edge are
+/// counted separately, as [underflow][Histogram::underflow] and [overflow][Histogram::overflow].
+///
+/// The edges are shared between all versions of the histogram, so adding a sample only
+/// copies the bin counts. Operations that read the histogram get it by reference.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Histogram {
+    edges: Arc<[f64]>,
+    counts: Vec<u64>,
+    underflow: u64,
+    overflow: u64,
+}
+
+impl Histogram {
+    /// An empty histogram with the given bin edges, which must be ascending.
+    ///
+    /// # Panics
+    ///
+    /// If there are fewer than two edges, or they aren't strictly ascending.
+    pub fn new(edges: impl Into<Vec<f64>>) -> Self {
+        let edges = edges.into();
+        assert!(edges.len() >= 2, "a histogram needs at least two bin edges");
+        assert!(
+            edges.windows(2).all(|w| w[0] < w[1]),
+            "histogram bin edges must be strictly ascending"
+        );
+        Self {
+            counts: vec![0; edges.len() - 1],
+            edges: edges.into(),
+            underflow: 0,
+            overflow: 0,
+        }
+    }
+
+    /// An empty histogram with `bins` equal-width bins from `min` to `max`.
+    pub fn uniform(min: f64, max: f64, bins: usize) -> Self {
+        let width = (max - min) / bins as f64;
+        Self::new(
+            (0..=bins)
+                .map(|i| {
+                    if i == bins {
+                        max
+                    } else {
+                        min + width * i as f64
+                    }
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// Adds a sample to its bin. NaN samples are ignored.
+    pub fn add(&mut self, value: f64) {
+        if value.is_nan() {
+            return;
+        }
+        match self.edges.partition_point(|edge| *edge <= value) {
+            0 => self.underflow += 1,
+            i if i == self.edges.len() => self.overflow += 1,
+            i => self.counts[i - 1] += 1,
+        }
+    }
+
+    pub fn edges(&self) -> &[f64] {
+        &self.edges
+    }
+
+    /// The number of samples in each bin.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// The number of samples below the first edge.
+    pub fn underflow(&self) -> u64 {
+        self.underflow
+    }
+
+    /// The number of samples at or above the last edge.
+    pub fn overflow(&self) -> u64 {
+        self.overflow
+    }
+
+    /// The number of samples added, including underflow and overflow.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum::<u64>() + self.underflow + self.overflow
+    }
+
+    /// Estimates the value below which `percent` of the samples fall, assuming samples are
+    /// spread evenly within each bin.
+    ///
+    /// Underflow and overflow samples are treated as if they were on the first and last edges.
+    /// Returns `None` if the histogram is empty.
+    pub fn percentile(&self, percent: f64) -> Option<f64> {
+        let total = self.total();
+        if total == 0 {
+            return None;
+        }
+        let rank = percent.clamp(0.0, 100.0) / 100.0 * total as f64;
+        let mut below = self.underflow as f64;
+        if rank <= below {
+            return Some(self.edges[0]);
+        }
+        for (i, count) in self.counts.iter().enumerate() {
+            let count = *count as f64;
+            if count > 0.0 && rank <= below + count {
+                let fraction = (rank - below) / count;
+                return Some(self.edges[i] + fraction * (self.edges[i + 1] - self.edges[i]));
+            }
+            below += count;
+        }
+        self.edges.last().copied()
+    }
+}
+
+impl MaybeHash for Histogram {
+    fn is_hashable(&self) -> bool {
+        true
+    }
+
+    fn hash_unchecked<H: Hasher>(&self, state: &mut H) {
+        self.counts.hash(state);
+        self.underflow.hash(state);
+        self.overflow.hash(state);
+        // The edges are included so that histograms with different bins don't share history.
+        for edge in self.edges.iter() {
+            edge.to_bits().hash(state);
+        }
+    }
+}
+
+impl<'h> Data<'h> for Histogram {
+    type Read = &'h Self;
+    type Sample = &'h Self;
+
+    fn to_read(&self, _written: Time) -> Self::Read {
+        let ptr = self as *const Self;
+        unsafe { &*ptr }
+    }
+    fn from_read(read: Self::Read, _now: Time) -> Self {
+        read.clone()
+    }
+    fn sample(read: Self::Read, _now: Time) -> Self::Sample {
+        read
+    }
+}
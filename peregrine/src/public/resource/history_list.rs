@@ -0,0 +1,241 @@
+use crate::Time;
+use crate::internal::history::PeregrineDefaultHashBuilder;
+use crate::public::resource::{Data, MaybeHash};
+use duplicate::duplicate_item;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+/// A list that only grows, like a buffer of log messages.
+///
+/// Elements are added in operations with `m: log.push(message);`. Unlike a `Vec`, each version
+/// of the list shares its elements with the version it was pushed onto, so pushing doesn't copy
+/// the list, and each version in history only adds one element. The hash of the list is kept up
+/// to date as elements are pushed, so hashing it doesn't visit every element either. Operations
+/// that read the list get a [HistoryListRef] to it.
+///
+/// A serialized list is written out in full, so lists loaded from a saved history no longer
+/// share their elements.
+pub struct HistoryList<T>(Option<Arc<Node<T>>>);
+
+struct Node<T> {
+    value: T,
+    len: usize,
+    /// The hash of every element up to this one, or `None` if any of them isn't hashable.
+    hash: Option<u64>,
+    previous: Option<Arc<Node<T>>>,
+}
+
+impl<T> HistoryList<T> {
+    /// An empty list, to use as the resource's initial condition.
+    pub fn new() -> Self {
+        HistoryList(None)
+    }
+
+    /// A view of the list, like the one given to operations that read it.
+    pub fn view(&self) -> HistoryListRef<'_, T> {
+        HistoryListRef(self.0.as_deref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.view().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.view().is_empty()
+    }
+
+    /// The most recently pushed element.
+    pub fn last(&self) -> Option<&T> {
+        self.view().last()
+    }
+
+    /// Iterates over the elements from the most recently pushed to the first.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.view().iter()
+    }
+
+    /// Copies the elements into a `Vec`, in the order they were pushed.
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.view().to_vec()
+    }
+}
+
+/// A [HistoryList] as read by operations, without access to push to it.
+pub struct HistoryListRef<'h, T>(Option<&'h Node<T>>);
+
+impl<'h, T> HistoryListRef<'h, T> {
+    pub fn len(&self) -> usize {
+        self.0.map_or(0, |node| node.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+
+    /// The most recently pushed element.
+    pub fn last(&self) -> Option<&'h T> {
+        self.0.map(|node| &node.value)
+    }
+
+    /// Iterates over the elements from the most recently pushed to the first.
+    pub fn iter(&self) -> impl Iterator<Item = &'h T> + use<'h, T> {
+        std::iter::successors(self.0, |node| node.previous.as_deref()).map(|node| &node.value)
+    }
+
+    /// Copies the elements into a `Vec`, in the order they were pushed.
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let mut result = self.iter().cloned().collect::<Vec<_>>();
+        result.reverse();
+        result
+    }
+}
+
+impl<T> Clone for HistoryListRef<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for HistoryListRef<'_, T> {}
+
+impl<T: MaybeHash> HistoryList<T> {
+    /// Adds an element to the end of the list.
+    pub fn push(&mut self, value: T) {
+        let previous = self.0.take();
+        let hash = previous
+            .as_ref()
+            .map_or(Some(0), |node| node.hash)
+            .filter(|_| value.is_hashable())
+            .map(|previous_hash| {
+                let mut hasher = PeregrineDefaultHashBuilder::default();
+                hasher.write_u64(previous_hash);
+                value.hash_unchecked(&mut hasher);
+                hasher.finish()
+            });
+        self.0 = Some(Arc::new(Node {
+            value,
+            len: previous.as_ref().map_or(0, |node| node.len) + 1,
+            hash,
+            previous,
+        }));
+    }
+}
+
+impl<T> Default for HistoryList<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for HistoryList<T> {
+    fn clone(&self) -> Self {
+        HistoryList(self.0.clone())
+    }
+}
+
+impl<T> Drop for HistoryList<T> {
+    fn drop(&mut self) {
+        // Unlinks the nodes one at a time, so that dropping a long list doesn't overflow the stack.
+        let mut next = self.0.take();
+        while let Some(node) = next {
+            next = match Arc::try_unwrap(node) {
+                Ok(mut node) => node.previous.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
+
+impl<T: PartialEq> PartialEq for HistoryList<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for HistoryList<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut elements = self.iter().collect::<Vec<_>>();
+        elements.reverse();
+        f.debug_list().entries(elements).finish()
+    }
+}
+
+impl<T: MaybeHash> FromIterator<T> for HistoryList<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut result = Self::new();
+        for value in iter {
+            result.push(value);
+        }
+        result
+    }
+}
+
+impl<T: Serialize> Serialize for HistoryList<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut elements = self.iter().collect::<Vec<_>>();
+        elements.reverse();
+        serializer.collect_seq(elements)
+    }
+}
+
+impl<'de, T: Deserialize<'de> + MaybeHash> Deserialize<'de> for HistoryList<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<T>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+#[duplicate_item(
+    ty head;
+    [HistoryList<T>] [self.0.as_deref()];
+    [HistoryListRef<'_, T>] [self.0];
+)]
+impl<T> MaybeHash for ty {
+    fn is_hashable(&self) -> bool {
+        head.is_none_or(|node| node.hash.is_some())
+    }
+
+    fn hash_unchecked<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        if let Some(node) = head {
+            node.hash
+                .expect("hashed a list with unhashable elements")
+                .hash(state);
+        }
+    }
+}
+
+impl<'h, T> Data<'h> for HistoryList<T>
+where
+    T: 'static + MaybeHash + Serialize + DeserializeOwned + Send + Sync,
+{
+    type Read = HistoryListRef<'h, T>;
+    type Sample = HistoryListRef<'h, T>;
+
+    fn to_read(&self, _written: Time) -> Self::Read {
+        // The nodes are behind an `Arc`, so they don't move when history is resized.
+        HistoryListRef(
+            self.0
+                .as_deref()
+                .map(|node| unsafe { &*(node as *const Node<T>) }),
+        )
+    }
+    fn from_read(read: Self::Read, _now: Time) -> Self {
+        HistoryList(read.0.map(|node| unsafe {
+            // Every node is owned by an `Arc`, which the list in history keeps alive.
+            let ptr = node as *const Node<T>;
+            Arc::increment_strong_count(ptr);
+            Arc::from_raw(ptr)
+        }))
+    }
+    fn sample(read: Self::Read, _now: Time) -> Self::Sample {
+        read
+    }
+}
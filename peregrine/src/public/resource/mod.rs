@@ -4,18 +4,31 @@
 //! in their models and activities.
 
 pub mod builtins;
+pub mod events;
+pub mod flags;
+pub mod histogram;
+pub mod history_list;
 pub mod piecewise;
 pub mod polynomial;
 pub mod timer;
 
 // Re-export commonly used types for convenience
+pub use crate::internal::operation::since::Writes;
+pub use crate::internal::operation::window::Extremes;
 pub use builtins::{elapsed, now};
+pub use events::Events;
+pub use flags::Flags;
+pub use histogram::Histogram;
+pub use history_list::{HistoryList, HistoryListRef};
 pub use piecewise::Piecewise;
 pub use polynomial::{Linear, Polynomial, Quadratic};
 pub use timer::Stopwatch;
 
 // Re-export the init function for internal use
 use crate::Time;
+use crate::internal::macro_prelude::DenseTime;
+use crate::internal::operation::Upstream;
+use crate::internal::timeline::Timelines;
 pub(crate) use builtins::init_builtins_timelines;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
@@ -73,6 +86,51 @@ pub trait Data<'h>:
     /// this function will need to provide an interface that is evolved in time to `now`.
     /// Unlike [from_read], you should try to do that without cloning or mutating any data.
     fn sample(read: Self::Read, now: Time) -> Self::Sample;
+
+    /// Whether [Plan::sample][crate::Plan::sample] blends between the writes around the sampled
+    /// time with [Data::interpolate], instead of evolving the previous write with [Data::sample].
+    ///
+    /// Useful when a value should be presented as moving smoothly from one write to the
+    /// next, such as waypoints, while operations still read the held value.
+    const INTERPOLATES: bool = false;
+
+    /// Create a sample at `now` between two consecutive writes, given with the times they
+    /// were written at. Only called if [Data::INTERPOLATES] is true, and only when there is
+    /// a write after `now`; otherwise [Data::sample] is used.
+    fn interpolate(prev: (Time, Self::Read), _next: (Time, Self::Read), now: Time) -> Self::Sample {
+        Self::sample(prev.1, now)
+    }
+
+    /// The second derivative with respect to time at `now`, per second squared, as a sample of
+    /// the same kind. Read in operations with `ref d2/dt2: resource`.
+    ///
+    /// Returns `None` by default, for data that doesn't have an analytic second derivative.
+    fn second_derivative(_read: Self::Read, _now: Time) -> Option<Self::Sample> {
+        None
+    }
+
+    /// Checks or fixes a value against the session's [FloatPolicy] before it is
+    /// written to history.
+    ///
+    /// Only floating point types, and types that contain them, need to override this.
+    fn apply_float_policy(&mut self, _policy: FloatPolicy) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// How to handle NaN and infinite values written to float resources.
+///
+/// Set with [Session::with_float_policy][crate::Session::with_float_policy].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FloatPolicy {
+    /// Store NaN and infinite values as-is. They are unhashable, so operations
+    /// that read them will not be cached by value.
+    #[default]
+    Allow,
+    /// Fail the simulation when a NaN or infinite value is written.
+    Error,
+    /// Clamp infinities to the largest finite value of the same sign, and fail on NaN.
+    Sanitize,
 }
 
 /// Marks a type as a resource label.
@@ -86,6 +144,13 @@ pub trait Resource: 'static + Sync + Copy {
     /// A unique identifier for this resource - NOT stable between compilations.
     const ID: u64;
 
+    /// The display unit of this resource's values, such as `"Wh"` or `"K"`.
+    ///
+    /// This is metadata for exporters and UIs, and has no effect on simulation.
+    /// Set it with the `#[unit = "..."]` attribute in [resource][crate::resource!]
+    /// or [model][crate::model!], or with a `[quantity::unit]` annotation after the data type.
+    const UNIT: Option<&'static str> = None;
+
     /// The type that is written from operations to history.
     type Data: for<'h> Data<'h>;
 
@@ -95,6 +160,111 @@ pub trait Resource: 'static + Sync + Copy {
     /// This is used when no explicit initial condition is provided in the model.
     /// Returns None if no default value was specified in the resource declaration.
     fn initial_condition() -> Option<Self::Data>;
+
+    /// Whether some written values mean "no change", as decided by [Resource::is_sentinel].
+    ///
+    /// Set it with the `#[sentinel = ...]` attribute in [resource][crate::resource!]
+    /// or [model][crate::model!].
+    const HAS_SENTINEL: bool = false;
+
+    /// Whether a written value means "no change", for resources with [a sentinel][Resource::HAS_SENTINEL].
+    ///
+    /// When an operation writes a sentinel value, it passes on the value it read, and the hash
+    /// that value is stored under, as if the write wasn't there. Downstream operations are still
+    /// requested again when the writer is inserted, since whether it writes the sentinel isn't
+    /// known until it runs, but they are served from the cache instead of running again. This is
+    /// useful for latched states like faults, where most writers only ever want to set the latch.
+    /// Since the previous value must be known, operations that write a sentinel without reading
+    /// the resource (`w:` instead of `m:`) produce an error.
+    fn is_sentinel(_value: &Self::Data) -> bool {
+        false
+    }
+
+    /// Whether values written to this resource are kept out of the session's history.
+    ///
+    /// Operations that write such a resource are never served from the cache, and the values
+    /// they write are only kept in memory by the plan. This is for large values that are cheap to
+    /// recompute, where storing every write would cost more than it saves.
+    ///
+    /// Set it with the `#[no_cache]` attribute in [resource][crate::resource!]
+    /// or [model][crate::model!].
+    const NO_CACHE: bool = false;
+
+    /// Whether the resource is private to each activity that uses it.
+    ///
+    /// Local resources aren't part of a model. Instead, each activity that uses one declares
+    /// it with [Ops::local_resource][crate::Ops::local_resource], and gets its own timeline
+    /// for it, which only the activity's operations can read and write. This is for passing
+    /// intermediate state between the operations of an activity. Only statically placed
+    /// operations can use them.
+    ///
+    /// Set it with the `#[local]` attribute in [resource][crate::resource!].
+    const LOCAL: bool = false;
+
+    /// Whether values written to this resource must follow [Resource::is_monotonic_step].
+    ///
+    /// Each write is checked against the value the operation read, and a write that breaks the
+    /// order is an error. This catches modeling bugs in values like elapsed-time counters. Since the
+    /// previous value must be known, operations that write it without reading it (`w:` instead
+    /// of `m:`) produce an error.
+    ///
+    /// Set it with the `#[monotonic(increasing)]` or `#[monotonic(decreasing)]` attribute in
+    /// [resource][crate::resource!] or [model][crate::model!].
+    const MONOTONIC: bool = false;
+
+    /// Whether `next` may be written after `previous`, for [monotonic][Resource::MONOTONIC] resources.
+    fn is_monotonic_step(_previous: &Self::Data, _next: &Self::Data) -> bool {
+        true
+    }
+
+    /// Provides the upstream for reads of resources that don't have their own timeline,
+    /// like the windows created by `ref range(..)` reads in [op][crate::op!].
+    #[doc(hidden)]
+    fn custom_upstream<'o>(
+        _timelines: &Timelines<'o>,
+        _time: DenseTime,
+    ) -> Option<&'o dyn Upstream<'o, Self>> {
+        None
+    }
+}
+
+/// Static metadata describing a resource, independent of its data type.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceDescriptor {
+    pub label: &'static str,
+    pub id: ResourceId,
+    pub unit: Option<&'static str>,
+}
+
+impl ResourceDescriptor {
+    pub fn of<R: Resource>() -> Self {
+        Self {
+            label: R::LABEL,
+            id: ResourceId::of::<R>(),
+            unit: R::UNIT,
+        }
+    }
+}
+
+/// A type-erased handle to a resource, for APIs that operate on many
+/// resources at once.
+///
+/// Like [Resource::ID], this is NOT stable between compilations.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ResourceId(u64);
+
+impl ResourceId {
+    pub fn of<R: Resource>() -> Self {
+        Self(R::ID)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+
+    pub(crate) fn from_id(id: u64) -> Self {
+        Self(id)
+    }
 }
 
 /// A trait for data that might or might not be hashable.
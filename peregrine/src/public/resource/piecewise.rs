@@ -1,6 +1,8 @@
 use crate as peregrine;
 use crate::Time;
+use crate::internal::timeline::duration_to_epoch;
 use crate::public::resource::Data;
+use crate::public::resource::polynomial::{Linear, Quadratic};
 use hifitime::Duration;
 use peregrine::MaybeHash;
 use serde::{Deserialize, Serialize};
@@ -13,6 +15,199 @@ pub struct Piecewise<T: MaybeHash> {
     pub pieces: SmallVec<(Duration, T), 2>,
 }
 
+impl<T: MaybeHash> Piecewise<T> {
+    /// Appends `other` to this function, starting `at` after the time it is written.
+    ///
+    /// The times of `other`'s pieces are shifted by `at`. Where the two overlap the later
+    /// function is preferred, so any of this function's pieces starting at or after `at` are
+    /// dropped. This is meant for splicing, for example, predicted telemetry onto the end of
+    /// actual telemetry.
+    ///
+    /// # Panics
+    ///
+    /// If `at` is negative.
+    pub fn concat(mut self, other: Self, at: Duration) -> Self {
+        assert!(
+            at >= Duration::ZERO,
+            "cannot concatenate a piecewise function at a negative offset ({at})"
+        );
+        self.pieces.retain(|(start, _)| *start < at);
+        if at == Duration::ZERO {
+            self.default = other.default;
+        } else {
+            self.pieces.push((at, *other.default));
+        }
+        self.pieces.extend(
+            other
+                .pieces
+                .into_iter()
+                .map(|(start, value)| (start + at, value)),
+        );
+        self
+    }
+}
+
+impl<T: MaybeHash + for<'h> Data<'h>> Piecewise<T> {
+    /// The piece in effect `at` after the function is written, advanced to that time.
+    pub fn piece_at(&self, at: Duration) -> T {
+        let index = self.pieces.partition_point(|(start, _)| *start <= at);
+        let (start, piece) = match index {
+            0 => (Duration::ZERO, &*self.default),
+            _ => (self.pieces[index - 1].0, &self.pieces[index - 1].1),
+        };
+        T::from_read(
+            piece.to_read(duration_to_epoch(start)),
+            duration_to_epoch(at),
+        )
+    }
+
+    /// Replaces the function between `start` and `end` with `segment`, which starts at `start`.
+    ///
+    /// After `end`, the function continues as it would have without the segment.
+    ///
+    /// # Panics
+    ///
+    /// If `start` is negative, or `end` isn't after `start`.
+    pub fn insert_segment(mut self, start: Duration, end: Duration, segment: T) -> Self {
+        assert!(
+            start >= Duration::ZERO && end > start,
+            "cannot insert a segment from {start} to {end}"
+        );
+        let resume = self.piece_at(end);
+        self.pieces
+            .retain(|(piece, _)| *piece < start || *piece > end);
+        let index = self.pieces.partition_point(|(piece, _)| *piece < start);
+        if start == Duration::ZERO {
+            self.default = Box::new(segment);
+            self.pieces.insert(index, (end, resume));
+        } else {
+            self.pieces.insert(index, (start, segment));
+            self.pieces.insert(index + 1, (end, resume));
+        }
+        self
+    }
+
+    /// Makes sure a piece starts `at` after the function is written, and returns the index of
+    /// the first piece that starts at or after it.
+    fn split_at(&mut self, at: Duration) -> usize {
+        let index = self.pieces.partition_point(|(start, _)| *start < at);
+        let starts_there = self
+            .pieces
+            .get(index)
+            .is_some_and(|(start, _)| *start == at);
+        if at > Duration::ZERO && !starts_there {
+            let piece = self.piece_at(at);
+            self.pieces.insert(index, (at, piece));
+        }
+        index
+    }
+
+    /// Builds a function from pieces and their starts, the first of which starts at zero.
+    fn from_segments(segments: Vec<(Duration, T)>) -> Self {
+        let mut segments = segments.into_iter();
+        let (_, default) = segments.next().expect("expected the default piece");
+        Piecewise {
+            default: Box::new(default),
+            pieces: segments.collect(),
+        }
+    }
+
+    /// Each piece with its start, and the start of the next piece if there is one.
+    fn segments(&self) -> impl Iterator<Item = (Duration, &T, Option<Duration>)> {
+        let starts = std::iter::once(Duration::ZERO).chain(self.pieces.iter().map(|(s, _)| *s));
+        let pieces = std::iter::once(&*self.default).chain(self.pieces.iter().map(|(_, p)| p));
+        let ends = self.pieces.iter().map(|(s, _)| Some(*s)).chain([None]);
+        starts.zip(pieces).zip(ends).map(|((s, p), e)| (s, p, e))
+    }
+}
+
+impl Piecewise<Linear> {
+    /// Limits the function to between `min` and `max`, splitting pieces where they cross
+    /// either bound, as for a battery that stops charging when it is full.
+    ///
+    /// # Panics
+    ///
+    /// If `min` is greater than `max`.
+    pub fn clamp(self, min: f64, max: f64) -> Self {
+        assert!(min <= max, "cannot clamp between {min} and {max}");
+        let mut result = Vec::new();
+        for (start, line, end) in self.segments() {
+            let rate = line.slope() / line.basis.to_seconds();
+            let length = end.map(|end| (end - start).to_seconds());
+            // The offsets into the piece where it crosses a bound.
+            let mut breaks = vec![0.0];
+            if rate != 0.0 {
+                for bound in [min, max] {
+                    let crossing = (bound - line.value) / rate;
+                    if crossing > 0.0 && length.is_none_or(|length| crossing < length) {
+                        breaks.push(crossing);
+                    }
+                }
+            }
+            breaks.sort_by(f64::total_cmp);
+            for (i, from) in breaks.iter().enumerate() {
+                // The last part of the last piece never ends, but it doesn't cross a bound
+                // either, so any later point tells which side of the bounds it is on.
+                let to = breaks.get(i + 1).copied().or(length).unwrap_or(from + 1.0);
+                let value = line.value + rate * from;
+                let middle = line.value + rate * (from + to) / 2.0;
+                let piece = if middle < min || middle > max {
+                    Linear::new(line.basis, middle.clamp(min, max), 0.0)
+                } else {
+                    Linear::new(line.basis, value, line.slope())
+                };
+                result.push((start + Duration::from_seconds(*from), piece));
+            }
+        }
+        Piecewise::from_segments(result)
+    }
+
+    /// Multiplies the function by `factor` from `at` after it is written onward.
+    ///
+    /// # Panics
+    ///
+    /// If `at` is negative.
+    pub fn scale_after(mut self, at: Duration, factor: f64) -> Self {
+        assert!(
+            at >= Duration::ZERO,
+            "cannot scale a piecewise function from a negative offset ({at})"
+        );
+        let index = self.split_at(at);
+        if at == Duration::ZERO {
+            scale(&mut self.default, factor);
+        }
+        for (_, piece) in &mut self.pieces[index..] {
+            scale(piece, factor);
+        }
+        self
+    }
+
+    /// The integral of the function, starting from `initial` when it is written, as for the
+    /// data volume accumulated from a downlink rate.
+    pub fn integrate(&self, initial: f64) -> Piecewise<Quadratic> {
+        let mut total = initial;
+        let mut result = Vec::new();
+        for (start, line, end) in self.segments() {
+            let basis = line.basis.to_seconds();
+            let (value, slope) = (line.value, line.slope());
+            result.push((
+                start,
+                Quadratic::new(line.basis, total, value * basis, slope * basis / 2.0),
+            ));
+            if let Some(end) = end {
+                let measure = (end - start).to_seconds() / basis;
+                total += basis * (value * measure + slope * measure * measure / 2.0);
+            }
+        }
+        Piecewise::from_segments(result)
+    }
+}
+
+fn scale(line: &mut Linear, factor: f64) {
+    line.value *= factor;
+    *line.slope_mut() *= factor;
+}
+
 impl<'h, T: Data<'h> + Clone + MaybeHash> Data<'h> for Piecewise<T> {
     type Read = (Time, &'h T, &'h [(Duration, T)]);
     type Sample = T::Sample;
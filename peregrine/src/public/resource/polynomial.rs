@@ -61,6 +61,26 @@ impl<
     fn sample(read: Self::Read, now: Time) -> Self::Sample {
         Self::from_read(read, now)
     }
+
+    fn second_derivative(read: Self::Read, now: Time) -> Option<Self::Sample> {
+        let this = Self::from_read(read, now);
+        let scale = this.basis.to_seconds().powi(-2);
+        let mut result = Self {
+            value: Y::zero(),
+            higher_coefficients: [Y::zero(); DEGREE],
+            basis: this.basis,
+        };
+        // The coefficient of `m^k` contributes `k * (k - 1) * m^(k - 2)`.
+        for k in 2..=DEGREE {
+            let term = this.higher_coefficients[k - 1] * ((k * (k - 1)) as f64 * scale);
+            if k == 2 {
+                result.value = term;
+            } else {
+                result.higher_coefficients[k - 3] = term;
+            }
+        }
+        Some(result)
+    }
 }
 
 impl<const DEGREE: usize, Y: Default + Copy + MaybeHash> Default for Polynomial<DEGREE, Y> {
@@ -1,16 +1,53 @@
 use crate::Time;
-use crate::internal::history::History;
+pub use crate::internal::diagnostics::TraceVerbosity;
+pub use crate::internal::history::ClosedPlanRetention;
+use crate::internal::history::{History, PeregrineDefaultHashBuilder, Reachability, Retired};
 use crate::internal::macro_prelude::peregrine_grounding;
+use crate::internal::operation::external::ExternalInputs;
 use crate::internal::operation::initial_conditions::InitialConditions;
+pub use crate::internal::placement::{CoincidentWritePolicy, GroundingErrorPolicy};
 use crate::public::Model;
-use crate::public::plan::Plan;
+use crate::public::plan::{Plan, WriteSamplePolicy};
+use crate::public::resource::{FloatPolicy, Resource};
+#[cfg(feature = "serde")]
+use crate::{Activity, public::plan::SavedPlan};
+#[cfg(feature = "serde")]
+use anyhow::Context;
+use anyhow::bail;
 use bumpalo_herd::Herd;
-use parking_lot::RwLock;
+use hifitime::Duration;
+use parking_lot::{Mutex, RwLock};
+use std::hash::{Hash, Hasher};
+
+/// Identifies blobs written by [Session::checkpoint].
+const CHECKPOINT_MAGIC: &[u8; 4] = b"PGHC";
+
+/// Incremented whenever the encoding of checkpoints changes.
+const CHECKPOINT_VERSION: u32 = 1;
 
 #[derive(Default)]
 pub struct Session {
     pub(crate) herd: Herd,
     pub(crate) history: RwLock<History>,
+    pub(crate) float_policy: FloatPolicy,
+    pub(crate) batched_grounding: bool,
+    pub(crate) max_activities: Option<usize>,
+    pub(crate) operation_timeout: Option<std::time::Duration>,
+    pub(crate) elapsed_tick: Option<Duration>,
+    pub(crate) coincident_writes: CoincidentWritePolicy,
+    pub(crate) grounding_errors: GroundingErrorPolicy,
+    pub(crate) write_sample_policy: WriteSamplePolicy,
+    pub(crate) dedicated_herds: bool,
+    pub(crate) write_coalescing: bool,
+    pub(crate) trace_verbosity: TraceVerbosity,
+    /// The single-threaded pool that simulations run in, if execution is deterministic.
+    pub(crate) deterministic_pool: Option<rayon::ThreadPool>,
+    pub(crate) external_inputs: ExternalInputs,
+    /// The history entries each plan can reach, if [history pruning][Session::with_history_pruning]
+    /// is enabled.
+    pub(crate) reachability: Option<Mutex<Reachability>>,
+    /// Values removed by [Session::prune_history], which reads may still borrow from.
+    pub(crate) retired: Mutex<Retired>,
 }
 
 impl Session {
@@ -18,6 +55,274 @@ impl Session {
         Self::default()
     }
 
+    /// Sets how NaN and infinite values written to float resources are handled.
+    ///
+    /// Defaults to [FloatPolicy::Allow].
+    pub fn with_float_policy(mut self, policy: FloatPolicy) -> Self {
+        self.float_policy = policy;
+        self
+    }
+
+    /// Sets whether overlapping dynamically-placed operations share their grounding requests.
+    ///
+    /// When an operation might read from any of several operations with undecided times,
+    /// all of their times need to be resolved first. With batching, each such cluster is resolved
+    /// once and shared by all readers; without it, every reader resolves the cluster separately.
+    /// Results are identical either way.
+    ///
+    /// Defaults to `false`.
+    pub fn with_batched_grounding(mut self, enabled: bool) -> Self {
+        self.batched_grounding = enabled;
+        self
+    }
+
+    /// Sets how an operation with a dynamic delay is ordered against static operations
+    /// at the exact time it is grounded to.
+    ///
+    /// Defaults to [CoincidentWritePolicy::DynamicLast], so the operation that waited for the
+    /// time writes last.
+    pub fn with_coincident_write_policy(mut self, policy: CoincidentWritePolicy) -> Self {
+        self.coincident_writes = policy;
+        self
+    }
+
+    /// Sets what happens to an operation with a dynamic delay when the delay fails to evaluate.
+    ///
+    /// Defaults to [GroundingErrorPolicy::Drop].
+    pub fn with_grounding_error_policy(mut self, policy: GroundingErrorPolicy) -> Self {
+        self.grounding_errors = policy;
+        self
+    }
+
+    /// Sets which value [Plan::sample] uses when sampling a resource at exactly the time of a write.
+    ///
+    /// Defaults to [WriteSamplePolicy::NewSegment].
+    pub fn with_write_sample_policy(mut self, policy: WriteSamplePolicy) -> Self {
+        self.write_sample_policy = policy;
+        self
+    }
+
+    /// Rounds the [elapsed][crate::elapsed] builtin down to a multiple of `tick` in plans created
+    /// by this session.
+    ///
+    /// Operations that read `elapsed` at slightly different times then see the same value, so
+    /// they can share cached outputs. This trades time precision for cache hits.
+    ///
+    /// By default, `elapsed` is exact.
+    pub fn with_elapsed_precision(mut self, tick: Duration) -> Self {
+        self.elapsed_tick = Some(tick);
+        self
+    }
+
+    /// Limits how long each operation body may run.
+    ///
+    /// A body that runs longer than the limit produces an error instead of its output.
+    /// Bodies can't be interrupted, so a body that never returns still hangs the simulation;
+    /// the overrun is reported as a `tracing` warning as soon as the limit passes, with the
+    /// `tracing` feature, so the hang is at least visible.
+    ///
+    /// There is no limit by default.
+    pub fn with_operation_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.operation_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets whether redundant writes are left out of the timelines when activities are inserted.
+    ///
+    /// When an activity writes a resource in several adjacent operations at the same time, and
+    /// none of them read it in between, only the last write is visible to anything else. With
+    /// coalescing, the earlier writes are not inserted into the resource's timeline, so there
+    /// are fewer entries to search and fewer operations to simulate. Resources that trigger
+    /// reactive daemons are never coalesced.
+    ///
+    /// Defaults to `false`.
+    pub fn with_write_coalescing(mut self, enabled: bool) -> Self {
+        self.write_coalescing = enabled;
+        self
+    }
+
+    /// Sets how much of the engine's behavior is reported to the `tracing` crate, like
+    /// operation runs, cache hits and misses, groundings, and timeline insertions.
+    ///
+    /// Only has an effect with the `tracing` feature. Defaults to [TraceVerbosity::Operations].
+    pub fn with_trace_verbosity(mut self, verbosity: TraceVerbosity) -> Self {
+        self.trace_verbosity = verbosity;
+        self
+    }
+
+    /// Gives each plan its own arena for activities and operations, freed when the plan is dropped.
+    ///
+    /// By default, all plans share the session's arena, which is only freed with the session.
+    /// That is faster to set up, but a long-lived session that churns through many short-lived
+    /// plans grows without bound.
+    pub fn with_dedicated_herds(mut self, enabled: bool) -> Self {
+        self.dedicated_herds = enabled;
+        self
+    }
+
+    /// Keeps this session's cache entries apart from those of sessions in other namespaces.
+    ///
+    /// Histories are keyed by resource ID and operation inputs, so unrelated models that
+    /// share a resource ID could reuse each other's outputs when their histories are moved
+    /// between sessions or merged. The namespace is mixed into every key, so entries written
+    /// in one namespace are never found from another. It is not serialized with the history;
+    /// a loaded history must be given the namespace it was written with to be reused.
+    ///
+    /// By default, sessions have no namespace.
+    pub fn with_cache_namespace(mut self, namespace: &str) -> Self {
+        let mut hasher = PeregrineDefaultHashBuilder::default();
+        namespace.hash(&mut hasher);
+        self.history.get_mut().set_namespace(hasher.finish().max(1));
+        self
+    }
+
+    /// Tracks which history entries each plan of the session can reach, so that the rest can be
+    /// dropped with [Session::prune_history].
+    ///
+    /// Each plan keeps a registry of its operations as they are inserted and removed. The hashes
+    /// of their cached outputs are only collected when a plan is dropped or a prune runs, which
+    /// takes time proportional to the size of the plans. Disabled by default.
+    pub fn with_history_pruning(mut self, enabled: bool) -> Self {
+        self.reachability = enabled.then(Mutex::default);
+        self
+    }
+
+    /// Runs simulations on a single thread, so that operations always run in the same order,
+    /// and records each operation's output as it runs.
+    ///
+    /// This is for regression tests; see [Plan::assert_matches_golden]. Simulation is much
+    /// slower, since nothing runs in parallel. Disabled by default.
+    pub fn with_deterministic_execution(mut self, enabled: bool) -> Self {
+        self.deterministic_pool = enabled.then(|| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(1)
+                .thread_name(|_| "peregrine-deterministic".to_string())
+                .build()
+                .expect("could not start the deterministic execution thread")
+        });
+        self
+    }
+
+    /// Limits how many activities each plan in this session may contain.
+    ///
+    /// Once a plan reaches the limit, [Plan::insert] returns an error instead of
+    /// growing further. There is no limit by default.
+    pub fn with_max_activities(mut self, max: usize) -> Self {
+        self.max_activities = Some(max);
+        self
+    }
+
+    /// Runs `op` in the deterministic execution pool if there is one, or on the current thread.
+    pub(crate) fn install<T: Send>(&self, op: impl FnOnce() -> T + Send) -> T {
+        match &self.deterministic_pool {
+            Some(pool) => pool.install(op),
+            None => op(),
+        }
+    }
+
+    /// Sets the version of an input from outside the simulation, like a loaded configuration
+    /// file, that operations read with `ref external: name`.
+    ///
+    /// Operations that read the input include its version in their hash, so changing the version
+    /// runs them again in every plan of the session, instead of reusing outputs computed from
+    /// the old input. Reading an input that was never given a version is an error.
+    pub fn set_external_version(&self, name: &str, version: u64) {
+        self.external_inputs.set(name, version);
+    }
+
+    /// Encodes the whole history as a binary blob, for checkpointing long-running sessions.
+    ///
+    /// The blob starts with a format version, so that [Session::restore] can reject blobs
+    /// written by incompatible versions of peregrine. The [cache namespace][Session::with_cache_namespace]
+    /// is not included. Fails if an entry in the history can't be encoded.
+    pub fn checkpoint(&self) -> anyhow::Result<Vec<u8>> {
+        let mut blob = CHECKPOINT_MAGIC.to_vec();
+        blob.extend_from_slice(&CHECKPOINT_VERSION.to_le_bytes());
+        bincode::serde::encode_into_std_write(
+            &*self.history.read(),
+            &mut blob,
+            bincode::config::standard(),
+        )
+        .context("failed to encode history")?;
+        Ok(blob)
+    }
+
+    /// Replaces the history with one encoded by [Session::checkpoint].
+    ///
+    /// Fails without changing the history if the blob is not a checkpoint, or was written
+    /// in a different format version.
+    pub fn restore(&mut self, blob: &[u8]) -> anyhow::Result<()> {
+        let Some(rest) = blob.strip_prefix(CHECKPOINT_MAGIC) else {
+            bail!("not a peregrine history checkpoint");
+        };
+        let Some((version, payload)) = rest.split_first_chunk::<4>() else {
+            bail!("history checkpoint is truncated");
+        };
+        let version = u32::from_le_bytes(*version);
+        if version != CHECKPOINT_VERSION {
+            bail!(
+                "history checkpoint has format version {version}, but this version of peregrine only reads version {CHECKPOINT_VERSION}"
+            );
+        }
+        let (mut history, _): (History, _) =
+            bincode::serde::decode_from_slice(payload, bincode::config::standard())?;
+        let history_mut = self.history.get_mut();
+        history.set_namespace(history_mut.namespace());
+        *history_mut = history;
+        Ok(())
+    }
+
+    /// Whether both plans resolve `R` at `time` to the same entry in this session's history.
+    ///
+    /// This means the plans genuinely share the cached state; plans that only happen to compute
+    /// equal values from different inputs don't. A diagnostic for checking that branches of a
+    /// plan reuse each other's work. Returns `false` if either plan is from a different session,
+    /// or fails to simulate `R` at `time`.
+    pub fn shares_state<'o, M: Model<'o> + 'o, R: Resource>(
+        &self,
+        a: &Plan<'o, M>,
+        b: &Plan<'o, M>,
+        time: Time,
+    ) -> bool {
+        if !a.is_in(self) || !b.is_in(self) {
+            return false;
+        }
+        match (a.state_hash::<R>(time), b.state_hash::<R>(time)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Removes the history entries that no plan of the session can reach, and returns how
+    /// many were removed.
+    ///
+    /// Live plans keep the entries of their operations' current outputs. The entries a dropped
+    /// plan could reach when it was dropped are kept according to `retention`, so that a plan
+    /// recreated soon after can still reuse them. The prune waits for simulations in progress,
+    /// and simulations started during it wait for it to finish. Anything removed is simulated
+    /// again if it is needed later. Fails if the session was not built
+    /// [with history pruning][Session::with_history_pruning].
+    ///
+    /// Values read from a plan, like by [Plan::sample] or [Plan::view], may borrow from the
+    /// history, so removed entries are not dropped right away. They are held by the session until
+    /// it is dropped or [Session::release_pruned_history] is called.
+    pub fn prune_history(&self, retention: ClosedPlanRetention) -> anyhow::Result<usize> {
+        let Some(reachability) = &self.reachability else {
+            bail!("history pruning is not enabled for this session");
+        };
+        let mut history = self.history.write();
+        let keep = reachability.lock().retained(retention);
+        Ok(history.prune(&keep, &mut self.retired.lock()))
+    }
+
+    /// Drops the history entries removed by [Session::prune_history].
+    ///
+    /// This needs the session exclusively, so no plan or value read from one can still
+    /// borrow them.
+    pub fn release_pruned_history(&mut self) {
+        self.retired.get_mut().release();
+    }
+
     pub fn into_history(self) -> History {
         self.history.into_inner()
     }
@@ -36,6 +341,29 @@ impl Session {
         drop(history);
         Plan::new(self, time, initial_conditions)
     }
+
+    /// Loads a plan that was serialized to JSON, with its start, initial conditions,
+    /// and activities under their saved IDs and anchors.
+    ///
+    /// The activities are run again, but the plan shares this session's history, so whatever
+    /// was already simulated for the saved plan, in this session or in a history restored
+    /// into it, is found in the cache. Fails if an activity type isn't registered in this
+    /// program, or an initial condition is for a resource that isn't in `M`.
+    #[cfg(feature = "serde")]
+    pub fn load_plan<'o, M: Model<'o> + 'o>(
+        &'o self,
+        reader: impl std::io::Read,
+    ) -> anyhow::Result<Plan<'o, M>>
+    where
+        Self: 'o,
+    {
+        let mut saved: SavedPlan<Box<dyn Activity>> =
+            serde_json::from_reader(reader).context("could not read the saved plan")?;
+        let initial_conditions = saved.initial_conditions::<M>()?;
+        let mut plan = self.new_plan(saved.start(), initial_conditions)?;
+        plan.restore(saved)?;
+        Ok(plan)
+    }
 }
 
 impl From<History> for Session {
@@ -3,6 +3,8 @@ mod util;
 use anyhow::Result;
 use peregrine::internal::history::History;
 use peregrine::*;
+use serde::{Deserialize, Serialize};
+use std::hash::Hash;
 use std::sync::atomic::Ordering;
 use util::*;
 
@@ -31,6 +33,28 @@ fn cache_across_runs() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn simulate_resources_fills_cache() -> Result<()> {
+    let session = Session::new();
+    let mut plan = init_plan(&session);
+
+    let (node, counter) = EvalCounter::new();
+
+    plan.insert(seconds(0), IncrementA)?;
+    plan.insert(seconds(1), node)?;
+    plan.insert(seconds(2), SetBToA)?;
+    plan.insert(seconds(3), IncrementA)?;
+
+    plan.simulate_resources(&[ResourceId::of::<a>(), ResourceId::of::<b>()], ..)?;
+    assert_eq!(1, counter.load(Ordering::SeqCst));
+
+    assert_eq!(2, plan.sample::<a>(seconds(4))?);
+    assert_eq!(1, plan.sample::<b>(seconds(4))?);
+    assert_eq!(1, counter.load(Ordering::SeqCst));
+
+    Ok(())
+}
+
 #[test]
 fn cache_within_single_run() -> Result<()> {
     let session = Session::new();
@@ -145,3 +169,742 @@ fn load_cache_after_rollbacks_sim_in_between() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn export_history_into_fresh_session() -> Result<()> {
+    let session = Session::new();
+    let mut plan = init_plan(&session);
+    let (node, counter) = EvalCounter::new();
+
+    plan.insert(seconds(0), IncrementA)?;
+    plan.insert(seconds(1), node)?;
+
+    assert_eq!(1, plan.sample::<a>(seconds(1))?);
+    assert_eq!(1, counter.load(Ordering::SeqCst));
+
+    let fresh = Session::new();
+    plan.export_history_into(&fresh, &[ResourceId::of::<a>()])?;
+
+    let mut plan = init_plan(&fresh);
+    let (node, counter) = EvalCounter::new();
+
+    plan.insert(seconds(0), IncrementA)?;
+    plan.insert(seconds(1), node)?;
+
+    assert_eq!(1, plan.sample::<a>(seconds(1))?);
+    assert_eq!(0, counter.load(Ordering::SeqCst));
+
+    Ok(())
+}
+
+/// Writes the whole number of seconds elapsed to `b`, counting how many times it runs.
+#[derive(Hash, Serialize, Deserialize)]
+struct RecordElapsed(UnhashedCounter);
+
+#[typetag::serde]
+impl Activity for RecordElapsed {
+    fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+        let counter = &self.0;
+        ops += op! {
+            counter.fetch_add(1, Ordering::SeqCst);
+            w: b = r:elapsed.to_seconds() as u32;
+        };
+        Ok(Duration::ZERO)
+    }
+}
+
+fn near_equal_elapsed_evaluations(session: &Session) -> Result<u16> {
+    let mut plan = init_plan(session);
+    let counter = UnhashedCounter::default();
+
+    // The ops are independent, so they are simulated one at a time to see the first one's output.
+    plan.insert(
+        Time::from_tai_seconds(1.0001),
+        RecordElapsed(counter.clone()),
+    )?;
+    assert_eq!(2, plan.sample::<b>(seconds(2))?);
+    plan.insert(
+        Time::from_tai_seconds(1.0002),
+        RecordElapsed(counter.clone()),
+    )?;
+    assert_eq!(2, plan.sample::<b>(seconds(2))?);
+
+    Ok(counter.load(Ordering::SeqCst))
+}
+
+#[test]
+fn quantized_elapsed_shares_cache() -> Result<()> {
+    assert_eq!(2, near_equal_elapsed_evaluations(&Session::new())?);
+
+    let quantized = Session::new().with_elapsed_precision(Duration::from_seconds(1.0));
+    assert_eq!(1, near_equal_elapsed_evaluations(&quantized)?);
+
+    Ok(())
+}
+
+mod cache_namespace {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    /// Simulates a plan with an [EvalCounter] in the session, and returns how many times it ran.
+    fn run_counter(session: &Session) -> Result<Arc<AtomicU16>> {
+        let mut plan = init_plan(session);
+        let (node, counter) = EvalCounter::new();
+
+        plan.insert(seconds(0), IncrementA)?;
+        plan.insert(seconds(1), node)?;
+        assert_eq!(1, plan.sample::<a>(seconds(1))?);
+
+        Ok(counter)
+    }
+
+    #[test]
+    fn namespaces_do_not_share_cache() -> Result<()> {
+        let session = Session::new().with_cache_namespace("thermal");
+        assert_eq!(1, run_counter(&session)?.load(Ordering::SeqCst));
+
+        // The same namespace reuses the history.
+        let session = Session::from(session.into_history()).with_cache_namespace("thermal");
+        assert_eq!(0, run_counter(&session)?.load(Ordering::SeqCst));
+
+        // A different namespace doesn't, even though the resources and operations are identical.
+        let session = Session::from(session.into_history()).with_cache_namespace("power");
+        assert_eq!(1, run_counter(&session)?.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    #[test]
+    fn merged_histories_stay_apart() -> Result<()> {
+        let thermal = Session::new().with_cache_namespace("thermal");
+        run_counter(&thermal)?;
+
+        let mut history = Session::new().into_history();
+        history.merge(thermal.into_history());
+        let session = Session::from(history);
+        assert_eq!(1, run_counter(&session)?.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+}
+
+mod no_cache {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Orbit {
+            #[no_cache]
+            ephemeris: Vec<u32> = vec![];
+            samples: u32 = 0;
+        }
+    }
+
+    /// Appends a point to the ephemeris, and records how many points it has.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Propagate(u32);
+
+    #[typetag::serde]
+    impl Activity for Propagate {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let point = self.0;
+            ops += op! { m: ephemeris.push(point); };
+            ops += op! { w: samples = r: ephemeris.len() as u32; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    fn points(plan: &Plan<Orbit>, time: Time) -> Result<Vec<u32>> {
+        let sample = plan.sample::<ephemeris>(time)?;
+        Ok((0..sample.len()).filter_map(|i| sample.get(i)).collect())
+    }
+
+    #[test]
+    fn no_cache_resource_has_no_history() -> Result<()> {
+        let session = Session::new();
+        {
+            let mut plan = session.new_plan::<Orbit>(seconds(0), initial_conditions! {})?;
+            plan.insert(seconds(1), Propagate(10))?;
+            plan.insert(seconds(2), Propagate(20))?;
+
+            assert_eq!(vec![10, 20], points(&plan, seconds(3))?);
+            assert_eq!(2, plan.sample::<samples>(seconds(3))?);
+
+            // Removing an activity recomputes the rest from upstream.
+            let id = plan.insert(seconds(0), Propagate(5))?;
+            assert_eq!(vec![5, 10, 20], points(&plan, seconds(3))?);
+            plan.remove(id)?;
+            assert_eq!(vec![10, 20], points(&plan, seconds(3))?);
+        }
+
+        let history = session.into_history();
+        assert_eq!(0, history.entry_count::<ephemeris>());
+        assert!(history.entry_count::<samples>() > 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rerun_writes_replace_their_values() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Orbit>(seconds(0), initial_conditions! {})?;
+        plan.insert(seconds(2), Propagate(20))?;
+        plan.insert(seconds(3), Propagate(30))?;
+        assert_eq!(vec![20, 30], points(&plan, seconds(4))?);
+        let retained = plan.uncached_entry_count::<ephemeris>();
+
+        // Each insertion and removal re-runs the writes after it.
+        for _ in 0..50 {
+            let id = plan.insert(seconds(1), Propagate(10))?;
+            assert_eq!(vec![10, 20, 30], points(&plan, seconds(4))?);
+            plan.remove(id)?;
+            assert_eq!(vec![20, 30], points(&plan, seconds(4))?);
+        }
+
+        assert_eq!(retained, plan.uncached_entry_count::<ephemeris>());
+
+        Ok(())
+    }
+}
+
+mod memo {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::Ordering;
+
+    /// Sums the first `n` squares once, and uses the sum in three operations.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SumOfSquares {
+        n: u32,
+        computations: UnhashedCounter,
+    }
+
+    #[typetag::serde]
+    impl Activity for SumOfSquares {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let sum = ops.memo(|| {
+                self.computations.fetch_add(1, Ordering::SeqCst);
+                (1..=self.n).map(|i| i * i).sum::<u32>()
+            });
+            ops += op! { m: a += *sum; };
+            ops += op! { w: b = *sum * 2; };
+            ops += op! { m: a += *sum + r:b; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn memo_is_computed_once() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let computations = UnhashedCounter::default();
+
+        let id = plan.insert(
+            seconds(0),
+            SumOfSquares {
+                n: 3,
+                computations: computations.clone(),
+            },
+        )?;
+        assert_eq!(1, computations.load(Ordering::SeqCst));
+        assert_eq!(14 + 14 + 28, plan.sample::<a>(seconds(1))?);
+
+        // A different memoized value changes the operations' hashes, so nothing stale is reused.
+        plan.remove(id)?;
+        plan.insert(
+            seconds(0),
+            SumOfSquares {
+                n: 4,
+                computations: computations.clone(),
+            },
+        )?;
+        assert_eq!(2, computations.load(Ordering::SeqCst));
+        assert_eq!(30 + 30 + 60, plan.sample::<a>(seconds(1))?);
+
+        Ok(())
+    }
+}
+
+mod verify_cache {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::Ordering;
+
+    /// Writes a value that is left out of its hash, so the cache can't tell when it changes.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SetAToUnhashed(UnhashedCounter);
+
+    #[typetag::serde]
+    impl Activity for SetAToUnhashed {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let value = &self.0;
+            ops += op! { w: a = value.load(Ordering::SeqCst) as u32; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn correct_model_has_no_discrepancies() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(1), IncrementA)?;
+        plan.insert(seconds(2), SetBToA)?;
+        plan.insert(seconds(3), AddBToA)?;
+        plan.insert(seconds(4), IncrementB)?;
+        assert_eq!(2, plan.sample::<a>(seconds(5))?);
+
+        assert!(plan.verify_cache(seconds(0)..seconds(5))?.is_empty());
+
+        // The plan still simulates normally afterward.
+        assert_eq!(2, plan.sample::<a>(seconds(5))?);
+        assert_eq!(2, plan.sample::<b>(seconds(5))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unhashed_input_is_reported() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let value = UnhashedCounter::default();
+        value.store(1, Ordering::SeqCst);
+
+        plan.insert(seconds(1), SetAToUnhashed(value.clone()))?;
+        plan.insert(seconds(2), IncrementA)?;
+        assert_eq!(2, plan.sample::<a>(seconds(3))?);
+
+        value.store(5, Ordering::SeqCst);
+        let discrepancies = plan.verify_cache(seconds(0)..seconds(3))?;
+
+        assert_eq!(1, discrepancies.len());
+        assert_eq!("a", discrepancies[0].resource);
+        assert_eq!(seconds(1), discrepancies[0].time);
+
+        Ok(())
+    }
+
+    #[test]
+    fn daemon_operations_are_verified() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let value = UnhashedCounter::default();
+        value.store(1, Ordering::SeqCst);
+        let daemon_value = value.clone();
+        plan.add_reactive_daemon(&[ResourceId::of::<b>()], move || {
+            vec![Box::new(SetAToUnhashed(daemon_value.clone())) as Box<dyn Activity>]
+        });
+
+        plan.insert(seconds(1), IncrementB)?;
+        assert_eq!(1, plan.sample::<a>(seconds(2))?);
+
+        value.store(5, Ordering::SeqCst);
+        let discrepancies = plan.verify_cache(seconds(0)..seconds(2))?;
+
+        assert_eq!(1, discrepancies.len());
+        assert_eq!("a", discrepancies[0].resource);
+        assert_eq!(seconds(1), discrepancies[0].time);
+
+        Ok(())
+    }
+}
+
+mod idempotence {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    /// Copies `a` into `b`, in an op that must not be rerun on its own.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct CopyOnce;
+
+    #[typetag::serde]
+    impl Activity for CopyOnce {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! {
+                #![not_idempotent]
+                w: b = r:a;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn not_idempotent_op_runs_once_per_insertion() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        let id = plan.insert(seconds(1), CopyOnce)?;
+        assert_eq!(0, plan.sample::<b>(seconds(2))?);
+
+        // Reinserting the whole activity is always allowed.
+        plan.insert(seconds(0), IncrementA)?;
+        plan.remove(id)?;
+        plan.insert(seconds(1), CopyOnce)?;
+        assert_eq!(1, plan.sample::<b>(seconds(2))?);
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "`#![not_idempotent]` operation was rerun")]
+    fn not_idempotent_op_panics_on_partial_rerun() {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(1), CopyOnce).unwrap();
+        assert_eq!(0, plan.sample::<b>(seconds(2)).unwrap());
+
+        // Changing the op's input reruns it alone.
+        plan.insert(seconds(0), IncrementA).unwrap();
+        let _ = plan.sample::<b>(seconds(2));
+    }
+}
+
+mod downstream_count {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::Ordering;
+
+    /// Increments `a`, recording how many downstreams had requested it.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct RecordDownstreams(UnhashedCounter);
+
+    #[typetag::serde]
+    impl Activity for RecordDownstreams {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let recorder = &self.0;
+            ops += op! {
+                m: a += 1;
+                recorder.store(downstream_count().unwrap() as u16, Ordering::SeqCst);
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn downstream_count_matches_readers() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let recorder = UnhashedCounter::default();
+
+        plan.insert(seconds(0), RecordDownstreams(recorder.clone()))?;
+        plan.insert(seconds(1), SetBToA)?;
+        plan.insert(seconds(2), AddBToA)?;
+        assert_eq!(2, plan.sample::<a>(seconds(3))?);
+
+        // Changing the op's input forces it to rerun, after both readers have registered.
+        plan.insert(seconds(-1), IncrementA)?;
+        assert_eq!(4, plan.sample::<a>(seconds(3))?);
+        assert_eq!(2, recorder.load(Ordering::SeqCst));
+
+        assert_eq!(None, downstream_count());
+
+        Ok(())
+    }
+}
+
+mod shares_state {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+
+    #[test]
+    fn branches_share_state_until_they_diverge() -> Result<()> {
+        let session = Session::new();
+        let mut first = init_plan(&session);
+        let mut second = init_plan(&session);
+
+        first.insert(seconds(0), IncrementA)?;
+        second.insert(seconds(0), IncrementA)?;
+        second.insert(seconds(5), IncrementA)?;
+
+        assert!(session.shares_state::<_, a>(&first, &second, seconds(2)));
+        assert!(!session.shares_state::<_, a>(&first, &second, seconds(6)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn equal_values_from_different_inputs_are_not_shared() -> Result<()> {
+        let session = Session::new();
+        let mut first = init_plan(&session);
+        let mut second = init_plan(&session);
+
+        first.insert(seconds(0), IncrementB)?;
+        first.insert(seconds(1), AddBToA)?;
+        second.insert(seconds(1), IncrementA)?;
+
+        assert_eq!(
+            first.sample::<a>(seconds(2))?,
+            second.sample::<a>(seconds(2))?
+        );
+        assert!(!session.shares_state::<_, a>(&first, &second, seconds(2)));
+
+        Ok(())
+    }
+}
+
+mod history_pruning {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn prunes_entries_closed_plans_no_longer_reach() -> Result<()> {
+        let session = Session::new().with_history_pruning(true);
+        {
+            let mut plan = init_plan(&session);
+            let (node, counter) = EvalCounter::new();
+            let increment = plan.insert(seconds(0), IncrementA)?;
+            plan.insert(seconds(1), node)?;
+            assert_eq!(1, plan.sample::<a>(seconds(2))?);
+            assert_eq!(1, counter.load(Ordering::SeqCst));
+
+            plan.remove(increment)?;
+            assert_eq!(0, plan.sample::<a>(seconds(2))?);
+            assert_eq!(2, counter.load(Ordering::SeqCst));
+        }
+
+        // The increment's output, and the counter's output after it.
+        let retention = ClosedPlanRetention::MostRecent(1);
+        assert_eq!(2, session.prune_history(retention)?);
+
+        // What the plan last reached is kept.
+        assert_eq!(0, session.prune_history(retention)?);
+
+        let mut plan = init_plan(&session);
+        let (node, counter) = EvalCounter::new();
+        plan.insert(seconds(1), node)?;
+        assert_eq!(0, plan.sample::<a>(seconds(2))?);
+        assert_eq!(0, counter.load(Ordering::SeqCst));
+
+        plan.insert(seconds(0), IncrementA)?;
+        assert_eq!(1, plan.sample::<a>(seconds(2))?);
+        assert_eq!(1, counter.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_recently_closed_plans() -> Result<()> {
+        let session = Session::new().with_history_pruning(true);
+        // Counters are left out of the hash, so each plan's counter only counts its own runs.
+        let build = |session: &Session| -> Result<u16> {
+            let mut plan = init_plan(session);
+            let (node, counter) = EvalCounter::new();
+            plan.insert(seconds(0), node)?;
+            assert_eq!(0, plan.sample::<a>(seconds(1))?);
+            Ok(counter.load(Ordering::SeqCst))
+        };
+
+        assert_eq!(1, build(&session)?);
+
+        let retention = ClosedPlanRetention::MostRecent(1);
+        assert_eq!(0, session.prune_history(retention)?);
+        assert_eq!(0, build(&session)?);
+
+        let retention = ClosedPlanRetention::ClosedWithin(std::time::Duration::from_secs(60));
+        assert_eq!(0, session.prune_history(retention)?);
+
+        assert_eq!(1, session.prune_history(ClosedPlanRetention::Discard)?);
+        assert_eq!(1, build(&session)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn prunes_while_plans_are_live() -> Result<()> {
+        let mut session = Session::new().with_history_pruning(true);
+        {
+            let mut plan = init_plan(&session);
+            let (node, counter) = EvalCounter::new();
+            let increment = plan.insert(seconds(0), IncrementA)?;
+            plan.insert(seconds(1), node)?;
+            assert_eq!(1, plan.sample::<a>(seconds(2))?);
+
+            plan.remove(increment)?;
+            assert_eq!(0, plan.sample::<a>(seconds(2))?);
+            assert_eq!(2, counter.load(Ordering::SeqCst));
+
+            // The increment's output, and the counter's output after it.
+            assert_eq!(2, session.prune_history(ClosedPlanRetention::Discard)?);
+            assert_eq!(0, session.prune_history(ClosedPlanRetention::Discard)?);
+
+            assert_eq!(0, plan.sample::<a>(seconds(2))?);
+            assert_eq!(2, counter.load(Ordering::SeqCst));
+
+            plan.insert(seconds(0), IncrementA)?;
+            assert_eq!(1, plan.sample::<a>(seconds(2))?);
+            assert_eq!(3, counter.load(Ordering::SeqCst));
+        }
+        session.release_pruned_history();
+
+        Ok(())
+    }
+
+    #[test]
+    fn requires_tracking() {
+        let session = Session::new();
+        assert!(session.prune_history(ClosedPlanRetention::Discard).is_err());
+    }
+}
+
+mod write_coalescing {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    /// Overwrites `a` three times in a row.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Overwrite;
+
+    #[typetag::serde]
+    impl Activity for Overwrite {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: a = 1; };
+            ops += op! { w: a = 2; };
+            ops += op! { w: a = 3; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Writes `a` three times, but reads it in between.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Accumulate;
+
+    #[typetag::serde]
+    impl Activity for Accumulate {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: a = 1; };
+            ops += op! { m: a += 1; };
+            ops += op! { m: a += 1; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn adjacent_writes_coalesce() -> Result<()> {
+        let session = Session::new().with_write_coalescing(true);
+        let mut plan = init_plan(&session);
+
+        let id = plan.insert(seconds(0), Overwrite)?;
+        assert_eq!(1, plan.view::<a>(seconds(0)..seconds(1))?.len());
+        assert_eq!(3, plan.sample::<a>(seconds(1))?);
+        plan.validate_integrity()?;
+
+        plan.remove(id)?;
+        plan.validate_integrity()?;
+        assert_eq!(0, plan.sample::<a>(seconds(1))?);
+
+        plan.insert(seconds(0), Accumulate)?;
+        assert_eq!(3, plan.view::<a>(seconds(0)..seconds(1))?.len());
+        assert_eq!(3, plan.sample::<a>(seconds(1))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn coalescing_is_opt_in() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), Overwrite)?;
+        assert_eq!(3, plan.view::<a>(seconds(0)..seconds(1))?.len());
+        assert_eq!(3, plan.sample::<a>(seconds(1))?);
+
+        Ok(())
+    }
+}
+
+mod touched_resources {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+
+    #[test]
+    fn includes_transitive_reads() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        // a at 3 depends on b at 2, which depends on a at 1.
+        plan.insert(seconds(1), IncrementA)?;
+        plan.insert(seconds(2), SetBToA)?;
+        plan.insert(seconds(3), AddBToA)?;
+
+        assert_eq!(2, plan.sample::<a>(seconds(4))?);
+        let mut expected = vec![ResourceId::of::<a>(), ResourceId::of::<b>()];
+        expected.sort();
+        assert_eq!(expected, plan.last_touched_resources());
+
+        // Once simulated, sampling b only touches b.
+        assert_eq!(1, plan.sample::<b>(seconds(4))?);
+        assert_eq!(vec![ResourceId::of::<b>()], plan.last_touched_resources());
+
+        Ok(())
+    }
+
+    #[test]
+    fn independent_resource_is_not_touched() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(1), IncrementA)?;
+        plan.insert(seconds(2), IncrementB)?;
+
+        assert_eq!(1, plan.sample::<a>(seconds(3))?);
+        assert_eq!(vec![ResourceId::of::<a>()], plan.last_touched_resources());
+
+        Ok(())
+    }
+}
+
+mod profiling {
+    #![cfg(feature = "profiling")]
+
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use std::fs::File;
+
+    #[test]
+    fn trace_has_entry_for_each_op() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), IncrementA)?;
+        plan.insert(seconds(1), SetBToA)?;
+        assert_eq!(1, plan.sample::<b>(seconds(2))?);
+
+        let path =
+            std::env::temp_dir().join(format!("peregrine-trace-{}.folded", std::process::id()));
+        plan.export_trace(File::create(&path)?)?;
+        let trace = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+
+        let lines = trace.lines().collect::<Vec<_>>();
+        assert_eq!(2, lines.len(), "{trace}");
+        assert!(lines.iter().any(|l| l.starts_with("a;op ")));
+        assert!(lines.iter().any(|l| l.starts_with("b;op ")));
+        for line in lines {
+            let (_, nanos) = line.rsplit_once(' ').unwrap();
+            assert!(nanos.parse::<u128>()? > 0);
+        }
+
+        Ok(())
+    }
+}
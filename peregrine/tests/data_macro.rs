@@ -1,5 +1,7 @@
 use peregrine::hifitime::TimeUnits;
-use peregrine::{Data, Linear, MaybeHash, Time};
+use peregrine::{
+    Activity, Data, Duration, Linear, MaybeHash, Ops, Session, Time, initial_conditions, model, op,
+};
 use serde::{Deserialize, Serialize};
 
 // Test basic struct with evolution
@@ -39,6 +41,7 @@ struct UnnamedStruct(Linear, u32);
 
 // Test enum with evolution
 #[derive(Data, MaybeHash, Clone, Serialize, Deserialize)]
+#[variant_tag]
 enum TestEnum {
     Unit,
     Single(Linear),
@@ -410,3 +413,66 @@ fn test_sample_consistency() {
     assert!((sample.value.value - evolved.value.value).abs() < 1e-10);
     assert_eq!(sample.count, evolved.count);
 }
+
+model! {
+    Modes {
+        mode: TestEnum;
+    }
+}
+
+#[derive(Hash, Serialize, Deserialize)]
+struct StartMultiple;
+
+#[typetag::serde]
+impl Activity for StartMultiple {
+    fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> peregrine::anyhow::Result<Duration> {
+        ops += op! {
+            w: mode = TestEnum::Multiple {
+                value: Linear::new(1.seconds(), 1.0, 1.0),
+                count: 2,
+            };
+        };
+        Ok(Duration::ZERO)
+    }
+}
+
+#[test]
+fn test_sampled_enum_variant() -> peregrine::anyhow::Result<()> {
+    let session = Session::new();
+    let mut plan = session.new_plan::<Modes>(
+        Time::from_tai_seconds(0.0),
+        initial_conditions! { mode: TestEnum::Unit },
+    )?;
+    plan.insert(Time::from_tai_seconds(1.0), StartMultiple)?;
+
+    let before = plan.sample::<mode>(Time::from_tai_seconds(0.5))?;
+    assert_eq!(TestEnumVariant::Unit, before.variant());
+
+    let after = plan.sample::<mode>(Time::from_tai_seconds(2.0))?;
+    match after.variant() {
+        TestEnumVariant::Multiple => {}
+        other => panic!("expected Multiple, found {other:?}"),
+    }
+    assert_eq!(
+        TestEnumVariant::Single,
+        TestEnum::Single(Linear::new(1.seconds(), 0.0, 0.0)).variant()
+    );
+
+    Ok(())
+}
+
+// Without `#[variant_tag]`, enums are free to define their own `variant()`.
+impl CustomSampleEnum {
+    fn variant(&self) -> &'static str {
+        match self {
+            CustomSampleEnum::Unit => "unit",
+            CustomSampleEnum::Single(_) => "single",
+            CustomSampleEnum::Multiple { .. } => "multiple",
+        }
+    }
+}
+
+#[test]
+fn test_variant_tag_is_opt_in() {
+    assert_eq!("unit", CustomSampleEnum::Unit.variant());
+}
@@ -0,0 +1,61 @@
+mod util;
+
+use anyhow::Result;
+use peregrine::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use util::*;
+
+/// Tracks the number of bytes currently allocated.
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Builds and drops the same plan twice in `session`, returning how many bytes the second one left allocated.
+///
+/// The first plan fills the session's history, so the second one only allocates in the arena.
+fn retained_by_plan(session: &Session) -> Result<usize> {
+    let mut retained = 0;
+    for _ in 0..2 {
+        let before = ALLOCATED.load(Ordering::SeqCst);
+        {
+            let mut plan = init_plan(session);
+            for i in 0..300 {
+                plan.insert(seconds(i), IncrementA)?;
+            }
+            assert_eq!(300, plan.sample::<a>(seconds(300))?);
+        }
+        retained = ALLOCATED.load(Ordering::SeqCst).saturating_sub(before);
+    }
+    Ok(retained)
+}
+
+#[test]
+fn dedicated_herd_is_freed_on_drop() -> Result<()> {
+    let shared = retained_by_plan(&Session::new())?;
+    let dedicated = retained_by_plan(&Session::new().with_dedicated_herds(true))?;
+
+    // Other threads in the test binary may allocate at the same time, so this only checks that
+    // the dedicated herd keeps far less than the shared one, which keeps the plan's arena.
+    assert!(
+        dedicated * 4 < shared,
+        "shared: {shared}, dedicated: {dedicated}"
+    );
+
+    Ok(())
+}
@@ -86,3 +86,1051 @@ fn basic_removal() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn max_activities() -> Result<()> {
+    let session = Session::new().with_max_activities(2);
+    let mut plan = init_plan(&session);
+
+    plan.insert(seconds(0), IncrementA)?;
+    let id = plan.insert(seconds(1), IncrementA)?;
+
+    let message = plan.insert(seconds(2), IncrementA).unwrap_err().to_string();
+    assert!(message.contains("maximum of 2 activities"), "{message}");
+    assert_eq!(2, plan.sample::<a>(seconds(3))?);
+
+    plan.remove(id)?;
+    plan.insert(seconds(2), IncrementA)?;
+    assert_eq!(2, plan.sample::<a>(seconds(3))?);
+
+    Ok(())
+}
+
+#[derive(Hash, serde::Serialize, serde::Deserialize)]
+struct MaybeAbort {
+    abort: bool,
+}
+
+#[typetag::serde]
+impl Activity for MaybeAbort {
+    fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+        ops += op! { m: a += 1; };
+        ops.abort_if(self.abort);
+        ops.wait(Duration::from_seconds(1.0));
+        ops += op! { m: a += 1; };
+        ops += op! { w: b = r: a; };
+
+        Ok(Duration::from_seconds(1.0))
+    }
+}
+
+#[test]
+fn abort_elides_later_operations() -> Result<()> {
+    let session = Session::new();
+    let mut plan = init_plan(&session);
+    plan.insert(seconds(0), MaybeAbort { abort: false })?;
+    let full = plan.view::<a>(seconds(0)..seconds(10))?.len();
+    assert_eq!(2, plan.sample::<a>(seconds(2))?);
+    assert_eq!(2, plan.sample::<b>(seconds(2))?);
+
+    let session = Session::new();
+    let mut plan = init_plan(&session);
+    plan.insert(seconds(0), MaybeAbort { abort: true })?;
+    let aborted = plan.view::<a>(seconds(0)..seconds(10))?.len();
+    assert_eq!(1, plan.sample::<a>(seconds(2))?);
+    assert_eq!(0, plan.sample::<b>(seconds(2))?);
+
+    assert!(aborted < full);
+
+    Ok(())
+}
+
+#[test]
+fn view_inclusive_next_includes_trailing_operation() -> Result<()> {
+    let session = Session::new();
+    let mut plan = init_plan(&session);
+
+    plan.insert(seconds(0), IncrementA)?;
+    plan.insert(seconds(2), IncrementA)?;
+    plan.insert(seconds(4), IncrementA)?;
+
+    let view = plan.view::<a>(seconds(1)..seconds(3))?;
+    assert_eq!(vec![(seconds(2), 2)], view);
+
+    let view = plan.view_inclusive_next::<a>(seconds(1)..seconds(3))?;
+    assert_eq!(vec![(seconds(2), 2), (seconds(4), 3)], view);
+
+    Ok(())
+}
+
+#[derive(Hash, serde::Serialize, serde::Deserialize)]
+struct IncrementThenCopy;
+
+#[typetag::serde]
+impl Activity for IncrementThenCopy {
+    fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+        ops += op! { m: a += 1; };
+        ops.wait(Duration::from_seconds(1.0));
+        ops += op! { w: b = r: a; };
+
+        Ok(Duration::from_seconds(1.0))
+    }
+}
+
+#[test]
+fn activity_operations_report_placements_and_resources() -> Result<()> {
+    let session = Session::new();
+    let mut plan = init_plan(&session);
+    let id = plan.insert(seconds(2), IncrementThenCopy)?;
+
+    let ops = plan.activity_operations(id)?;
+    assert_eq!(2, ops.len());
+
+    assert_eq!(OperationTime::Static(seconds(2)), ops[0].time);
+    assert_eq!(vec![ResourceDescriptor::of::<a>()], ops[0].reads);
+    assert_eq!(vec![ResourceDescriptor::of::<a>()], ops[0].writes);
+
+    assert_eq!(OperationTime::Static(seconds(3)), ops[1].time);
+    assert_eq!(vec![ResourceDescriptor::of::<a>()], ops[1].reads);
+    assert_eq!(vec![ResourceDescriptor::of::<b>()], ops[1].writes);
+
+    plan.remove(id)?;
+    assert!(plan.activity_operations(id).is_err());
+
+    Ok(())
+}
+
+/// Increments `a` several times, then copies it to `b`.
+#[derive(Hash, serde::Serialize, serde::Deserialize)]
+struct Burns(u32);
+
+#[typetag::serde]
+impl Activity for Burns {
+    fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+        for _ in 0..self.0 {
+            ops += op! { m: a += 1; };
+            ops.wait(Duration::from_seconds(1.0));
+        }
+        ops += op! { w: b = r: a; };
+
+        Ok(Duration::from_seconds(self.0 as f64))
+    }
+}
+
+#[test]
+fn activity_aggregate_sums_operation_costs() -> Result<()> {
+    let session = Session::new();
+    let mut plan = init_plan(&session);
+    let id = plan.insert(seconds(0), Burns(3))?;
+
+    let cost = |info: &OperationInfo| {
+        info.writes
+            .iter()
+            .map(|w| {
+                if *w == ResourceDescriptor::of::<a>() {
+                    2
+                } else {
+                    5
+                }
+            })
+            .sum::<u32>()
+    };
+    assert_eq!(11, plan.activity_aggregate(id, cost)?);
+
+    plan.remove(id)?;
+    assert!(plan.activity_aggregate(id, cost).is_err());
+
+    Ok(())
+}
+
+/// Adds `b` to `a` when verbose, and otherwise just increments `a`.
+#[derive(Hash, serde::Serialize, serde::Deserialize)]
+struct MaybeAddB {
+    verbose: bool,
+}
+
+#[typetag::serde]
+impl Activity for MaybeAddB {
+    fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+        let verbose = self.verbose;
+        ops += op! {
+            if const verbose {
+                m: a += r: b;
+            } else {
+                m: a += 1;
+            }
+        };
+        Ok(Duration::ZERO)
+    }
+}
+
+#[test]
+fn const_branch_skips_unused_reads() -> Result<()> {
+    let session = Session::new();
+    let mut plan = init_plan(&session);
+
+    plan.insert(seconds(0), IncrementB)?;
+    let quiet = plan.insert(seconds(1), MaybeAddB { verbose: false })?;
+    let verbose = plan.insert(seconds(2), MaybeAddB { verbose: true })?;
+
+    let quiet_ops = plan.activity_operations(quiet)?;
+    assert_eq!(vec![ResourceDescriptor::of::<a>()], quiet_ops[0].reads);
+
+    let verbose_ops = plan.activity_operations(verbose)?;
+    assert_eq!(
+        vec![ResourceDescriptor::of::<b>(), ResourceDescriptor::of::<a>()],
+        verbose_ops[0].reads
+    );
+
+    assert_eq!(1, plan.sample::<a>(seconds(1))?);
+    assert_eq!(2, plan.sample::<a>(seconds(2))?);
+
+    Ok(())
+}
+
+/// Increments `a` twice, then adds ten to it when the activity ends.
+#[derive(Hash, serde::Serialize, serde::Deserialize)]
+struct CleanupAtEnd;
+
+#[typetag::serde]
+impl Activity for CleanupAtEnd {
+    fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+        ops.at_activity_end(op! { m: a += 10; });
+        ops += op! { m: a += 1; };
+        ops.wait(Duration::from_seconds(2.0));
+        ops += op! { m: a += 1; };
+
+        Ok(Duration::from_seconds(5.0))
+    }
+}
+
+#[test]
+fn at_activity_end_places_op_at_duration() -> Result<()> {
+    let session = Session::new();
+    let mut plan = init_plan(&session);
+    let id = plan.insert(seconds(0), CleanupAtEnd)?;
+
+    let ops = plan.activity_operations(id)?;
+    assert_eq!(3, ops.len());
+    assert_eq!(OperationTime::Static(seconds(5)), ops[2].time);
+
+    assert_eq!(2, plan.sample::<a>(seconds(4))?);
+    assert_eq!(12, plan.sample::<a>(seconds(5))?);
+
+    Ok(())
+}
+
+#[test]
+fn reconstructed_plan_keeps_activity_ids() -> Result<()> {
+    let session = Session::new();
+    let mut plan = init_plan(&session);
+
+    let first = plan.insert(seconds(0), IncrementA)?;
+    let removed = plan.insert(seconds(1), IncrementA)?;
+    let last = plan.insert(seconds(2), SetBToA)?;
+    plan.remove(removed)?;
+    let next = plan.next_activity_id();
+
+    let mut reloaded = init_plan(&session);
+    reloaded.insert_with_id(last, seconds(2), SetBToA)?;
+    reloaded.insert_with_id(first, seconds(0), IncrementA)?;
+    reloaded.set_next_activity_id(next)?;
+
+    assert_eq!(1, reloaded.sample::<b>(seconds(3))?);
+    assert!(
+        reloaded
+            .insert_with_id(first, seconds(0), IncrementA)
+            .is_err()
+    );
+
+    // Both plans allocate the same ID next, rather than reusing the removed one.
+    let reloaded_next = reloaded.insert(seconds(3), IncrementB)?;
+    assert_eq!(plan.insert(seconds(3), IncrementB)?, reloaded_next);
+    assert_ne!(removed, reloaded_next);
+
+    reloaded.remove(first)?;
+    assert_eq!(0, reloaded.sample::<b>(seconds(2))?);
+
+    Ok(())
+}
+
+#[test]
+fn activities_touching_resource() -> Result<()> {
+    let session = Session::new();
+    let mut plan = init_plan(&session);
+
+    let increment_a = plan.insert(seconds(0), IncrementA)?;
+    let increment_b = plan.insert(seconds(1), IncrementB)?;
+    let set_b = plan.insert(seconds(2), SetBToA)?;
+
+    assert_eq!(vec![increment_a, set_b], plan.activities_touching::<a>());
+    assert_eq!(vec![increment_b, set_b], plan.activities_touching::<b>());
+
+    plan.remove(set_b)?;
+    assert_eq!(vec![increment_a], plan.activities_touching::<a>());
+
+    Ok(())
+}
+
+mod activity_tree {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::hifitime::TimeUnits;
+    use peregrine::*;
+
+    #[test]
+    fn anchored_activities_form_a_tree() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let root = plan.insert(seconds(0), IncrementA)?;
+        let late = plan.insert_anchored(root, Anchor::Start(5.seconds()), IncrementB)?;
+        let early = plan.insert_anchored(root, Anchor::End(1.seconds()), SetBToA)?;
+        let grandchild = plan.insert_anchored(late, Anchor::End(2.seconds()), SetAToB)?;
+        plan.insert(seconds(3), AddBToA)?;
+
+        let children = plan.children(root)?;
+        assert_eq!(
+            vec![(early, seconds(1)), (late, seconds(5))],
+            children
+                .iter()
+                .map(|child| (child.id, child.start))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(Anchor::End(1.seconds())), children[0].anchor);
+        assert!(plan.children(grandchild)?.is_empty());
+
+        let tree = plan.activity_tree(root)?;
+        assert_eq!(root, tree.activity.id);
+        assert_eq!(None, tree.activity.anchor);
+        assert_eq!(2, tree.children.len());
+        let leaf = &tree.children[1].children[0];
+        assert_eq!(
+            (grandchild, seconds(7)),
+            (leaf.activity.id, leaf.activity.start)
+        );
+        assert!(leaf.children.is_empty());
+
+        // Moving the root moves the whole tree.
+        plan.move_activity(root, seconds(10))?;
+        let tree = plan.activity_tree(root)?;
+        assert_eq!(seconds(17), tree.children[1].children[0].activity.start);
+
+        assert!(plan.children(ActivityId::new(100)).is_err());
+
+        Ok(())
+    }
+}
+
+mod anchoring {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    /// Increments `a` at the start, and lasts five seconds.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SlowIncrementA;
+
+    #[typetag::serde]
+    impl Activity for SlowIncrementA {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops += op! {
+                m: a += 1;
+            };
+
+            Ok(5.seconds())
+        }
+    }
+
+    #[test]
+    fn anchored_activities_follow_their_parent() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        let parent = plan.insert(seconds(0), SlowIncrementA)?;
+        let child = plan.insert_anchored(parent, Anchor::End(1.seconds()), SetBToA)?;
+        plan.insert_anchored(child, Anchor::Start(1.seconds()), IncrementB)?;
+        plan.insert(seconds(10), IncrementA)?;
+
+        assert_eq!(0, plan.sample::<b>(seconds(5))?);
+        assert_eq!(1, plan.sample::<b>(seconds(6))?);
+        assert_eq!(2, plan.sample::<b>(seconds(8))?);
+
+        plan.move_activity(parent, seconds(20))?;
+        assert_eq!(0, plan.sample::<b>(seconds(8))?);
+        assert_eq!(2, plan.sample::<b>(seconds(26))?);
+        assert_eq!(3, plan.sample::<b>(seconds(28))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn removing_the_parent_unanchors_children() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        let parent = plan.insert(seconds(0), SlowIncrementA)?;
+        let child = plan.insert_anchored(parent, Anchor::Start(2.seconds()), IncrementB)?;
+        plan.remove(parent)?;
+        assert_eq!(1, plan.sample::<b>(seconds(3))?);
+        assert!(
+            plan.insert_anchored(parent, Anchor::Start(Duration::ZERO), IncrementB)
+                .is_err()
+        );
+
+        plan.insert_with_id(parent, seconds(0), SlowIncrementA)?;
+        plan.move_activity(parent, seconds(10))?;
+        assert_eq!(1, plan.sample::<b>(seconds(3))?);
+
+        plan.move_activity(child, seconds(4))?;
+        assert_eq!(0, plan.sample::<b>(seconds(3))?);
+
+        Ok(())
+    }
+}
+
+mod merge_activities {
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Rover {
+            sol_counter: u32 = 0;
+        }
+    }
+
+    /// Increments the sol counter once a day, for `sols` days.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct IncrementSol {
+        sols: u32,
+    }
+
+    #[typetag::serde]
+    impl Activity for IncrementSol {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            for _ in 0..self.sols {
+                ops += op! { m: sol_counter += 1; };
+                ops.wait(Duration::from_days(1.0));
+            }
+            Ok(Duration::from_days(self.sols as f64))
+        }
+    }
+
+    impl MergeActivity for IncrementSol {
+        fn merge(&self, next: &Self) -> Option<Self> {
+            Some(IncrementSol {
+                sols: self.sols + next.sols,
+            })
+        }
+    }
+
+    fn days(d: i32) -> Time {
+        Time::from_tai_days(d as f64)
+    }
+
+    #[test]
+    fn merged_activity_is_equivalent() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Rover>(days(0), initial_conditions! {})?;
+
+        let first = plan.insert(days(1), IncrementSol { sols: 2 })?;
+        let second = plan.insert(days(3), IncrementSol { sols: 3 })?;
+        let before = plan.view::<sol_counter>(days(0)..days(10))?;
+
+        plan.merge_adjacent_activities::<IncrementSol>(first, second)?;
+        plan.validate_integrity()?;
+
+        assert_eq!(5, plan.activity_operations(first)?.len());
+        assert!(plan.activity_operations(second).is_err());
+        assert_eq!(before, plan.view::<sol_counter>(days(0)..days(10))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn gaps_are_not_merged() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Rover>(days(0), initial_conditions! {})?;
+
+        let first = plan.insert(days(1), IncrementSol { sols: 1 })?;
+        let second = plan.insert(days(3), IncrementSol { sols: 1 })?;
+
+        assert!(
+            plan.merge_adjacent_activities::<IncrementSol>(first, second)
+                .is_err()
+        );
+        assert_eq!(2, plan.sample::<sol_counter>(days(5))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn anchored_children_follow_the_merge() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Rover>(days(0), initial_conditions! {})?;
+
+        let first = plan.insert(days(1), IncrementSol { sols: 1 })?;
+        let second =
+            plan.insert_anchored(first, Anchor::End(Duration::ZERO), IncrementSol { sols: 2 })?;
+        let after_first =
+            plan.insert_anchored(first, Anchor::End(Duration::ZERO), IncrementSol { sols: 1 })?;
+        let during_second = plan.insert_anchored(
+            second,
+            Anchor::Start(Duration::from_days(1.0)),
+            IncrementSol { sols: 1 },
+        )?;
+        assert_eq!(days(2), plan.children(first)?[0].start);
+
+        plan.merge_adjacent_activities::<IncrementSol>(first, second)?;
+        plan.validate_integrity()?;
+
+        let children = plan.children(first)?;
+        assert_eq!(
+            vec![
+                (
+                    during_second,
+                    days(3),
+                    Some(Anchor::Start(Duration::from_days(2.0)))
+                ),
+                (after_first, days(4), Some(Anchor::End(Duration::ZERO))),
+            ],
+            children
+                .iter()
+                .map(|child| (child.id, child.start, child.anchor))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(5, plan.sample::<sol_counter>(days(6))?);
+
+        Ok(())
+    }
+}
+
+mod insert_batch {
+    use crate::util::*;
+    use anyhow::{Result, bail};
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    /// Creates an operation, then fails.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct FailsAfterOps;
+
+    #[typetag::serde]
+    impl Activity for FailsAfterOps {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { m: a += 100; };
+            bail!("failed after creating operations")
+        }
+    }
+
+    #[test]
+    fn batch_matches_individual_inserts() -> Result<()> {
+        let session = Session::new();
+        let mut individual = init_plan(&session);
+        individual.insert(seconds(0), IncrementA)?;
+        individual.insert(seconds(1), SetBToA)?;
+        individual.insert(seconds(2), AddBToA)?;
+        individual.insert(seconds(3), IncrementB)?;
+
+        let mut batched = init_plan(&session);
+        let ids = batched.insert_batch([
+            (seconds(0), Box::new(IncrementA) as Box<dyn Activity>),
+            (seconds(1), Box::new(SetBToA)),
+            (seconds(2), Box::new(AddBToA)),
+            (seconds(3), Box::new(IncrementB)),
+        ])?;
+        batched.validate_integrity()?;
+
+        assert_eq!((0..4).map(ActivityId::new).collect::<Vec<_>>(), ids);
+        assert_eq!(ActivityId::new(4), batched.next_activity_id());
+        for time in [seconds(2), seconds(4)] {
+            assert_eq!(individual.sample::<a>(time)?, batched.sample::<a>(time)?);
+            assert_eq!(individual.sample::<b>(time)?, batched.sample::<b>(time)?);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn batch_invalidates_simulated_results() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        plan.insert(seconds(0), IncrementA)?;
+        plan.insert(seconds(5), SetBToA)?;
+        assert_eq!(1, plan.sample::<b>(seconds(6))?);
+
+        plan.insert_batch([
+            (seconds(1), Box::new(IncrementA) as Box<dyn Activity>),
+            (seconds(2), Box::new(IncrementA)),
+            (seconds(3), Box::new(AddBToA)),
+        ])?;
+        assert_eq!(3, plan.sample::<b>(seconds(6))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_batch_leaves_plan_unchanged() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let first = plan.insert(seconds(0), IncrementA)?;
+        assert_eq!(1, plan.sample::<a>(seconds(5))?);
+        let next_id = plan.next_activity_id();
+
+        let result = plan.insert_batch([
+            (seconds(1), Box::new(IncrementA) as Box<dyn Activity>),
+            (seconds(2), Box::new(FailsAfterOps)),
+            (seconds(3), Box::new(IncrementA)),
+        ]);
+        assert!(result.is_err());
+        plan.validate_integrity()?;
+
+        assert_eq!(next_id, plan.next_activity_id());
+        assert_eq!(vec![first], plan.activities_touching::<a>());
+        assert_eq!(1, plan.sample::<a>(seconds(5))?);
+
+        Ok(())
+    }
+}
+
+mod failed_insert {
+    use crate::util::*;
+    use anyhow::{Result, bail};
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    /// Creates operations, then fails.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct FailsAfterTwoOps;
+
+    #[typetag::serde]
+    impl Activity for FailsAfterTwoOps {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { m: a += 100; };
+            ops += op! { w: b = 100; };
+            bail!("failed after creating operations")
+        }
+    }
+
+    #[test]
+    fn failed_activity_leaves_plan_unchanged() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        let first = plan.insert(seconds(0), IncrementA)?;
+        let second = plan.insert(seconds(2), SetBToA)?;
+        let next_id = plan.next_activity_id();
+
+        assert!(plan.insert(seconds(1), FailsAfterTwoOps).is_err());
+
+        assert_eq!(next_id, plan.next_activity_id());
+        assert_eq!(vec![first, second], plan.activities_touching::<a>());
+        assert_eq!(vec![second], plan.activities_touching::<b>());
+        plan.validate_integrity()?;
+
+        assert_eq!(1, plan.sample::<a>(seconds(3))?);
+        assert_eq!(1, plan.sample::<b>(seconds(3))?);
+
+        // The plan keeps working normally afterward.
+        let id = plan.insert(seconds(1), IncrementA)?;
+        assert_eq!(next_id, id);
+        assert_eq!(2, plan.sample::<b>(seconds(3))?);
+
+        Ok(())
+    }
+}
+
+mod update_activity {
+    use crate::util::seconds;
+    use anyhow::{Result, anyhow};
+    use peregrine::hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Rover {
+            odometer: u32 = 0;
+            images: u32 = 0;
+        }
+    }
+
+    /// Drives `meters` one second at a time.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Drive {
+        meters: u32,
+    }
+
+    #[typetag::serde]
+    impl Activity for Drive {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            if self.meters == 0 {
+                return Err(anyhow!("cannot drive zero meters"));
+            }
+            for _ in 0..self.meters {
+                ops.wait(1.seconds());
+                ops += op! { m: odometer += 1; };
+            }
+            Ok((self.meters as i64).seconds())
+        }
+    }
+
+    /// Takes an image.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Image;
+
+    #[typetag::serde]
+    impl Activity for Image {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops += op! { m: images += 1; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn updated_activities_keep_their_place() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Rover>(seconds(0), initial_conditions! {})?;
+        let drive = plan.insert(seconds(1), Drive { meters: 2 })?;
+        let image = plan.insert_anchored(drive, Anchor::End(1.seconds()), Image)?;
+        assert_eq!(2, plan.sample::<odometer>(seconds(10))?);
+
+        plan.update(drive, Drive { meters: 5 })?;
+        plan.validate_integrity()?;
+        assert_eq!(5, plan.sample::<odometer>(seconds(10))?);
+
+        // The image is anchored to the end of the drive, which is now later.
+        let tree = plan.activity_tree(drive)?;
+        assert_eq!(seconds(1), tree.activity.start);
+        assert_eq!(
+            (image, seconds(7)),
+            (
+                tree.children[0].activity.id,
+                tree.children[0].activity.start
+            )
+        );
+        assert_eq!(0, plan.sample::<images>(seconds(6))?);
+        assert_eq!(1, plan.sample::<images>(seconds(7))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_updates_leave_the_plan_unchanged() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Rover>(seconds(0), initial_conditions! {})?;
+        let drive = plan.insert(seconds(0), Drive { meters: 3 })?;
+        let image = plan.insert(seconds(1), Image)?;
+
+        assert!(plan.update(drive, Drive { meters: 0 }).is_err());
+        assert!(plan.update(drive, Image).is_err());
+        assert!(plan.update(image, Drive { meters: 1 }).is_err());
+        assert!(plan.update(ActivityId::new(100), Image).is_err());
+        plan.validate_integrity()?;
+
+        assert_eq!(3, plan.sample::<odometer>(seconds(10))?);
+        assert_eq!(1, plan.sample::<images>(seconds(10))?);
+
+        Ok(())
+    }
+}
+
+mod shift {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::hifitime::TimeUnits;
+    use peregrine::*;
+
+    #[test]
+    fn shifts_plan_by_a_day() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), IncrementA)?;
+        plan.insert(seconds(1), SetBToA)?;
+        plan.insert(seconds(2), IncrementA)?;
+
+        let before = [0, 1, 2, 3]
+            .map(|s| Ok((plan.sample::<a>(seconds(s))?, plan.sample::<b>(seconds(s))?)))
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+
+        plan.shift(1.days())?;
+
+        let after = [0, 1, 2, 3]
+            .map(|s| {
+                let time = seconds(s) + 1.days();
+                Ok((plan.sample::<a>(time)?, plan.sample::<b>(time)?))
+            })
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(before, after);
+        assert_eq!(vec![(1, 0), (1, 1), (2, 1), (2, 1)], after);
+
+        // Before the shifted start, there are no initial conditions to sample.
+        assert!(plan.sample::<a>(seconds(0)).is_err());
+
+        plan.shift(-1.days())?;
+        assert_eq!(2, plan.sample::<a>(seconds(3))?);
+
+        Ok(())
+    }
+}
+
+mod branch {
+    #![cfg(feature = "serde")]
+
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::hifitime::TimeUnits;
+    use peregrine::*;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[test]
+    fn merges_changes_from_both_branches() -> Result<()> {
+        let session = Session::new();
+        let mut base = init_plan(&session);
+        base.insert(seconds(0), IncrementA)?;
+        let set_b = base.insert(seconds(1), SetBToA)?;
+        assert_eq!(1, base.sample::<b>(seconds(2))?);
+
+        let mut first = base.branch()?;
+        let mut second = base.branch()?;
+        assert_eq!(1, first.sample::<b>(seconds(2))?);
+        assert_eq!(PlanDelta::default(), first.delta());
+
+        let from_first = first.insert(seconds(3), IncrementA)?;
+        second.remove(set_b)?;
+        let from_second = second.insert(seconds(4), IncrementB)?;
+        assert_eq!(from_first, from_second);
+        assert_eq!(
+            PlanDelta {
+                inserted: BTreeSet::from([from_second]),
+                removed: BTreeSet::from([set_b]),
+            },
+            second.delta()
+        );
+
+        assert_eq!(
+            BTreeMap::from([(from_first, from_first)]),
+            base.merge(&first)?
+        );
+        assert!(session.shares_state::<_, a>(&base, &first, seconds(5)));
+
+        // The second branch's new activity already has its ID taken by the first's.
+        let ids = base.merge(&second)?;
+        let renumbered = ids[&from_second];
+        assert_ne!(from_second, renumbered);
+        assert!(!base.delta().inserted.contains(&set_b));
+
+        assert_eq!(2, base.sample::<a>(seconds(5))?);
+        assert_eq!(1, base.sample::<b>(seconds(5))?);
+
+        base.remove(renumbered)?;
+        assert_eq!(0, base.sample::<b>(seconds(5))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_conflicting_changes() -> Result<()> {
+        let session = Session::new();
+        let mut base = init_plan(&session);
+        let id = base.insert(seconds(0), IncrementA)?;
+
+        let mut first = base.branch()?;
+        let mut second = base.branch()?;
+        first.remove(id)?;
+        first.insert_with_id(id, seconds(0), IncrementB)?;
+        second.remove(id)?;
+
+        base.merge(&first)?;
+        assert!(base.merge(&second).is_err());
+
+        // The failed merge left the plan unchanged.
+        assert_eq!(0, base.sample::<a>(seconds(1))?);
+        assert_eq!(1, base.sample::<b>(seconds(1))?);
+
+        // Removing the same activity on both sides is not a conflict.
+        let mut third = base.branch()?;
+        let mut fourth = base.branch()?;
+        third.remove(id)?;
+        fourth.remove(id)?;
+        base.merge(&third)?;
+        base.merge(&fourth)?;
+        assert_eq!(0, base.sample::<b>(seconds(1))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn merged_activities_stay_anchored() -> Result<()> {
+        let session = Session::new();
+        let mut base = init_plan(&session);
+        let root = base.insert(seconds(0), IncrementA)?;
+
+        let mut branch = base.branch()?;
+        let child = branch.insert_anchored(root, Anchor::Start(2.seconds()), SetBToA)?;
+        let grandchild = branch.insert_anchored(child, Anchor::End(1.seconds()), IncrementB)?;
+
+        // Taking the child's ID here renumbers both inserted activities.
+        base.insert(seconds(5), IncrementA)?;
+        let ids = base.merge(&branch)?;
+        assert_ne!(child, ids[&child]);
+
+        let tree = base.activity_tree(root)?;
+        assert_eq!(
+            (ids[&child], Some(Anchor::Start(2.seconds()))),
+            (
+                tree.children[0].activity.id,
+                tree.children[0].activity.anchor
+            )
+        );
+        let leaf = &tree.children[0].children[0];
+        assert_eq!(
+            (ids[&grandchild], Some(Anchor::End(1.seconds()))),
+            (leaf.activity.id, leaf.activity.anchor)
+        );
+
+        // Moving the root moves the merged activities with it.
+        base.move_activity(root, seconds(10))?;
+        assert_eq!(
+            seconds(12),
+            base.activity_tree(root)?.children[0].activity.start
+        );
+        assert_eq!(3, base.sample::<b>(seconds(14))?);
+
+        Ok(())
+    }
+}
+
+mod snapshot {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+
+    #[test]
+    fn forks_plan_from_snapshot() -> Result<()> {
+        let session = Session::new();
+        let mut original = init_plan(&session);
+
+        original.insert(seconds(0), IncrementA)?;
+        original.insert(seconds(1), IncrementA)?;
+        original.insert(seconds(2), SetBToA)?;
+        original.insert(seconds(4), IncrementA)?;
+
+        let snapshot = original.snapshot(seconds(3))?;
+        assert_eq!(Some(&2), snapshot.get::<a>());
+        assert_eq!(Some(&2), snapshot.get::<b>());
+
+        let forked =
+            session.new_plan::<AB>(seconds(0), InitialConditions::from_snapshot(snapshot))?;
+        assert_eq!(
+            original.sample::<a>(seconds(3))?,
+            forked.sample::<a>(seconds(0))?
+        );
+        assert_eq!(
+            original.sample::<b>(seconds(3))?,
+            forked.sample::<b>(seconds(0))?
+        );
+
+        Ok(())
+    }
+}
+
+mod priority {
+    use crate::util::*;
+    use anyhow::Result;
+    use parking_lot::Mutex;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    /// The values written by [Write], in the order their bodies ran.
+    static RUN_ORDER: Mutex<Vec<u32>> = Mutex::new(vec![]);
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Write {
+        value: u32,
+        priority: bool,
+    }
+
+    #[typetag::serde]
+    impl Activity for Write {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let value = self.value;
+            if self.priority {
+                ops += op! {
+                    #![priority]
+                    RUN_ORDER.lock().push(value);
+                    w: a = value;
+                };
+            } else {
+                ops += op! {
+                    RUN_ORDER.lock().push(value);
+                    w: a = value;
+                };
+            }
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn prioritized_operations_run_first() -> Result<()> {
+        // A single worker makes the order the operations are picked up in observable.
+        let session = Session::new().with_deterministic_execution(true);
+        let mut plan = init_plan(&session);
+
+        plan.insert(
+            seconds(0),
+            Write {
+                value: 1,
+                priority: true,
+            },
+        )?;
+        for value in 2..5 {
+            plan.insert(
+                seconds(value as i32),
+                Write {
+                    value,
+                    priority: false,
+                },
+            )?;
+        }
+
+        let view = plan.view::<a>(seconds(0)..seconds(5))?;
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            view.into_iter().map(|(_, a)| a).collect::<Vec<_>>()
+        );
+        assert_eq!(Some(&1), RUN_ORDER.lock().first());
+
+        Ok(())
+    }
+}
+
+mod export_dag {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+
+    #[test]
+    fn exports_read_edges() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), IncrementA)?;
+        plan.insert(seconds(1), SetBToA)?;
+        plan.sample::<b>(seconds(2))?;
+
+        let mut dot = vec![];
+        plan.export_dag(&mut dot)?;
+        let dot = String::from_utf8(dot)?;
+
+        assert!(dot.starts_with("digraph operations {"), "{dot}");
+        assert!(dot.contains("op1 -> op0 [label=\"a\"];"), "{dot}");
+        assert!(dot.contains("op0 -> other0 [label=\"a\"];"), "{dot}");
+
+        Ok(())
+    }
+}
@@ -3,12 +3,13 @@ mod util;
 use hifitime::TimeUnits;
 use peregrine::anyhow::Result;
 use peregrine::{
-    Activity, Duration, Ops, OpsReceiver, Resource, Session, delay, initial_conditions, model, op,
+    Activity, Duration, Ops, OpsReceiver, Resource, ResourceId, Session, delay, initial_conditions,
+    model, op,
 };
 use serde::{Deserialize, Serialize};
 use util::{AB, a, b};
 
-use crate::util::{IncrementA, seconds};
+use crate::util::{IncrementA, IncrementB, seconds};
 
 model! {
     pub ReactTest {}
@@ -154,3 +155,283 @@ fn test_react_all_removal() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn runtime_daemon_inserts_activities() -> Result<()> {
+    let session = Session::new();
+    let mut plan = session.new_plan::<AB>(seconds(0), initial_conditions! { a: 0, b: 0 })?;
+
+    plan.add_reactive_daemon(&[ResourceId::of::<a>()], || {
+        vec![Box::new(IncrementB) as Box<dyn Activity>]
+    });
+
+    plan.insert(seconds(1), IncrementA)?;
+    assert_eq!(1, plan.sample::<b>(seconds(2))?);
+
+    let second = plan.insert(seconds(2), IncrementA)?;
+    assert_eq!(2, plan.sample::<b>(seconds(3))?);
+
+    // The daemon's operations are removed along with the write that triggered them.
+    plan.remove(second)?;
+    assert_eq!(1, plan.sample::<b>(seconds(3))?);
+
+    Ok(())
+}
+
+/// Fails without creating operations.
+#[derive(Hash, Serialize, Deserialize)]
+struct Fails;
+
+#[typetag::serde]
+impl Activity for Fails {
+    fn run(&self, _ops: Ops) -> Result<Duration> {
+        peregrine::anyhow::bail!("daemon activity failed")
+    }
+}
+
+#[test]
+fn failed_runtime_daemon_fails_the_insert() -> Result<()> {
+    let session = Session::new();
+    let mut plan = session.new_plan::<AB>(seconds(0), initial_conditions! { a: 0, b: 0 })?;
+    plan.add_reactive_daemon(&[ResourceId::of::<b>()], || {
+        vec![Box::new(Fails) as Box<dyn Activity>]
+    });
+
+    plan.insert(seconds(1), IncrementA)?;
+    let next_id = plan.next_activity_id();
+    let error = plan.insert(seconds(2), IncrementB).unwrap_err();
+    assert_eq!("daemon activity failed", error.to_string());
+
+    plan.validate_integrity()?;
+    assert_eq!(next_id, plan.next_activity_id());
+    assert_eq!(0, plan.sample::<b>(seconds(3))?);
+    assert_eq!(1, plan.sample::<a>(seconds(3))?);
+
+    Ok(())
+}
+
+mod recurring_daemon {
+    use hifitime::TimeUnits;
+    use peregrine::anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    model! {
+        pub Battery {
+            charge: f64 = 100.0;
+            ticks: u32;
+        }
+        daemon recurring(1.hours()) => decay(1);
+    }
+
+    model! {
+        pub Stalled {
+            stalled_ticks: u32;
+        }
+        daemon recurring(Duration::ZERO) => stall();
+    }
+
+    model! {
+        pub Telemetry {
+            level: u32 = 0;
+            sampled: u32 = 0;
+        }
+        daemon recurring(1.minutes()) => sample_level();
+    }
+
+    model! {
+        pub Clock {
+            clock_sample: u32 = 0;
+            clock_report: u32 = 0;
+        }
+        daemon recurring(1.minutes()) => sample_clock();
+    }
+
+    model! {
+        pub Drifting {
+            first_sample: u32 = 0;
+            later_sample: u32 = 0;
+        }
+        daemon recurring(1.hours()) => drift();
+    }
+
+    /// How many ticks of [Drifting]'s daemon have run.
+    static DRIFTING_TICKS: AtomicU32 = AtomicU32::new(0);
+
+    /// Writes a different resource after the first tick.
+    fn drift(mut ops: Ops) {
+        if DRIFTING_TICKS.fetch_add(1, Ordering::SeqCst) == 0 {
+            ops += op! { w: first_sample = 1; };
+        } else {
+            ops += op! { w: later_sample = 1; };
+        }
+    }
+
+    fn sample_clock(mut ops: Ops) {
+        ops += op! { w: clock_sample = r: elapsed.to_seconds() as u32; };
+    }
+
+    /// How many ticks of [Telemetry]'s daemon have run.
+    static TELEMETRY_TICKS: AtomicU32 = AtomicU32::new(0);
+
+    fn sample_level(mut ops: Ops) {
+        TELEMETRY_TICKS.fetch_add(1, Ordering::SeqCst);
+        ops += op! { w: sampled = r: level; };
+    }
+
+    fn decay(mut ops: Ops, amount: u32) {
+        ops += op! {
+            m: charge -= amount as f64;
+            m: ticks += 1;
+        };
+    }
+
+    fn stall(mut ops: Ops) {
+        ops += op! { m: stalled_ticks += 1; };
+    }
+
+    /// Sets the charge.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Charge(u32);
+
+    #[typetag::serde]
+    impl Activity for Charge {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let level = self.0;
+            ops += op! { w: charge = level as f64; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    fn hours(h: f64) -> Time {
+        Time::from_tai_seconds(h * 3600.0)
+    }
+
+    #[test]
+    fn ticks_over_the_queried_range() -> Result<()> {
+        let session = Session::new();
+        let plan = session.new_plan::<Battery>(hours(0.0), initial_conditions! {})?;
+
+        assert_eq!(99.0, plan.sample::<charge>(hours(0.0))?);
+        assert_eq!(98.0, plan.sample::<charge>(hours(1.5))?);
+        assert_eq!(11, plan.sample::<ticks>(hours(10.0))?);
+        assert_eq!(
+            vec![(hours(2.0), 3), (hours(3.0), 4)],
+            plan.view::<ticks>(hours(1.5)..hours(3.5))?
+        );
+        plan.validate_integrity()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn ticks_before_activities_at_the_same_time() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Battery>(hours(0.0), initial_conditions! {})?;
+
+        plan.insert(hours(2.0), Charge(50))?;
+        assert_eq!(50.0, plan.sample::<charge>(hours(2.0))?);
+        assert_eq!(48.0, plan.sample::<charge>(hours(4.0))?);
+
+        // Ticks that already ran see new activities.
+        plan.insert(hours(3.5), Charge(80))?;
+        assert_eq!(79.0, plan.sample::<charge>(hours(4.0))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn shifting_restarts_ticks() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Battery>(hours(0.0), initial_conditions! {})?;
+
+        assert_eq!(3, plan.sample::<ticks>(hours(2.0))?);
+        plan.shift(30.minutes())?;
+        assert_eq!(2, plan.sample::<ticks>(hours(2.0))?);
+        assert_eq!(3, plan.sample::<ticks>(hours(2.5))?);
+        plan.validate_integrity()?;
+
+        Ok(())
+    }
+
+    /// Sets the level.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SetLevel(u32);
+
+    #[typetag::serde]
+    impl Activity for SetLevel {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let new_level = self.0;
+            ops += op! { w: level = new_level; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn independent_ticks_only_run_where_they_are_read() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Telemetry>(hours(0.0), initial_conditions! {})?;
+
+        plan.insert(hours(1.0), SetLevel(5))?;
+        assert_eq!(5, plan.sample::<sampled>(hours(1000.0))?);
+        assert_eq!(
+            vec![(hours(1000.0), 5), (hours(1000.0) + 1.minutes(), 5)],
+            plan.view::<sampled>(hours(1000.0)..=hours(1000.0) + 1.minutes())?
+        );
+        // The first tick, the ticks around the activity, and the ticks around the queries,
+        // instead of every tick for a thousand hours.
+        assert!(TELEMETRY_TICKS.load(Ordering::SeqCst) < 10);
+        plan.validate_integrity()?;
+
+        Ok(())
+    }
+
+    /// Reports the clock sample from eight hours before.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct ClockReport;
+
+    #[typetag::serde]
+    impl Activity for ClockReport {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: clock_report = ref(-8.hours()): clock_sample; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn look_back_reads_see_every_tick() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Clock>(hours(0.0), initial_conditions! {})?;
+
+        plan.insert(hours(10.0), ClockReport)?;
+        // Look-back reads come before the tick at the same time, so this is the tick before it.
+        assert_eq!(2 * 3600 - 60, plan.sample::<clock_report>(hours(10.0))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_ticks_that_change_resources() -> Result<()> {
+        let session = Session::new();
+        let plan = session.new_plan::<Drifting>(hours(0.0), initial_conditions! {})?;
+
+        let message = format!("{:#}", plan.sample::<later_sample>(hours(3.0)).unwrap_err());
+        assert!(
+            message.contains("reads or writes different resources than the first tick"),
+            "{message}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_non_positive_periods() {
+        let session = Session::new();
+        assert!(
+            session
+                .new_plan::<Stalled>(hours(0.0), initial_conditions! {})
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,928 @@
+mod util;
+
+use peregrine::Time;
+
+fn minutes(m: i64) -> Time {
+    Time::from_tai_seconds(m as f64 * 60.0)
+}
+
+mod at_end {
+    use crate::minutes;
+    use anyhow::Result;
+    use hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Thermal {
+            heater_setpoint: f64;
+            planned_setpoint: f64 = 0.0;
+        }
+    }
+
+    /// Records the setpoint it will leave behind, then changes it.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Heat {
+        setpoint: u32,
+        cleanup: bool,
+    }
+
+    #[typetag::serde]
+    impl Activity for Heat {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let setpoint = self.setpoint;
+            ops += op! { w: planned_setpoint = ref at_end: heater_setpoint; };
+            ops.wait(5.minutes());
+            ops += op! { w: heater_setpoint = setpoint as f64; };
+            if self.cleanup {
+                ops.at_activity_end(op! { m: heater_setpoint -= 1.0; });
+            }
+            Ok(10.minutes())
+        }
+    }
+
+    /// Changes the setpoint without reading anything.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Set(u32);
+
+    #[typetag::serde]
+    impl Activity for Set {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let setpoint = self.0;
+            ops += op! { w: heater_setpoint = setpoint as f64; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn early_op_reads_value_at_activity_end() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session
+            .new_plan::<Thermal>(minutes(0), initial_conditions! { heater_setpoint: 20.0 })?;
+
+        plan.insert(
+            minutes(0),
+            Heat {
+                setpoint: 30,
+                cleanup: false,
+            },
+        )?;
+        assert_eq!(30.0, plan.sample::<planned_setpoint>(minutes(1))?);
+        assert_eq!(20.0, plan.sample::<heater_setpoint>(minutes(1))?);
+
+        plan.insert(
+            minutes(20),
+            Heat {
+                setpoint: 40,
+                cleanup: true,
+            },
+        )?;
+        // Includes the operation added at the activity's end.
+        assert_eq!(39.0, plan.sample::<planned_setpoint>(minutes(21))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tracks_changes_before_activity_end() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session
+            .new_plan::<Thermal>(minutes(0), initial_conditions! { heater_setpoint: 20.0 })?;
+
+        plan.insert(
+            minutes(0),
+            Heat {
+                setpoint: 30,
+                cleanup: false,
+            },
+        )?;
+        assert_eq!(30.0, plan.sample::<planned_setpoint>(minutes(1))?);
+
+        // Between the activity's own write and its end.
+        let overwrite = plan.insert(minutes(8), Set(25))?;
+        assert_eq!(25.0, plan.sample::<planned_setpoint>(minutes(1))?);
+
+        // After the end doesn't count.
+        plan.insert(minutes(12), Set(50))?;
+        assert_eq!(25.0, plan.sample::<planned_setpoint>(minutes(1))?);
+
+        plan.remove(overwrite)?;
+        assert_eq!(30.0, plan.sample::<planned_setpoint>(minutes(1))?);
+
+        Ok(())
+    }
+}
+
+mod look_back {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    /// Sets `b` to the value `a` had five seconds earlier.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SetBToEarlierA;
+
+    #[typetag::serde]
+    impl Activity for SetBToEarlierA {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops += op! {
+                w: b = ref(-5.seconds()): a;
+            };
+
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Increments `a`, and sets `b` to the value `a` had before any operations at the same time.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct IncrementAAndSetBToPriorA;
+
+    #[typetag::serde]
+    impl Activity for IncrementAAndSetBToPriorA {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops += op! {
+                m: a += 1;
+            };
+            ops += op! {
+                w: b = ref(Duration::ZERO): a;
+            };
+
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Tries to read `a` in the future.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct LookAhead;
+
+    #[typetag::serde]
+    impl Activity for LookAhead {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops += op! {
+                w: b = ref(5.seconds()): a;
+            };
+
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn reads_earlier_value() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), IncrementA)?;
+        plan.insert(seconds(5), SetBToEarlierA)?;
+        plan.insert(seconds(10), SetBToEarlierA)?;
+
+        assert_eq!(0, plan.sample::<b>(seconds(6))?);
+        assert_eq!(1, plan.sample::<b>(seconds(11))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tracks_changes_before_the_earlier_time() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(10), SetBToEarlierA)?;
+        assert_eq!(0, plan.sample::<b>(seconds(11))?);
+
+        // After the earlier time doesn't count.
+        plan.insert(seconds(7), IncrementA)?;
+        assert_eq!(0, plan.sample::<b>(seconds(11))?);
+
+        let increment = plan.insert(seconds(4), IncrementA)?;
+        assert_eq!(1, plan.sample::<b>(seconds(11))?);
+
+        plan.remove(increment)?;
+        assert_eq!(0, plan.sample::<b>(seconds(11))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn zero_offset_reads_before_the_same_time() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), IncrementAAndSetBToPriorA)?;
+        assert_eq!(1, plan.sample::<a>(seconds(1))?);
+        assert_eq!(0, plan.sample::<b>(seconds(1))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_looking_ahead() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), LookAhead)?;
+        assert!(plan.sample::<b>(seconds(1)).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_looking_before_the_plan() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        // The plan starts at -1.
+        plan.insert(seconds(3), SetBToEarlierA)?;
+        assert!(plan.sample::<b>(seconds(4)).is_err());
+
+        plan.insert(seconds(4), SetBToEarlierA)?;
+        assert_eq!(0, plan.sample::<b>(seconds(5))?);
+
+        Ok(())
+    }
+}
+
+mod since {
+    use crate::minutes;
+    use anyhow::Result;
+    use hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Radio {
+            downlink_buffer: Vec<String> = vec![];
+            logged: u32 = 0;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Log(String);
+
+    #[typetag::serde]
+    impl Activity for Log {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let message = &self.0;
+            ops += op! { m: downlink_buffer.push(message.clone()); };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Counts the messages logged while it was running.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Pass {
+        minutes: i64,
+    }
+
+    #[typetag::serde]
+    impl Activity for Pass {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let span = self.minutes.minutes();
+            ops.wait(span);
+            ops += op! {
+                w: logged = ref since(ops_time - span): downlink_buffer.len() as u32;
+            };
+            Ok(span)
+        }
+    }
+
+    /// Looks back further than the default limit.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct LongPass {
+        explicit_limit: bool,
+    }
+
+    #[typetag::serde]
+    impl Activity for LongPass {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let explicit_limit = self.explicit_limit;
+            ops += op! {
+                if const explicit_limit {
+                    w: logged = ref since(ops_time - 2.days(), 3.days()): downlink_buffer.len() as u32;
+                } else {
+                    w: logged = ref since(ops_time - 2.days()): downlink_buffer.len() as u32;
+                }
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    fn log(plan: &mut Plan<Radio>, m: i64) -> Result<ActivityId> {
+        plan.insert(minutes(m), Log(format!("minute {m}")))
+    }
+
+    #[test]
+    fn counts_writes_during_activity() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Radio>(minutes(0), initial_conditions! {})?;
+
+        log(&mut plan, 1)?;
+        log(&mut plan, 3)?;
+        log(&mut plan, 5)?;
+        log(&mut plan, 12)?;
+        plan.insert(minutes(2), Pass { minutes: 8 })?;
+
+        assert_eq!(2, plan.sample::<logged>(minutes(10))?);
+
+        // Writes inside the span invalidate the count; writes outside it don't change it.
+        let id = log(&mut plan, 7)?;
+        assert_eq!(3, plan.sample::<logged>(minutes(10))?);
+        log(&mut plan, 0)?;
+        assert_eq!(3, plan.sample::<logged>(minutes(10))?);
+        plan.remove(id)?;
+        assert_eq!(2, plan.sample::<logged>(minutes(10))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn looking_back_past_the_limit_fails() -> Result<()> {
+        let session = Session::new();
+        let start = minutes(0);
+        let mut plan = session.new_plan::<Radio>(start, initial_conditions! {})?;
+        let end = start + 3.days();
+
+        log(&mut plan, 60)?;
+        plan.insert(
+            end,
+            LongPass {
+                explicit_limit: false,
+            },
+        )?;
+        assert!(plan.sample::<logged>(end).is_err());
+
+        let mut plan = session.new_plan::<Radio>(start, initial_conditions! {})?;
+        log(&mut plan, 24 * 60 + 1)?;
+        log(&mut plan, 60)?;
+        plan.insert(
+            end,
+            LongPass {
+                explicit_limit: true,
+            },
+        )?;
+        assert_eq!(1, plan.sample::<logged>(end)?);
+
+        Ok(())
+    }
+}
+
+mod changed_since {
+    use crate::util::*;
+    use anyhow::Result;
+    use hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    /// Sets `b` to whether `a` was written in the last few seconds.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SetBToAChanged(i64);
+
+    #[typetag::serde]
+    impl Activity for SetBToAChanged {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let span = self.0.seconds();
+            ops += op! {
+                w: b = ref changed_since(ops_time - span): a as u32;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn write_in_window_is_a_change() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(1), IncrementA)?;
+        plan.insert(seconds(5), SetBToAChanged(10))?;
+        assert_eq!(1, plan.sample::<b>(seconds(6))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_before_window_is_not_a_change() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(1), IncrementA)?;
+        plan.insert(seconds(5), SetBToAChanged(2))?;
+        assert_eq!(0, plan.sample::<b>(seconds(6))?);
+
+        // A new write inside the window is noticed.
+        plan.insert(seconds(4), IncrementA)?;
+        assert_eq!(1, plan.sample::<b>(seconds(6))?);
+
+        Ok(())
+    }
+}
+
+mod or_default {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    resource! {
+        /// Not included in the AB model, so it never has a value.
+        transient: u32;
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SetBToTransient;
+
+    #[typetag::serde]
+    impl Activity for SetBToTransient {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: b = ref or(7): transient; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SetBToAOr;
+
+    #[typetag::serde]
+    impl Activity for SetBToAOr {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: b = ref or(7): a + 1; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn missing_resource_reads_default() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), SetBToTransient)?;
+        assert_eq!(7, plan.sample::<b>(seconds(1))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn present_resource_reads_value() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), SetBToAOr)?;
+        assert_eq!(1, plan.sample::<b>(seconds(1))?);
+
+        // Changes to the resource are still seen.
+        plan.insert(seconds(-1) + Duration::from_milliseconds(1.0), IncrementA)?;
+        assert_eq!(2, plan.sample::<b>(seconds(1))?);
+
+        Ok(())
+    }
+}
+
+mod next_change {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Power {
+            battery: f64;
+            time_to_change: Option<Duration>;
+        }
+    }
+
+    /// Drains the battery by a fixed amount.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Drain(u32);
+
+    #[typetag::serde]
+    impl Activity for Drain {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let amount = self.0;
+            ops += op! { m: battery -= amount as f64; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Records how long until the battery is next written.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Forecast;
+
+    #[typetag::serde]
+    impl Activity for Forecast {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: time_to_change = ref next_change: battery; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn reads_gap_to_next_write() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Power>(
+            seconds(0.0),
+            initial_conditions! { battery: 100.0, time_to_change: None },
+        )?;
+
+        plan.insert(seconds(5.0), Forecast)?;
+        assert_eq!(None, plan.sample::<time_to_change>(seconds(6.0))?);
+
+        let later = plan.insert(seconds(12.0), Drain(10))?;
+        assert_eq!(
+            Some(Duration::from_seconds(7.0)),
+            plan.sample::<time_to_change>(seconds(6.0))?
+        );
+
+        let sooner = plan.insert(seconds(8.0), Drain(10))?;
+        assert_eq!(
+            Some(Duration::from_seconds(3.0)),
+            plan.sample::<time_to_change>(seconds(6.0))?
+        );
+
+        // Writes before the reader and after the next write don't matter.
+        plan.insert(seconds(2.0), Drain(10))?;
+        plan.insert(seconds(20.0), Drain(10))?;
+        assert_eq!(
+            Some(Duration::from_seconds(3.0)),
+            plan.sample::<time_to_change>(seconds(6.0))?
+        );
+
+        plan.remove(sooner)?;
+        assert_eq!(
+            Some(Duration::from_seconds(7.0)),
+            plan.sample::<time_to_change>(seconds(6.0))?
+        );
+
+        plan.remove(later)?;
+        assert_eq!(
+            Some(Duration::from_seconds(15.0)),
+            plan.sample::<time_to_change>(seconds(6.0))?
+        );
+
+        Ok(())
+    }
+}
+
+mod second_derivative {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Thermal {
+            temperature: Quadratic;
+            heater_cycles: u32;
+            ramp_change: f64;
+            cycles_curve: bool;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct MeasureRampChange;
+
+    #[typetag::serde]
+    impl Activity for MeasureRampChange {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! {
+                w: ramp_change = ref d2/dt2: temperature.map(|d| d.value).unwrap_or(f64::NAN);
+                w: cycles_curve = (ref d2/dt2: heater_cycles).is_some();
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn quadratic_has_constant_second_derivative() -> Result<()> {
+        let session = Session::new();
+        // 1 + 0.5m + 3m^2, where m is in units of two seconds, so d2/dt2 is 2 * 3 / 2^2.
+        let mut plan = session.new_plan::<Thermal>(
+            seconds(0.0),
+            initial_conditions! {
+                temperature: Quadratic::new(Duration::from_seconds(2.0), 1.0, 0.5, 3.0),
+                heater_cycles: 0,
+                ramp_change: 0.0,
+                cycles_curve: true,
+            },
+        )?;
+
+        plan.insert(seconds(5.0), MeasureRampChange)?;
+        assert_eq!(1.5, plan.sample::<ramp_change>(seconds(6.0))?);
+
+        plan.insert(seconds(40.0), MeasureRampChange)?;
+        assert_eq!(1.5, plan.sample::<ramp_change>(seconds(41.0))?);
+
+        // Data without an analytic second derivative reads as `None`.
+        assert!(!plan.sample::<cycles_curve>(seconds(41.0))?);
+
+        Ok(())
+    }
+}
+
+mod source {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Power {
+            battery: f64;
+            last_writer: Option<ActivityId>;
+        }
+    }
+
+    /// Drains the battery by a fixed amount.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct DrainBattery(u32);
+
+    #[typetag::serde]
+    impl Activity for DrainBattery {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let amount = self.0;
+            ops += op! { m: battery -= amount as f64; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Records which activity last wrote the battery.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Audit;
+
+    #[typetag::serde]
+    impl Activity for Audit {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: last_writer = ref source: battery; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn source_matches_writer() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Power>(
+            seconds(0.0),
+            initial_conditions! { battery: 100.0, last_writer: None },
+        )?;
+
+        plan.insert(seconds(5.0), Audit)?;
+        assert_eq!(None, plan.sample::<last_writer>(seconds(6.0))?);
+
+        let first = plan.insert(seconds(1.0), DrainBattery(10))?;
+        assert_eq!(Some(first), plan.sample::<last_writer>(seconds(6.0))?);
+
+        let second = plan.insert(seconds(3.0), DrainBattery(10))?;
+        assert_eq!(Some(second), plan.sample::<last_writer>(seconds(6.0))?);
+
+        // Writes after the reader don't matter.
+        plan.insert(seconds(7.0), DrainBattery(10))?;
+        assert_eq!(Some(second), plan.sample::<last_writer>(seconds(6.0))?);
+
+        plan.remove(second)?;
+        assert_eq!(Some(first), plan.sample::<last_writer>(seconds(6.0))?);
+
+        Ok(())
+    }
+}
+
+mod window_range {
+    use crate::minutes;
+    use anyhow::Result;
+    use hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Thermal {
+            temperature: f64;
+            trough: f64;
+            peak: f64;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SetTemperature(i32);
+
+    #[typetag::serde]
+    impl Activity for SetTemperature {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let value = self.0;
+            ops += op! { w: temperature = value as f64; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Records the extremes of the temperature over the last ten minutes.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct RecordRange;
+
+    #[typetag::serde]
+    impl Activity for RecordRange {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! {
+                let extremes = ref range(10.minutes()): temperature;
+                w: trough = extremes.min;
+                w: peak = extremes.max;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn window_extremes_match_writes() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Thermal>(
+            minutes(0),
+            initial_conditions! { temperature: 20.0, trough: 0.0, peak: 0.0 },
+        )?;
+
+        plan.insert(minutes(5), SetTemperature(25))?;
+        plan.insert(minutes(12), SetTemperature(18))?;
+        plan.insert(minutes(15), SetTemperature(30))?;
+        plan.insert(minutes(20), RecordRange)?;
+
+        // The window is 10..20 minutes; the value at its start was written at 5 minutes.
+        assert_eq!(18.0, plan.sample::<trough>(minutes(21))?);
+        assert_eq!(30.0, plan.sample::<peak>(minutes(21))?);
+
+        // Writes in the window invalidate the read.
+        plan.insert(minutes(17), SetTemperature(40))?;
+        assert_eq!(40.0, plan.sample::<peak>(minutes(21))?);
+
+        // Writes before the window only matter if they set the value at its start.
+        plan.insert(minutes(8), SetTemperature(10))?;
+        assert_eq!(10.0, plan.sample::<trough>(minutes(21))?);
+        plan.insert(minutes(2), SetTemperature(0))?;
+        assert_eq!(10.0, plan.sample::<trough>(minutes(21))?);
+
+        Ok(())
+    }
+}
+
+mod external_inputs {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::Ordering;
+
+    /// Writes the version of the loaded configuration to `a`, counting how many times it runs.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct ApplyConfig(UnhashedCounter);
+
+    #[typetag::serde]
+    impl Activity for ApplyConfig {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let runs = &self.0;
+            ops += op! {
+                runs.fetch_add(1, Ordering::SeqCst);
+                w: a = ref external: config_version as u32;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn bumping_the_version_reruns_readers() -> Result<()> {
+        let session = Session::new();
+        session.set_external_version("config_version", 1);
+        let mut plan = init_plan(&session);
+        let runs = UnhashedCounter::default();
+
+        plan.insert(seconds(0), ApplyConfig(runs.clone()))?;
+        plan.insert(seconds(1), IncrementA)?;
+        assert_eq!(2, plan.sample::<a>(seconds(2))?);
+        assert_eq!(1, runs.load(Ordering::SeqCst));
+
+        session.set_external_version("config_version", 2);
+        assert_eq!(3, plan.sample::<a>(seconds(2))?);
+        assert_eq!(2, runs.load(Ordering::SeqCst));
+
+        // Setting the same version again changes nothing.
+        session.set_external_version("config_version", 2);
+        assert_eq!(3, plan.sample::<a>(seconds(2))?);
+        assert_eq!(2, runs.load(Ordering::SeqCst));
+
+        // Returning to an old version reuses the outputs cached for it.
+        session.set_external_version("config_version", 1);
+        assert_eq!(2, plan.sample::<a>(seconds(2))?);
+        assert_eq!(2, runs.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    #[test]
+    fn unversioned_inputs_are_errors() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), ApplyConfig(UnhashedCounter::default()))?;
+        let message = format!("{:#}", plan.sample::<a>(seconds(1)).unwrap_err());
+        assert!(message.contains("config_version"), "{message}");
+
+        Ok(())
+    }
+}
+
+mod ops_elapsed {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Timers {
+            uptime: Duration;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct RecordUptime;
+
+    #[typetag::serde]
+    impl Activity for RecordUptime {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: uptime = ops_elapsed; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn writes_elapsed_since_plan_start() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Timers>(
+            seconds(10.0),
+            initial_conditions! { uptime: Duration::ZERO },
+        )?;
+        for t in [12.0, 15.5, 40.0] {
+            plan.insert(seconds(t), RecordUptime)?;
+        }
+
+        assert_eq!(
+            Duration::from_seconds(2.0),
+            plan.sample::<uptime>(seconds(13.0))?
+        );
+        assert_eq!(
+            Duration::from_seconds(5.5),
+            plan.sample::<uptime>(seconds(20.0))?
+        );
+        assert_eq!(
+            Duration::from_seconds(30.0),
+            plan.sample::<uptime>(seconds(41.0))?
+        );
+
+        Ok(())
+    }
+}
+
+mod ops_time {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Stamps {
+            from_now: Time;
+            from_ops_time: Time;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Stamp;
+
+    #[typetag::serde]
+    impl Activity for Stamp {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! {
+                w: from_now = r: now;
+                w: from_ops_time = ops_time;
+            };
+            ops.wait(delay! { Duration::from_seconds(5.0) => Duration::from_seconds(3.0) });
+            ops += op! {
+                w: from_now = r: now;
+                w: from_ops_time = ops_time;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn ops_time_matches_now() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Stamps>(
+            seconds(0.0),
+            initial_conditions! { from_now: seconds(0.0), from_ops_time: seconds(0.0) },
+        )?;
+        plan.insert(seconds(1.0), Stamp)?;
+
+        for t in [2.0, 10.0] {
+            let expected = plan.sample::<from_now>(seconds(t))?;
+            assert_eq!(expected, plan.sample::<from_ops_time>(seconds(t))?);
+        }
+        assert_eq!(seconds(1.0), plan.sample::<from_ops_time>(seconds(2.0))?);
+        assert_eq!(seconds(4.0), plan.sample::<from_ops_time>(seconds(10.0))?);
+
+        Ok(())
+    }
+}
@@ -256,3 +256,100 @@ fn test_group_synchronization() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+model! {
+    CountTest {
+        #[count]
+        pub pump_*_on: bool; {a: true, b: false, c: false}
+    }
+}
+
+#[derive(Hash, Serialize, Deserialize)]
+pub struct TogglePumps;
+
+#[typetag::serde]
+impl Activity for TogglePumps {
+    fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> anyhow::Result<Duration> {
+        ops += op! { w:pump_b_on = true; };
+        ops.wait(5.0.seconds());
+        ops += op! { w:pump_c_on = true; };
+        ops.wait(5.0.seconds());
+        ops += op! { m:pump_on.a = false; };
+
+        Ok(Duration::ZERO)
+    }
+}
+
+#[test]
+fn test_group_count() -> anyhow::Result<()> {
+    assert_eq!(pump_on_count::initial_condition(), Some(1));
+
+    let session = Session::new();
+    let mut plan = session.new_plan::<CountTest>(seconds(-1), initial_conditions! {})?;
+    plan.insert(seconds(0), TogglePumps)?;
+
+    assert_eq!(1, plan.sample::<pump_on_count>(seconds(-1))?);
+    assert_eq!(2, plan.sample::<pump_on_count>(seconds(1))?);
+    assert_eq!(3, plan.sample::<pump_on_count>(seconds(6))?);
+    assert_eq!(2, plan.sample::<pump_on_count>(seconds(11))?);
+
+    Ok(())
+}
+
+#[test]
+fn test_group_count_with_overridden_initial_conditions() -> anyhow::Result<()> {
+    let session = Session::new();
+    let mut plan = session.new_plan::<CountTest>(
+        seconds(-1),
+        initial_conditions! { pump_a_on: false, pump_c_on: true },
+    )?;
+    assert_eq!(1, plan.sample::<pump_on_count>(seconds(-1))?);
+
+    plan.insert(seconds(0), TogglePumps)?;
+    assert_eq!(2, plan.sample::<pump_on_count>(seconds(1))?);
+    assert_eq!(2, plan.sample::<pump_on_count>(seconds(6))?);
+    assert_eq!(2, plan.sample::<pump_on_count>(seconds(11))?);
+
+    Ok(())
+}
+
+model! {
+    IndexTest {
+        pub channel_*_active: bool = false; {a, b, c}
+        pub channel_writes: u32 = 0;
+    }
+}
+
+/// Turns on the channel given as an argument.
+#[derive(Hash, Serialize, Deserialize)]
+pub struct Activate(ChannelActive);
+
+#[typetag::serde]
+impl Activity for Activate {
+    fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> anyhow::Result<Duration> {
+        let channel = self.0;
+        ops += op! {
+            w: channel_active[const channel] = true;
+            m: channel_writes += 1;
+        };
+        Ok(Duration::ZERO)
+    }
+}
+
+#[test]
+fn test_group_member_selected_by_argument() -> anyhow::Result<()> {
+    let session = Session::new();
+    let mut plan = session.new_plan::<IndexTest>(seconds(-1), initial_conditions! {})?;
+    plan.insert(seconds(0), Activate(ChannelActive::B))?;
+
+    assert!(!plan.sample::<channel_a_active>(seconds(1))?);
+    assert!(plan.sample::<channel_b_active>(seconds(1))?);
+    assert!(!plan.sample::<channel_c_active>(seconds(1))?);
+    assert_eq!(1, plan.sample::<channel_writes>(seconds(1))?);
+
+    // Only the selected member is written.
+    assert_eq!(1, plan.activities_touching::<channel_b_active>().len());
+    assert!(plan.activities_touching::<channel_a_active>().is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,1517 @@
+mod util;
+
+mod flags {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    const THERMAL: usize = 0;
+    const POWER: usize = 5;
+
+    model! {
+        Faults {
+            fault_flags: Flags<8> = Flags::new();
+            power_fault: bool = false;
+        }
+    }
+
+    /// Raises or clears a fault flag.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Fault {
+        index: usize,
+        raised: bool,
+    }
+
+    #[typetag::serde]
+    impl Activity for Fault {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let index = self.index;
+            let raised = self.raised;
+            ops += op! {
+                m: fault_flags.assign(index, raised);
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Copies the power fault flag into its own resource.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct CheckPower;
+
+    #[typetag::serde]
+    impl Activity for CheckPower {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! {
+                w: power_fault = r: fault_flags.get(POWER);
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn sets_and_reads_individual_flags() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Faults>(seconds(0), initial_conditions! {})?;
+
+        plan.insert(
+            seconds(1),
+            Fault {
+                index: THERMAL,
+                raised: true,
+            },
+        )?;
+        plan.insert(
+            seconds(2),
+            Fault {
+                index: POWER,
+                raised: true,
+            },
+        )?;
+        plan.insert(seconds(3), CheckPower)?;
+        plan.insert(
+            seconds(4),
+            Fault {
+                index: THERMAL,
+                raised: false,
+            },
+        )?;
+
+        let flags = plan.sample::<fault_flags>(seconds(3))?;
+        assert!(flags.get(THERMAL));
+        assert!(flags.get(POWER));
+        assert!(!flags.get(1));
+        assert_eq!(0b100001, flags.bits());
+        assert!(plan.sample::<power_fault>(seconds(3))?);
+
+        let flags = plan.sample::<fault_flags>(seconds(5))?;
+        assert!(!flags.get(THERMAL));
+        assert_eq!(1, flags.count());
+
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn rejects_out_of_range_flags() {
+        Flags::<8>::new().set(8);
+    }
+}
+
+mod events {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, PartialEq, Hash, Serialize, Deserialize)]
+    enum Downlink {
+        Started { pass: u32 },
+        Finished { pass: u32, bytes: u64 },
+        Aborted { pass: u32 },
+    }
+
+    impl_maybe_hash_for_hashable![Downlink];
+
+    model! {
+        Comms {
+            downlink_log: Events<Downlink> = Events::new();
+            passes: u32 = 0;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Pass {
+        bytes: u64,
+    }
+
+    #[typetag::serde]
+    impl Activity for Pass {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let total = self.bytes;
+            ops += op! {
+                m: passes += 1;
+                emit: downlink_log <- Downlink::Started { pass: passes };
+            };
+            ops.wait(Duration::from_seconds(10.0));
+            ops += op! {
+                emit: downlink_log <- Downlink::Finished { pass: r: passes, bytes: total };
+            };
+            Ok(Duration::from_seconds(10.0))
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct AbortedPass;
+
+    #[typetag::serde]
+    impl Activity for AbortedPass {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! {
+                m: passes += 1;
+                emit: downlink_log <- Downlink::Started { pass: passes };
+                emit: downlink_log <- Downlink::Aborted { pass: passes };
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn events_are_queried_in_order() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Comms>(seconds(0), initial_conditions! {})?;
+
+        plan.insert(seconds(100), Pass { bytes: 2000 })?;
+        plan.insert(seconds(50), Pass { bytes: 1000 })?;
+
+        let events = plan.events::<downlink_log, _>(seconds(0)..seconds(200))?;
+        assert_eq!(
+            vec![
+                (seconds(50), &Downlink::Started { pass: 1 }),
+                (
+                    seconds(60),
+                    &Downlink::Finished {
+                        pass: 1,
+                        bytes: 1000
+                    }
+                ),
+                (seconds(100), &Downlink::Started { pass: 2 }),
+                (
+                    seconds(110),
+                    &Downlink::Finished {
+                        pass: 2,
+                        bytes: 2000
+                    }
+                ),
+            ],
+            events
+        );
+
+        assert_eq!(
+            2,
+            plan.events::<downlink_log, _>(seconds(55)..seconds(105))?
+                .len()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_window_has_no_events() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Comms>(seconds(0), initial_conditions! {})?;
+
+        plan.insert(seconds(50), Pass { bytes: 1000 })?;
+
+        assert!(
+            plan.events::<downlink_log, _>(seconds(70)..seconds(90))?
+                .is_empty()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn events_emitted_by_one_op_are_all_kept() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Comms>(seconds(0), initial_conditions! {})?;
+
+        plan.insert(seconds(50), AbortedPass)?;
+
+        assert_eq!(
+            vec![
+                (seconds(50), &Downlink::Started { pass: 1 }),
+                (seconds(50), &Downlink::Aborted { pass: 1 }),
+            ],
+            plan.events::<downlink_log, _>(..)?
+        );
+
+        Ok(())
+    }
+}
+
+mod histogram {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Observatory {
+            durations: Histogram = Histogram::uniform(0.0, 10.0, 5);
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Observe {
+        minutes: u32,
+    }
+
+    #[typetag::serde]
+    impl Activity for Observe {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let minutes = self.minutes;
+            ops += op! { m: durations.add(minutes as f64); };
+            Ok(Duration::from_seconds(minutes as f64 * 60.0))
+        }
+    }
+
+    #[test]
+    fn samples_are_binned() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Observatory>(seconds(0), initial_conditions! {})?;
+
+        for (i, minutes) in [1, 3, 3, 5, 7, 9, 12].into_iter().enumerate() {
+            plan.insert(seconds(1000 * (i as i32 + 1)), Observe { minutes })?;
+        }
+
+        let histogram = plan.sample::<durations>(seconds(1000))?;
+        assert_eq!(&[1, 0, 0, 0, 0], histogram.counts());
+
+        let histogram = plan.sample::<durations>(seconds(10000))?;
+        assert_eq!(&[1, 2, 1, 1, 1], histogram.counts());
+        assert_eq!(1, histogram.overflow());
+        assert_eq!(7, histogram.total());
+        assert_eq!(Some(5.0), histogram.percentile(50.0));
+        assert_eq!(Some(10.0), histogram.percentile(100.0));
+
+        Ok(())
+    }
+}
+
+mod history_list {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Rover {
+            sol: u32 = 0;
+            downlink_log: HistoryList<String> = HistoryList::new();
+            log_length: u32 = 0;
+        }
+    }
+
+    /// Logs the current sol, and advances it.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct LogSol;
+
+    #[typetag::serde]
+    impl Activity for LogSol {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops += op! {
+                m: downlink_log.push(format!("Sol {}", r: sol));
+                m: sol += 1;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Records how many messages have been logged.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct CountLog;
+
+    #[typetag::serde]
+    impl Activity for CountLog {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops += op! { w: log_length = r: downlink_log.len() as u32; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn history_list_accumulates_writes() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Rover>(seconds(0), initial_conditions! {})?;
+        for i in 1..=3 {
+            plan.insert(seconds(i), LogSol)?;
+        }
+        plan.insert(seconds(4), CountLog)?;
+
+        let log = plan.sample::<downlink_log>(seconds(5))?;
+        assert_eq!(vec!["Sol 0", "Sol 1", "Sol 2"], log.to_vec());
+        assert_eq!(Some("Sol 2"), log.last().map(String::as_str));
+        assert_eq!(3, plan.sample::<log_length>(seconds(5))?);
+
+        // Inserting before the end only changes the log from there on.
+        plan.insert(seconds(0), LogSol)?;
+        let log = plan.sample::<downlink_log>(seconds(5))?;
+        assert_eq!(vec!["Sol 0", "Sol 1", "Sol 2", "Sol 3"], log.to_vec());
+        assert_eq!(4, plan.sample::<log_length>(seconds(5))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn equal_lists_hash_the_same() {
+        let pushed = {
+            let mut list = HistoryList::new();
+            list.push(1u32);
+            list.push(2);
+            list
+        };
+        let collected = [1u32, 2].into_iter().collect::<HistoryList<_>>();
+        let hash = |list: &HistoryList<u32>| {
+            let mut hasher = std::hash::DefaultHasher::new();
+            list.hash_unchecked(&mut hasher);
+            std::hash::Hasher::finish(&hasher)
+        };
+
+        assert_eq!(pushed, collected);
+        assert_eq!(hash(&pushed), hash(&collected));
+        assert_ne!(hash(&pushed), hash(&[2u32, 1].into_iter().collect()));
+    }
+
+    #[test]
+    fn history_list_round_trips_through_serde() -> Result<()> {
+        let list = ["a", "b", "c"]
+            .map(String::from)
+            .into_iter()
+            .collect::<HistoryList<_>>();
+        let json = serde_json::to_string(&list)?;
+        assert_eq!(r#"["a","b","c"]"#, json);
+        assert_eq!(list, serde_json::from_str(&json)?);
+
+        // Dropping a long list doesn't recurse through every element.
+        let long = (0..1_000_000u32).collect::<HistoryList<_>>();
+        assert_eq!(1_000_000, long.len());
+        drop(long);
+
+        Ok(())
+    }
+}
+
+mod latch {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Thermal {
+            temperature: u32;
+            fault_detected: bool;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SetTemperature(u32);
+
+    #[typetag::serde]
+    impl Activity for SetTemperature {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let celsius = self.0;
+            ops += op! { w: temperature = celsius; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct CheckFault;
+
+    #[typetag::serde]
+    impl Activity for CheckFault {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { latch: fault_detected = r: temperature > 100; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct ClearFault;
+
+    #[typetag::serde]
+    impl Activity for ClearFault {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { reset: fault_detected; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn latch_holds_until_reset() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Thermal>(
+            seconds(-1),
+            initial_conditions! {
+                temperature: 20,
+                fault_detected: false,
+            },
+        )?;
+
+        plan.insert(seconds(0), CheckFault)?;
+        plan.insert(seconds(1), SetTemperature(150))?;
+        plan.insert(seconds(2), CheckFault)?;
+        plan.insert(seconds(3), SetTemperature(20))?;
+        plan.insert(seconds(4), CheckFault)?;
+        plan.insert(seconds(6), ClearFault)?;
+        plan.insert(seconds(7), CheckFault)?;
+
+        assert!(!plan.sample::<fault_detected>(seconds(1))?);
+        assert!(plan.sample::<fault_detected>(seconds(3))?);
+        assert!(plan.sample::<fault_detected>(seconds(5))?);
+        assert!(!plan.sample::<fault_detected>(seconds(6))?);
+        assert!(!plan.sample::<fault_detected>(seconds(8))?);
+
+        Ok(())
+    }
+}
+
+mod monotonic {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Clock {
+            /// Mission elapsed seconds, which never go backward.
+            #[monotonic(increasing)]
+            elapsed: u32;
+            /// Wheel odometers, in meters, which never go backward.
+            #[monotonic(increasing)]
+            pub odometer_*: u32 = 0; {left, right}
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Advance(i32);
+
+    #[typetag::serde]
+    impl Activity for Advance {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let step = self.0;
+            ops += op! { m: elapsed = (elapsed as i32 + step) as u32; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Reset;
+
+    #[typetag::serde]
+    impl Activity for Reset {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: elapsed = 0; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn increasing_writes_are_allowed() -> Result<()> {
+        let session = Session::new();
+        let mut plan =
+            session.new_plan::<Clock>(seconds(0.0), initial_conditions! { elapsed: 10 })?;
+        plan.insert(seconds(1.0), Advance(5))?;
+        plan.insert(seconds(2.0), Advance(0))?;
+
+        assert_eq!(15, plan.sample::<elapsed>(seconds(3.0))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn decreasing_write_errors() -> Result<()> {
+        let session = Session::new();
+        let mut plan =
+            session.new_plan::<Clock>(seconds(0.0), initial_conditions! { elapsed: 10 })?;
+        plan.insert(seconds(1.0), Advance(5))?;
+        plan.insert(seconds(2.0), Advance(-3))?;
+
+        assert_eq!(15, plan.sample::<elapsed>(seconds(1.5))?);
+        let message = format!("{:#}", plan.sample::<elapsed>(seconds(3.0)).unwrap_err());
+        assert!(message.contains("monotonic resource elapsed"), "{message}");
+
+        Ok(())
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Roll(i32);
+
+    #[typetag::serde]
+    impl Activity for Roll {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let meters = self.0;
+            ops += op! { m: odometer_left = (odometer_left as i32 + meters) as u32; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn group_members_are_checked() -> Result<()> {
+        let session = Session::new();
+        let mut plan =
+            session.new_plan::<Clock>(seconds(0.0), initial_conditions! { elapsed: 10 })?;
+        plan.insert(seconds(1.0), Roll(5))?;
+        plan.insert(seconds(2.0), Roll(-3))?;
+
+        assert_eq!(5, plan.sample::<odometer_left>(seconds(1.5))?);
+        let message = format!(
+            "{:#}",
+            plan.sample::<odometer_left>(seconds(3.0)).unwrap_err()
+        );
+        assert!(
+            message.contains("monotonic resource odometer_left"),
+            "{message}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_only_monotonic_errors() -> Result<()> {
+        let session = Session::new();
+        let mut plan =
+            session.new_plan::<Clock>(seconds(0.0), initial_conditions! { elapsed: 10 })?;
+        plan.insert(seconds(1.0), Reset)?;
+
+        let message = format!("{:#}", plan.sample::<elapsed>(seconds(2.0)).unwrap_err());
+        assert!(message.contains("without reading it"), "{message}");
+
+        Ok(())
+    }
+}
+
+mod sentinel {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    model! {
+        Faults {
+            /// The latched fault code. Writing `u32::MAX` leaves it unchanged.
+            #[sentinel = u32::MAX]
+            fault_code: u32;
+            /// The power limit in watts, unlimited by default. Writing `-1.0` leaves it unchanged.
+            #[sentinel = -1.0]
+            power_limit: f64 = f64::INFINITY;
+            over_limit: bool = false;
+            /// Per-subsystem fault codes, with the same sentinel on each member.
+            #[sentinel = u32::MAX]
+            pub subsystem_*_code: u32 = 0; {power, thermal}
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct ReportFault(u32);
+
+    #[typetag::serde]
+    impl Activity for ReportFault {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let code = self.0;
+            ops += op! { m: fault_code = code; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct OverwriteFault(u32);
+
+    #[typetag::serde]
+    impl Activity for OverwriteFault {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let code = self.0;
+            ops += op! { w: fault_code = code; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct LimitPower(u32);
+
+    #[typetag::serde]
+    impl Activity for LimitPower {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let watts = self.0;
+            ops += op! { m: power_limit = watts as f64 - 1.0; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    static LIMIT_CHECKS: AtomicU16 = AtomicU16::new(0);
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct CheckLimit;
+
+    #[typetag::serde]
+    impl Activity for CheckLimit {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! {
+                LIMIT_CHECKS.fetch_add(1, Ordering::SeqCst);
+                w: over_limit = r: power_limit < 100.0;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn sentinel_writes_keep_previous_value() -> Result<()> {
+        let session = Session::new();
+        let mut plan =
+            session.new_plan::<Faults>(seconds(0.0), initial_conditions! { fault_code: 0 })?;
+        plan.insert(seconds(1.0), ReportFault(u32::MAX))?;
+        plan.insert(seconds(2.0), ReportFault(3))?;
+        plan.insert(seconds(3.0), ReportFault(u32::MAX))?;
+
+        assert_eq!(0, plan.sample::<fault_code>(seconds(1.5))?);
+        assert_eq!(3, plan.sample::<fault_code>(seconds(3.5))?);
+
+        let values = plan
+            .view::<fault_code>(seconds(0.0)..seconds(4.0))?
+            .into_iter()
+            .map(|(_, v)| v)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0, 0, 3, 3], values);
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_only_sentinel_errors() -> Result<()> {
+        let session = Session::new();
+        let mut plan =
+            session.new_plan::<Faults>(seconds(0.0), initial_conditions! { fault_code: 0 })?;
+        plan.insert(seconds(1.0), OverwriteFault(u32::MAX))?;
+
+        let message = format!("{:#}", plan.sample::<fault_code>(seconds(2.0)).unwrap_err());
+        assert!(
+            message.contains("sentinel value of fault_code"),
+            "{message}"
+        );
+
+        Ok(())
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct ReportThermal(u32);
+
+    #[typetag::serde]
+    impl Activity for ReportThermal {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let code = self.0;
+            ops += op! { m: subsystem_thermal_code = code; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn group_members_keep_previous_value() -> Result<()> {
+        let session = Session::new();
+        let mut plan =
+            session.new_plan::<Faults>(seconds(0.0), initial_conditions! { fault_code: 0 })?;
+        plan.insert(seconds(1.0), ReportThermal(4))?;
+        plan.insert(seconds(2.0), ReportThermal(u32::MAX))?;
+
+        assert_eq!(4, plan.sample::<subsystem_thermal_code>(seconds(3.0))?);
+        assert_eq!(0, plan.sample::<subsystem_power_code>(seconds(3.0))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sentinel_writes_dont_rerun_downstreams() -> Result<()> {
+        let session = Session::new();
+        let mut plan =
+            session.new_plan::<Faults>(seconds(0.0), initial_conditions! { fault_code: 0 })?;
+        plan.insert(seconds(2.0), CheckLimit)?;
+
+        assert!(!plan.sample::<over_limit>(seconds(3.0))?);
+        assert_eq!(1, LIMIT_CHECKS.load(Ordering::SeqCst));
+
+        // The unlimited power limit isn't hashable, so the check is only served from the cache
+        // if the sentinel write responds with the hash of the initial condition.
+        plan.insert(seconds(1.0), LimitPower(0))?;
+        assert!(!plan.sample::<over_limit>(seconds(3.0))?);
+        assert_eq!(1, LIMIT_CHECKS.load(Ordering::SeqCst));
+
+        plan.insert(seconds(1.5), LimitPower(50))?;
+        assert!(plan.sample::<over_limit>(seconds(3.0))?);
+        assert_eq!(2, LIMIT_CHECKS.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+}
+
+mod segments {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Data, MaybeHash, Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+    enum Mode {
+        Idle,
+        Science,
+        Downlink,
+    }
+
+    model! {
+        Spacecraft {
+            mode: Mode;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SetMode(u8);
+
+    #[typetag::serde]
+    impl Activity for SetMode {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let code = self.0;
+            ops += op! {
+                w: mode = match code {
+                    0 => Mode::Idle,
+                    1 => Mode::Science,
+                    _ => Mode::Downlink,
+                };
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn segments_collapse_equal_writes() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session
+            .new_plan::<Spacecraft>(seconds(0.0), initial_conditions! { mode: Mode::Idle })?;
+
+        plan.insert(seconds(2.0), SetMode(1))?;
+        plan.insert(seconds(4.0), SetMode(1))?;
+        plan.insert(seconds(6.0), SetMode(2))?;
+        plan.insert(seconds(8.0), SetMode(0))?;
+
+        // The repeated write at 4 seconds doesn't start a new segment.
+        assert_eq!(
+            vec![
+                (seconds(2.0), seconds(6.0), Mode::Science),
+                (seconds(6.0), seconds(8.0), Mode::Downlink),
+                (seconds(8.0), seconds(9.0), Mode::Idle),
+            ],
+            plan.segments::<mode>(seconds(2.0)..seconds(9.0))?
+        );
+
+        assert_eq!(
+            vec![
+                (seconds(3.0), seconds(6.0), Mode::Science),
+                (seconds(6.0), seconds(7.0), Mode::Downlink),
+            ],
+            plan.segments::<mode>(seconds(3.0)..seconds(7.0))?
+        );
+
+        Ok(())
+    }
+}
+
+mod piecewise_concat {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Telemetry {
+            signal: Piecewise<Linear>;
+        }
+    }
+
+    /// Splices a prediction onto the end of measured telemetry.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Splice;
+
+    #[typetag::serde]
+    impl Activity for Splice {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! {
+                w: signal = {
+                    // Rises from 0 at 1/s, then falls from 10 at 1/s after 10s.
+                    let actual = pieces!(
+                        Linear::new(1.seconds(), 0.0, 1.0),
+                        (10.seconds(), Linear::new(1.seconds(), 10.0, -1.0)),
+                    );
+                    // Holds at 5, then rises at 2/s after 5s.
+                    let predicted = pieces!(
+                        Linear::new(1.seconds(), 5.0, 0.0),
+                        (5.seconds(), Linear::new(1.seconds(), 5.0, 2.0)),
+                    );
+                    actual.concat(predicted, 10.seconds())
+                };
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    fn value(plan: &Plan<'_, Telemetry>, s: f64) -> Result<f64> {
+        Ok(plan.sample::<signal>(seconds(s))?.value)
+    }
+
+    #[test]
+    fn samples_across_the_join() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Telemetry>(
+            seconds(0.0),
+            initial_conditions! { signal: pieces!(Linear::new(1.seconds(), 0.0, 0.0)) },
+        )?;
+        plan.insert(seconds(0.0), Splice)?;
+
+        assert_eq!(9.0, value(&plan, 9.0)?);
+        // The prediction overlaps the second piece of the actual telemetry, and replaces it.
+        assert_eq!(5.0, value(&plan, 10.0)?);
+        assert_eq!(5.0, value(&plan, 14.0)?);
+        assert_eq!(5.0, value(&plan, 15.0)?);
+        assert_eq!(9.0, value(&plan, 17.0)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn concat_at_zero_replaces_everything() {
+        let first = pieces!(
+            Linear::new(1.seconds(), 1.0, 0.0),
+            (3.seconds(), Linear::new(1.seconds(), 2.0, 0.0)),
+        );
+        let second = pieces!(
+            Linear::new(1.seconds(), 7.0, 0.0),
+            (4.seconds(), Linear::new(1.seconds(), 8.0, 0.0)),
+        );
+        let joined = first.concat(second, Duration::ZERO);
+
+        assert_eq!(7.0, joined.default.value);
+        assert_eq!(1, joined.pieces.len());
+        assert_eq!(4.seconds(), joined.pieces[0].0);
+    }
+}
+
+mod piecewise_editing {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Spacecraft {
+            battery: Piecewise<Linear> = pieces!(Linear::constant(50.0));
+            downlink_rate: Piecewise<Linear> = pieces!(Linear::constant(0.0));
+            data_volume: Piecewise<Quadratic> = pieces!(Quadratic::constant(0.0));
+        }
+    }
+
+    /// Charges the battery at 10/s until it is full, while a heater draws it down 20
+    /// for the first three seconds.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Charge;
+
+    #[typetag::serde]
+    impl Activity for Charge {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops += op! {
+                w: battery = pieces!(Linear::new(1.seconds(), 50.0, 10.0))
+                    .insert_segment(0.seconds(), 3.seconds(), Linear::new(1.seconds(), 30.0, 10.0))
+                    .clamp(0.0, 100.0);
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Downlinks at 2/s during a pass from 10s to 20s, at half rate after 15s, and
+    /// accumulates the downlinked volume.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct DownlinkPass;
+
+    #[typetag::serde]
+    impl Activity for DownlinkPass {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops += op! {
+                let rate = m: downlink_rate
+                    .clone()
+                    .insert_segment(10.seconds(), 20.seconds(), Linear::constant(2.0))
+                    .scale_after(15.seconds(), 0.5);
+                m: data_volume = rate.integrate(m: data_volume.default.value);
+                m: downlink_rate = rate;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn battery_is_clamped_to_capacity() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Spacecraft>(seconds(0.0), initial_conditions! {})?;
+        plan.insert(seconds(0.0), Charge)?;
+
+        let battery = |s| -> Result<f64> { Ok(plan.sample::<battery>(seconds(s))?.value) };
+        assert_eq!(40.0, battery(1.0)?);
+        // The charge resumes where it would have been without the heater.
+        assert_eq!(80.0, battery(3.0)?);
+        assert_eq!(100.0, battery(5.0)?);
+        assert_eq!(100.0, battery(60.0)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn data_volume_integrates_the_downlink_rate() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Spacecraft>(seconds(0.0), initial_conditions! {})?;
+        plan.insert(seconds(0.0), DownlinkPass)?;
+
+        let rate = |s| -> Result<f64> { Ok(plan.sample::<downlink_rate>(seconds(s))?.value) };
+        assert_eq!(
+            vec![0.0, 2.0, 1.0, 0.0],
+            vec![rate(5.0)?, rate(12.0)?, rate(17.0)?, rate(25.0)?]
+        );
+
+        let volume = |s| -> Result<f64> { Ok(plan.sample::<data_volume>(seconds(s))?.value) };
+        assert_eq!(
+            vec![0.0, 4.0, 10.0, 15.0, 15.0],
+            vec![
+                volume(10.0)?,
+                volume(12.0)?,
+                volume(15.0)?,
+                volume(20.0)?,
+                volume(30.0)?
+            ]
+        );
+
+        Ok(())
+    }
+}
+
+mod interpolation {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    /// A position that is held between writes, but presented as moving linearly between them.
+    #[derive(Copy, Clone, MaybeHash, Serialize, Deserialize)]
+    struct Waypoint(f64);
+
+    impl<'h> Data<'h> for Waypoint {
+        type Read = f64;
+        type Sample = f64;
+
+        const INTERPOLATES: bool = true;
+
+        fn to_read(&self, _written: Time) -> f64 {
+            self.0
+        }
+
+        fn from_read(read: f64, _now: Time) -> Self {
+            Waypoint(read)
+        }
+
+        fn sample(read: f64, _now: Time) -> f64 {
+            read
+        }
+
+        fn interpolate(prev: (Time, f64), next: (Time, f64), now: Time) -> f64 {
+            let fraction = (now - prev.0).to_seconds() / (next.0 - prev.0).to_seconds();
+            prev.1 + (next.1 - prev.1) * fraction
+        }
+    }
+
+    model! {
+        Rover {
+            position: Waypoint;
+            held: f64;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct MoveTo(i32);
+
+    #[typetag::serde]
+    impl Activity for MoveTo {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let target = self.0;
+            ops += op! {
+                w: position = Waypoint(target as f64);
+                w: held = target as f64;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn interpolates_between_writes() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Rover>(
+            seconds(0),
+            initial_conditions! { position: Waypoint(0.0), held: 0.0 },
+        )?;
+
+        plan.insert(seconds(10), MoveTo(100))?;
+        plan.insert(seconds(20), MoveTo(50))?;
+
+        assert_eq!(20.0, plan.sample::<position>(seconds(2))?);
+        assert_eq!(100.0, plan.sample::<position>(seconds(10))?);
+        assert_eq!(75.0, plan.sample::<position>(seconds(15))?);
+
+        // After the last write there is nothing to blend toward, so the value is held.
+        assert_eq!(50.0, plan.sample::<position>(seconds(30))?);
+
+        // Resources that don't opt in hold the previous write.
+        assert_eq!(100.0, plan.sample::<held>(seconds(15))?);
+
+        Ok(())
+    }
+}
+
+mod diff_resource {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Power {
+            battery: f64;
+        }
+    }
+
+    /// Drains the battery by a fixed amount.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Discharge(u32);
+
+    #[typetag::serde]
+    impl Activity for Discharge {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let amount = self.0;
+            ops += op! { m: battery -= amount as f64; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn diff_diverges_after_extra_drain() -> Result<()> {
+        let session = Session::new();
+        let mut baseline =
+            session.new_plan::<Power>(seconds(0.0), initial_conditions! { battery: 100.0 })?;
+        let mut variant =
+            session.new_plan::<Power>(seconds(0.0), initial_conditions! { battery: 100.0 })?;
+
+        for plan in [&mut baseline, &mut variant] {
+            plan.insert(seconds(1.0), Discharge(10))?;
+            plan.insert(seconds(7.0), Discharge(10))?;
+        }
+        variant.insert(seconds(4.5), Discharge(25))?;
+
+        let diff = baseline.diff_resource::<battery>(
+            &variant,
+            seconds(0.0)..seconds(10.0),
+            1.seconds(),
+        )?;
+        assert_eq!(10, diff.len());
+
+        let (same, diverged): (Vec<_>, Vec<_>) = diff.iter().partition(|(_, a, b)| a == b);
+        assert_eq!(5, same.len());
+        assert!(same.iter().all(|(t, _, _)| *t < seconds(4.5)));
+        assert!(
+            diverged
+                .iter()
+                .all(|(t, a, b)| *t > seconds(4.5) && a - b == 25.0)
+        );
+        assert_eq!((seconds(8.0), 80.0, 55.0), diff[8]);
+
+        Ok(())
+    }
+}
+
+mod derived {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Power {
+            generated: f64 = 10.0;
+            consumed: f64 = 4.0;
+            derived margin: f64 = generated - consumed;
+            derived headroom: f64 = f64::max(margin_floor(generated), consumed) / 2.0;
+        }
+    }
+
+    fn margin_floor(generated: f64) -> f64 {
+        generated - 1.0
+    }
+
+    /// Turns on a load.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Load(u32);
+
+    #[typetag::serde]
+    impl Activity for Load {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            let watts = self.0;
+            ops += op! { m: consumed += watts as f64; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn derived_resources_follow_their_sources() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Power>(seconds(0), initial_conditions! {})?;
+        assert_eq!(6.0, plan.sample::<margin>(seconds(0))?);
+        assert_eq!(4.5, plan.sample::<headroom>(seconds(0))?);
+
+        plan.insert(seconds(1), Load(3))?;
+        plan.insert(seconds(2), Load(5))?;
+        assert_eq!(
+            vec![(seconds(1), 3.0), (seconds(2), -2.0)],
+            plan.view::<margin>(seconds(1)..seconds(3))?
+        );
+        assert_eq!(6.0, plan.sample::<headroom>(seconds(2))?);
+
+        Ok(())
+    }
+}
+
+mod expose {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+
+    mod power {
+        use anyhow::Result;
+        use peregrine::*;
+        use serde::{Deserialize, Serialize};
+
+        model! {
+            pub Power {
+                pub(super) battery: f64 = 100.0;
+            }
+        }
+
+        #[derive(Hash, Serialize, Deserialize)]
+        pub struct Drain;
+
+        #[typetag::serde]
+        impl Activity for Drain {
+            fn run(&self, mut ops: Ops) -> Result<Duration> {
+                ops += op! { m: battery -= 10.0; };
+                Ok(Duration::ZERO)
+            }
+        }
+    }
+
+    model! {
+        pub Spacecraft {
+            mode: u32;
+        }
+        mod power::Power;
+        expose read power::battery from power::Power;
+    }
+
+    #[test]
+    fn sample_exposed_resource() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Spacecraft>(seconds(0.0), initial_conditions! {})?;
+
+        assert_eq!(100.0, plan.sample::<power_battery>(seconds(0.0))?);
+
+        plan.insert(seconds(1.0), power::Drain)?;
+        plan.insert(seconds(2.0), power::Drain)?;
+
+        assert_eq!(90.0, plan.sample::<power_battery>(seconds(1.5))?);
+        assert_eq!(80.0, plan.sample::<power_battery>(seconds(3.0))?);
+
+        Ok(())
+    }
+}
+
+mod units {
+    #![cfg(feature = "uom")]
+
+    use anyhow::Result;
+    use peregrine::uom::si::{energy::joule, f64::Power, power::watt};
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Spacecraft {
+            battery: f64 [energy::joule] = 100.0;
+            heater_*: f64 [power::watt] = 2.0; { main, backup }
+        }
+    }
+
+    /// Runs both heaters for ten seconds.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Heat;
+
+    #[typetag::serde]
+    impl Activity for Heat {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops += op! {
+                let seconds = peregrine::uom::si::f64::Time::new::<peregrine::uom::si::time::second>(10.0);
+                m: battery -= (r: heater_main + r: heater_backup) * seconds;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn unit_annotations_make_quantities() -> Result<()> {
+        assert_eq!(Some("joule"), battery::UNIT);
+        assert_eq!(Some("watt"), heater_main::UNIT);
+
+        let session = Session::new();
+        let mut plan = session.new_plan::<Spacecraft>(
+            Time::from_tai_seconds(0.0),
+            initial_conditions! { heater_backup: Power::new::<watt>(3.0) },
+        )?;
+        plan.insert(Time::from_tai_seconds(1.0), Heat)?;
+
+        let battery = plan.sample::<battery>(Time::from_tai_seconds(2.0))?;
+        assert_eq!(50.0, battery.get::<joule>());
+
+        Ok(())
+    }
+}
+
+mod resource_metadata {
+    use peregrine::{Model, Resource, ResourceDescriptor, ResourceId, model, resource};
+
+    resource! {
+        #[unit = "Wh"]
+        pub battery_energy: f64 = 0.0;
+    }
+
+    model! {
+        pub Thermal {
+            /// The temperature of the spacecraft bus.
+            #[unit = "K"]
+            pub bus_temperature: f64 = 290.0;
+            pub heater_on: bool = false;
+        }
+    }
+
+    model! {
+        pub Spacecraft {}
+        use battery_energy;
+        mod Thermal;
+    }
+
+    #[test]
+    fn resource_unit_const() {
+        assert_eq!(Some("Wh"), battery_energy::UNIT);
+        assert_eq!(Some("K"), bus_temperature::UNIT);
+        assert_eq!(None, heater_on::UNIT);
+    }
+
+    #[test]
+    fn unit_in_model_descriptor() {
+        let descriptor = Spacecraft::descriptor();
+
+        assert_eq!(3, descriptor.resources.len());
+        assert_eq!(
+            Some(&ResourceDescriptor {
+                label: "battery_energy",
+                id: ResourceId::of::<battery_energy>(),
+                unit: Some("Wh"),
+            }),
+            descriptor.get("battery_energy")
+        );
+        assert_eq!(Some("K"), descriptor.get("bus_temperature").unwrap().unit);
+        assert_eq!(None, descriptor.get("heater_on").unwrap().unit);
+    }
+}
+
+mod local_resources {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    resource! {
+        /// A running total that each activity keeps for itself.
+        #[local]
+        tally: u32;
+    }
+
+    /// Starts its own tally, bumps it, and reports it in `a`.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Tally(u32);
+
+    #[typetag::serde]
+    impl Activity for Tally {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops.local_resource::<tally>(self.0)?;
+            ops += op! {
+                m: tally += 1;
+            };
+            ops += op! {
+                w: a = r: tally;
+            };
+
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Declares a local resource twice, or declares one that isn't local.
+    #[derive(Hash, Serialize, Deserialize)]
+    enum BadDeclaration {
+        Twice,
+        NotLocal,
+    }
+
+    #[typetag::serde]
+    impl Activity for BadDeclaration {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            match self {
+                BadDeclaration::Twice => {
+                    ops.local_resource::<tally>(0)?;
+                    ops.local_resource::<tally>(0)?;
+                }
+                BadDeclaration::NotLocal => ops.local_resource::<a>(0)?,
+            }
+            ops += op! {
+                m: tally += 1;
+            };
+
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn activities_keep_their_own_state() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        let first = plan.insert(seconds(0), Tally(5))?;
+        plan.insert(seconds(1), Tally(10))?;
+
+        assert_eq!(6, plan.sample::<a>(seconds(0))?);
+        assert_eq!(11, plan.sample::<a>(seconds(1))?);
+
+        plan.remove(first)?;
+        assert_eq!(0, plan.sample::<a>(seconds(0))?);
+        assert_eq!(11, plan.sample::<a>(seconds(1))?);
+
+        plan.insert_with_id(first, seconds(2), Tally(20))?;
+        assert_eq!(21, plan.sample::<a>(seconds(2))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_bad_declarations() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        assert!(plan.insert(seconds(0), BadDeclaration::Twice).is_err());
+        assert!(plan.insert(seconds(0), BadDeclaration::NotLocal).is_err());
+
+        plan.insert(seconds(0), Tally(1))?;
+        assert_eq!(2, plan.sample::<a>(seconds(0))?);
+
+        Ok(())
+    }
+}
+
+mod std_duration {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Timeouts {
+            /// A retry timeout, unrelated to the simulation's time scale.
+            retry_timeout: std::time::Duration;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct DoubleTimeout;
+
+    #[typetag::serde]
+    impl Activity for DoubleTimeout {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { m: retry_timeout *= 2; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn std_duration_resource() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Timeouts>(
+            seconds(0.0),
+            initial_conditions! { retry_timeout: std::time::Duration::from_millis(250) },
+        )?;
+        plan.insert(seconds(1.0), DoubleTimeout)?;
+        plan.insert(seconds(2.0), DoubleTimeout)?;
+
+        assert_eq!(
+            std::time::Duration::from_millis(1000),
+            plan.sample::<retry_timeout>(seconds(3.0))?
+        );
+
+        Ok(())
+    }
+}
+
+mod type_conversion {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Logging {
+            battery: f32;
+            battery_log: f64;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct DrainBattery;
+
+    #[typetag::serde]
+    impl Activity for DrainBattery {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { mut: battery -= 0.25; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Adds the current battery level to the log, converting it from `f32`.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct LogBattery;
+
+    #[typetag::serde]
+    impl Activity for LogBattery {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { mut: battery_log += ref:battery as f64; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn copy_f32_into_f64() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Logging>(
+            seconds(0.0),
+            initial_conditions! { battery: 1.5, battery_log: 0.0 },
+        )?;
+
+        plan.insert(seconds(1.0), LogBattery)?;
+        plan.insert(seconds(2.0), DrainBattery)?;
+        plan.insert(seconds(3.0), LogBattery)?;
+        assert_eq!(2.75, plan.sample::<battery_log>(seconds(4.0))?);
+
+        // The log depends on the battery, even though their types differ.
+        plan.insert(seconds(0.5), DrainBattery)?;
+        assert_eq!(2.25, plan.sample::<battery_log>(seconds(4.0))?);
+
+        Ok(())
+    }
+}
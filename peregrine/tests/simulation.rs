@@ -0,0 +1,1434 @@
+mod util;
+
+mod checkpoint {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use std::sync::atomic::Ordering;
+
+    /// Simulates a plan with an [EvalCounter] in the session, and returns how many times it ran.
+    fn run_counter(session: &Session) -> Result<u16> {
+        let mut plan = init_plan(session);
+        let (node, counter) = EvalCounter::new();
+
+        plan.insert(seconds(0), IncrementA)?;
+        plan.insert(seconds(1), node)?;
+        assert_eq!(1, plan.sample::<a>(seconds(1))?);
+
+        Ok(counter.load(Ordering::SeqCst))
+    }
+
+    #[test]
+    fn restored_checkpoint_reuses_history() -> Result<()> {
+        let session = Session::new();
+        assert_eq!(1, run_counter(&session)?);
+        let blob = session.checkpoint()?;
+
+        let mut restored = Session::new();
+        restored.restore(&blob)?;
+        assert_eq!(0, run_counter(&restored)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_version_is_rejected() -> Result<()> {
+        let session = Session::new();
+        run_counter(&session)?;
+        let mut blob = session.checkpoint()?;
+        blob[4] = blob[4].wrapping_add(1);
+
+        let mut restored = Session::new();
+        let error = restored.restore(&blob).unwrap_err();
+        assert!(error.to_string().contains("format version"));
+        assert!(restored.restore(b"not a checkpoint").is_err());
+
+        // The failed restores left the history empty.
+        assert_eq!(1, run_counter(&restored)?);
+
+        Ok(())
+    }
+}
+
+mod save_load {
+    #![cfg(feature = "serde")]
+
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    /// Increments `a` at the start, and lasts five seconds.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SlowIncrementA;
+
+    #[typetag::serde]
+    impl Activity for SlowIncrementA {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops += op! {
+                m: a += 1;
+            };
+
+            Ok(5.seconds())
+        }
+    }
+
+    /// Increments `a` once a second, `times` times.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct IncrementAEverySecond {
+        times: u32,
+    }
+
+    #[typetag::serde]
+    impl Activity for IncrementAEverySecond {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            for _ in 0..self.times {
+                ops += op! { m: a += 1; };
+                ops.wait(1.seconds());
+            }
+            Ok((self.times as i64).seconds())
+        }
+    }
+
+    impl MergeActivity for IncrementAEverySecond {
+        fn merge(&self, next: &Self) -> Option<Self> {
+            Some(IncrementAEverySecond {
+                times: self.times + next.times,
+            })
+        }
+    }
+
+    #[test]
+    fn loaded_plans_keep_their_activities() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<AB>(seconds(-1), initial_conditions! { a: 3, b: 0 })?;
+        let parent = plan.insert(seconds(0), SlowIncrementA)?;
+        let removed = plan.insert(seconds(1), IncrementB)?;
+        plan.insert_anchored(parent, Anchor::End(1.seconds()), SetBToA)?;
+        plan.remove(removed)?;
+        assert_eq!(4, plan.sample::<b>(seconds(6))?);
+
+        let saved = serde_json::to_vec(&plan)?;
+        let mut loaded = session.load_plan::<AB>(saved.as_slice())?;
+        assert!(session.shares_state::<_, b>(&plan, &loaded, seconds(6)));
+        assert_eq!(PlanDelta::default(), loaded.delta());
+
+        // IDs of activities removed before saving are not reused.
+        assert!(loaded.next_activity_id() > removed);
+
+        // The anchored activity still follows its parent.
+        loaded.move_activity(parent, seconds(10))?;
+        assert_eq!(0, loaded.sample::<b>(seconds(15))?);
+        assert_eq!(4, loaded.sample::<b>(seconds(16))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn loaded_activities_can_be_merged() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let first = plan.insert(seconds(0), IncrementAEverySecond { times: 2 })?;
+        let second = plan.insert(seconds(2), IncrementAEverySecond { times: 3 })?;
+
+        let saved = serde_json::to_vec(&plan)?;
+        let mut loaded = session.load_plan::<AB>(saved.as_slice())?;
+        loaded.merge_adjacent_activities::<IncrementAEverySecond>(first, second)?;
+        loaded.validate_integrity()?;
+        assert_eq!(5, loaded.activity_operations(first)?.len());
+        assert_eq!(5, loaded.sample::<a>(seconds(10))?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unknown_resources() -> Result<()> {
+        let session = Session::new();
+        let saved = serde_json::json!({
+            "start": seconds(0),
+            "initial_conditions": { "a": 0, "b": 0, "c": 0 },
+            "next_id": 0,
+            "activities": [],
+        });
+        assert!(
+            session
+                .load_plan::<AB>(saved.to_string().as_bytes())
+                .is_err()
+        );
+
+        Ok(())
+    }
+}
+
+mod serde_name {
+    use anyhow::Result;
+    use peregrine::internal::history::{History, InnerHistory};
+    use peregrine::*;
+
+    model! {
+        Power {
+            /// Previously named `battery`.
+            #[serde_name = "battery"]
+            #[derive(Debug)]
+            battery_charge: f64;
+        }
+    }
+
+    /// `battery_history.bin` was written when the resource was declared as `battery: f64`,
+    /// after an operation drained it from 100.0 to 90.0.
+    #[test]
+    fn renamed_resource_loads_old_history() -> Result<()> {
+        let bytes = include_bytes!("fixtures/battery_history.bin");
+        let (history, _): (History, _) =
+            bincode::serde::decode_from_slice(bytes, bincode::config::standard())?;
+
+        let inner = history.into_inner();
+        let battery = inner
+            .get::<InnerHistory<battery_charge>>()
+            .expect("history for the renamed resource should be loaded");
+        assert!(format!("{battery:?}").contains("90.0"), "{battery:?}");
+
+        Ok(())
+    }
+}
+
+mod golden {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+
+    fn build(session: &Session, extra: bool) -> Result<Plan<'_, AB>> {
+        let mut plan = init_plan(session);
+        plan.insert(seconds(1), IncrementA)?;
+        plan.insert(seconds(2), SetBToA)?;
+        plan.insert(seconds(3), AddBToA)?;
+        if extra {
+            plan.insert(seconds(2), IncrementB)?;
+        }
+        Ok(plan)
+    }
+
+    #[test]
+    fn golden_trace_round_trip() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("peregrine-golden-{}.txt", std::process::id()));
+
+        let session = Session::new().with_deterministic_execution(true);
+        let plan = build(&session, false)?;
+        assert_eq!(2, plan.sample::<a>(seconds(4))?);
+        plan.write_golden(&path)?;
+        assert_eq!(3, std::fs::read_to_string(&path)?.lines().count());
+
+        let session = Session::new().with_deterministic_execution(true);
+        let plan = build(&session, false)?;
+        assert_eq!(2, plan.sample::<a>(seconds(4))?);
+        plan.assert_matches_golden(&path)?;
+
+        let session = Session::new().with_deterministic_execution(true);
+        let plan = build(&session, true)?;
+        plan.sample::<a>(seconds(4))?;
+        assert!(plan.assert_matches_golden(&path).is_err());
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn requires_deterministic_execution() -> Result<()> {
+        let session = Session::new();
+        let plan = build(&session, false)?;
+        plan.sample::<a>(seconds(4))?;
+
+        assert!(
+            plan.write_golden(std::env::temp_dir().join("unused"))
+                .is_err()
+        );
+
+        Ok(())
+    }
+}
+
+mod playback {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+
+    #[test]
+    fn playback_advances_by_scaled_interval() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(1), IncrementA)?;
+        plan.insert(seconds(1), IncrementB)?;
+        plan.insert(seconds(3), IncrementA)?;
+
+        let ticks = plan
+            .playback::<(a, b)>(seconds(0), 100.0, std::time::Duration::from_millis(10))
+            .take(5)
+            .collect::<Result<Vec<_>>>()?;
+
+        let times = ticks.iter().map(|(t, _)| *t).collect::<Vec<_>>();
+        assert_eq!(
+            vec![seconds(0), seconds(1), seconds(2), seconds(3), seconds(4)],
+            times
+        );
+
+        let values = ticks.into_iter().map(|(_, v)| v).collect::<Vec<_>>();
+        assert_eq!(vec![(0, 0), (1, 1), (1, 1), (2, 1), (2, 1)], values);
+
+        Ok(())
+    }
+}
+
+mod sample_at_all_events {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+
+    #[test]
+    fn merges_event_times_of_all_resources() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(1), IncrementA)?;
+        plan.insert(seconds(2), IncrementB)?;
+        plan.insert(seconds(3), IncrementA)?;
+        plan.insert(seconds(6), IncrementB)?;
+
+        let table = plan.sample_at_all_events(
+            &[ResourceId::of::<a>(), ResourceId::of::<b>()],
+            seconds(0)..seconds(5),
+        )?;
+        let table = table
+            .iter()
+            .map(|(time, row)| {
+                (
+                    *time,
+                    *row[&ResourceId::of::<a>()].get::<a>().unwrap(),
+                    *row[&ResourceId::of::<b>()].get::<b>().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                (seconds(0), 0, 0),
+                (seconds(1), 1, 0),
+                (seconds(2), 1, 1),
+                (seconds(3), 2, 1),
+            ],
+            table
+        );
+
+        Ok(())
+    }
+}
+
+mod view_bounds {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+
+    #[test]
+    fn point_view_holds_the_governing_write() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), IncrementA)?;
+        plan.insert(seconds(2), IncrementA)?;
+        plan.insert(seconds(2), IncrementA)?;
+
+        let values = |view: Vec<(Time, u32)>| view.into_iter().map(|(_, a)| a).collect::<Vec<_>>();
+
+        assert_eq!(vec![1], values(plan.view::<a>(seconds(1)..=seconds(1))?));
+        assert_eq!(vec![3], values(plan.view::<a>(seconds(2)..=seconds(2))?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn empty_views_are_empty() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), IncrementA)?;
+        plan.insert(seconds(2), IncrementA)?;
+
+        let (early, late) = (seconds(1), seconds(3));
+        assert!(plan.view::<a>(seconds(2)..seconds(2))?.is_empty());
+        assert!(plan.view::<a>(late..early)?.is_empty());
+        assert!(plan.view::<a>(late..=early)?.is_empty());
+        assert!(plan.view_inclusive_next::<a>(late..early)?.is_empty());
+
+        Ok(())
+    }
+}
+
+mod reduce {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+
+    #[test]
+    fn sums_values_in_range() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        for i in 0..5 {
+            plan.insert(seconds(i), IncrementA)?;
+        }
+
+        // a is 1, 2, 3, 4, 5 at seconds 0 through 4.
+        let sum = plan.reduce::<a, _>(seconds(1)..seconds(4), 0, |sum, _, value| sum + value)?;
+        assert_eq!(2 + 3 + 4, sum);
+
+        let count = plan.reduce::<a, _>(seconds(0)..=seconds(4), 0, |count, _, _| count + 1)?;
+        assert_eq!(5, count);
+
+        // The value from before the bounds isn't included.
+        let empty =
+            plan.reduce::<a, _>(seconds(10)..seconds(20), 0, |sum, _, value| sum + value)?;
+        assert_eq!(0, empty);
+
+        Ok(())
+    }
+
+    #[test]
+    fn folds_in_order_on_a_single_thread() -> Result<()> {
+        let session = Session::new().with_deterministic_execution(true);
+        let mut plan = init_plan(&session);
+
+        for i in 0..100 {
+            plan.insert(seconds(i), IncrementA)?;
+        }
+
+        // The pool's only thread is the one folding, so it has to run the ops while it waits.
+        let values =
+            plan.reduce::<a, _>(seconds(0)..seconds(100), vec![], |mut values, _, value| {
+                values.push(value);
+                values
+            })?;
+        assert_eq!((1..=100).collect::<Vec<_>>(), values);
+
+        Ok(())
+    }
+}
+
+mod constraints {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn violations_cover_the_failing_intervals() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let low = plan.add_constraint::<a>(|a| a < 2);
+        let even = plan.add_constraint::<b>(|b| b % 2 == 0);
+
+        plan.insert(seconds(1), IncrementA)?;
+        let second = plan.insert(seconds(2), IncrementA)?;
+        plan.insert(seconds(3), IncrementB)?;
+        plan.insert(seconds(5), IncrementA)?;
+        plan.insert(seconds(7), IncrementB)?;
+
+        assert_eq!(
+            vec![
+                Violation {
+                    constraint: low,
+                    start: seconds(2),
+                    end: seconds(10),
+                },
+                Violation {
+                    constraint: even,
+                    start: seconds(3),
+                    end: seconds(7),
+                },
+            ],
+            plan.violations(seconds(0)..seconds(10))?
+        );
+
+        // Violations are clipped to the bounds.
+        assert_eq!(
+            vec![
+                Violation {
+                    constraint: low,
+                    start: seconds(4),
+                    end: seconds(5),
+                },
+                Violation {
+                    constraint: even,
+                    start: seconds(4),
+                    end: seconds(5),
+                },
+            ],
+            plan.violations(seconds(4)..seconds(5))?
+        );
+
+        plan.remove(second)?;
+        assert_eq!(
+            vec![(even, seconds(3)), (low, seconds(5))],
+            plan.violations(seconds(0)..seconds(10))?
+                .into_iter()
+                .map(|v| (v.constraint, v.start))
+                .collect::<Vec<_>>()
+        );
+
+        plan.remove_constraint(low)?;
+        assert!(plan.remove_constraint(low).is_err());
+        assert_eq!(
+            vec![even],
+            plan.violations(seconds(0)..seconds(10))?
+                .into_iter()
+                .map(|v| v.constraint)
+                .collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn only_affected_constraints_are_checked_again() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let checks = Arc::new(AtomicUsize::new(0));
+        let counter = checks.clone();
+        plan.add_constraint::<a>(move |a| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            a < 2
+        });
+        plan.add_constraint::<b>(|b| b < 2);
+
+        plan.insert(seconds(1), IncrementA)?;
+        plan.violations(seconds(0)..seconds(10))?;
+        let checked = checks.load(Ordering::SeqCst);
+        assert!(checked > 0);
+
+        plan.insert(seconds(2), IncrementB)?;
+        plan.violations(seconds(0)..seconds(10))?;
+        assert_eq!(checked, checks.load(Ordering::SeqCst));
+
+        plan.insert(seconds(3), IncrementA)?;
+        assert_eq!(1, plan.violations(seconds(0)..seconds(10))?.len());
+        assert!(checks.load(Ordering::SeqCst) > checked);
+
+        Ok(())
+    }
+
+    model! {
+        Battery {
+            charge: Linear;
+        }
+    }
+
+    /// Stops the drain and fills the battery back up.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Recharge;
+
+    #[typetag::serde]
+    impl Activity for Recharge {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: charge = Linear::new(1.seconds(), 20.0, 0.0); };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn violations_start_where_evolving_values_cross() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Battery>(
+            seconds(0),
+            initial_conditions! { charge: Linear::new(1.seconds(), 10.0, -1.0) },
+        )?;
+        let empty = plan.add_constraint::<charge>(|c| c.value > 0.0);
+        plan.insert(seconds(15), Recharge)?;
+
+        assert_eq!(
+            vec![Violation {
+                constraint: empty,
+                start: seconds(10),
+                end: seconds(15),
+            }],
+            plan.violations(seconds(0)..seconds(20))?
+        );
+
+        Ok(())
+    }
+}
+
+mod first_violation {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    model! {
+        Power {
+            charge: i32 = 10;
+        }
+    }
+
+    /// Changes the battery charge by a fixed amount.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Draw(i32);
+
+    #[typetag::serde]
+    impl Activity for Draw {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let amount = self.0;
+            ops += op! { m: charge -= amount; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    static COUNTED_DRAWS: AtomicU16 = AtomicU16::new(0);
+
+    /// Like [Draw], but counts how many times it runs.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct CountedDraw(i32);
+
+    #[typetag::serde]
+    impl Activity for CountedDraw {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let amount = self.0;
+            ops += op! {
+                COUNTED_DRAWS.fetch_add(1, Ordering::SeqCst);
+                m: charge -= amount;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn earliest_violation_is_returned() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Power>(seconds(0), initial_conditions! {})?;
+
+        plan.insert(seconds(10), Draw(15))?;
+        plan.insert(seconds(20), Draw(-20))?;
+        plan.insert(seconds(30), Draw(25))?;
+
+        let non_negative = |charge: i32| charge >= 0;
+        assert_eq!(
+            Some(seconds(10)),
+            plan.first_violation::<charge>(seconds(0)..seconds(40), non_negative)?
+        );
+        assert_eq!(
+            Some(seconds(30)),
+            plan.first_violation::<charge>(seconds(20)..seconds(40), non_negative)?
+        );
+        assert_eq!(
+            None,
+            plan.first_violation::<charge>(seconds(20)..seconds(30), non_negative)?
+        );
+
+        // A violation that began before the bounds is reported at their start.
+        assert_eq!(
+            Some(seconds(15)),
+            plan.first_violation::<charge>(seconds(15)..seconds(40), non_negative)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn writes_after_the_violation_are_not_simulated() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Power>(seconds(0), initial_conditions! {})?;
+
+        plan.insert(seconds(10), Draw(15))?;
+        plan.insert(seconds(20), CountedDraw(1))?;
+        plan.insert(seconds(30), CountedDraw(2))?;
+
+        let non_negative = |charge: i32| charge >= 0;
+        assert_eq!(
+            Some(seconds(10)),
+            plan.first_violation::<charge>(seconds(0)..seconds(40), non_negative)?
+        );
+        assert_eq!(0, COUNTED_DRAWS.load(Ordering::SeqCst));
+
+        assert_eq!(
+            Some(seconds(20)),
+            plan.first_violation::<charge>(seconds(20)..seconds(40), non_negative)?
+        );
+        assert_eq!(1, COUNTED_DRAWS.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+}
+
+mod grounding {
+    use crate::util::*;
+    use peregrine::*;
+
+    use hifitime::Duration;
+    use peregrine::anyhow::Result;
+    use serde::{Deserialize, Serialize};
+
+    /// Appends its id to the digits of `a` after a dynamic delay.
+    #[derive(Hash, Serialize, Deserialize)]
+    pub struct DelayedAppend {
+        id: u32,
+        delay: u32,
+    }
+
+    #[typetag::serde]
+    impl Activity for DelayedAppend {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let id = self.id;
+            let delay = Duration::from_seconds(self.delay as f64);
+            ops.wait(delay! { Duration::from_seconds(10.0) => delay });
+            ops += op! { m: a = a * 10 + id; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Samples `a` after two ops whose possible placements overlap, and which are grounded in the
+    /// opposite order to their insertion.
+    fn reversed_pair(session: &Session) -> Result<u32> {
+        let mut plan = init_plan(session);
+        plan.insert(seconds(0), DelayedAppend { id: 1, delay: 5 })?;
+        plan.insert(seconds(1), DelayedAppend { id: 2, delay: 1 })?;
+        plan.sample::<a>(seconds(8))
+    }
+
+    /// Mixing up which grounding came from which op used to make the read pick the wrong op,
+    /// and never finish.
+    #[test]
+    fn overlapping_ops_are_read_in_grounded_order() -> Result<()> {
+        // Simulates on another thread, so that a deadlock fails the test instead of hanging it.
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || sender.send(reversed_pair(&Session::new())).unwrap());
+
+        assert_eq!(
+            21,
+            receiver.recv_timeout(std::time::Duration::from_secs(10))??
+        );
+
+        Ok(())
+    }
+
+    /// Samples `a` every second across a cluster of ops whose possible placements all overlap,
+    /// and whose actual order differs from their insertion order.
+    fn overlapping_cluster(session: &Session) -> Result<Vec<u32>> {
+        let mut plan = init_plan(session);
+        plan.insert(seconds(0), DelayedAppend { id: 1, delay: 7 })?;
+        plan.insert(seconds(1), DelayedAppend { id: 2, delay: 2 })?;
+        plan.insert(seconds(2), DelayedAppend { id: 3, delay: 8 })?;
+        plan.insert(seconds(3), DelayedAppend { id: 4, delay: 1 })?;
+
+        (0..15).map(|s| plan.sample::<a>(seconds(s))).collect()
+    }
+
+    #[test]
+    fn batched_grounding_matches_per_node() -> Result<()> {
+        let batched = overlapping_cluster(&Session::new().with_batched_grounding(true))?;
+        let per_node = overlapping_cluster(&Session::new())?;
+
+        assert_eq!(batched, per_node);
+
+        let mut progression = batched;
+        progression.dedup();
+        assert_eq!(vec![0, 2, 24, 241, 2413], progression);
+
+        Ok(())
+    }
+
+    /// Appends its id to the digits of `a` immediately.
+    #[derive(Hash, Serialize, Deserialize)]
+    pub struct Append(u32);
+
+    #[typetag::serde]
+    impl Activity for Append {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let id = self.0;
+            ops += op! { m: a = a * 10 + id; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Grounds a dynamic write onto the exact time of a static write, inserting the static one first or last.
+    fn coincident_writes(session: Session, static_first: bool) -> Result<u32> {
+        let mut plan = session.new_plan::<AB>(seconds(0), initial_conditions! { a: 0, b: 0 })?;
+        if static_first {
+            plan.insert(seconds(2), Append(5))?;
+        }
+        plan.insert(seconds(1), DelayedAppend { id: 1, delay: 1 })?;
+        if !static_first {
+            plan.insert(seconds(2), Append(5))?;
+        }
+        plan.sample::<a>(seconds(3))
+    }
+
+    #[test]
+    fn coincident_write_policy() -> Result<()> {
+        for static_first in [true, false] {
+            assert_eq!(
+                15,
+                coincident_writes(
+                    Session::new()
+                        .with_coincident_write_policy(CoincidentWritePolicy::DynamicFirst),
+                    static_first
+                )?
+            );
+            assert_eq!(
+                51,
+                coincident_writes(
+                    Session::new().with_coincident_write_policy(CoincidentWritePolicy::DynamicLast),
+                    static_first
+                )?
+            );
+
+            // By default the dynamic write comes last.
+            assert_eq!(51, coincident_writes(Session::new(), static_first)?);
+        }
+
+        Ok(())
+    }
+}
+
+mod grounding_errors {
+    use crate::util::*;
+    use anyhow::{Result, anyhow};
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    /// Fails while writing `a`.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct BreakA;
+
+    #[typetag::serde]
+    impl Activity for BreakA {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! {
+                m: a += 1;
+                if a > 0 {
+                    return Err(anyhow!("sensor failure"));
+                }
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Writes `b` after a delay that depends on `a`.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct WaitOnA;
+
+    #[typetag::serde]
+    impl Activity for WaitOnA {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops.wait(
+                delay! { Duration::from_seconds(10.0) => Duration::from_seconds(r: a as f64) },
+            );
+            ops += op! { w: b = 7; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Views `b` after the failure of `a` has already been reported by a view of `a`.
+    fn view_b(policy: GroundingErrorPolicy) -> Result<Vec<(Time, u32)>> {
+        let session = Session::new().with_grounding_error_policy(policy);
+        let mut plan = init_plan(&session);
+        plan.insert(seconds(0), BreakA)?;
+        plan.insert(seconds(1), WaitOnA)?;
+
+        assert!(plan.view::<a>(seconds(0)..seconds(20)).is_err());
+        plan.view::<b>(seconds(0)..seconds(20))
+    }
+
+    #[test]
+    fn drop_leaves_the_operation_out() -> Result<()> {
+        assert_eq!(vec![(seconds(-1), 0)], view_b(GroundingErrorPolicy::Drop)?);
+        Ok(())
+    }
+
+    #[test]
+    fn min_bound_places_the_operation_early() -> Result<()> {
+        assert_eq!(
+            vec![(seconds(-1), 0), (seconds(1), 7)],
+            view_b(GroundingErrorPolicy::MinBound)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn propagate_reports_an_error() {
+        let error = view_b(GroundingErrorPolicy::Propagate).unwrap_err();
+        assert_eq!(
+            1,
+            error
+                .to_string()
+                .matches("could not place the operation")
+                .count(),
+            "{error}"
+        );
+    }
+}
+
+mod retry {
+    use crate::util::*;
+    use anyhow::{Result, anyhow};
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::Ordering;
+
+    /// Increments `a`, failing the first `failures` times it runs.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct FlakyIncrement {
+        failures: u16,
+        transient: bool,
+        attempts: UnhashedCounter,
+    }
+
+    #[typetag::serde]
+    impl Activity for FlakyIncrement {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let failures = self.failures;
+            let transient = self.transient;
+            let attempts = &self.attempts;
+            ops += op! {
+                #![retry(3, std::time::Duration::from_millis(1))]
+                if attempts.fetch_add(1, Ordering::SeqCst) < failures {
+                    let error = anyhow!("service unavailable");
+                    return Err(if transient { Transient(error).into() } else { error });
+                }
+                m: a += 1;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn succeeds_after_transient_failures() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let attempts = UnhashedCounter::default();
+
+        plan.insert(
+            seconds(0),
+            FlakyIncrement {
+                failures: 2,
+                transient: true,
+                attempts: attempts.clone(),
+            },
+        )?;
+        assert_eq!(1, plan.sample::<a>(seconds(1))?);
+        assert_eq!(3, attempts.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    #[test]
+    fn gives_up_after_the_retry_count() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let attempts = UnhashedCounter::default();
+
+        plan.insert(
+            seconds(0),
+            FlakyIncrement {
+                failures: 5,
+                transient: true,
+                attempts: attempts.clone(),
+            },
+        )?;
+        let message = format!("{:#}", plan.sample::<a>(seconds(1)).unwrap_err());
+        assert!(message.contains("gave up after 3 retries"), "{message}");
+        assert_eq!(4, attempts.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    #[test]
+    fn other_errors_are_not_retried() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let attempts = UnhashedCounter::default();
+
+        plan.insert(
+            seconds(0),
+            FlakyIncrement {
+                failures: 1,
+                transient: false,
+                attempts: attempts.clone(),
+            },
+        )?;
+        assert!(plan.sample::<a>(seconds(1)).is_err());
+        assert_eq!(1, attempts.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+
+    /// Increments `a` on the blocking pool, failing transiently the first `retried_failures`
+    /// times, then increments `b` there, failing transiently the first time without retrying.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct FlakyBlockingIncrements {
+        retried_failures: u16,
+        retried: UnhashedCounter,
+        unretried: UnhashedCounter,
+    }
+
+    #[typetag::serde]
+    impl Activity for FlakyBlockingIncrements {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let failures = self.retried_failures;
+            let retried = self.retried.clone();
+            let unretried = self.unretried.clone();
+            ops += op! {
+                #![retry(3, std::time::Duration::from_millis(1))]
+                #![blocking]
+                if retried.fetch_add(1, Ordering::SeqCst) < failures {
+                    return Err(Transient(anyhow!("service unavailable")).into());
+                }
+                m: a += 1;
+            };
+            ops += op! {
+                #![blocking]
+                if unretried.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return Err(Transient(anyhow!("service unavailable")).into());
+                }
+                m: b += 1;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn retries_belong_to_their_operation() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        let retried = UnhashedCounter::default();
+        let unretried = UnhashedCounter::default();
+
+        plan.insert(
+            seconds(0),
+            FlakyBlockingIncrements {
+                retried_failures: 2,
+                retried: retried.clone(),
+                unretried: unretried.clone(),
+            },
+        )?;
+        assert_eq!(1, plan.sample::<a>(seconds(1))?);
+        assert_eq!(3, retried.load(Ordering::SeqCst));
+        assert!(plan.sample::<b>(seconds(1)).is_err());
+        assert_eq!(1, unretried.load(Ordering::SeqCst));
+
+        Ok(())
+    }
+}
+
+mod operation_timeout {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+    use std::time::Duration as StdDuration;
+
+    /// Increments `a` after sleeping for the given number of milliseconds.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SlowIncrement(u64);
+
+    #[typetag::serde]
+    impl Activity for SlowIncrement {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let millis = self.0;
+            ops += op! {
+                std::thread::sleep(StdDuration::from_millis(millis));
+                m: a += 1;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn slow_operation_times_out() -> Result<()> {
+        let session = Session::new().with_operation_timeout(StdDuration::from_millis(50));
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), SlowIncrement(0))?;
+        assert_eq!(1, plan.sample::<a>(seconds(1))?);
+
+        plan.insert(seconds(2), SlowIncrement(200))?;
+        let message = format!("{:#}", plan.sample::<a>(seconds(3)).unwrap_err());
+        assert!(message.contains("exceeded its timeout"), "{message}");
+
+        Ok(())
+    }
+}
+
+mod blocking_ops {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration as StdDuration, Instant};
+
+    /// Opened by a quick operation, and waited on by blocking ones.
+    #[derive(Clone, Default, Hash, Serialize, Deserialize)]
+    struct Gate {
+        /// Nonzero once the gate is open.
+        open: UnhashedCounter,
+        /// Counts the blocking operations that gave up waiting for the gate.
+        gave_up: UnhashedCounter,
+    }
+
+    impl Gate {
+        /// Waits until the gate is opened. Gives up eventually, so that a failing test doesn't hang.
+        fn wait(&self) {
+            let deadline = Instant::now() + StdDuration::from_secs(10);
+            while self.open.load(Ordering::SeqCst) == 0 {
+                if Instant::now() > deadline {
+                    self.gave_up.fetch_add(1, Ordering::SeqCst);
+                    return;
+                }
+                std::thread::yield_now();
+            }
+        }
+    }
+
+    /// Sets `a` once the gate opens, like a slow IO query.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SlowQuery(u32, Gate);
+
+    #[typetag::serde]
+    impl Activity for SlowQuery {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let value = self.0;
+            let gate = &self.1;
+            ops += op! {
+                #![blocking]
+                gate.wait();
+                w: a = value;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    /// Increments `b`, then opens the gate.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct QuickIncrement(Gate);
+
+    #[typetag::serde]
+    impl Activity for QuickIncrement {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let gate = &self.0;
+            ops += op! {
+                m: b += 1;
+                gate.open.store(1, Ordering::SeqCst);
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn blocking_ops_leave_workers_free() -> Result<()> {
+        // With a single compute worker, a waiting op that occupied it would keep the quick op
+        // from ever opening the gate.
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build()?;
+
+        pool.install(|| {
+            let session = Session::new();
+            let mut plan = init_plan(&session);
+            let gate = Gate::default();
+
+            plan.insert(seconds(0), SlowQuery(1, gate.clone()))?;
+            plan.insert(seconds(1), SlowQuery(2, gate.clone()))?;
+            plan.insert(seconds(2), QuickIncrement(gate.clone()))?;
+
+            plan.simulate_resources(&[ResourceId::of::<b>(), ResourceId::of::<a>()], ..)?;
+            assert_eq!(0, gate.gave_up.load(Ordering::SeqCst));
+
+            assert_eq!(2, plan.sample::<a>(seconds(3))?);
+            assert_eq!(1, plan.sample::<b>(seconds(3))?);
+
+            Ok(())
+        })
+    }
+}
+
+mod cancellation {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+    use std::sync::LazyLock;
+    use std::time::Duration as StdDuration;
+
+    static TOKEN: LazyLock<CancelToken> = LazyLock::new(CancelToken::new);
+
+    /// Increments `a`, and cancels the simulation it runs in.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct IncrementAndCancel;
+
+    #[typetag::serde]
+    impl Activity for IncrementAndCancel {
+        fn run(&self, mut ops: Ops) -> Result<Duration> {
+            ops += op! {
+                TOKEN.cancel();
+                m: a += 1;
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn cancelled_views_can_be_resumed() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        plan.insert(seconds(0), IncrementA)?;
+        plan.insert(seconds(1), IncrementAndCancel)?;
+        plan.insert(seconds(2), IncrementA)?;
+
+        let options = SimOptions {
+            cancel_token: Some(TOKEN.clone()),
+            ..Default::default()
+        };
+        let err = plan
+            .view_with::<a>(seconds(0)..seconds(3), options)
+            .unwrap_err();
+        assert_eq!(Some(&Interrupted::Cancelled), err.downcast_ref());
+
+        let values = plan.view::<a>(seconds(0)..seconds(3))?;
+        assert_eq!(
+            vec![1, 2, 3],
+            values.into_iter().map(|(_, v)| v).collect::<Vec<_>>()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn timed_out_views_can_be_resumed() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        plan.insert(seconds(0), IncrementA)?;
+        plan.insert(seconds(1), IncrementB)?;
+
+        let options = SimOptions {
+            timeout: Some(StdDuration::ZERO),
+            ..Default::default()
+        };
+        let err = plan
+            .view_with::<a>(seconds(0)..seconds(2), options)
+            .unwrap_err();
+        assert_eq!(Some(&Interrupted::TimedOut), err.downcast_ref());
+
+        assert_eq!(1, plan.sample::<a>(seconds(1))?);
+        assert_eq!(
+            1,
+            plan.view_with::<a>(seconds(0)..seconds(2), SimOptions::default())?
+                .len()
+        );
+
+        Ok(())
+    }
+}
+
+mod horizon {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+
+    #[test]
+    fn marks_extrapolated_region() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+        assert_eq!(None, plan.horizon());
+
+        for i in 0..3 {
+            plan.insert(seconds(i), IncrementA)?;
+        }
+        assert_eq!(Some(seconds(2)), plan.horizon());
+
+        let view = plan.view_to_horizon::<a>(seconds(0)..seconds(100))?;
+        assert_eq!(Some(seconds(2)), view.extrapolated_from);
+        assert_eq!(
+            vec![(seconds(0), 1), (seconds(1), 2), (seconds(2), 3)],
+            view.values
+        );
+
+        // Bounds within the horizon are viewed normally.
+        let view = plan.view_to_horizon::<a>(seconds(0)..seconds(2))?;
+        assert_eq!(None, view.extrapolated_from);
+        assert_eq!(plan.view::<a>(seconds(0)..seconds(2))?, view.values);
+
+        // Bounds entirely past the horizon are extrapolated from their start.
+        let view = plan.view_to_horizon::<a>(seconds(50)..seconds(100))?;
+        assert_eq!(Some(seconds(50)), view.extrapolated_from);
+        assert_eq!(vec![(seconds(2), 3)], view.values);
+
+        Ok(())
+    }
+}
+
+mod after_view {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn hook_fires_once_per_view() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        let calls = Arc::new(Mutex::new(vec![]));
+        let recorded = calls.clone();
+        plan.on_after_view(move |stats| recorded.lock().unwrap().push(stats.clone()));
+
+        for i in 0..3 {
+            plan.insert(seconds(i), IncrementA)?;
+        }
+
+        plan.view::<a>(seconds(0)..seconds(10))?;
+        {
+            let calls = calls.lock().unwrap();
+            assert_eq!(1, calls.len());
+            assert_eq!("a", calls[0].resource);
+            assert_eq!(3, calls[0].values);
+        }
+
+        plan.view::<b>(seconds(0)..seconds(10))?;
+        let next = plan.view_inclusive_next::<a>(seconds(0)..seconds(1))?;
+        {
+            let calls = calls.lock().unwrap();
+            assert_eq!(3, calls.len());
+            assert_eq!("b", calls[1].resource);
+            // With no writes in the bounds, the view holds just the initial condition.
+            assert_eq!(1, calls[1].values);
+            assert_eq!(next.len(), calls[2].values);
+        }
+
+        // Other queries don't fire it.
+        plan.sample::<a>(seconds(5))?;
+        assert_eq!(3, calls.lock().unwrap().len());
+
+        Ok(())
+    }
+}
+
+mod profile {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Rover {
+            #[unit = "m"]
+            odometer: Linear;
+        }
+    }
+
+    /// Stops the rover at six meters.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Park;
+
+    #[typetag::serde]
+    impl Activity for Park {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: odometer = Linear::new(1.seconds(), 6.0, 0.0); };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn profiles_evaluate_between_writes() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Rover>(
+            seconds(0),
+            initial_conditions! { odometer: Linear::new(1.seconds(), 0.0, 2.0) },
+        )?;
+        plan.insert(seconds(3), Park)?;
+
+        let profile = plan
+            .profile::<odometer>(seconds(0)..seconds(5), 1.seconds())?
+            .map(|sample| sample.value);
+        assert_eq!("odometer", profile.resource);
+        assert_eq!(Some("m"), profile.unit);
+        assert_eq!(
+            vec![0.0, 2.0, 4.0, 6.0, 6.0],
+            profile.points.iter().map(|(_, v)| *v).collect::<Vec<_>>()
+        );
+
+        let mut csv = vec![];
+        profile.write_csv(&mut csv)?;
+        let csv = String::from_utf8(csv)?;
+        let mut lines = csv.lines();
+        assert_eq!(Some("time,odometer (m)"), lines.next());
+        assert_eq!(Some(format!("{},2", seconds(1)).as_str()), lines.nth(1));
+        assert_eq!(3, lines.count());
+
+        assert!(
+            plan.profile::<odometer>(seconds(0)..seconds(5), Duration::ZERO)
+                .is_err()
+        );
+
+        Ok(())
+    }
+}
+
+mod initial_conditions_json {
+    use crate::util::seconds;
+    use peregrine::anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Data, MaybeHash, Serialize, Deserialize)]
+    pub enum Mode {
+        Idle,
+        Science,
+    }
+
+    model! {
+        pub Spacecraft {
+            pub json_battery: f64;
+            pub json_mode: Mode;
+            pub json_uptime: u32 = 0;
+            pub json_target: String;
+        }
+    }
+
+    resource! {
+        pub json_orphan: u32;
+    }
+
+    #[test]
+    fn plan_starts_from_json_state() -> Result<()> {
+        let state = include_str!("fixtures/spacecraft_state.json");
+        let conditions = InitialConditions::from_json(state.as_bytes())?;
+
+        let session = Session::new();
+        let plan = session.new_plan::<Spacecraft>(seconds(0.0), conditions)?;
+
+        assert_eq!(plan.sample::<json_battery>(seconds(1.0))?, 87.5);
+        assert_eq!(plan.sample::<json_mode>(seconds(1.0))?, Mode::Science);
+        // Missing from the file, so it keeps its default.
+        assert_eq!(plan.sample::<json_uptime>(seconds(1.0))?, 0);
+        assert_eq!(plan.sample::<json_target>(seconds(1.0))?, "Europa");
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_label_is_rejected() {
+        let result = InitialConditions::from_json(r#"{ "json_unknown": 1 }"#.as_bytes());
+        let message = format!("{:#}", result.err().unwrap());
+        assert!(message.contains("json_unknown"), "{message}");
+    }
+
+    #[test]
+    fn mistyped_value_is_rejected() {
+        let result = InitialConditions::from_json(r#"{ "json_orphan": "three" }"#.as_bytes());
+        let message = format!("{:#}", result.err().unwrap());
+        assert!(message.contains("json_orphan"), "{message}");
+    }
+}
@@ -0,0 +1,97 @@
+#![cfg(feature = "tracing")]
+
+mod util;
+
+use anyhow::Result;
+use peregrine::*;
+use std::fmt::Debug;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use util::*;
+
+/// Records the message of every event, from every thread.
+#[derive(Default)]
+struct Recorder {
+    next_span: AtomicU64,
+    messages: Mutex<Vec<String>>,
+}
+
+struct MessageVisitor<'a>(&'a mut Option<String>);
+
+impl Visit for MessageVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            *self.0 = Some(format!("{value:?}"));
+        }
+    }
+}
+
+impl Subscriber for &'static Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, _span: &Attributes<'_>) -> Id {
+        Id::from_u64(self.next_span.fetch_add(1, Ordering::SeqCst) + 1)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut message = None;
+        event.record(&mut MessageVisitor(&mut message));
+        if let Some(message) = message {
+            self.messages.lock().unwrap().push(message);
+        }
+    }
+
+    fn enter(&self, _span: &Id) {}
+
+    fn exit(&self, _span: &Id) {}
+}
+
+#[test]
+fn reports_cache_misses_and_hits() -> Result<()> {
+    // Operations run on the thread pool, so the subscriber has to be global.
+    let recorder: &'static Recorder = Box::leak(Box::default());
+    tracing::subscriber::set_global_default(recorder)?;
+
+    let session = Session::new().with_trace_verbosity(TraceVerbosity::All);
+    let mut plan = init_plan(&session);
+    plan.insert(seconds(0), IncrementA)?;
+    plan.insert(seconds(1), SetBToA)?;
+
+    assert_eq!(1, plan.sample::<b>(seconds(2))?);
+    {
+        let messages = recorder.messages.lock().unwrap();
+        assert_eq!(
+            2,
+            messages.iter().filter(|m| *m == "cache miss").count(),
+            "{messages:?}"
+        );
+        assert!(messages.iter().any(|m| m == "timeline insert"));
+        assert!(!messages.iter().any(|m| m == "cache hit"));
+    }
+
+    // A second plan with the same activities finds their outputs in the cache.
+    let mut other = init_plan(&session);
+    other.insert(seconds(0), IncrementA)?;
+    other.insert(seconds(1), SetBToA)?;
+    recorder.messages.lock().unwrap().clear();
+
+    assert_eq!(1, other.sample::<b>(seconds(2))?);
+    let messages = recorder.messages.lock().unwrap();
+    assert_eq!(
+        2,
+        messages.iter().filter(|m| *m == "cache hit").count(),
+        "{messages:?}"
+    );
+    assert!(!messages.iter().any(|m| m == "cache miss"));
+
+    Ok(())
+}
@@ -6,6 +6,7 @@ use peregrine::*;
 use peregrine_macros::op;
 use serde::{Deserialize, Serialize};
 use std::hash::{Hash, Hasher};
+use std::ops::Deref;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU16, Ordering};
 
@@ -80,10 +81,13 @@ impl Activity for AddBToA {
 }
 
 #[derive(Serialize, Deserialize)]
-pub struct EvalCounter(HashableAtomicU16);
+pub struct EvalCounter(UnhashedCounter);
 
-#[derive(Clone, Serialize, Deserialize)]
-pub struct HashableAtomicU16(Arc<AtomicU16>);
+/// A counter shared between a test and the activities it inserts.
+///
+/// It is left out of hashes, so holding one doesn't change how an activity's operations are cached.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct UnhashedCounter(Arc<AtomicU16>);
 
 #[typetag::serde]
 impl Activity for EvalCounter {
@@ -91,24 +95,33 @@ impl Activity for EvalCounter {
         let counter = &self.0;
         ops += op! {
             m:a;
-            counter.0.fetch_add(1, Ordering::SeqCst);
+            counter.fetch_add(1, Ordering::SeqCst);
         };
 
         Ok(Duration::ZERO)
     }
 }
 
-impl Hash for HashableAtomicU16 {
+impl Hash for UnhashedCounter {
     fn hash<H: Hasher>(&self, _state: &mut H) {}
 }
 
+impl Deref for UnhashedCounter {
+    type Target = AtomicU16;
+
+    fn deref(&self) -> &AtomicU16 {
+        &self.0
+    }
+}
+
 impl EvalCounter {
     // Cargo test incorrectly warns that this function is not used.
     // It totally is, I don't know what its talking about.
     #[allow(unused)]
     pub fn new() -> (Self, Arc<AtomicU16>) {
-        let counter = Arc::new(AtomicU16::new(0));
-        (Self(HashableAtomicU16(counter.clone())), counter)
+        let counter = UnhashedCounter::default();
+        let shared = counter.0.clone();
+        (Self(counter), shared)
     }
 }
 
@@ -131,6 +144,6 @@ pub fn init_plan(session: &Session) -> Plan<AB> {
         .unwrap()
 }
 
-pub fn seconds(s: i32) -> Time {
-    Time::from_tai_seconds(s as f64)
+pub fn seconds(s: impl Into<f64>) -> Time {
+    Time::from_tai_seconds(s.into())
 }
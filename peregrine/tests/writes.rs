@@ -0,0 +1,326 @@
+mod util;
+
+mod double_write {
+    use crate::util::*;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    /// Sets `a` to 1, then 3, then 2 if `twice`.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SetATwice {
+        twice: bool,
+    }
+
+    #[typetag::serde]
+    impl Activity for SetATwice {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let twice = self.twice;
+            ops += op! {
+                w: a = 1;
+                w: a = 3;
+                if twice {
+                    w: a = 2;
+                }
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn last_write_wins() -> Result<()> {
+        let session = Session::new();
+        let mut plan = init_plan(&session);
+
+        plan.insert(seconds(0), SetATwice { twice: false })?;
+        plan.insert(seconds(1), SetATwice { twice: true })?;
+
+        assert_eq!(3, plan.sample::<a>(seconds(0))?);
+        assert_eq!(2, plan.sample::<a>(seconds(1))?);
+
+        Ok(())
+    }
+}
+
+mod compare_and_swap {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, MaybeHash, Serialize, Deserialize)]
+    enum Mode {
+        Idle,
+        Busy,
+    }
+    impl_copy_static_data![Mode];
+
+    model! {
+        Machine {
+            mode: Mode;
+            label: String;
+            claimed: bool;
+            claims: u32;
+        }
+    }
+
+    /// Claims the machine if it is idle, and counts successful claims.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Claim;
+
+    #[typetag::serde]
+    impl Activity for Claim {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! {
+                w: claimed = cas: mode, Mode::Idle => Mode::Busy;
+                if claimed {
+                    m: claims += 1;
+                }
+            };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Release;
+
+    #[typetag::serde]
+    impl Activity for Release {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { cas: mode, Mode::Busy => Mode::Idle; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Relabel;
+
+    #[typetag::serde]
+    impl Activity for Relabel {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { cas: label, "idle" => "busy"; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn swaps_only_on_expected_value() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Machine>(
+            seconds(0),
+            initial_conditions! {
+                mode: Mode::Idle,
+                label: "idle".to_string(),
+                claimed: false,
+                claims: 0,
+            },
+        )?;
+
+        plan.insert(seconds(1), Claim)?;
+        assert_eq!(Mode::Busy, plan.sample::<mode>(seconds(1))?);
+        assert!(plan.sample::<claimed>(seconds(1))?);
+
+        // The machine is already busy, so the second claim fails and changes nothing.
+        plan.insert(seconds(2), Claim)?;
+        assert_eq!(Mode::Busy, plan.sample::<mode>(seconds(2))?);
+        assert!(!plan.sample::<claimed>(seconds(2))?);
+        assert_eq!(1, plan.sample::<claims>(seconds(2))?);
+
+        // Releasing it in between lets the second claim succeed.
+        plan.insert(seconds(1) + Duration::from_milliseconds(500.0), Release)?;
+        assert!(plan.sample::<claimed>(seconds(2))?);
+        assert_eq!(2, plan.sample::<claims>(seconds(2))?);
+
+        plan.insert(seconds(3), Relabel)?;
+        plan.insert(seconds(4), Relabel)?;
+        assert_eq!("busy", plan.sample::<label>(seconds(4))?);
+
+        Ok(())
+    }
+}
+
+mod comparison_write {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Thermal {
+            temperature: u32;
+            limit: u32;
+            overheat: bool;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SetTemperature(u32);
+
+    #[typetag::serde]
+    impl Activity for SetTemperature {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let value = self.0;
+            ops += op! { w: temperature = value; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct SetLimit(u32);
+
+    #[typetag::serde]
+    impl Activity for SetLimit {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            let value = self.0;
+            ops += op! { w: limit = value; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct CheckOverheat;
+
+    #[typetag::serde]
+    impl Activity for CheckOverheat {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { mut: overheat = ref:temperature > ref:limit; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn flag_follows_comparison() -> Result<()> {
+        let session = Session::new();
+        let mut plan = session.new_plan::<Thermal>(
+            seconds(0),
+            initial_conditions! { temperature: 20, limit: 50, overheat: false },
+        )?;
+
+        let check = plan.insert(seconds(10), CheckOverheat)?;
+        assert!(!plan.sample::<overheat>(seconds(11))?);
+
+        // Crossing the limit before the check flips the flag.
+        let hot = plan.insert(seconds(5), SetTemperature(60))?;
+        assert!(plan.sample::<overheat>(seconds(11))?);
+
+        // So does moving the limit back above the temperature.
+        plan.insert(seconds(7), SetLimit(80))?;
+        assert!(!plan.sample::<overheat>(seconds(11))?);
+
+        plan.remove(hot)?;
+        plan.insert(seconds(8), SetTemperature(90))?;
+        assert!(plan.sample::<overheat>(seconds(11))?);
+
+        plan.remove(check)?;
+        assert!(!plan.sample::<overheat>(seconds(11))?);
+
+        Ok(())
+    }
+}
+
+mod float_policy {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Floats {
+            x: f64;
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct WriteNan;
+
+    #[typetag::serde]
+    impl Activity for WriteNan {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: x = f64::NAN; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[derive(Hash, Serialize, Deserialize)]
+    struct WriteInfinity;
+
+    #[typetag::serde]
+    impl Activity for WriteInfinity {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: x = f64::INFINITY; };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    #[test]
+    fn nan_write_errors() -> Result<()> {
+        let session = Session::new().with_float_policy(FloatPolicy::Error);
+        let mut plan = session.new_plan::<Floats>(seconds(0.0), initial_conditions! { x: 1.0 })?;
+        plan.insert(seconds(1.0), WriteNan)?;
+
+        let message = plan.sample::<x>(seconds(2.0)).unwrap_err().to_string();
+        assert!(message.contains("resource x"), "{message}");
+        assert!(message.contains(&seconds(1.0).to_string()), "{message}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn sanitize_clamps_infinity() -> Result<()> {
+        let session = Session::new().with_float_policy(FloatPolicy::Sanitize);
+        let mut plan = session.new_plan::<Floats>(seconds(0.0), initial_conditions! { x: 1.0 })?;
+        plan.insert(seconds(1.0), WriteInfinity)?;
+
+        assert_eq!(f64::MAX, plan.sample::<x>(seconds(2.0))?);
+
+        Ok(())
+    }
+}
+
+mod write_sample_policy {
+    use crate::util::seconds;
+    use anyhow::Result;
+    use peregrine::hifitime::TimeUnits;
+    use peregrine::*;
+    use serde::{Deserialize, Serialize};
+
+    model! {
+        Motion {
+            position: Linear;
+        }
+    }
+
+    /// Stops at 100.
+    #[derive(Hash, Serialize, Deserialize)]
+    struct Stop;
+
+    #[typetag::serde]
+    impl Activity for Stop {
+        fn run<'o>(&'o self, mut ops: Ops<'_, 'o>) -> Result<Duration> {
+            ops += op! { w: position = Linear::new(1.seconds(), 100.0, 0.0); };
+            Ok(Duration::ZERO)
+        }
+    }
+
+    fn sample_at_stop(session: &Session) -> Result<f64> {
+        let mut plan = session.new_plan::<Motion>(
+            seconds(0),
+            initial_conditions! { position: Linear::new(1.seconds(), 0.0, 1.0) },
+        )?;
+        plan.insert(seconds(10), Stop)?;
+        Ok(plan.sample::<position>(seconds(10))?.value)
+    }
+
+    #[test]
+    fn samples_new_segment_at_write_by_default() -> Result<()> {
+        assert_eq!(100.0, sample_at_stop(&Session::new())?);
+        Ok(())
+    }
+
+    #[test]
+    fn samples_previous_segment_at_write() -> Result<()> {
+        let session = Session::new().with_write_sample_policy(WriteSamplePolicy::PreviousSegment);
+        assert_eq!(10.0, sample_at_stop(&session)?);
+        Ok(())
+    }
+}
@@ -19,6 +19,20 @@ pub fn generate_data_impl(input: DeriveInput) -> TokenStream {
     let (_, ty_generics, where_clause) = input.generics.split_for_impl();
 
     let sample_type = parse_sample_attribute(&input);
+    let variant_tag_attr = input
+        .attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("variant_tag"));
+    if let Some(attr) = variant_tag_attr {
+        if let Err(e) = attr.meta.require_path_only() {
+            return e.to_compile_error().into();
+        }
+        if !matches!(input.data, syn::Data::Enum(_)) {
+            return syn::Error::new_spanned(attr, "`#[variant_tag]` is only supported on enums")
+                .to_compile_error()
+                .into();
+        }
+    }
 
     // Check if the type has fields
     let has_fields = match &input.data {
@@ -95,8 +109,14 @@ pub fn generate_data_impl(input: DeriveInput) -> TokenStream {
             sample_body,
             is_struct,
         );
+        let variant_tag = if variant_tag_attr.is_some() {
+            generate_variant_tag(name, &variants, visibility, &input.generics, None)
+        } else {
+            quote! {}
+        };
         return quote! {
             #read_type
+            #variant_tag
 
             impl #modified_impl_generics peregrine::Data<'h> for #name #ty_generics #where_clause {
                 type Read = #read_type_name #modified_ty_generics;
@@ -185,9 +205,21 @@ pub fn generate_data_impl(input: DeriveInput) -> TokenStream {
         sample_body,
         is_struct,
     );
+    let variant_tag = if variant_tag_attr.is_some() {
+        generate_variant_tag(
+            name,
+            &variants,
+            visibility,
+            &input.generics,
+            Some((&sample_type_name, &modified_generics)),
+        )
+    } else {
+        quote! {}
+    };
     quote! {
         #read_type
         #sample_type_def
+        #variant_tag
 
         impl #modified_impl_generics peregrine::Data<'h> for #name #ty_generics #where_clause {
             type Read = #read_type_name #modified_ty_generics;
@@ -199,6 +231,60 @@ pub fn generate_data_impl(input: DeriveInput) -> TokenStream {
     .into()
 }
 
+/// Generate a fieldless `{Name}Variant` enum, and `variant()` accessors on the data type
+/// and (if it is a separate type) the sample type, so callers can branch on the active variant
+/// without naming the generated sample type. Only generated for enums marked `#[variant_tag]`.
+fn generate_variant_tag(
+    name: &Ident,
+    variants: &[Variant],
+    visibility: &syn::Visibility,
+    generics: &Generics,
+    sample: Option<(&Ident, &Generics)>,
+) -> TokenStream2 {
+    let tag_name = format_ident!("{}Variant", name);
+    let variant_names = variants.iter().map(|v| &v.ident).collect::<Vec<_>>();
+    let patterns = variants
+        .iter()
+        .map(|v| {
+            let ident = &v.ident;
+            match &v.fields {
+                Fields::Named(_) => quote! { #ident { .. } },
+                Fields::Unnamed(_) => quote! { #ident(..) },
+                Fields::Unit => quote! { #ident },
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let accessor = |type_name: &Ident, generics: &Generics| {
+        let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+        quote! {
+            impl #impl_generics #type_name #ty_generics #where_clause {
+                /// The active variant, without its data.
+                #visibility fn variant(&self) -> #tag_name {
+                    match self {
+                        #(#type_name::#patterns => #tag_name::#variant_names,)*
+                    }
+                }
+            }
+        }
+    };
+
+    let data_accessor = accessor(name, generics);
+    let sample_accessor = sample
+        .map(|(sample_name, sample_generics)| accessor(sample_name, sample_generics))
+        .unwrap_or_default();
+
+    quote! {
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+        #visibility enum #tag_name {
+            #(#variant_names,)*
+        }
+
+        #data_accessor
+        #sample_accessor
+    }
+}
+
 /// Extract sample type from #[sample = "TypeName"] or #[sample = Self] attribute
 fn parse_sample_attribute(input: &DeriveInput) -> Option<String> {
     for attr in &input.attrs {
@@ -25,6 +25,11 @@ const MAX_PREGENERATED_ORDER: i32 = -1;
 
 #[proc_macro]
 pub fn op(input: TokenStream) -> TokenStream {
+    match operation::split_member_index(input.clone().into()) {
+        Ok(Some(indexed)) => return indexed.into_token_stream().into(),
+        Ok(None) => {}
+        Err(e) => return e.to_compile_error().into(),
+    }
     let op = parse_macro_input!(input as Op);
     op.into_token_stream().into()
 }
@@ -256,15 +261,16 @@ pub fn delay(input: TokenStream) -> TokenStream {
             use peregrine::internal::macro_prelude::{builtins::elapsed, peregrine_grounding};
             move |placement| peregrine::internal::macro_prelude::Delay {
                 node: (op! { w: peregrine_grounding = r: elapsed + std::cmp::min(#tt, #expr); })(placement),
-                min: placement.min().when,
-                max: placement.max().when + #expr,
+                // The bounds are offsets from the placement, which is added when the delay is applied.
+                min: peregrine::Duration::ZERO,
+                max: #expr,
             }
         }
     };
     expanded.into()
 }
 
-#[proc_macro_derive(Data, attributes(sample))]
+#[proc_macro_derive(Data, attributes(sample, variant_tag))]
 pub fn derive_data(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     data::generate_data_impl(input)
@@ -1,20 +1,28 @@
-use crate::model::{Daemon, Model};
+use crate::model::{Daemon, Derived, Exposed, Model, RecurringDaemon, replace_bare_names};
+use crate::resource::Resource;
+use heck::ToSnakeCase;
 use proc_macro2::Ident;
+use quote::{ToTokens, format_ident};
 use syn::parse::{Parse, ParseStream};
-use syn::{Token, Visibility, braced, parenthesized};
+use syn::{Path, Token, Type, Visibility, braced, parenthesized};
 
 impl Model {
     fn parse_extras(input: ParseStream) -> syn::Result<Self> {
         let mut sub_models = vec![];
         let mut daemons = vec![];
+        let mut recurring = vec![];
         let mut imported_resources = vec![];
+        let mut exposed = vec![];
 
         // Now parse submodels and daemons outside the model block
         while !input.is_empty() {
             if input.peek(Token![mod])
                 || input.peek(Token![use])
                 || (input.peek(syn::Ident)
-                    && input.fork().parse::<Ident>().is_ok_and(|id| id == "react"))
+                    && input
+                        .fork()
+                        .parse::<Ident>()
+                        .is_ok_and(|id| id == "react" || id == "daemon" || id == "expose"))
             {
                 // Continue parsing
             } else {
@@ -30,9 +38,13 @@ impl Model {
             } else if input.peek(syn::Ident) && input.fork().parse::<Ident>()? == "react" {
                 let daemon = parse_daemon(input)?;
                 daemons.push(daemon);
+            } else if input.peek(syn::Ident) && input.fork().parse::<Ident>()? == "daemon" {
+                recurring.push(parse_recurring(input)?);
+            } else if input.peek(syn::Ident) && input.fork().parse::<Ident>()? == "expose" {
+                exposed.push(parse_exposed(input)?);
             } else {
                 return Err(input.error(
-                    "Expected `use` for submodel import or `react` for daemon declaration.",
+                    "Expected `use` for submodel import, `react` or `daemon` for daemon declaration, or `expose` for a read-only accessor.",
                 ));
             }
 
@@ -45,6 +57,9 @@ impl Model {
             new_resources: vec![],
             sub_models,
             daemons,
+            recurring,
+            exposed,
+            derived: vec![],
         })
     }
 }
@@ -61,15 +76,50 @@ impl Parse for Model {
         braced!(body in input);
 
         while !body.is_empty() {
-            result.new_resources.push(body.parse()?);
+            if is_derived(&body) {
+                result.derived.push(parse_derived(&body)?);
+                continue;
+            }
+            let span = body.span();
+            let resource: Resource = body.parse()?;
+            let local = match &resource {
+                Resource::Single(single) => single.options.local,
+                Resource::Group(group) => group.options.local,
+            };
+            if local {
+                return Err(syn::Error::new(
+                    span,
+                    "local resources can't be part of a model; declare them with `resource!` \
+                    and `Ops::local_resource` instead",
+                ));
+            }
+            result.new_resources.push(resource);
         }
 
         let post_extras = Self::parse_extras(input)?;
         result.sub_models.extend(post_extras.sub_models);
         result.daemons.extend(post_extras.daemons);
+        result.recurring.extend(post_extras.recurring);
         result
             .imported_resources
             .extend(post_extras.imported_resources);
+        result.exposed.extend(post_extras.exposed);
+
+        for exposed in &result.exposed {
+            let sub_model = exposed.sub_model.to_token_stream().to_string();
+            if !result
+                .sub_models
+                .iter()
+                .any(|m| m.to_token_stream().to_string() == sub_model)
+            {
+                return Err(syn::Error::new_spanned(
+                    &exposed.sub_model,
+                    format!(
+                        "`{sub_model}` is not a submodel of this model; add `mod {sub_model};`"
+                    ),
+                ));
+            }
+        }
 
         Ok(result)
     }
@@ -117,3 +167,111 @@ fn parse_daemon(input: ParseStream) -> syn::Result<Daemon> {
         react_to_all,
     })
 }
+
+fn parse_recurring(input: ParseStream) -> syn::Result<RecurringDaemon> {
+    let _: Ident = input.parse()?; // consume 'daemon'
+
+    let kind: Ident = input.parse()?;
+    if kind != "recurring" {
+        return Err(syn::Error::new_spanned(
+            kind,
+            "Only recurring daemons can be declared with `daemon`: `daemon recurring(PERIOD) => function();`",
+        ));
+    }
+
+    let period_paren;
+    parenthesized!(period_paren in input);
+    let period = period_paren.parse()?;
+
+    let _: Token![=>] = input.parse()?;
+    let function_call = input.parse()?;
+
+    Ok(RecurringDaemon {
+        period,
+        function_call,
+    })
+}
+
+fn parse_exposed(input: ParseStream) -> syn::Result<Exposed> {
+    let _: Ident = input.parse()?; // consume 'expose'
+
+    let access: Ident = input.parse()?;
+    if access != "read" {
+        return Err(syn::Error::new_spanned(
+            access,
+            "Only read access can be exposed: `expose read resource from SubModel;`",
+        ));
+    }
+
+    let resource: Path = input.parse()?;
+
+    let from: Ident = input.parse()?;
+    if from != "from" {
+        return Err(syn::Error::new_spanned(
+            from,
+            "Expected `from` in `expose read resource from SubModel;`",
+        ));
+    }
+
+    let sub_model: Path = input.parse()?;
+
+    let name = if input.peek(Token![as]) {
+        let _: Token![as] = input.parse()?;
+        input.parse()?
+    } else {
+        let model_name = &sub_model.segments.last().unwrap().ident;
+        let resource_name = &resource.segments.last().unwrap().ident;
+        format_ident!(
+            "{}_{}",
+            model_name.to_string().to_snake_case(),
+            resource_name
+        )
+    };
+
+    Ok(Exposed {
+        resource,
+        sub_model,
+        name,
+    })
+}
+
+/// Whether the next declaration is `derived name: Type = ...;`, not a resource named `derived`.
+fn is_derived(input: ParseStream) -> bool {
+    let fork = input.fork();
+    fork.parse::<Visibility>().is_ok()
+        && fork.parse::<Ident>().is_ok_and(|id| id == "derived")
+        && fork.peek(syn::Ident)
+}
+
+fn parse_derived(input: ParseStream) -> syn::Result<Derived> {
+    let visibility = input.parse()?;
+    let _: Ident = input.parse()?; // consume 'derived'
+    let name = input.parse()?;
+    let _: Token![:] = input.parse()?;
+    let data_type: Type = input.parse()?;
+    let _: Token![=] = input.parse()?;
+    let mut expr = input.parse()?;
+    let _: Token![;] = input.parse()?;
+
+    let mut sources: Vec<Ident> = vec![];
+    replace_bare_names(&mut expr, |ident| {
+        if !sources.contains(ident) {
+            sources.push(ident.clone());
+        }
+        None
+    });
+    if sources.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &expr,
+            "a derived resource must be computed from at least one resource",
+        ));
+    }
+
+    Ok(Derived {
+        visibility,
+        name,
+        data_type,
+        expr,
+        sources,
+    })
+}
@@ -3,7 +3,8 @@ mod output;
 
 use crate::resource::Resource;
 use proc_macro2::Ident;
-use syn::{Path, Visibility};
+use syn::visit_mut::{self, VisitMut};
+use syn::{Expr, Path, Type, Visibility};
 
 pub struct Model {
     visibility: Visibility,
@@ -12,6 +13,33 @@ pub struct Model {
     new_resources: Vec<Resource>,
     sub_models: Vec<Path>,
     daemons: Vec<Daemon>,
+    recurring: Vec<RecurringDaemon>,
+    exposed: Vec<Exposed>,
+    derived: Vec<Derived>,
+}
+
+/// A read-only accessor for a submodel resource: `expose read battery from Power;`
+#[derive(Debug, Clone)]
+pub struct Exposed {
+    /// The submodel's resource.
+    pub resource: Path,
+    /// The submodel the resource comes from.
+    pub sub_model: Path,
+    /// The generated accessor resource. Defaults to `power_battery` for the above example,
+    /// or can be set with `expose read battery from Power as name;`.
+    pub name: Ident,
+}
+
+/// A read-only resource computed from others: `derived margin: f64 = generated - consumed;`
+#[derive(Debug, Clone)]
+pub struct Derived {
+    pub visibility: Visibility,
+    pub name: Ident,
+    pub data_type: Type,
+    /// The expression, with bare names for the resources it is computed from.
+    pub expr: Expr,
+    /// The bare names in `expr`, in order of first appearance.
+    pub sources: Vec<Ident>,
 }
 
 #[derive(Debug, Clone)]
@@ -20,3 +48,39 @@ pub struct Daemon {
     pub function_call: syn::ExprCall,
     pub react_to_all: bool,
 }
+
+/// A daemon that runs at a fixed period: `daemon recurring(1.hours()) => tick();`
+#[derive(Debug, Clone)]
+pub struct RecurringDaemon {
+    pub period: syn::Expr,
+    pub function_call: syn::ExprCall,
+}
+
+/// Calls `f` on each lowercase bare name in an expression, like `battery` but not `MAX`,
+/// `f64::max`, or the function in `max(a, b)`, and replaces the name with what it returns.
+pub fn replace_bare_names(expr: &mut Expr, f: impl FnMut(&Ident) -> Option<Expr>) {
+    struct BareNames<F>(F);
+
+    impl<F: FnMut(&Ident) -> Option<Expr>> VisitMut for BareNames<F> {
+        fn visit_expr_mut(&mut self, expr: &mut Expr) {
+            match expr {
+                Expr::Path(path) if path.qself.is_none() => {
+                    if let Some(ident) = path.path.get_ident()
+                        && !ident.to_string().starts_with(char::is_uppercase)
+                        && let Some(replacement) = (self.0)(ident)
+                    {
+                        *expr = replacement;
+                    }
+                }
+                Expr::Call(call) => {
+                    for arg in &mut call.args {
+                        self.visit_expr_mut(arg);
+                    }
+                }
+                _ => visit_mut::visit_expr_mut(self, expr),
+            }
+        }
+    }
+
+    BareNames(f).visit_expr_mut(expr);
+}
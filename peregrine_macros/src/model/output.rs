@@ -1,10 +1,11 @@
 use crate::resource::Resource::Group;
 use crate::resource::output::{
-    generate_enum_name, generate_group_name, generate_member_resource_ident, generate_variant_name,
+    generate_count_name, generate_enum_name, generate_group_name, generate_member_resource_ident,
+    generate_single_resource_definition, generate_variant_name,
 };
 use crate::{
-    model::{Daemon, Model},
-    resource::GroupResource,
+    model::{Daemon, Derived, Exposed, Model, RecurringDaemon, replace_bare_names},
+    resource::{GroupResource, ResourceOptions},
 };
 use proc_macro2::TokenStream;
 use quote::{ToTokens, TokenStreamExt, format_ident, quote};
@@ -18,6 +19,9 @@ impl ToTokens for Model {
             new_resources,
             sub_models,
             daemons,
+            recurring,
+            exposed,
+            derived,
         } = self;
 
         let new_resource_names = new_resources.iter().flat_map(|r| match r {
@@ -35,6 +39,12 @@ impl ToTokens for Model {
                     "{}",
                     generate_group_name(&group.name_pattern)
                 )))
+                .chain(
+                    group
+                        .options
+                        .count
+                        .then(|| format_ident!("{}", generate_count_name(&group.name_pattern))),
+                )
                 .collect(),
         });
 
@@ -44,9 +54,149 @@ impl ToTokens for Model {
             .chain(new_resource_names.clone().map(|id| id.into()))
             .collect::<Vec<_>>();
 
+        let exposed_names = exposed.iter().map(|e| &e.name).collect::<Vec<_>>();
+        let exposed_sources = exposed.iter().map(|e| &e.resource).collect::<Vec<_>>();
+        let exposed_definitions = exposed.iter().map(|Exposed { resource, name, .. }| {
+            generate_single_resource_definition(
+                name,
+                &syn::Type::Verbatim(quote! { <#resource as peregrine::Resource>::Data }),
+                &[],
+                // The data type is spelled through the submodel resource, which may be
+                // less visible than the model, so the accessor is kept private.
+                &syn::Visibility::Inherited,
+                None,
+                &ResourceOptions {
+                    read_only: true,
+                    ..Default::default()
+                },
+            )
+        });
+
+        let derived_names = derived.iter().map(|d| &d.name).collect::<Vec<_>>();
+        let derived_definitions = derived.iter().map(|d| {
+            generate_single_resource_definition(
+                &d.name,
+                &d.data_type,
+                &[],
+                &d.visibility,
+                None,
+                &ResourceOptions {
+                    read_only: true,
+                    ..Default::default()
+                },
+            )
+        });
+        // The initial value is computed from the sources' initial values, each bound to a
+        // local of the same name.
+        let derived_initial_values = derived.iter().map(|d| {
+            let Derived {
+                data_type,
+                expr,
+                sources,
+                ..
+            } = d;
+            let fallbacks = sources
+                .iter()
+                .map(|s| initial_value_fallback(&s.to_token_stream()));
+            quote! {{
+                let start = peregrine::internal::macro_prelude::duration_to_epoch(time);
+                #(
+                    let #sources = match initial_conditions.get::<#sources>() {
+                        Some(value) => value.clone(),
+                        None => #fallbacks
+                    };
+                    let #sources = <<#sources as peregrine::Resource>::Data as peregrine::Data>::sample(
+                        peregrine::Data::to_read(&#sources, start),
+                        start,
+                    );
+                )*
+                let initial_value: #data_type = #expr;
+                initial_value
+            }}
+        });
+
+        // Group counts start from their members' initial values, which may be overridden by
+        // the initial conditions.
+        let counted_groups = new_resources
+            .iter()
+            .filter_map(|r| match r {
+                Group(group) if group.options.count => Some(group),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let count_names = counted_groups
+            .iter()
+            .map(|g| format_ident!("{}", generate_count_name(&g.name_pattern)))
+            .collect::<Vec<_>>();
+        let count_initial_values = counted_groups.iter().map(|g| {
+            let members = g
+                .members
+                .iter()
+                .map(|m| generate_member_resource_ident(&g.name_pattern, &m.to_string()))
+                .collect::<Vec<_>>();
+            let fallbacks = members
+                .iter()
+                .map(|m| initial_value_fallback(&m.to_token_stream()));
+            quote! {{
+                let mut count = 0u32;
+                #(
+                    let member: bool = match initial_conditions.get::<#members>() {
+                        Some(value) => *value,
+                        None => #fallbacks
+                    };
+                    count += member as u32;
+                )*
+                count
+            }}
+        });
+
         let mut daemons = daemons.clone();
+        // Daemon ops can write to derived resources because they write them through a generic
+        // parameter, which the read-only check doesn't apply to.
+        daemons.extend(derived.iter().map(|d| {
+            let Derived {
+                name,
+                data_type,
+                expr,
+                sources,
+                ..
+            } = d;
+            let mut tagged = expr.clone();
+            replace_bare_names(&mut tagged, |ident| {
+                Some(syn::Expr::Verbatim(quote! { r: #ident }))
+            });
+            Daemon {
+                resources: sources.iter().map(|s| s.clone().into()).collect(),
+                function_call: syn::parse(
+                    quote! {
+                        (|ops| {
+                            fn derive<TO: peregrine::Resource<Data = #data_type>>(mut ops: peregrine::Ops) {
+                                ops += peregrine::op! {
+                                    w: TO = #tagged;
+                                };
+                            }
+                            derive::<#name>(ops)
+                        })()
+                    }
+                    .into(),
+                )
+                .expect("Could not generate derived resource update call"),
+                react_to_all: false,
+            }
+        }));
+        daemons.extend(exposed.iter().map(|Exposed { resource, name, .. }| {
+            Daemon {
+                resources: vec![resource.clone()],
+                function_call: syn::parse(
+                    quote! { peregrine::internal::resource::group::mirror::<#resource, #name>() }
+                        .into(),
+                )
+                .expect("Could not generate exposed resource mirror call"),
+                react_to_all: false,
+            }
+        }));
         daemons.extend(new_resources.iter().flat_map(|r| match r {
-            Group(GroupResource { name_pattern, members, ..}) => {
+            Group(GroupResource { name_pattern, members, options, ..}) => {
                 let member_resources = members.iter().map(|m| generate_member_resource_ident(name_pattern, &m.to_string())).collect::<Vec<_>>();
                 let group_ident = format_ident!("{}", crate::resource::output::generate_group_name(name_pattern));
                 let enum_ident = format_ident!("{}", generate_enum_name(name_pattern));
@@ -69,6 +219,41 @@ impl ToTokens for Model {
                     }.into()).unwrap(),
                     react_to_all: false
                 });
+                if options.count {
+                    // Daemons aren't triggered by other daemons' writes, so the count reacts to
+                    // writes to the members and the group separately, and reads whichever was written.
+                    // The count is read-only, so like derived resources it's written through a
+                    // generic parameter.
+                    let count_ident = format_ident!("{}", generate_count_name(name_pattern));
+                    result.push(Daemon {
+                        resources: member_resources.iter().map(|m| syn::parse(m.into_token_stream().into()).unwrap()).collect(),
+                        function_call: syn::parse(quote! {
+                            (|ops| {
+                                fn count<TO: peregrine::Resource<Data = u32>>(mut ops: peregrine::Ops) {
+                                    ops += peregrine::op! {
+                                        w: TO = 0 #(+ r:#member_resources as u32)*;
+                                    };
+                                }
+                                count::<#count_ident>(ops)
+                            })()
+                        }.into()).unwrap(),
+                        react_to_all: false
+                    });
+                    result.push(Daemon {
+                        resources: vec![syn::parse(group_ident.to_token_stream().into()).unwrap()],
+                        function_call: syn::parse(quote! {
+                            (|ops| {
+                                fn count<TO: peregrine::Resource<Data = u32>>(mut ops: peregrine::Ops) {
+                                    ops += peregrine::op! {
+                                        w: TO = 0 #(+ r:#group_ident.#members as u32)*;
+                                    };
+                                }
+                                count::<#count_ident>(ops)
+                            })()
+                        }.into()).unwrap(),
+                        react_to_all: false
+                    });
+                }
                 result
             }
             _ => vec![]
@@ -99,20 +284,66 @@ impl ToTokens for Model {
                         let result = std::cell::RefCell::new(vec![]);
                         let ops = peregrine::Ops::new(placement, &member, &result, new_order.clone());
                         #function_call;
+                        Ok(result.into_inner())
+                    })
+                )
+            }
+        });
+
+        let recurring = recurring.iter().map(|d| {
+            let RecurringDaemon {
+                period,
+                mut function_call,
+            } = d.clone();
+
+            function_call
+                .args
+                .insert(0, syn::Expr::Verbatim(quote!(ops)));
+
+            quote! {
+                peregrine::internal::macro_prelude::RecurringDaemon::new(
+                    #period,
+                    Box::new(move |placement, member| {
+                        let result = std::cell::RefCell::new(vec![]);
+                        let order = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(placement.get_order()));
+                        let ops = peregrine::Ops::new(placement, &member, &result, order);
+                        #function_call;
                         result.into_inner()
                     })
                 )
             }
         });
 
+        let resource_fallbacks = resources
+            .iter()
+            .map(|r| initial_value_fallback(&r.to_token_stream()))
+            .collect::<Vec<_>>();
+        let exposed_fallbacks = exposed_sources
+            .iter()
+            .map(|r| initial_value_fallback(&r.to_token_stream()))
+            .collect::<Vec<_>>();
+
         let result = quote! {
             #visibility enum #name {}
 
             impl<'o> peregrine::Model<'o> for #name {
                 fn init_history(history: &mut peregrine::internal::macro_prelude::History) {
                     #(history.init::<#resources>();)*
+                    #(history.init::<#exposed_names>();)*
+                    #(history.init::<#derived_names>();)*
                     #(#sub_models::init_history(history);)*
                 }
+                fn describe_resources(descriptors: &mut Vec<peregrine::public::resource::ResourceDescriptor>) {
+                    #(descriptors.push(peregrine::public::resource::ResourceDescriptor::of::<#resources>());)*
+                    #(descriptors.push(peregrine::public::resource::ResourceDescriptor::of::<#exposed_names>());)*
+                    #(descriptors.push(peregrine::public::resource::ResourceDescriptor::of::<#derived_names>());)*
+                    #(#sub_models::describe_resources(descriptors);)*
+                }
+                fn visit_resources<V: peregrine::public::ResourceVisitor>(visitor: &mut V) -> peregrine::anyhow::Result<()> {
+                    #(visitor.visit::<#resources>()?;)*
+                    #(#sub_models::visit_resources(visitor)?;)*
+                    Ok(())
+                }
                 fn init_timelines(
                     time: peregrine::Duration,
                     initial_conditions: &mut peregrine::internal::macro_prelude::InitialConditions,
@@ -120,28 +351,39 @@ impl ToTokens for Model {
                     order: std::sync::Arc<std::sync::atomic::AtomicU64>
                 ) -> peregrine::anyhow::Result<()> {
                     use peregrine::Resource;
+                    // Derived resources and group counts go first, before the initial
+                    // conditions of their sources are taken.
+                    #(
+                        if !timelines.contains_resource::<#derived_names>() {
+                            let initial_value = #derived_initial_values;
+                            timelines.init_for_resource::<#derived_names>(
+                                time,
+                                peregrine::internal::macro_prelude::InitialConditionOp::new(
+                                    time,
+                                    initial_value
+                                )
+                            );
+                        }
+                    )*
+
+                    #(
+                        if !timelines.contains_resource::<#count_names>() {
+                            let initial_value = #count_initial_values;
+                            timelines.init_for_resource::<#count_names>(
+                                time,
+                                peregrine::internal::macro_prelude::InitialConditionOp::new(
+                                    time,
+                                    initial_value
+                                )
+                            );
+                        }
+                    )*
+
                     #(
                         if !timelines.contains_resource::<#resources>() {
                             let initial_value = match initial_conditions.take::<#resources>() {
                                 Some(value) => value,
-                                None => if let Some(def) = <#resources as peregrine::Resource>::initial_condition() {
-                                    def
-                                } else {
-                                    let type_default = peregrine::internal::macro_prelude::spez::spez! {
-                                        for #resources::Unit;
-                                        match<T: peregrine::Resource> T where T::Data: Default -> Option<T::Data> {
-                                            Some(T::Data::default())
-                                        }
-                                        match<T> T -> Option<<#resources as peregrine::Resource>::Data> {
-                                            None
-                                        }
-                                    };
-                                    if let Some(td) = type_default {
-                                        td
-                                    } else {
-                                        peregrine::anyhow::bail!("No initial condition provided for resource {}.\nEither implement Default or provide a value to initial_conditions! or resource!/model!.", #resources::LABEL)
-                                    }
-                                }
+                                None => #resource_fallbacks
                             };
                             timelines.init_for_resource::<#resources>(
                                 time,
@@ -153,6 +395,22 @@ impl ToTokens for Model {
                         }
                     )*
 
+                    #(
+                        if !timelines.contains_resource::<#exposed_names>() {
+                            let initial_value = match initial_conditions.get::<#exposed_sources>() {
+                                Some(value) => value.clone(),
+                                None => #exposed_fallbacks
+                            };
+                            timelines.init_for_resource::<#exposed_names>(
+                                time,
+                                peregrine::internal::macro_prelude::InitialConditionOp::new(
+                                    time,
+                                    initial_value
+                                )
+                            );
+                        }
+                    )*
+
                     #(
                         let new_order = order.clone();
                         timelines.add_reactive_daemon(
@@ -161,6 +419,13 @@ impl ToTokens for Model {
                         );
                     )*
 
+                    #(
+                        timelines.add_recurring_daemon(
+                            peregrine::internal::macro_prelude::peregrine_macros::random_u64!(),
+                            #recurring
+                        )?;
+                    )*
+
                     #(#sub_models::init_timelines(time, initial_conditions, timelines, order.clone())?;)*
 
                     Ok(())
@@ -168,8 +433,35 @@ impl ToTokens for Model {
             }
 
             #(#new_resources)*
+            #(#exposed_definitions)*
+            #(#derived_definitions)*
         };
 
         tokens.append_all(result);
     }
 }
+
+/// Generates the initial value of a resource that wasn't given one in `initial_conditions!`,
+/// from its declared default or its type's [Default] impl.
+fn initial_value_fallback(resource: &TokenStream) -> TokenStream {
+    quote! {
+        if let Some(def) = <#resource as peregrine::Resource>::initial_condition() {
+            def
+        } else {
+            let type_default = peregrine::internal::macro_prelude::spez::spez! {
+                for #resource::Unit;
+                match<T: peregrine::Resource> T where T::Data: Default -> Option<T::Data> {
+                    Some(T::Data::default())
+                }
+                match<T> T -> Option<<#resource as peregrine::Resource>::Data> {
+                    None
+                }
+            };
+            if let Some(td) = type_default {
+                td
+            } else {
+                peregrine::anyhow::bail!("No initial condition provided for resource {}.\nEither implement Default or provide a value to initial_conditions! or resource!/model!.", #resource::LABEL)
+            }
+        }
+    }
+}
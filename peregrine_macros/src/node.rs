@@ -1,5 +1,5 @@
 use proc_macro2::{Ident, TokenStream};
-use quote::quote;
+use quote::{format_ident, quote};
 
 pub struct Node {
     pub name: Ident,
@@ -62,6 +62,98 @@ impl Node {
             .chain(read_write_types.iter())
             .collect::<Vec<_>>();
 
+        let previous_reads = read_write_responses
+            .iter()
+            .map(|r| format_ident!("{r}_previous"))
+            .collect::<Vec<_>>();
+        let raw_read_onlys = read_only_responses
+            .iter()
+            .map(|r| format_ident!("{r}_raw"))
+            .collect::<Vec<_>>();
+
+        // Sentinel writes are stored as written, so that replays from the cache can recognize
+        // them, and read-write resources respond with the value that was read in their place.
+        // Write-only resources have no previous value to fall back to, so it is an error.
+        let write_only_sentinel_checks = writes
+            .iter()
+            .zip(&write_only_types)
+            .map(|(write, ty)| {
+                quote! {
+                    if <#ty as Resource>::is_sentinel(&#write) {
+                        peregrine::anyhow::bail!(
+                            "wrote the sentinel value of {} without reading it; use `m:` so the previous value can be kept",
+                            #ty::LABEL
+                        );
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let output_type = quote! { (u64, #writes_name<'o, #(#write_types,)*>) };
+        let response_fns = writes
+            .iter()
+            .map(|w| format_ident!("{w}_response"))
+            .collect::<Vec<_>>();
+        let response_bodies = writes
+            .iter()
+            .zip(&write_types)
+            .enumerate()
+            .map(|(i, (write, ty))| match i.checked_sub(write_only_types.len()) {
+                None => quote! { (hash, writes.#write) },
+                Some(j) => {
+                    let previous = &read_write_responses[j];
+                    quote! {
+                        if <#ty as Resource>::HAS_SENTINEL {
+                            let time_as_epoch = duration_to_epoch(
+                                unsafe {
+                                    (*self.grounding_result.get()).expect("expected grounding result to be present").expect("expected grounding result to be ok")
+                                }.when
+                            );
+                            if <#ty as Resource>::is_sentinel(&<#ty as Resource>::Data::from_read(writes.#write, time_as_epoch)) {
+                                return match unsafe { (*self.reads.get()).#previous } {
+                                    Some(Ok(previous)) => previous,
+                                    _ => unreachable!("expected the value read before a sentinel write to be present"),
+                                };
+                            }
+                        }
+                        (hash, writes.#write)
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Monotonic writes are checked against the value that was read. Write-only resources
+        // have no previous value to check against, so it is an error.
+        let monotonic_checks = writes
+            .iter()
+            .zip(&write_types)
+            .enumerate()
+            .map(|(i, (write, ty))| match i.checked_sub(write_only_types.len()) {
+                None => quote! {
+                    if <#ty as Resource>::MONOTONIC {
+                        peregrine::anyhow::bail!(
+                            "wrote to monotonic resource {} without reading it; use `m:` so the write can be checked",
+                            #ty::LABEL
+                        );
+                    }
+                },
+                Some(j) => {
+                    let previous = &previous_reads[j];
+                    quote! {
+                        if <#ty as Resource>::MONOTONIC && !<#ty as Resource>::is_sentinel(&#write) {
+                            let previous = <#ty as Resource>::Data::from_read(#previous, time_as_epoch);
+                            if !<#ty as Resource>::is_monotonic_step(&previous, &#write) {
+                                peregrine::anyhow::bail!(
+                                    "write to monotonic resource {} goes against its order",
+                                    #ty::LABEL
+                                );
+                            }
+                        }
+                    }
+                }
+            })
+            .collect::<Vec<_>>();
+
         let first_write_type = write_types[0];
         let all_but_one_write_type = &write_types[1..];
 
@@ -86,7 +178,9 @@ impl Node {
 
                 body: B,
                 reads: UnsafeSyncCell<#reads_name<'o, #(#read_types,)*>>,
-                grounding_result: UnsafeSyncCell<Option<InternalResult<DenseTime>>>
+                grounding_result: UnsafeSyncCell<Option<InternalResult<DenseTime>>>,
+                prioritized: bool,
+                retry: Option<(u32, std::time::Duration)>,
             }
 
             #[allow(clippy::unused_unit)]
@@ -98,8 +192,26 @@ impl Node {
                         reads: Default::default(),
                         grounding_result: UnsafeSyncCell::new(placement.get_static().map(Ok)),
                         placement,
+                        prioritized: false,
+                        retry: None,
                     }
                 }
+                /// Set by a leading `#![priority]`.
+                pub fn prioritize(mut self) -> Self {
+                    self.prioritized = true;
+                    self
+                }
+                /// Set by a leading `#![retry(..)]`; see [with_retries][peregrine::internal::exec::with_retries].
+                pub fn retry(mut self, retries: u32, backoff: std::time::Duration) -> Self {
+                    self.retry = Some((retries, backoff));
+                    self
+                }
+                #(
+                    /// The hash and value that downstreams of this write see.
+                    fn #response_fns(&self, (hash, writes): #output_type) -> (u64, <<#write_types as Resource>::Data as Data<'o>>::Read) {
+                        #response_bodies
+                    }
+                )*
                 fn run_continuations(&self, mut state: parking_lot::MutexGuard<OperationState<(u64, #writes_name<'o, #(#write_types,)*>), #continuations_name<'o, #(#write_types,)*>, #downstreams_name<'o, #(#write_types,)*>>>, scope: &rayon::Scope<'s>, timelines: &'s Timelines<'o>, env: ExecEnvironment<'s, 'o>) {
                     let order = self.placement.get_order();
                     let mut swapped_continuations = smallvec::SmallVec::new();
@@ -112,7 +224,8 @@ impl Node {
                     for c in swapped_continuations.drain(start_index..) {
                         match c {
                             #(#continuations_name::#writes(c) => {
-                                scope.spawn(move |s| c.run(output.map(|r| (r.0, r.1.#writes)), order, s, timelines, env.reset()));
+                                let response = output.map(|r| self.#response_fns(r));
+                                scope.spawn(move |s| { let (env, _guard) = env.start_task(); c.run(response, order, s, timelines, env) });
                             })*
                         }
                     }
@@ -120,7 +233,7 @@ impl Node {
                     if env.stack_counter < STACK_LIMIT {
                         match swapped_continuations.remove(0) {
                             #(#continuations_name::#writes(c) => {
-                                c.run(output.map(|r| (r.0, r.1.#writes)), order, scope, timelines, env.increment());
+                                c.run(output.map(|r| self.#response_fns(r)), order, scope, timelines, env.increment());
                             })*
                         }
                     }
@@ -153,13 +266,17 @@ impl Node {
                             if num_requests == 0 && env.stack_counter < STACK_LIMIT {
                                 #read_upstreams.expect("expected upstream to be present").request(continuation, already_registered, scope, timelines, env.increment());
                             } else {
-                                scope.spawn(move |s| #read_upstreams.expect("expected upstream to be present").request(continuation, already_registered, s, timelines, env.reset()));
+                                scope.spawn(move |s| { let (env, _guard) = env.start_task(); #read_upstreams.expect("expected upstream to be present").request(continuation, already_registered, s, timelines, env) });
                             }
                         }
                     )*
                 }
 
-                fn run(&'o self, env: ExecEnvironment<'s, 'o>) -> InternalResult<(u64, #writes_name<'o, #(#write_types,)*>)> {
+                fn run(&'o self, timelines: &Timelines<'o>, env: ExecEnvironment<'s, 'o>) -> InternalResult<(u64, #writes_name<'o, #(#write_types,)*>)> {
+                    if let Some(interrupt) = env.interrupt && interrupt.check() {
+                        return Err(ObservedErrorOutput);
+                    }
+
                     let reads = self.reads.get();
 
                     let (#((#read_response_hashes, #read_responses),)*) = unsafe {
@@ -172,10 +289,12 @@ impl Node {
                         }.when
                     );
 
+                    let (#(#previous_reads,)*) = (#(#read_write_responses,)*);
+                    let (#(#raw_read_onlys,)*) = (#(#read_only_responses,)*);
                     let (#(#read_write_responses,)*) = (#(<#read_write_types as Resource>::Data::from_read(#read_write_responses, time_as_epoch),)*);
                     let (#(#read_only_responses,)*) = (#(<#read_only_types as Resource>::Data::sample(#read_only_responses, time_as_epoch),)*);
 
-                    let hash = {
+                    let hash = peregrine::internal::exec::with_ops_time(time_as_epoch, timelines.start(), || {
                         use std::hash::{Hasher, BuildHasher, Hash};
 
                         let mut state = PeregrineDefaultHashBuilder::default();
@@ -191,23 +310,70 @@ impl Node {
                         )*
 
                         state.finish()
-                    };
+                    });
 
-                    let result = if let Some(#first_write) = env.history.get::<#first_write_type>(hash, time_as_epoch) {
+                    let cached = if env.cache_audit.is_none() #(&& !<#write_types as Resource>::NO_CACHE)* {
+                        let cached = env.history.get::<#first_write_type>(hash, time_as_epoch);
+                        peregrine::internal::diagnostics::cache_lookup(&env, <Self as NodeId>::ID, &[#(#write_types::LABEL,)*], time_as_epoch, cached.is_some());
+                        cached
+                    } else {
+                        None
+                    };
+                    let result = if let Some(#first_write) = cached {
                         #(let #all_but_one_write = env.history.get::<#all_but_one_write_type>(hash, time_as_epoch).expect("expected all write outputs from past run to be written to history");)*
                         Ok((hash, #writes_name {
                             #(#writes),*
                         }))
                     } else {
-                        self.body.call((#(#read_only_responses,)* #(#read_write_responses,)*))
+                        let downstream_count = self.state.lock().downstreams.len();
+                        peregrine::internal::diagnostics::in_operation_span(&env, <Self as NodeId>::ID, &[#(#write_types::LABEL,)*], time_as_epoch, || {
+                            peregrine::internal::exec::with_ops_time(time_as_epoch, timelines.start(), || {
+                                peregrine::internal::exec::with_downstream_count(downstream_count, || {
+                                    peregrine::internal::exec::run_body(timelines, <Self as NodeId>::ID, &[#(#write_types::LABEL,)*], env.operation_timeout.copied(), time_as_epoch, || {
+                                        peregrine::internal::exec::with_retries(
+                                            self.retry,
+                                            (#(#read_only_responses,)* #(#read_write_responses,)*),
+                                            || (
+                                                #(<#read_only_types as Resource>::Data::sample(#raw_read_onlys, time_as_epoch),)*
+                                                #(<#read_write_types as Resource>::Data::from_read(#previous_reads, time_as_epoch),)*
+                                            ),
+                                            |inputs| self.body.call(inputs),
+                                        )
+                                    })
+                                })
+                            })
+                        })
+                            .and_then(|(#(mut #writes,)*)| {
+                                #(#write_only_sentinel_checks)*
+                                #(#monotonic_checks)*
+                                #(
+                                    #writes.apply_float_policy(env.float_policy)
+                                        .with_context(|| format!("invalid write to resource {}", #write_types::LABEL))?;
+                                )*
+                                if let Some(audit) = env.cache_audit {
+                                    #(audit.check::<#write_types>(env.history, hash, &#writes, time_as_epoch);)*
+                                }
+                                Ok((#(#writes,)*))
+                            })
                             .with_context(|| {
                                 format!("occurred at {}", time_as_epoch)
                             })
                             .map(|(#(#writes,)*)| (hash, #writes_name {
-                                #(#writes: env.history.insert::<#write_types>(hash, #writes, time_as_epoch),)*
+                                #(#writes: if <#write_types as Resource>::NO_CACHE {
+                                    timelines.insert_uncached::<#write_types>(self as *const Self as *const u8 as usize, #writes, time_as_epoch)
+                                } else {
+                                    env.history.insert::<#write_types>(hash, #writes, time_as_epoch)
+                                },)*
                             }))
                     };
 
+                    if timelines.is_recording() && let Ok(output) = result {
+                        timelines.record_execution(time_as_epoch, &[#((
+                            #write_types::LABEL,
+                            peregrine::internal::exec::encode_output::<#write_types>(self.#response_fns(output).1, time_as_epoch),
+                        ),)*]);
+                    }
+
                     result.map_err(|e| {
                         env.errors.push(e);
                         ObservedErrorOutput
@@ -238,20 +404,26 @@ impl Node {
             impl<'o, B: #body_function_bound, #resources_generics_decl> Node<'o> for #name<'o, B, #resources_generics_usage> {
                 fn insert_self(&'o self, timelines: &Timelines<'o>, is_daemon: bool) -> Result<()> {
                     let notify_time = self.placement.min();
+                    let address = self as *const Self as *const u8 as usize;
                     #(
-                        let previous = timelines.insert::<#write_types>(self.placement, self, is_daemon);
-                        assert!(!previous.is_empty());
-                        for p in previous {
-                            p.notify_downstreams(notify_time);
+                        if !timelines.is_coalesced::<#write_types>(address) {
+                            let previous = timelines.insert::<#write_types>(self.placement, self, is_daemon);
+                            assert!(!previous.is_empty());
+                            for p in previous {
+                                timelines.notify(p, notify_time);
+                            }
                         }
                     )*
                     Ok(())
                 }
                 fn remove_self(&self, timelines: &Timelines<'o>, is_daemon: bool) -> Result<()> {
+                    let address = self as *const Self as *const u8 as usize;
                     #(
-                        let removed = timelines.remove::<#write_types>(self.placement, is_daemon);
-                        if !removed && !is_daemon {
-                            bail!("Removal failed; could not find self at the expected time.")
+                        if !timelines.is_coalesced::<#write_types>(address) {
+                            let removed = timelines.remove::<#write_types>(self.placement, is_daemon);
+                            if !removed && !is_daemon {
+                                bail!("Removal failed; could not find self at the expected time.")
+                            }
                         }
                     )*
 
@@ -267,6 +439,41 @@ impl Node {
 
                     Ok(())
                 }
+                fn info(&self) -> peregrine::public::activity::OperationInfo {
+                    peregrine::public::activity::OperationInfo {
+                        time: self.placement.into(),
+                        reads: vec![#(peregrine::public::resource::ResourceDescriptor::of::<#read_types>(),)*],
+                        writes: vec![#(peregrine::public::resource::ResourceDescriptor::of::<#write_types>(),)*],
+                    }
+                }
+                fn clear_cache(&self) {
+                    let reads = self.reads.get();
+                    unsafe {
+                        #((*reads).#read_responses = None;)*
+                    }
+                    self.clear_cached_downstreams();
+                }
+                fn cached_hash(&self) -> Option<u64> {
+                    match self.state.lock().status {
+                        OperationStatus::Done(Ok((hash, _))) => Some(hash),
+                        _ => None,
+                    }
+                }
+                fn failed(&self) -> bool {
+                    matches!(self.state.lock().status, OperationStatus::Done(Err(_)))
+                }
+                fn upstreams(&self) -> Vec<(&'static str, usize)> {
+                    let reads = self.reads.get();
+                    let mut result = vec![];
+                    #(
+                        if let Some(upstream) = unsafe { (*reads).#read_upstreams }
+                            && let Some(address) = upstream.source_address()
+                        {
+                            result.push((#read_types::LABEL, address));
+                        }
+                    )*
+                    result
+                }
             }
 
             #[allow(unreachable_code)]
@@ -304,7 +511,7 @@ impl Node {
                     if state.response_counter == 0 {
                         drop(state);
 
-                        let result = self.run(env);
+                        let result = self.run(timelines, env);
 
                         let mut state = self.state.lock();
                         state.status = OperationStatus::Done(result);
@@ -380,7 +587,7 @@ impl Node {
                             if let Ok((_, t)) = value {
                                 if #num_reads == 0 {
                                     drop(state);
-                                    let result = self.run(env);
+                                    let result = self.run(timelines, env);
 
                                     let mut state = self.state.lock();
                                     state.status = OperationStatus::Done(result);
@@ -420,6 +627,7 @@ impl Node {
                     timelines: &'s Timelines<'o>,
                     env: ExecEnvironment<'s, 'o>
                 ) where 'o: 's {
+                    timelines.touch::<R>();
                     let mut state = self.state.lock();
                     if !already_registered {
                         if let Some(d) = continuation.to_downstream() {
@@ -449,7 +657,7 @@ impl Node {
                                 Some(t) => {
                                     if #num_reads == 0 {
                                         drop(state);
-                                        let result = self.run(env);
+                                        let result = self.run(timelines, env);
 
                                         let mut state = self.state.lock();
                                         state.status = OperationStatus::Done(result);
@@ -477,15 +685,14 @@ impl Node {
                         OperationStatus::Done(r) => {
                             drop(state);
                             let send = r.map(|o| {
-                                let value = castaway::match_type!(R::INSTANCE, {
+                                castaway::match_type!(R::INSTANCE, {
                                     #(
                                         #write_types as _ => {
-                                            unsafe { std::mem::transmute_copy(&o.1.#writes) }
+                                            unsafe { std::mem::transmute_copy(&self.#response_fns(o)) }
                                         },
                                     )*
                                     _ => unreachable!()
-                                });
-                                (o.0, value)
+                                })
                             });
                             continuation.run(send, self.placement.get_order(), scope, timelines, env.increment());
                         }
@@ -529,6 +736,10 @@ impl Node {
                     self.state.lock().downstreams.push(wrapped);
                 }
 
+                fn prioritized(&self) -> bool {
+                    self.prioritized
+                }
+
                 fn request_grounding<'s>(
                     &'o self,
                     continuation: GroundingContinuation<'o>,
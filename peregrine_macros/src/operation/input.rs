@@ -1,11 +1,13 @@
-use crate::operation::Op;
 use crate::operation::input::InteractionType::*;
+use crate::operation::{
+    AtEndRead, ExternalRead, LookBackRead, MemberIndex, NextChangeRead, Op, OrRead, Retry,
+    SecondDerivativeRead, SinceRead, SourceRead, WindowRead,
+};
 use derive_more::{Deref, DerefMut};
-use proc_macro2::Ident;
-use quote::format_ident;
+use proc_macro2::{Delimiter, Group, Ident, Punct, Spacing, Span, TokenStream, TokenTree};
+use quote::{format_ident, quote};
 use regex::Regex;
 use std::collections::HashMap;
-use syn::buffer::Cursor;
 use syn::parse::{Parse, ParseStream};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -39,6 +41,8 @@ impl Interactions {
                     ),
                 ));
             }
+            // A resource tagged more than once is still one read and/or write; repeated
+            // assignments in the body just overwrite the local, so the last one wins.
             let existing_mut = self.get_mut(&id).unwrap();
             *existing_mut = existing_mut.merge(ty);
         } else {
@@ -57,20 +61,440 @@ impl InteractionType {
 
 impl Parse for Op {
     fn parse(input_stream: ParseStream) -> syn::Result<Self> {
+        let tokens: TokenStream = input_stream.parse()?;
+        let (attributes, tokens) = strip_attributes(tokens)?;
+
+        let mut op = match split_const_branch(tokens.clone())? {
+            Some(ConstBranch {
+                guard,
+                taken,
+                not_taken,
+            }) => {
+                let mut op = Op::from_body(taken)?;
+                let mut not_taken = Op::from_body(not_taken)?;
+                attributes.apply(&mut not_taken);
+                op.const_branch = Some((guard, Box::new(not_taken)));
+                op
+            }
+            None => Op::from_body(tokens)?,
+        };
+        attributes.apply(&mut op);
+        Ok(op)
+    }
+}
+
+/// The leading `#![...]` attributes of an op body.
+#[derive(Default, Clone)]
+struct OpAttributes {
+    blocking: bool,
+    priority: bool,
+    not_idempotent: bool,
+    retry: Option<Retry>,
+}
+
+impl OpAttributes {
+    fn apply(&self, op: &mut Op) {
+        op.blocking = self.blocking;
+        op.priority = self.priority;
+        op.not_idempotent = self.not_idempotent;
+        op.retry = self.retry.clone();
+    }
+}
+
+/// Removes the leading `#![blocking]`, `#![priority]`, `#![idempotent]`, `#![not_idempotent]`,
+/// and `#![retry(..)]` attributes.
+fn strip_attributes(tokens: TokenStream) -> syn::Result<(OpAttributes, TokenStream)> {
+    let trees = tokens.into_iter().collect::<Vec<_>>();
+    let mut attributes = OpAttributes::default();
+    let mut idempotent = None;
+    let mut rest = &trees[..];
+    while let [
+        TokenTree::Punct(hash),
+        TokenTree::Punct(bang),
+        TokenTree::Group(attribute),
+        ..,
+    ] = rest
+        && hash.as_char() == '#'
+        && bang.as_char() == '!'
+        && attribute.delimiter() == Delimiter::Bracket
+    {
+        if let Some(retry) = parse_retry(attribute)? {
+            if attributes.retry.is_some() {
+                return Err(syn::Error::new(
+                    attribute.span(),
+                    "an op can only have one `retry` attribute",
+                ));
+            }
+            attributes.retry = Some(retry);
+            rest = &rest[3..];
+            continue;
+        }
+        let declared = match attribute.stream().to_string().as_str() {
+            "blocking" => {
+                attributes.blocking = true;
+                None
+            }
+            "priority" => {
+                attributes.priority = true;
+                None
+            }
+            "idempotent" => Some(true),
+            "not_idempotent" => Some(false),
+            other => {
+                return Err(syn::Error::new(
+                    attribute.span(),
+                    format!("unknown op attribute `{other}`"),
+                ));
+            }
+        };
+        if let Some(declared) = declared {
+            if idempotent.is_some_and(|i| i != declared) {
+                return Err(syn::Error::new(
+                    attribute.span(),
+                    "an op can't be both `idempotent` and `not_idempotent`",
+                ));
+            }
+            idempotent = Some(declared);
+        }
+        rest = &rest[3..];
+    }
+    attributes.not_idempotent = idempotent == Some(false);
+    Ok((attributes, rest.iter().cloned().collect()))
+}
+
+/// Parses `retry(RETRIES)` or `retry(RETRIES, BACKOFF)`, or returns `None` for other attributes.
+fn parse_retry(attribute: &Group) -> syn::Result<Option<Retry>> {
+    let trees = attribute.stream().into_iter().collect::<Vec<_>>();
+    let [TokenTree::Ident(retry), TokenTree::Group(arguments)] = &trees[..] else {
+        return Ok(None);
+    };
+    if retry != "retry" || arguments.delimiter() != Delimiter::Parenthesis {
+        return Ok(None);
+    }
+
+    let arguments = arguments.stream().into_iter().collect::<Vec<_>>();
+    let (retries, backoff) = match arguments
+        .iter()
+        .position(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == ','))
+    {
+        Some(comma) => (
+            arguments[..comma].iter().cloned().collect::<TokenStream>(),
+            Some(
+                arguments[comma + 1..]
+                    .iter()
+                    .cloned()
+                    .collect::<TokenStream>(),
+            ),
+        ),
+        None => (arguments.into_iter().collect(), None),
+    };
+    if retries.is_empty() || backoff.as_ref().is_some_and(|b| b.is_empty()) {
+        return Err(syn::Error::new(
+            retry.span(),
+            "expected `#![retry(RETRIES)]` or `#![retry(RETRIES, BACKOFF)]`",
+        ));
+    }
+    Ok(Some(Retry { retries, backoff }))
+}
+
+/// A top-level `if const GUARD { .. } else { .. }` in an op body.
+///
+/// The guard is evaluated when the op is constructed, and the op is built from only the
+/// branch that was taken. So resources used in only one branch are not read when the other is taken.
+struct ConstBranch {
+    guard: TokenStream,
+    taken: TokenStream,
+    not_taken: TokenStream,
+}
+
+fn split_const_branch(tokens: TokenStream) -> syn::Result<Option<ConstBranch>> {
+    let trees = tokens.into_iter().collect::<Vec<_>>();
+
+    let Some(start) = find_if_const(&trees) else {
+        return Ok(None);
+    };
+
+    let Some(then_index) = trees[start + 2..]
+        .iter()
+        .position(|tt| matches!(tt, TokenTree::Group(g) if g.delimiter() == Delimiter::Brace))
+        .map(|i| i + start + 2)
+    else {
+        return Err(syn::Error::new(
+            trees[start].span(),
+            "expected a block after the `if const` guard",
+        ));
+    };
+
+    let guard = trees[start + 2..then_index]
+        .iter()
+        .cloned()
+        .collect::<TokenStream>();
+    if guard.is_empty() {
+        return Err(syn::Error::new(
+            trees[start].span(),
+            "expected a guard expression after `if const`",
+        ));
+    }
+    let then_block = trees[then_index].clone();
+
+    let (else_block, end) = match trees.get(then_index + 1) {
+        Some(TokenTree::Ident(i)) if i == "else" => match trees.get(then_index + 2) {
+            Some(block @ TokenTree::Group(g)) if g.delimiter() == Delimiter::Brace => {
+                (block.clone(), then_index + 3)
+            }
+            _ => {
+                return Err(syn::Error::new(
+                    i.span(),
+                    "`if const` only supports a plain `else` block",
+                ));
+            }
+        },
+        _ => (
+            TokenTree::Group(proc_macro2::Group::new(Delimiter::Brace, quote! {})),
+            then_index + 1,
+        ),
+    };
+
+    let prefix = &trees[..start];
+    let suffix = &trees[end..];
+
+    if let Some(second) = find_if_const(suffix) {
+        return Err(syn::Error::new(
+            suffix[second].span(),
+            "only one `if const` is allowed per op",
+        ));
+    }
+
+    let join = |block: TokenTree| {
+        prefix
+            .iter()
+            .cloned()
+            .chain(std::iter::once(block))
+            .chain(suffix.iter().cloned())
+            .collect::<TokenStream>()
+    };
+
+    Ok(Some(ConstBranch {
+        guard,
+        taken: join(then_block),
+        not_taken: join(else_block),
+    }))
+}
+
+/// Finds `group[const INDEX]` in an op and replaces it with a `$member` placeholder.
+///
+/// Every occurrence in the op must name the same group and index.
+pub fn split_member_index(tokens: TokenStream) -> syn::Result<Option<MemberIndex>> {
+    let mut found = None;
+    let body = replace_member_index(tokens, &mut found)?;
+    Ok(found.map(|(group, index)| MemberIndex { group, index, body }))
+}
+
+fn replace_member_index(
+    tokens: TokenStream,
+    found: &mut Option<(Ident, TokenStream)>,
+) -> syn::Result<TokenStream> {
+    let trees = tokens.into_iter().collect::<Vec<_>>();
+    let mut result = vec![];
+    let mut i = 0;
+    while i < trees.len() {
+        if let [TokenTree::Ident(group), TokenTree::Group(brackets), ..] = &trees[i..]
+            && brackets.delimiter() == Delimiter::Bracket
+            && let Some(TokenTree::Ident(c)) = brackets.stream().into_iter().next()
+            && c == "const"
+        {
+            let index = brackets
+                .stream()
+                .into_iter()
+                .skip(1)
+                .collect::<TokenStream>();
+            if index.is_empty() {
+                return Err(syn::Error::new(
+                    c.span(),
+                    "expected an index expression after `const`",
+                ));
+            }
+            match found {
+                Some((existing_group, existing_index))
+                    if existing_group != group
+                        || existing_index.to_string() != index.to_string() =>
+                {
+                    return Err(syn::Error::new(
+                        group.span(),
+                        "only one group member can be indexed with `[const ..]` per op",
+                    ));
+                }
+                Some(_) => {}
+                None => *found = Some((group.clone(), index)),
+            }
+            result.push(TokenTree::Punct(Punct::new('$', Spacing::Alone)));
+            result.push(TokenTree::Ident(Ident::new("member", group.span())));
+            i += 2;
+            continue;
+        }
+
+        result.push(match &trees[i] {
+            TokenTree::Group(g) => {
+                let mut group = Group::new(g.delimiter(), replace_member_index(g.stream(), found)?);
+                group.set_span(g.span());
+                TokenTree::Group(group)
+            }
+            other => other.clone(),
+        });
+        i += 1;
+    }
+    Ok(result.into_iter().collect())
+}
+
+fn find_if_const(trees: &[TokenTree]) -> Option<usize> {
+    trees.windows(2).position(|w| {
+        matches!((&w[0], &w[1]), (TokenTree::Ident(a), TokenTree::Ident(b)) if a == "if" && b == "const")
+    })
+}
+
+impl Op {
+    fn from_body(tokens: TokenStream) -> syn::Result<Self> {
         let mut interactions = Interactions::new();
 
-        let read_regex =
-            Regex::new(r"[^[:alpha:][:digit:]_]r[[:space:]]*:[[:space:]]*(?<ident>[a-zA-Z0-9_]+)")
-                .unwrap();
+        let read_regex = Regex::new(
+            r"[^[:alpha:][:digit:]_](?:r|ref)[[:space:]]*:[[:space:]]*(?<ident>[a-zA-Z0-9_]+)",
+        )
+        .unwrap();
         let write_regex =
             Regex::new(r"[^[:alpha:][:digit:]_]w[[:space:]]*:[[:space:]]*(?<ident>[a-zA-Z0-9_]+)")
                 .unwrap();
-        let read_write_regex =
-            Regex::new(r"[^[:alpha:][:digit:]_]m[[:space:]]*:[[:space:]]*(?<ident>[a-zA-Z0-9_]+)")
-                .unwrap();
-        let tag_only_regex = Regex::new(r"([^[:alpha:][:digit:]_])(r|w|m)[[:space:]]*:").unwrap();
+        let read_write_regex = Regex::new(
+            r"[^[:alpha:][:digit:]_](?:m|mut)[[:space:]]*:[[:space:]]*(?<ident>[a-zA-Z0-9_]+)",
+        )
+        .unwrap();
+        let tag_only_regex =
+            Regex::new(r"([^[:alpha:][:digit:]_])(r|w|m|ref|mut)[[:space:]]*:").unwrap();
+
+        let uses_time = contains_ident(tokens.clone(), "ops_time");
+        let uses_elapsed = contains_ident(tokens.clone(), "ops_elapsed");
+
+        let tokens = expand_cas(tokens)?;
+        let mut logs = vec![];
+        let tokens = expand_emits(tokens, &mut logs)?;
+        let tokens = expand_latches(tokens)?;
+
+        let (tokens, windows) = extract_reads(tokens, "range", true, |read, windows| {
+            let alias = format_ident!("__peregrine_range_{}_{}", windows.len(), read.resource);
+            windows.push(WindowRead {
+                alias: alias.clone(),
+                resource: read.resource,
+                length: read.arguments,
+            });
+            Ok(quote! { #alias })
+        })?;
+        let (tokens, sources) = extract_reads(tokens, "source", false, |read, sources| {
+            let alias = format_ident!("__peregrine_source_{}", read.resource);
+            if !sources.iter().any(|s: &SourceRead| s.alias == alias) {
+                sources.push(SourceRead {
+                    alias: alias.clone(),
+                    resource: read.resource,
+                });
+            }
+            Ok(quote! { #alias })
+        })?;
+        let (tokens, next_changes) =
+            extract_reads(tokens, "next_change", false, |read, next_changes| {
+                let alias = format_ident!("__peregrine_next_change_{}", read.resource);
+                if !next_changes
+                    .iter()
+                    .any(|n: &NextChangeRead| n.alias == alias)
+                {
+                    next_changes.push(NextChangeRead {
+                        alias: alias.clone(),
+                        resource: read.resource,
+                    });
+                }
+                Ok(quote! { #alias })
+            })?;
+        let (tokens, at_ends) = extract_reads(tokens, "at_end", false, |read, at_ends| {
+            let alias = format_ident!("__peregrine_at_end_{}", read.resource);
+            if !at_ends.iter().any(|a: &AtEndRead| a.alias == alias) {
+                at_ends.push(AtEndRead {
+                    alias: alias.clone(),
+                    resource: read.resource,
+                });
+            }
+            Ok(quote! { #alias })
+        })?;
+        let (tokens, look_backs) = extract_reads(tokens, "", true, |read, look_backs| {
+            let alias = format_ident!(
+                "__peregrine_look_back_{}_{}",
+                look_backs.len(),
+                read.resource
+            );
+            look_backs.push(LookBackRead {
+                alias: alias.clone(),
+                resource: read.resource,
+                offset: read.arguments,
+            });
+            Ok(quote! { #alias })
+        })?;
+        let (tokens, second_derivatives) =
+            extract_reads(tokens, "d2/dt2", false, |read, second_derivatives| {
+                let alias = format_ident!("__peregrine_second_derivative_{}", read.resource);
+                if !second_derivatives
+                    .iter()
+                    .any(|d: &SecondDerivativeRead| d.alias == alias)
+                {
+                    second_derivatives.push(SecondDerivativeRead {
+                        alias: alias.clone(),
+                        resource: read.resource,
+                    });
+                }
+                Ok(quote! { #alias })
+            })?;
+        let (tokens, externals) = extract_reads(tokens, "external", false, |read, externals| {
+            let alias = format_ident!("__peregrine_external_{}", read.resource);
+            if !externals.iter().any(|e: &ExternalRead| e.alias == alias) {
+                externals.push(ExternalRead {
+                    alias: alias.clone(),
+                    name: read.resource,
+                });
+            }
+            Ok(quote! { #alias })
+        })?;
+        let (tokens, mut sinces) = extract_sinces(tokens, "since")?;
+        let (tokens, changed_sinces) = extract_sinces(tokens, "changed_since")?;
+        sinces.extend(changed_sinces);
+        let (tokens, ors) = extract_reads(tokens, "or", true, |read, ors| {
+            if read.arguments.is_empty() {
+                return Err(syn::Error::new(
+                    read.span,
+                    "expected `ref or(DEFAULT): resource`",
+                ));
+            }
+            let alias = format_ident!("__peregrine_or_{}", read.resource);
+            if !ors.iter().any(|o: &OrRead| o.alias == alias) {
+                ors.push(OrRead {
+                    alias: alias.clone(),
+                    resource: read.resource,
+                });
+            }
+            let default = read.arguments;
+            Ok(quote! { (#alias.unwrap_or_else(|| #default)) })
+        })?;
 
-        let mut input = input_stream.to_string();
+        let aliases = windows
+            .iter()
+            .map(|w| &w.alias)
+            .chain(sources.iter().map(|s| &s.alias))
+            .chain(next_changes.iter().map(|n| &n.alias))
+            .chain(at_ends.iter().map(|a| &a.alias))
+            .chain(look_backs.iter().map(|l| &l.alias))
+            .chain(second_derivatives.iter().map(|d| &d.alias))
+            .chain(externals.iter().map(|e| &e.alias))
+            .chain(sinces.iter().map(|s| &s.alias))
+            .chain(ors.iter().map(|o| &o.alias));
+        for alias in aliases {
+            interactions.insert(alias.clone(), Read)?;
+        }
+
+        let mut input = tokens.to_string();
         input.insert(0, ' ');
 
         for cap in read_regex.captures_iter(&input) {
@@ -83,6 +507,22 @@ impl Parse for Op {
             interactions.insert(format_ident!("{}", cap["ident"]), ReadWrite)?;
         }
 
+        // The value at the end would be this op's own write, which it is still waiting on.
+        for at_end in &at_ends {
+            if interactions
+                .get(&at_end.resource)
+                .is_some_and(|ty| *ty != Read)
+            {
+                return Err(syn::Error::new(
+                    at_end.resource.span(),
+                    format!(
+                        "an op can't read `ref at_end: {0}` and also write to {0}",
+                        at_end.resource
+                    ),
+                ));
+            }
+        }
+
         let mut reads = vec![];
         let mut writes = vec![];
         let mut read_writes = vec![];
@@ -97,14 +537,405 @@ impl Parse for Op {
 
         let body = tag_only_regex.replace_all(&input, "$1").parse()?;
 
-        input_stream.step(|_| Ok(((), Cursor::empty())))?;
-
         Ok(Op {
             reads,
             writes,
             read_writes,
             body,
             internal: false,
+            uses_time,
+            uses_elapsed,
+            const_branch: None,
+            blocking: false,
+            priority: false,
+            not_idempotent: false,
+            retry: None,
+            windows,
+            sources,
+            sinces,
+            ors,
+            next_changes,
+            at_ends,
+            look_backs,
+            second_derivatives,
+            externals,
+            logs,
         })
     }
 }
+
+fn contains_ident(tokens: TokenStream, name: &str) -> bool {
+    tokens.into_iter().any(|tt| match tt {
+        TokenTree::Ident(i) => i == name,
+        TokenTree::Group(g) => contains_ident(g.stream(), name),
+        _ => false,
+    })
+}
+
+/// A read written as `ref MARKER: resource`, or `ref MARKER(ARGUMENTS): resource`.
+struct MarkedRead {
+    /// Where the marker is, for errors.
+    span: Span,
+    /// The arguments, with any reads inside them already replaced. Empty if there are none.
+    arguments: TokenStream,
+    resource: Ident,
+}
+
+/// Replaces each read marked with `marker` in `tokens`, including in nested groups, with the
+/// tokens returned by `replace`, and returns the reads that `replace` collected.
+///
+/// The marker is matched against the text of the tokens between `ref` and the colon, so
+/// multi-token markers like `d2/dt2` work. If `with_arguments` is set, the marker must be
+/// followed by a parenthesized group.
+fn extract_reads<T>(
+    tokens: TokenStream,
+    marker: &str,
+    with_arguments: bool,
+    mut replace: impl FnMut(MarkedRead, &mut Vec<T>) -> syn::Result<TokenStream>,
+) -> syn::Result<(TokenStream, Vec<T>)> {
+    fn walk<T>(
+        tokens: TokenStream,
+        marker: &str,
+        with_arguments: bool,
+        replace: &mut impl FnMut(MarkedRead, &mut Vec<T>) -> syn::Result<TokenStream>,
+        reads: &mut Vec<T>,
+    ) -> syn::Result<TokenStream> {
+        let trees = tokens.into_iter().collect::<Vec<_>>();
+        let mut result = TokenStream::new();
+        let mut i = 0;
+        while i < trees.len() {
+            if let Some((mut read, length)) = match_read(&trees[i..], marker, with_arguments) {
+                read.arguments = walk(read.arguments, marker, with_arguments, replace, reads)?;
+                result.extend(replace(read, reads)?);
+                i += length;
+                continue;
+            }
+
+            result.extend([match &trees[i] {
+                TokenTree::Group(g) => {
+                    let stream = walk(g.stream(), marker, with_arguments, replace, reads)?;
+                    let mut group = Group::new(g.delimiter(), stream);
+                    group.set_span(g.span());
+                    TokenTree::Group(group)
+                }
+                other => other.clone(),
+            }]);
+            i += 1;
+        }
+        Ok(result)
+    }
+
+    let mut reads = vec![];
+    let tokens = walk(tokens, marker, with_arguments, &mut replace, &mut reads)?;
+    Ok((tokens, reads))
+}
+
+/// Matches a read marked with `marker` at the start of `trees`, and returns it with the
+/// number of tokens it spans.
+fn match_read(
+    trees: &[TokenTree],
+    marker: &str,
+    with_arguments: bool,
+) -> Option<(MarkedRead, usize)> {
+    let Some(TokenTree::Ident(r)) = trees.first() else {
+        return None;
+    };
+    if r != "ref" {
+        return None;
+    }
+    let mut span = r.span();
+    let mut length = 1;
+    let mut text = String::new();
+    while text.len() < marker.len() {
+        let tree = trees.get(length)?;
+        if let TokenTree::Group(_) = tree {
+            return None;
+        }
+        if text.is_empty() {
+            span = tree.span();
+        }
+        text += &tree.to_string();
+        length += 1;
+    }
+    if text != marker {
+        return None;
+    }
+
+    let arguments = match trees.get(length)? {
+        TokenTree::Group(g) if with_arguments && g.delimiter() == Delimiter::Parenthesis => {
+            length += 1;
+            g.stream()
+        }
+        _ if with_arguments => return None,
+        _ => TokenStream::new(),
+    };
+    match &trees[length..] {
+        [TokenTree::Punct(colon), TokenTree::Ident(resource), ..]
+            if colon.as_char() == ':' && colon.spacing() == Spacing::Alone =>
+        {
+            let read = MarkedRead {
+                span,
+                arguments,
+                resource: resource.clone(),
+            };
+            Some((read, length + 2))
+        }
+        _ => None,
+    }
+}
+
+/// Replaces each `cas: resource, EXPECTED => NEW` with a block that reads and writes the resource,
+/// writes `NEW` only if it equals `EXPECTED`, and evaluates to whether it did.
+///
+/// `NEW` extends to the end of the statement.
+fn expand_cas(tokens: TokenStream) -> syn::Result<TokenStream> {
+    let trees = tokens.into_iter().collect::<Vec<_>>();
+    let mut result = vec![];
+    let mut i = 0;
+    while i < trees.len() {
+        if let [
+            TokenTree::Ident(cas),
+            TokenTree::Punct(colon),
+            TokenTree::Ident(resource),
+            TokenTree::Punct(comma),
+            ..,
+        ] = &trees[i..]
+            && cas == "cas"
+            && colon.as_char() == ':'
+            && colon.spacing() == Spacing::Alone
+            && comma.as_char() == ','
+        {
+            let rest = &trees[i + 4..];
+            let end = rest
+                .iter()
+                .position(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == ';'))
+                .unwrap_or(rest.len());
+            let arrow = rest[..end].windows(2).position(|w| {
+                matches!((&w[0], &w[1]), (TokenTree::Punct(a), TokenTree::Punct(b))
+                    if a.as_char() == '=' && a.spacing() == Spacing::Joint && b.as_char() == '>')
+            });
+            let Some(arrow) = arrow else {
+                return Err(syn::Error::new(
+                    cas.span(),
+                    "expected `cas: resource, EXPECTED => NEW`",
+                ));
+            };
+            let expected = rest[..arrow].iter().cloned().collect::<TokenStream>();
+            let new = rest[arrow + 2..end]
+                .iter()
+                .cloned()
+                .collect::<TokenStream>();
+            if expected.is_empty() || new.is_empty() {
+                return Err(syn::Error::new(
+                    cas.span(),
+                    "expected `cas: resource, EXPECTED => NEW`",
+                ));
+            }
+            let expected = expand_cas(expected)?;
+            let new = expand_cas(new)?;
+            let block = quote! {
+                {
+                    let __peregrine_swapped = m: #resource == (#expected);
+                    if __peregrine_swapped {
+                        #resource = ::core::convert::Into::into(#new);
+                    }
+                    __peregrine_swapped
+                }
+            };
+            result.push(TokenTree::Group(Group::new(Delimiter::Brace, block)));
+            i += 4 + end;
+            continue;
+        }
+
+        result.push(match &trees[i] {
+            TokenTree::Group(g) => {
+                let mut group = Group::new(g.delimiter(), expand_cas(g.stream())?);
+                group.set_span(g.span());
+                TokenTree::Group(group)
+            }
+            other => other.clone(),
+        });
+        i += 1;
+    }
+    Ok(result.into_iter().collect())
+}
+
+/// Replaces each `emit: log <- EVENT` with a push of `EVENT` onto the op's write to the event
+/// log resource, and records the log in `logs`.
+///
+/// `EVENT` extends to the end of the statement.
+fn expand_emits(tokens: TokenStream, logs: &mut Vec<Ident>) -> syn::Result<TokenStream> {
+    let trees = tokens.into_iter().collect::<Vec<_>>();
+    let mut result = vec![];
+    let mut i = 0;
+    while i < trees.len() {
+        if let [
+            TokenTree::Ident(emit),
+            TokenTree::Punct(colon),
+            TokenTree::Ident(resource),
+            TokenTree::Punct(lt),
+            TokenTree::Punct(minus),
+            ..,
+        ] = &trees[i..]
+            && emit == "emit"
+            && colon.as_char() == ':'
+            && colon.spacing() == Spacing::Alone
+            && lt.as_char() == '<'
+            && minus.as_char() == '-'
+        {
+            let rest = &trees[i + 5..];
+            let end = rest
+                .iter()
+                .position(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == ';'))
+                .unwrap_or(rest.len());
+            let event = rest[..end].iter().cloned().collect::<TokenStream>();
+            if event.is_empty() {
+                return Err(syn::Error::new(
+                    emit.span(),
+                    "expected `emit: log <- EVENT`",
+                ));
+            }
+            let event = expand_emits(event, logs)?;
+            if !logs.contains(resource) {
+                logs.push(resource.clone());
+            }
+            result.extend(quote! {
+                w: #resource.push(#event)
+            });
+            i += 5 + end;
+            continue;
+        }
+
+        result.push(match &trees[i] {
+            TokenTree::Group(g) => {
+                let mut group = Group::new(g.delimiter(), expand_emits(g.stream(), logs)?);
+                group.set_span(g.span());
+                TokenTree::Group(group)
+            }
+            other => other.clone(),
+        });
+        i += 1;
+    }
+    Ok(result.into_iter().collect())
+}
+
+/// Replaces each `latch: flag = CONDITION` with a read and write of the boolean resource that
+/// sets it if `CONDITION` is true and otherwise leaves it as it was, and each `reset: flag;`
+/// statement with a write of `false`.
+///
+/// `CONDITION` extends to the end of the statement.
+fn expand_latches(tokens: TokenStream) -> syn::Result<TokenStream> {
+    let trees = tokens.into_iter().collect::<Vec<_>>();
+    let mut result = vec![];
+    let mut i = 0;
+    while i < trees.len() {
+        if let [
+            TokenTree::Ident(latch),
+            TokenTree::Punct(colon),
+            TokenTree::Ident(resource),
+            TokenTree::Punct(eq),
+            ..,
+        ] = &trees[i..]
+            && latch == "latch"
+            && colon.as_char() == ':'
+            && colon.spacing() == Spacing::Alone
+            && eq.as_char() == '='
+            && eq.spacing() == Spacing::Alone
+        {
+            let rest = &trees[i + 4..];
+            let end = rest
+                .iter()
+                .position(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == ';'))
+                .unwrap_or(rest.len());
+            let condition = rest[..end].iter().cloned().collect::<TokenStream>();
+            if condition.is_empty() {
+                return Err(syn::Error::new(
+                    latch.span(),
+                    "expected `latch: flag = CONDITION`",
+                ));
+            }
+            let condition = expand_latches(condition)?;
+            result.extend(quote! {
+                m: #resource |= #condition
+            });
+            i += 4 + end;
+            continue;
+        }
+
+        if let [
+            TokenTree::Ident(reset),
+            TokenTree::Punct(colon),
+            TokenTree::Ident(resource),
+            TokenTree::Punct(semi),
+            ..,
+        ] = &trees[i..]
+            && reset == "reset"
+            && colon.as_char() == ':'
+            && colon.spacing() == Spacing::Alone
+            && semi.as_char() == ';'
+        {
+            result.extend(quote! {
+                w: #resource = false
+            });
+            i += 3;
+            continue;
+        }
+
+        result.push(match &trees[i] {
+            TokenTree::Group(g) => {
+                let mut group = Group::new(g.delimiter(), expand_latches(g.stream())?);
+                group.set_span(g.span());
+                TokenTree::Group(group)
+            }
+            other => other.clone(),
+        });
+        i += 1;
+    }
+    Ok(result.into_iter().collect())
+}
+
+/// Replaces each `ref since(START): resource` or `ref since(START, LIMIT): resource` with
+/// the writes since `START`, read through a generated identifier.
+///
+/// With the `changed_since` marker, each `ref changed_since(..): resource` is read the same
+/// way, but is replaced with whether there were any writes.
+fn extract_sinces(tokens: TokenStream, marker: &str) -> syn::Result<(TokenStream, Vec<SinceRead>)> {
+    extract_reads(tokens, marker, true, |read, sinces| {
+        let arguments = read.arguments.into_iter().collect::<Vec<_>>();
+        let (start, limit) = match arguments
+            .iter()
+            .position(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == ','))
+        {
+            Some(comma) => (
+                arguments[..comma].iter().cloned().collect::<TokenStream>(),
+                Some(
+                    arguments[comma + 1..]
+                        .iter()
+                        .cloned()
+                        .collect::<TokenStream>(),
+                ),
+            ),
+            None => (arguments.into_iter().collect(), None),
+        };
+        if start.is_empty() || limit.as_ref().is_some_and(|l| l.is_empty()) {
+            return Err(syn::Error::new(
+                read.span,
+                format!(
+                    "expected `ref {marker}(START): resource` or `ref {marker}(START, LIMIT): resource`"
+                ),
+            ));
+        }
+
+        let alias = format_ident!("__peregrine_{}_{}_{}", marker, sinces.len(), read.resource);
+        sinces.push(SinceRead {
+            alias: alias.clone(),
+            resource: read.resource,
+            limit,
+        });
+        Ok(if marker == "since" {
+            quote! { (#alias.since(#start)?) }
+        } else {
+            quote! { (!#alias.since(#start)?.is_empty()) }
+        })
+    })
+}
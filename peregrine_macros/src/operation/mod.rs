@@ -1,13 +1,147 @@
 mod input;
 mod output;
 
+pub use input::split_member_index;
+
 use proc_macro2::{Ident, TokenStream};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Op {
     pub reads: Vec<Ident>,
     pub writes: Vec<Ident>,
     pub read_writes: Vec<Ident>,
     pub body: TokenStream,
     pub internal: bool,
+    /// Whether the body uses `ops_time`.
+    pub uses_time: bool,
+    /// Whether the body uses `ops_elapsed`.
+    pub uses_elapsed: bool,
+    /// Set when the body contains an `if const GUARD { .. }` branch.
+    ///
+    /// `self` is the op with the branch taken, and this is the guard and the op without it.
+    pub const_branch: Option<(TokenStream, Box<Op>)>,
+    /// Set by a leading `#![blocking]`; the body runs on the blocking pool.
+    pub blocking: bool,
+    /// Set by a leading `#![priority]`; root requests for the op are picked up before others.
+    pub priority: bool,
+    /// Set by a leading `#![not_idempotent]`; re-running the body without the rest of its
+    /// activity fails a debug assertion.
+    pub not_idempotent: bool,
+    /// Set by a leading `#![retry(..)]`; the body is run again when it fails with a
+    /// transient error.
+    pub retry: Option<Retry>,
+    /// `ref range(WINDOW): resource` reads, which are also included in `reads`.
+    pub windows: Vec<WindowRead>,
+    /// `ref source: resource` reads, which are also included in `reads`.
+    pub sources: Vec<SourceRead>,
+    /// `ref since(START): resource` reads, which are also included in `reads`.
+    pub sinces: Vec<SinceRead>,
+    /// `ref or(DEFAULT): resource` reads, which are also included in `reads`.
+    pub ors: Vec<OrRead>,
+    /// `ref next_change: resource` reads, which are also included in `reads`.
+    pub next_changes: Vec<NextChangeRead>,
+    /// `ref at_end: resource` reads, which are also included in `reads`.
+    pub at_ends: Vec<AtEndRead>,
+    /// `ref(OFFSET): resource` reads, which are also included in `reads`.
+    pub look_backs: Vec<LookBackRead>,
+    /// `ref d2/dt2: resource` reads, which are also included in `reads`.
+    pub second_derivatives: Vec<SecondDerivativeRead>,
+    /// `ref external: name` reads, which are also included in `reads`.
+    pub externals: Vec<ExternalRead>,
+    /// Logs appended to with `emit: log <- EVENT`, which are also included in `writes`.
+    pub logs: Vec<Ident>,
+}
+
+/// How many times to retry an op body that fails with a transient error.
+#[derive(Debug, Clone)]
+pub struct Retry {
+    pub retries: TokenStream,
+    /// The delay before the first retry, if given. It doubles for each retry after that.
+    pub backoff: Option<TokenStream>,
+}
+
+/// A read of a resource's extremes over the window before the op.
+#[derive(Debug, Clone)]
+pub struct WindowRead {
+    /// The name the read is replaced with in the body, and the alias of its pseudo-resource.
+    pub alias: Ident,
+    pub resource: Ident,
+    pub length: TokenStream,
+}
+
+/// A read of the activity that wrote a resource's value.
+#[derive(Debug, Clone)]
+pub struct SourceRead {
+    /// The name the read is replaced with in the body, and the alias of its pseudo-resource.
+    pub alias: Ident,
+    pub resource: Ident,
+}
+
+/// A read of the time until a resource's next scheduled write.
+#[derive(Debug, Clone)]
+pub struct NextChangeRead {
+    /// The name the read is replaced with in the body, and the alias of its pseudo-resource.
+    pub alias: Ident,
+    pub resource: Ident,
+}
+
+/// A read of a resource's value at the end of the op's activity.
+#[derive(Debug, Clone)]
+pub struct AtEndRead {
+    /// The name the read is replaced with in the body, and the alias of its pseudo-resource.
+    pub alias: Ident,
+    pub resource: Ident,
+}
+
+/// A read of a resource's value at a fixed offset before the op.
+#[derive(Debug, Clone)]
+pub struct LookBackRead {
+    /// The name the read is replaced with in the body, and the alias of its pseudo-resource.
+    pub alias: Ident,
+    pub resource: Ident,
+    pub offset: TokenStream,
+}
+
+/// A read of a resource's second derivative.
+#[derive(Debug, Clone)]
+pub struct SecondDerivativeRead {
+    /// The name the read is replaced with in the body, and the alias of its pseudo-resource.
+    pub alias: Ident,
+    pub resource: Ident,
+}
+
+/// A read of the version of an input from outside the simulation.
+#[derive(Debug, Clone)]
+pub struct ExternalRead {
+    /// The name the read is replaced with in the body, and the alias of its pseudo-resource.
+    pub alias: Ident,
+    pub name: Ident,
+}
+
+/// A read of a resource that might not have a value.
+#[derive(Debug, Clone)]
+pub struct OrRead {
+    /// The name the read is replaced with in the body, and the alias of its pseudo-resource.
+    pub alias: Ident,
+    pub resource: Ident,
+}
+
+/// A read of the values written to a resource since a past time.
+#[derive(Debug, Clone)]
+pub struct SinceRead {
+    /// The name of the read's pseudo-resource.
+    pub alias: Ident,
+    pub resource: Ident,
+    /// The longest the read can look back, if given.
+    pub limit: Option<TokenStream>,
+}
+
+/// An op that reads or writes a group member chosen by `group[const INDEX]`.
+#[derive(Debug, Clone)]
+pub struct MemberIndex {
+    pub group: Ident,
+    /// Evaluated when the op is constructed, to select the member.
+    pub index: TokenStream,
+    /// The op, with the indexed group replaced by `$member`.
+    pub body: TokenStream,
 }
@@ -1,9 +1,13 @@
-use crate::operation::Op;
+use crate::operation::{
+    AtEndRead, ExternalRead, LookBackRead, MemberIndex, NextChangeRead, Op, OrRead, Retry,
+    SecondDerivativeRead, SinceRead, SourceRead, WindowRead,
+};
 use crate::{
     MAX_PREGENERATED_ORDER, impl_node, impl_read_structs_internal, impl_write_structs_internal,
 };
 use proc_macro2::{Ident, TokenStream};
 use quote::{ToTokens, format_ident, quote};
+use rand::Rng;
 
 impl Op {
     fn body_function(&self) -> TokenStream {
@@ -23,14 +27,66 @@ impl Op {
             (quote! { peregrine }, quote! { Fn })
         };
 
+        // The captured marker makes the closure's hash include the grounded time.
+        let (time_marker, time_binding) = if self.uses_time {
+            (
+                quote! { let __peregrine_ops_time = #crate_name::internal::exec::OpsTime; },
+                quote! { let ops_time: #crate_name::Time = __peregrine_ops_time.get(); },
+            )
+        } else {
+            (quote! {}, quote! {})
+        };
+        let (elapsed_marker, elapsed_binding) = if self.uses_elapsed {
+            (
+                quote! { let __peregrine_ops_elapsed = #crate_name::internal::exec::OpsElapsed; },
+                quote! { let ops_elapsed: #crate_name::Duration = __peregrine_ops_elapsed.get(); },
+            )
+        } else {
+            (quote! {}, quote! {})
+        };
+
+        // The captured marker remembers whether the body has already run.
+        let (once_marker, once_check) = if self.not_idempotent {
+            (
+                quote! { let __peregrine_run_once = #crate_name::internal::exec::RunOnce::default(); },
+                quote! { __peregrine_run_once.check(); },
+            )
+        } else {
+            (quote! {}, quote! {})
+        };
+
+        // Logs start each op empty, so that every `emit:` in the body appends to the same write.
+        let (logs, write_onlys): (Vec<_>, Vec<_>) =
+            write_onlys.iter().partition(|w| self.logs.contains(w));
+
+        let mut inner = quote! {
+            #(let mut #logs: <#logs as #crate_name::Resource>::Data = ::core::default::Default::default();)*
+            #(#[allow(unused_mut)] let mut #write_onlys: <#write_onlys as #crate_name::Resource>::Data;)*
+            #body
+            Ok((#(#all_writes,)*))
+        };
+        if self.blocking {
+            inner = quote! {
+                #crate_name::internal::exec::run_blocking(move || {
+                    #inner
+                })
+            };
+        }
+
         quote! {
-            #crate_name::internal::macro_prelude::serde_closure::#fn_name!(move |#(#read_onlys: <<#read_onlys as #crate_name::Resource>::Data as #crate_name::Data>::Sample,)*
-            #(mut #read_writes: <#read_writes as #crate_name::Resource>::Data,)*|
-            -> #crate_name::anyhow::Result<(#(<#all_writes as #crate_name::Resource>::Data,)*)> {
-                #(#[allow(unused_mut)] let mut #write_onlys: <#write_onlys as #crate_name::Resource>::Data;)*
-                #body
-                Ok((#(#all_writes,)*))
-            })
+            {
+                #time_marker
+                #elapsed_marker
+                #once_marker
+                #crate_name::internal::macro_prelude::serde_closure::#fn_name!(move |#(#read_onlys: <<#read_onlys as #crate_name::Resource>::Data as #crate_name::Data>::Sample,)*
+                #(mut #read_writes: <#read_writes as #crate_name::Resource>::Data,)*|
+                -> #crate_name::anyhow::Result<(#(<#all_writes as #crate_name::Resource>::Data,)*)> {
+                    #time_binding
+                    #elapsed_binding
+                    #once_check
+                    #inner
+                })
+            }
         }
     }
 
@@ -53,6 +109,30 @@ impl Op {
 
 impl ToTokens for Op {
     fn to_tokens(&self, tokens: &mut TokenStream) {
+        if let Some((guard, not_taken)) = &self.const_branch {
+            let crate_name = if self.internal {
+                quote! { crate }
+            } else {
+                quote! { peregrine }
+            };
+            let taken = Op {
+                const_branch: None,
+                ..self.clone()
+            };
+            let not_taken = Op {
+                internal: self.internal,
+                ..(**not_taken).clone()
+            };
+            tokens.extend(quote! {
+                if #guard {
+                    #crate_name::public::activity::ConstBranchOp::Taken(#taken)
+                } else {
+                    #crate_name::public::activity::ConstBranchOp::NotTaken(#not_taken)
+                }
+            });
+            return;
+        }
+
         let idents = self.make_idents();
 
         let num_read_onlys = idents.read_onlys.len();
@@ -101,7 +181,168 @@ impl ToTokens for Op {
             quote! { #crate_name::internal::macro_prelude:: }
         };
 
-        let instantiation = result(&idents, self.body_function(), mod_name);
+        // The node calls the body again on transient errors.
+        let retry = self.retry.as_ref().map(|Retry { retries, backoff }| {
+            let backoff = backoff
+                .clone()
+                .unwrap_or_else(|| quote! { ::std::time::Duration::from_millis(10) });
+            quote! { .retry(#retries, #backoff) }
+        });
+
+        let instantiation = result(
+            &idents,
+            self.body_function(),
+            mod_name,
+            self.priority,
+            retry,
+        );
+
+        let write_checks = if self.internal {
+            quote! {}
+        } else {
+            let all_writes = &idents.all_writes;
+            // Autoref specialization only picks the read-only branch for concrete resources,
+            // so ops that write generic resources are unaffected.
+            quote! {
+                #(
+                    let _: () = peregrine::internal::macro_prelude::spez::spez! {
+                        for <#all_writes as peregrine::Resource>::INSTANCE;
+                        match<T: peregrine::internal::resource::ReadOnly> T -> peregrine::internal::resource::CannotWriteToReadOnlyResource {
+                            peregrine::internal::resource::CannotWriteToReadOnlyResource
+                        }
+                        match<T> T -> () {}
+                    };
+                )*
+            }
+        };
+
+        let windows = self.windows.iter().map(|window| {
+            let WindowRead {
+                alias,
+                resource,
+                length,
+            } = window;
+            let length_name = format_ident!("{alias}_length");
+            let id = rand::rng().random::<u64>();
+            quote! {
+                #[allow(non_camel_case_types)]
+                struct #length_name;
+                impl #crate_name::internal::operation::window::WindowLength for #length_name {
+                    const ID: u64 = #id;
+                    fn length() -> #crate_name::Duration {
+                        #length
+                    }
+                }
+                #[allow(non_camel_case_types)]
+                type #alias = #crate_name::internal::operation::window::Window<#resource, #length_name>;
+            }
+        });
+
+        let sources = self.sources.iter().map(|SourceRead { alias, resource }| {
+            quote! {
+                #[allow(non_camel_case_types)]
+                type #alias = #crate_name::internal::operation::source::Source<#resource>;
+            }
+        });
+
+        let next_changes = self
+            .next_changes
+            .iter()
+            .map(|NextChangeRead { alias, resource }| {
+                quote! {
+                    #[allow(non_camel_case_types)]
+                    type #alias = #crate_name::internal::operation::next_change::NextChange<#resource>;
+                }
+            });
+
+        let at_ends = self.at_ends.iter().map(|AtEndRead { alias, resource }| {
+            quote! {
+                #[allow(non_camel_case_types)]
+                type #alias = #crate_name::internal::operation::at_end::AtEnd<#resource>;
+            }
+        });
+
+        let look_backs = self.look_backs.iter().map(|look_back| {
+            let LookBackRead {
+                alias,
+                resource,
+                offset,
+            } = look_back;
+            let offset_name = format_ident!("{alias}_offset");
+            let id = rand::rng().random::<u64>();
+            quote! {
+                #[allow(non_camel_case_types)]
+                struct #offset_name;
+                impl #crate_name::internal::operation::look_back::LookBackOffset for #offset_name {
+                    const ID: u64 = #id;
+                    fn offset() -> #crate_name::Duration {
+                        #offset
+                    }
+                }
+                #[allow(non_camel_case_types)]
+                type #alias = #crate_name::internal::operation::look_back::LookBack<#resource, #offset_name>;
+            }
+        });
+
+        let second_derivatives = self.second_derivatives.iter().map(
+            |SecondDerivativeRead { alias, resource }| {
+                quote! {
+                    #[allow(non_camel_case_types)]
+                    type #alias = #crate_name::internal::operation::second_derivative::SecondDerivative<#resource>;
+                }
+            },
+        );
+
+        let externals = self.externals.iter().map(|ExternalRead { alias, name }| {
+            let name_marker = format_ident!("{alias}_name");
+            let label = name.to_string();
+            let id = rand::rng().random::<u64>();
+            quote! {
+                #[allow(non_camel_case_types)]
+                struct #name_marker;
+                impl #crate_name::internal::operation::external::ExternalName for #name_marker {
+                    const NAME: &'static str = #label;
+                    const ID: u64 = #id;
+                }
+                #[allow(non_camel_case_types)]
+                type #alias = #crate_name::internal::operation::external::External<#name_marker>;
+            }
+        });
+
+        let ors = self.ors.iter().map(|OrRead { alias, resource }| {
+            quote! {
+                #[allow(non_camel_case_types)]
+                type #alias = #crate_name::internal::operation::or_default::OrDefault<#resource>;
+            }
+        });
+
+        let sinces = self.sinces.iter().map(|SinceRead { alias, resource, limit }| {
+            let limit_name = format_ident!("{alias}_limit");
+            match limit {
+                Some(limit) => {
+                    let id = rand::rng().random::<u64>();
+                    quote! {
+                        #[allow(non_camel_case_types)]
+                        struct #limit_name;
+                        impl #crate_name::internal::operation::window::WindowLength for #limit_name {
+                            const ID: u64 = #id;
+                            fn length() -> #crate_name::Duration {
+                                #limit
+                            }
+                        }
+                        #[allow(non_camel_case_types)]
+                        type #alias = #crate_name::internal::operation::since::Since<#resource, #limit_name>;
+                    }
+                }
+                None => quote! {
+                    #[allow(non_camel_case_types)]
+                    type #alias = #crate_name::internal::operation::since::Since<
+                        #resource,
+                        #crate_name::internal::operation::since::DefaultSinceLimit,
+                    >;
+                },
+            }
+        });
 
         let result = quote! {
             {
@@ -111,6 +352,16 @@ impl ToTokens for Op {
                     use peregrine::internal::macro_prelude::*;
                     #declarations
                 }
+                #(#windows)*
+                #(#sources)*
+                #(#next_changes)*
+                #(#at_ends)*
+                #(#look_backs)*
+                #(#second_derivatives)*
+                #(#externals)*
+                #(#sinces)*
+                #(#ors)*
+                #write_checks
                 #instantiation
             }
         };
@@ -126,7 +377,13 @@ struct Idents {
     all_writes: Vec<Ident>,
 }
 
-fn result(idents: &Idents, body_function: TokenStream, mod_name: TokenStream) -> TokenStream {
+fn result(
+    idents: &Idents,
+    body_function: TokenStream,
+    mod_name: TokenStream,
+    priority: bool,
+    retry: Option<TokenStream>,
+) -> TokenStream {
     let Idents {
         read_onlys,
         write_onlys,
@@ -144,7 +401,24 @@ fn result(idents: &Idents, body_function: TokenStream, mod_name: TokenStream) ->
         #(#read_onlys,)* #(#write_onlys,)* #(#read_writes,)*
     };
 
+    let prioritize = priority.then(|| quote! { .prioritize() });
+
     quote! {
-        move |placement| #mod_name #op_name::<'_,_, #resources_generics>::new(placement, #body_function)
+        move |placement| #mod_name #op_name::<'_,_, #resources_generics>::new(placement, #body_function)#prioritize #retry
+    }
+}
+
+impl ToTokens for MemberIndex {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let MemberIndex { group, index, body } = self;
+        // The group's selector macro calls back with the selected member's resource.
+        tokens.extend(quote! {
+            {
+                macro_rules! __peregrine_indexed_op {
+                    ($member:ident) => { peregrine::op! { #body } };
+                }
+                #group!(#index, __peregrine_indexed_op)
+            }
+        });
     }
 }
@@ -1,7 +1,8 @@
-use crate::resource::{GroupResource, Resource, SingleResource};
+use crate::resource::{GroupResource, Resource, ResourceOptions, SingleResource};
+use heck::ToUpperCamelCase;
 use std::collections::HashMap;
 use syn::parse::{Parse, ParseStream};
-use syn::{Attribute, Ident, Token, Visibility, braced};
+use syn::{Attribute, Expr, Ident, Lit, Token, Type, Visibility, braced, bracketed, parse_quote};
 
 pub struct MultiResource {
     pub resources: Vec<Resource>,
@@ -9,7 +10,7 @@ pub struct MultiResource {
 
 impl Parse for Resource {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let attrs = input.call(Attribute::parse_outer)?;
+        let (attrs, mut options) = parse_options(input.call(Attribute::parse_outer)?)?;
         let visibility: Visibility = input.parse()?;
 
         // Parse the identifier pattern, which might contain asterisks
@@ -32,13 +33,36 @@ impl Parse for Resource {
         let name_pattern = name_parts.join("");
 
         let _: Token![:] = input.parse()?;
-        let data_type = input.parse()?;
+        let mut data_type: Type = input.parse()?;
+
+        let unit = if input.peek(syn::token::Bracket) {
+            let unit: UnitAnnotation = input.parse()?;
+            data_type = unit.quantity_type(&data_type)?;
+            options.unit.get_or_insert_with(|| unit.label());
+            Some(unit)
+        } else {
+            None
+        };
+        let in_unit = |default: Expr| match &unit {
+            Some(unit) => unit.quantity(&data_type, default),
+            None => default,
+        };
+
+        if options.count && !has_asterisk {
+            return Err(input.error("`#[count]` is only supported on resource groups"));
+        }
+        if options.count && !matches!(&data_type, Type::Path(path) if path.path.is_ident("bool")) {
+            return Err(syn::Error::new_spanned(
+                &data_type,
+                "`#[count]` is only supported on `bool` groups",
+            ));
+        }
 
         if has_asterisk {
             // Resource group syntax
             let default_expr = if input.peek(Token![=]) {
                 let _: Token![=] = input.parse()?;
-                Some(input.parse()?)
+                Some(in_unit(input.parse()?))
             } else {
                 None
             };
@@ -69,7 +93,7 @@ impl Parse for Resource {
                     let _: Token![:] = content.parse()?;
                     let default: syn::Expr = content.parse()?;
 
-                    individual_defaults.insert(member.to_string(), default);
+                    individual_defaults.insert(member.to_string(), in_unit(default));
                     members.push(member);
 
                     if content.peek(Token![,]) {
@@ -84,6 +108,7 @@ impl Parse for Resource {
                 data_type,
                 default_expr,
                 attrs,
+                options,
                 members,
                 individual_defaults,
             }))
@@ -91,7 +116,7 @@ impl Parse for Resource {
             // Regular single resource syntax
             let default_expr = if input.peek(Token![=]) {
                 let _: Token![=] = input.parse()?;
-                Some(input.parse()?)
+                Some(in_unit(input.parse()?))
             } else {
                 None
             };
@@ -106,11 +131,125 @@ impl Parse for Resource {
                 data_type,
                 default_expr,
                 attrs,
+                options,
             }))
         }
     }
 }
 
+/// A `[quantity::unit]` annotation after a resource's storage type, like `f64 [energy::joule]`.
+///
+/// The resource's data becomes the matching [uom](https://crates.io/crates/uom) quantity, so
+/// that unit mistakes in operations are type errors.
+struct UnitAnnotation {
+    quantity: Ident,
+    unit: Ident,
+}
+
+impl Parse for UnitAnnotation {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let content;
+        bracketed!(content in input);
+        let quantity = content.parse()?;
+        let _: Token![::] = content.parse()?;
+        let unit = content.parse()?;
+        if !content.is_empty() {
+            return Err(content.error("expected a unit like `[energy::joule]`"));
+        }
+        Ok(UnitAnnotation { quantity, unit })
+    }
+}
+
+impl UnitAnnotation {
+    /// The quantity type with the given storage type, e.g. `uom::si::f64::Energy` for `f64`.
+    fn quantity_type(&self, storage: &Type) -> syn::Result<Type> {
+        let Some(storage) = (match storage {
+            Type::Path(path) if path.qself.is_none() => path.path.get_ident(),
+            _ => None,
+        }) else {
+            return Err(syn::Error::new_spanned(
+                storage,
+                "units need a primitive storage type, like `f64`",
+            ));
+        };
+        let quantity = Ident::new(
+            &self.quantity.to_string().to_upper_camel_case(),
+            self.quantity.span(),
+        );
+        Ok(parse_quote! { peregrine::uom::si::#storage::#quantity })
+    }
+
+    /// Converts a number in this unit to a quantity.
+    fn quantity(&self, quantity_type: &Type, value: Expr) -> Expr {
+        let Self { quantity, unit } = self;
+        parse_quote! { <#quantity_type>::new::<peregrine::uom::si::#quantity::#unit>(#value) }
+    }
+
+    fn label(&self) -> syn::LitStr {
+        syn::LitStr::new(&self.unit.to_string(), self.unit.span())
+    }
+}
+
+/// Separates peregrine's resource attributes from the ones that should be
+/// forwarded to the generated label type.
+fn parse_options(attrs: Vec<Attribute>) -> syn::Result<(Vec<Attribute>, ResourceOptions)> {
+    let mut options = ResourceOptions::default();
+    let mut forwarded = vec![];
+
+    for attr in attrs {
+        if attr.path().is_ident("unit") {
+            options.unit = Some(parse_string_attribute(&attr)?);
+        } else if attr.path().is_ident("serde_name") {
+            options.serde_name = Some(parse_string_attribute(&attr)?);
+        } else if attr.path().is_ident("sentinel") {
+            options.sentinel = Some(attr.meta.require_name_value()?.value.clone());
+        } else if attr.path().is_ident("count") {
+            attr.meta.require_path_only()?;
+            options.count = true;
+        } else if attr.path().is_ident("no_cache") {
+            attr.meta.require_path_only()?;
+            options.no_cache = true;
+        } else if attr.path().is_ident("local") {
+            attr.meta.require_path_only()?;
+            options.local = true;
+        } else if attr.path().is_ident("monotonic") {
+            let direction: Ident = attr.parse_args()?;
+            options.monotonic = Some(match direction.to_string().as_str() {
+                "increasing" => true,
+                "decreasing" => false,
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        direction,
+                        "expected `increasing` or `decreasing`",
+                    ));
+                }
+            });
+        } else {
+            forwarded.push(attr);
+        }
+    }
+
+    Ok((forwarded, options))
+}
+
+/// Parses an attribute of the form `#[name = "value"]`.
+fn parse_string_attribute(attr: &Attribute) -> syn::Result<syn::LitStr> {
+    let name_value = attr.meta.require_name_value()?;
+    match &name_value.value {
+        Expr::Lit(expr_lit) => match &expr_lit.lit {
+            Lit::Str(lit_str) => Ok(lit_str.clone()),
+            _ => Err(syn::Error::new_spanned(
+                &name_value.value,
+                "expected a string literal",
+            )),
+        },
+        _ => Err(syn::Error::new_spanned(
+            &name_value.value,
+            "expected a string literal",
+        )),
+    }
+}
+
 impl Parse for MultiResource {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         let mut resources = Vec::new();
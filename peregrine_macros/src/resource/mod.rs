@@ -19,6 +19,7 @@ pub struct SingleResource {
     pub data_type: Type,
     pub default_expr: Option<syn::Expr>,
     pub attrs: Vec<syn::Attribute>,
+    pub options: ResourceOptions,
 }
 
 #[derive(Debug)]
@@ -28,6 +29,33 @@ pub struct GroupResource {
     pub data_type: Type,
     pub default_expr: Option<syn::Expr>, // Shared default for all members
     pub attrs: Vec<syn::Attribute>,
+    pub options: ResourceOptions,
     pub members: Vec<Ident>,
     pub individual_defaults: HashMap<String, syn::Expr>, // Individual defaults
 }
+
+/// Peregrine-specific attributes on a resource declaration.
+///
+/// These are stripped out during parsing, and the remaining attributes
+/// are forwarded to the generated resource label.
+#[derive(Debug, Default, Clone)]
+pub struct ResourceOptions {
+    /// `#[unit = "Wh"]`
+    pub unit: Option<syn::LitStr>,
+    /// `#[serde_name = "battery"]`
+    pub serde_name: Option<syn::LitStr>,
+    /// `#[sentinel = Fault::None]`
+    pub sentinel: Option<syn::Expr>,
+    /// `#[count]`, on a `bool` group. Adds a resource counting the members that are `true`.
+    pub count: bool,
+    /// `#[no_cache]`. Values written to the resource are kept out of history.
+    pub no_cache: bool,
+    /// `#[local]`. The resource is private to each activity that declares it.
+    pub local: bool,
+    /// `#[monotonic(increasing)]` or `#[monotonic(decreasing)]`; true if increasing.
+    pub monotonic: Option<bool>,
+    /// Whether ops are forbidden from writing to the resource.
+    ///
+    /// Not settable by attribute; only used for accessors generated by `expose read` in `model!`.
+    pub read_only: bool,
+}
@@ -1,4 +1,4 @@
-use crate::resource::{GroupResource, MultiResource, Resource, SingleResource};
+use crate::resource::{GroupResource, MultiResource, Resource, ResourceOptions, SingleResource};
 use heck::ToUpperCamelCase;
 use quote::{ToTokens, format_ident, quote};
 use syn::{Expr, Ident};
@@ -38,6 +38,11 @@ pub fn generate_group_name(pattern: &str) -> String {
         .join("_")
 }
 
+/// The name of a group's `#[count]` resource, e.g. `heater_active_count` for `heater_*_active`.
+pub fn generate_count_name(pattern: &str) -> String {
+    format!("{}_count", generate_group_name(pattern))
+}
+
 /// Convert a member name to UpperCamelCase for enum variants
 /// e.g., "main" -> "Main", "a" -> "A"
 pub fn generate_variant_name(member: &str) -> String {
@@ -45,12 +50,13 @@ pub fn generate_variant_name(member: &str) -> String {
 }
 
 /// Generate a single resource definition with the given name, data type, attributes, visibility, and default
-fn generate_single_resource_definition(
+pub fn generate_single_resource_definition(
     resource_name: &Ident,
     data_type: &syn::Type,
     attrs: &[syn::Attribute],
     visibility: &syn::Visibility,
     default_expr: Option<&syn::Expr>,
+    options: &ResourceOptions,
 ) -> proc_macro2::TokenStream {
     let default_impl = if let Some(default) = default_expr {
         quote! { Some(#default) }
@@ -58,6 +64,59 @@ fn generate_single_resource_definition(
         quote! { None }
     };
 
+    let unit = if let Some(unit) = &options.unit {
+        quote! { Some(#unit) }
+    } else {
+        quote! { None }
+    };
+
+    let serde_name = if let Some(serde_name) = &options.serde_name {
+        quote! { #serde_name }
+    } else {
+        quote! { peregrine::internal::macro_prelude::peregrine_macros::code_to_str!(#resource_name) }
+    };
+
+    let sentinel_impl = if let Some(sentinel) = &options.sentinel {
+        quote! {
+            const HAS_SENTINEL: bool = true;
+
+            fn is_sentinel(value: &Self::Data) -> bool {
+                *value == #sentinel
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let no_cache = options.no_cache;
+    let local = options.local;
+
+    let monotonic_impl = match options.monotonic {
+        Some(increasing) => {
+            let step = if increasing {
+                quote! { *next >= *previous }
+            } else {
+                quote! { *next <= *previous }
+            };
+            quote! {
+                const MONOTONIC: bool = true;
+
+                fn is_monotonic_step(previous: &Self::Data, next: &Self::Data) -> bool {
+                    #step
+                }
+            }
+        }
+        None => quote! {},
+    };
+
+    let read_only_impl = if options.read_only {
+        quote! {
+            impl peregrine::internal::resource::ReadOnly for #resource_name {}
+        }
+    } else {
+        quote! {}
+    };
+
     quote! {
         #(#attrs)*
         #[derive(Copy, Clone)]
@@ -69,17 +128,31 @@ fn generate_single_resource_definition(
         impl peregrine::public::resource::Resource for #resource_name {
             const LABEL: &'static str = peregrine::internal::macro_prelude::peregrine_macros::code_to_str!(#resource_name);
             const ID: u64 = peregrine::internal::macro_prelude::peregrine_macros::random_u64!();
+            const UNIT: Option<&'static str> = #unit;
             type Data = #data_type;
             const INSTANCE: Self = Self::Unit;
+            const NO_CACHE: bool = #no_cache;
+            const LOCAL: bool = #local;
 
             fn initial_condition() -> Option<Self::Data> {
                 #default_impl
             }
+
+            #sentinel_impl
+            #monotonic_impl
         }
 
         impl peregrine::internal::resource::ResourceHistoryPlugin for #resource_name {
+            fn id(&self) -> u64 {
+                <#resource_name as peregrine::public::resource::Resource>::ID
+            }
+
+            fn label(&self) -> &'static str {
+                <#resource_name as peregrine::public::resource::Resource>::LABEL
+            }
+
             fn write_type_string(&self) -> String {
-                peregrine::internal::macro_prelude::peregrine_macros::code_to_str!(#resource_name).to_string()
+                #serde_name.to_string()
             }
 
             fn ser<'h>(&self, input: &'h peregrine::internal::macro_prelude::type_map::concurrent::TypeMap, type_map: &'h mut peregrine::internal::macro_prelude::type_reg::untagged::TypeMap<String>) {
@@ -97,7 +170,7 @@ fn generate_single_resource_definition(
                         let sub_history = sub.into_inner().downcast::<peregrine::internal::history::InnerHistory<#resource_name>>();
                         match sub_history {
                             Ok(downcasted) => {
-                                output.insert(*downcasted);
+                                peregrine::internal::history::absorb(output, *downcasted);
                             }
                             Err(_) => unreachable!()
                         }
@@ -105,9 +178,44 @@ fn generate_single_resource_definition(
                     None => {}
                 }
             }
+            fn merge(&self, target: &mut peregrine::internal::macro_prelude::type_map::concurrent::TypeMap, source: &mut peregrine::internal::macro_prelude::type_map::concurrent::TypeMap) {
+                if let Some(incoming) = source.remove::<peregrine::internal::history::InnerHistory<#resource_name>>() {
+                    peregrine::internal::history::absorb(target, incoming);
+                }
+            }
+            fn retain(&self, history: &mut peregrine::internal::macro_prelude::type_map::concurrent::TypeMap, keep: &dyn Fn(u64) -> bool, retired: &mut peregrine::internal::history::Retired) -> usize {
+                peregrine::internal::history::retain::<#resource_name>(history, keep, retired)
+            }
+            fn request_range<'s, 'o: 's>(
+                &self,
+                timelines: &'s peregrine::internal::timeline::Timelines<'o>,
+                bounds: (std::ops::Bound<peregrine::internal::placement::DenseTime>, std::ops::Bound<peregrine::internal::placement::DenseTime>),
+                scope: &peregrine::internal::macro_prelude::rayon::Scope<'s>,
+                env: peregrine::internal::exec::ExecEnvironment<'s, 'o>,
+            ) -> peregrine::anyhow::Result<peregrine::internal::exec::PendingRange<'o>> {
+                peregrine::internal::exec::request_range::<#resource_name>(timelines, bounds, scope, env)
+            }
+            fn sample_range<'s, 'o: 's>(
+                &self,
+                timelines: &'s peregrine::internal::timeline::Timelines<'o>,
+                bounds: (std::ops::Bound<peregrine::internal::placement::DenseTime>, std::ops::Bound<peregrine::internal::placement::DenseTime>),
+                scope: &peregrine::internal::macro_prelude::rayon::Scope<'s>,
+                env: peregrine::internal::exec::ExecEnvironment<'s, 'o>,
+            ) -> peregrine::anyhow::Result<peregrine::internal::exec::PendingSamples<'o>> {
+                peregrine::internal::exec::sample_range::<#resource_name>(timelines, bounds, scope, env)
+            }
+            fn insert_json(
+                &self,
+                conditions: &mut peregrine::internal::operation::initial_conditions::InitialConditions,
+                value: peregrine::internal::macro_prelude::serde_json::Value,
+            ) -> peregrine::anyhow::Result<()> {
+                conditions.insert_json::<#resource_name>(value)
+            }
         }
 
         peregrine::internal::macro_prelude::inventory::submit!(&(#resource_name::Unit) as &dyn peregrine::internal::resource::ResourceHistoryPlugin);
+
+        #read_only_impl
     }
 }
 
@@ -119,6 +227,7 @@ impl ToTokens for SingleResource {
             &self.attrs,
             &self.visibility,
             self.default_expr.as_ref(),
+            &self.options,
         );
         tokens.extend(resource_def);
     }
@@ -151,8 +260,11 @@ impl ToTokens for GroupResource {
                 PartialEq,
                 Debug,
                 peregrine::internal::macro_prelude::enum_iterator::Sequence,
-                std::hash::Hash
+                std::hash::Hash,
+                peregrine::internal::macro_prelude::serde::Serialize,
+                peregrine::internal::macro_prelude::serde::Deserialize
             )]
+            #[serde(crate = "peregrine::internal::macro_prelude::serde")]
             #[struct_derive(Clone, Debug, peregrine::Data, peregrine::MaybeHash, peregrine::internal::macro_prelude::serde::Serialize, peregrine::internal::macro_prelude::serde::Deserialize)]
             #[struct_bounds(for<'his> peregrine::Data<'his>)]
             #[struct_attr(serde(bound(serialize = "T: for<'his> peregrine::Data<'his>")))]
@@ -184,14 +296,77 @@ impl ToTokens for GroupResource {
             None
         };
 
+        // Sentinels and monotonic checks compare member values, so they only apply to the
+        // members.
+        let group_options = ResourceOptions {
+            sentinel: None,
+            monotonic: None,
+            ..self.options.clone()
+        };
         tokens.extend(generate_single_resource_definition(
             &group_name,
             &group_type,
             &self.attrs,
             &self.visibility,
             group_default.as_ref(),
+            &group_options,
         ));
 
+        if self.options.count {
+            let count_name = format_ident!("{}", generate_count_name(&self.name_pattern));
+            let member_defaults = self
+                .members
+                .iter()
+                .filter_map(|m| {
+                    self.individual_defaults
+                        .get(&m.to_string())
+                        .or(self.default_expr.as_ref())
+                })
+                .collect::<Vec<_>>();
+            let count_default: Expr =
+                syn::parse(quote! { 0 #(+ (#member_defaults) as u32)* }.into()).unwrap();
+            let doc = format!(
+                "The number of members of the `{}` group that are `true`. Maintained by the model.",
+                group_name
+            );
+            tokens.extend(generate_single_resource_definition(
+                &count_name,
+                &syn::parse(quote! { u32 }.into()).unwrap(),
+                &[syn::parse_quote! { #[doc = #doc] }],
+                &self.visibility,
+                Some(&count_default),
+                &ResourceOptions {
+                    read_only: true,
+                    ..Default::default()
+                },
+            ));
+        }
+
+        // A selector for `group[const index]` in ops, in the macro namespace under the
+        // group's name. It calls back into the op with the selected member's resource.
+        let selector = format_ident!("__peregrine_select_{}", group_name);
+        let member_resources = self
+            .members
+            .iter()
+            .map(|m| generate_member_resource_ident(&self.name_pattern, &m.to_string()));
+        let selector_visibility = match visibility {
+            syn::Visibility::Inherited => quote! {},
+            _ => quote! { pub(crate) },
+        };
+        tokens.extend(quote! {
+            #[doc(hidden)]
+            #[allow(unused_macros)]
+            macro_rules! #selector {
+                ($index:expr, $op:ident) => {
+                    match $index {
+                        #(#enum_name::#variants => peregrine::public::activity::IndexedOp::new($op!(#member_resources)),)*
+                    }
+                };
+            }
+            #[allow(unused_imports)]
+            #selector_visibility use #selector as #group_name;
+        });
+
         // Expand resource group into individual resources
         for member in &self.members {
             let member_name =
@@ -212,6 +387,7 @@ impl ToTokens for GroupResource {
                 &self.attrs,
                 &self.visibility,
                 member_default,
+                &self.options,
             );
             tokens.extend(resource_def);
         }
@@ -135,11 +135,14 @@ pub fn make_plan(input: TokenStream) -> TokenStream {
         };
         result = quote! {
             #result
-            plan.insert(#time, #activity)?;
+            (#time, Box::new(#activity) as Box<dyn peregrine::Activity>),
         }
     }
 
-    result.into()
+    quote! {
+        plan.insert_batch([#result])?;
+    }
+    .into()
 }
 
 #[proc_macro]